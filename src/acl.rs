@@ -0,0 +1,704 @@
+use crate::KafkaError;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+// kept next to the partition directories in the first log dir
+const ACLS_FILE_NAME: &str = "acls";
+
+// the name every CLUSTER resource acl is bound to
+pub const CLUSTER_RESOURCE_NAME: &str = "kafka-cluster";
+const WILDCARD: &str = "*";
+const WILDCARD_PRINCIPAL: &str = "User:*";
+// the principal of connections that didn't authenticate
+const ANONYMOUS_PRINCIPAL: &str = "User:ANONYMOUS";
+
+pub const RESOURCE_TYPE_ANY: i8 = 1;
+pub const RESOURCE_TYPE_TOPIC: i8 = 2;
+pub const RESOURCE_TYPE_GROUP: i8 = 3;
+pub const RESOURCE_TYPE_CLUSTER: i8 = 4;
+const RESOURCE_TYPES: &[(i8, &str)] = &[
+    (RESOURCE_TYPE_TOPIC, "TOPIC"),
+    (RESOURCE_TYPE_GROUP, "GROUP"),
+    (RESOURCE_TYPE_CLUSTER, "CLUSTER"),
+    (5, "TRANSACTIONAL_ID"),
+    (6, "DELEGATION_TOKEN"),
+    (7, "USER"),
+];
+
+pub const PATTERN_TYPE_ANY: i8 = 1;
+// a filter-only pattern type, matching every acl that applies to the filter's resource name
+pub const PATTERN_TYPE_MATCH: i8 = 2;
+pub const PATTERN_TYPE_LITERAL: i8 = 3;
+pub const PATTERN_TYPE_PREFIXED: i8 = 4;
+const PATTERN_TYPES: &[(i8, &str)] = &[
+    (PATTERN_TYPE_LITERAL, "LITERAL"),
+    (PATTERN_TYPE_PREFIXED, "PREFIXED"),
+];
+
+pub const OPERATION_ANY: i8 = 1;
+pub const OPERATION_ALL: i8 = 2;
+pub const OPERATION_READ: i8 = 3;
+pub const OPERATION_WRITE: i8 = 4;
+pub const OPERATION_CREATE: i8 = 5;
+pub const OPERATION_DELETE: i8 = 6;
+pub const OPERATION_ALTER: i8 = 7;
+pub const OPERATION_DESCRIBE: i8 = 8;
+pub const OPERATION_CLUSTER_ACTION: i8 = 9;
+pub const OPERATION_DESCRIBE_CONFIGS: i8 = 10;
+pub const OPERATION_ALTER_CONFIGS: i8 = 11;
+const OPERATIONS: &[(i8, &str)] = &[
+    (OPERATION_ALL, "ALL"),
+    (OPERATION_READ, "READ"),
+    (OPERATION_WRITE, "WRITE"),
+    (OPERATION_CREATE, "CREATE"),
+    (OPERATION_DELETE, "DELETE"),
+    (OPERATION_ALTER, "ALTER"),
+    (OPERATION_DESCRIBE, "DESCRIBE"),
+    (OPERATION_CLUSTER_ACTION, "CLUSTER_ACTION"),
+    (OPERATION_DESCRIBE_CONFIGS, "DESCRIBE_CONFIGS"),
+    (OPERATION_ALTER_CONFIGS, "ALTER_CONFIGS"),
+    (12, "IDEMPOTENT_WRITE"),
+    (13, "CREATE_TOKENS"),
+    (14, "DESCRIBE_TOKENS"),
+];
+
+pub const PERMISSION_TYPE_ANY: i8 = 1;
+pub const PERMISSION_TYPE_DENY: i8 = 2;
+pub const PERMISSION_TYPE_ALLOW: i8 = 3;
+const PERMISSION_TYPES: &[(i8, &str)] = &[
+    (PERMISSION_TYPE_DENY, "DENY"),
+    (PERMISSION_TYPE_ALLOW, "ALLOW"),
+];
+
+fn code_name(names: &[(i8, &'static str)], code: i8) -> Option<&'static str> {
+    names
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, name)| *name)
+}
+
+fn name_code(names: &[(i8, &str)], name: &str) -> Option<i8> {
+    names
+        .iter()
+        .find(|(_, n)| *n == name)
+        .map(|(code, _)| *code)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AclBinding {
+    pub resource_type: i8,
+    pub resource_name: String,
+    pub pattern_type: i8,
+    // `User:<name>`, or `User:*` for everyone
+    pub principal: String,
+    // a client ip, or `*` for any
+    pub host: String,
+    pub operation: i8,
+    pub permission_type: i8,
+}
+
+impl AclBinding {
+    // why kafka wouldn't store the binding, if it wouldn't
+    pub fn validate(&self) -> Result<(), String> {
+        if code_name(RESOURCE_TYPES, self.resource_type).is_none() {
+            return Err(format!("invalid resource type {}", self.resource_type));
+        }
+        if code_name(PATTERN_TYPES, self.pattern_type).is_none() {
+            return Err(format!(
+                "pattern type must be LITERAL or PREFIXED, got {}",
+                self.pattern_type
+            ));
+        }
+        if code_name(OPERATIONS, self.operation).is_none() {
+            return Err(format!("invalid operation {}", self.operation));
+        }
+        if code_name(PERMISSION_TYPES, self.permission_type).is_none() {
+            return Err(format!(
+                "permission type must be ALLOW or DENY, got {}",
+                self.permission_type
+            ));
+        }
+        if self.resource_name.is_empty() {
+            return Err("resource name must not be empty".to_string());
+        }
+        if self.resource_type == RESOURCE_TYPE_CLUSTER
+            && self.resource_name != CLUSTER_RESOURCE_NAME
+        {
+            return Err(format!(
+                "the cluster resource is named {CLUSTER_RESOURCE_NAME}"
+            ));
+        }
+        if !self
+            .principal
+            .split_once(':')
+            .is_some_and(|(kind, name)| !kind.is_empty() && !name.is_empty())
+        {
+            return Err(format!(
+                "principal must be <type>:<name>, got {}",
+                self.principal
+            ));
+        }
+        // a tab or newline would break the line the binding is persisted as
+        if [&self.resource_name, &self.principal, &self.host]
+            .iter()
+            .any(|field| field.contains(['\t', '\n', '\r']))
+        {
+            return Err("acl fields must not contain tabs or newlines".to_string());
+        }
+        Ok(())
+    }
+
+    fn applies_to(&self, resource_type: i8, resource_name: &str) -> bool {
+        self.resource_type == resource_type
+            && match self.pattern_type {
+                PATTERN_TYPE_LITERAL => {
+                    self.resource_name == resource_name || self.resource_name == WILDCARD
+                }
+                PATTERN_TYPE_PREFIXED => resource_name.starts_with(&self.resource_name),
+                _ => false,
+            }
+    }
+
+    // `<type>\t<pattern>\t<principal>\t<host>\t<operation>\t<permission>\t<resource name>`
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            code_name(RESOURCE_TYPES, self.resource_type).unwrap_or_default(),
+            code_name(PATTERN_TYPES, self.pattern_type).unwrap_or_default(),
+            self.principal,
+            self.host,
+            code_name(OPERATIONS, self.operation).unwrap_or_default(),
+            code_name(PERMISSION_TYPES, self.permission_type).unwrap_or_default(),
+            self.resource_name
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(7, '\t');
+        let binding = AclBinding {
+            resource_type: name_code(RESOURCE_TYPES, fields.next()?)?,
+            pattern_type: name_code(PATTERN_TYPES, fields.next()?)?,
+            principal: fields.next()?.to_string(),
+            host: fields.next()?.to_string(),
+            operation: name_code(OPERATIONS, fields.next()?)?,
+            permission_type: name_code(PERMISSION_TYPES, fields.next()?)?,
+            resource_name: fields.next()?.to_string(),
+        };
+        binding.validate().ok().map(|()| binding)
+    }
+}
+
+// what DescribeAcls and DeleteAcls select bindings by, unset fields match anything
+pub struct AclBindingFilter {
+    pub resource_type: i8,
+    pub resource_name: Option<String>,
+    pub pattern_type: i8,
+    pub principal: Option<String>,
+    pub host: Option<String>,
+    pub operation: i8,
+    pub permission_type: i8,
+}
+
+// a null filter field matches anything
+fn field_matches(filter: &Option<String>, value: &str) -> bool {
+    match filter {
+        Some(filter) => filter == value,
+        None => true,
+    }
+}
+
+impl AclBindingFilter {
+    pub fn matches(&self, binding: &AclBinding) -> bool {
+        let resource_matches = match (self.pattern_type, &self.resource_name) {
+            (PATTERN_TYPE_MATCH, Some(name)) => binding.applies_to(binding.resource_type, name),
+            (PATTERN_TYPE_ANY | PATTERN_TYPE_MATCH, None) => true,
+            (PATTERN_TYPE_ANY, Some(name)) => &binding.resource_name == name,
+            (pattern_type, name) => {
+                binding.pattern_type == pattern_type && field_matches(name, &binding.resource_name)
+            }
+        };
+
+        (self.resource_type == RESOURCE_TYPE_ANY || self.resource_type == binding.resource_type)
+            && resource_matches
+            && field_matches(&self.principal, &binding.principal)
+            && field_matches(&self.host, &binding.host)
+            && (self.operation == OPERATION_ANY || self.operation == binding.operation)
+            && (self.permission_type == PERMISSION_TYPE_ANY
+                || self.permission_type == binding.permission_type)
+    }
+}
+
+// the acl bindings, persisted one per line like the topic config overrides
+pub struct AclStore {
+    // unset when acls only live in memory
+    path: Option<PathBuf>,
+    acls: Mutex<Vec<AclBinding>>,
+}
+
+impl AclStore {
+    pub fn load(log_dir: impl AsRef<Path>) -> Result<Arc<Self>, KafkaError> {
+        let path = log_dir.as_ref().join(ACLS_FILE_NAME);
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let acls = contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                AclBinding::from_line(line).ok_or_else(|| {
+                    KafkaError::InvalidConfig(format!(
+                        "malformed line in {}: {line}",
+                        path.display()
+                    ))
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Arc::new(AclStore {
+            path: Some(path),
+            acls: Mutex::new(acls),
+        }))
+    }
+
+    pub fn in_memory() -> Arc<Self> {
+        Arc::new(AclStore {
+            path: None,
+            acls: Mutex::new(vec![]),
+        })
+    }
+
+    pub fn list(&self, filter: &AclBindingFilter) -> Vec<AclBinding> {
+        let acls = self.acls.lock().unwrap();
+        acls.iter()
+            .filter(|binding| filter.matches(binding))
+            .cloned()
+            .collect()
+    }
+
+    // `bindings` have to be validated already, ones that are already there aren't duplicated
+    pub fn create(&self, bindings: &[AclBinding]) -> Result<(), KafkaError> {
+        let mut acls = self.acls.lock().unwrap();
+        let mut updated = acls.clone();
+        for binding in bindings {
+            if !updated.contains(binding) {
+                updated.push(binding.clone());
+            }
+        }
+
+        self.persist(&updated)?;
+        *acls = updated;
+        Ok(())
+    }
+
+    // the bindings each filter deleted, a binding matching several filters is reported by
+    // the first of them
+    pub fn delete(&self, filters: &[AclBindingFilter]) -> Result<Vec<Vec<AclBinding>>, KafkaError> {
+        let mut acls = self.acls.lock().unwrap();
+        let mut remaining = acls.clone();
+        let deleted = filters
+            .iter()
+            .map(|filter| {
+                let (matched, kept) = remaining
+                    .drain(..)
+                    .partition::<Vec<_>, _>(|binding| filter.matches(binding));
+                remaining = kept;
+                matched
+            })
+            .collect();
+
+        self.persist(&remaining)?;
+        *acls = remaining;
+        Ok(deleted)
+    }
+
+    // written to a temp file first so a crash can't leave a half-written file behind
+    fn persist(&self, acls: &[AclBinding]) -> Result<(), KafkaError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let contents = acls
+            .iter()
+            .map(|binding| binding.to_line() + "\n")
+            .collect::<String>();
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, contents)?;
+        std::fs::rename(&tmp_path, path)?;
+
+        Ok(())
+    }
+}
+
+// decides what a principal may do. the broker only has one (StandardAuthorizer), picked by
+// authorizer.class.name, but requests only go through this
+pub trait Authorizer: Send + Sync {
+    fn authorize(
+        &self,
+        principal: &str,
+        host: &str,
+        operation: i8,
+        resource_type: i8,
+        resource_name: &str,
+    ) -> bool;
+
+    fn acls(&self, filter: &AclBindingFilter) -> Vec<AclBinding>;
+    fn create_acls(&self, bindings: &[AclBinding]) -> Result<(), KafkaError>;
+    fn delete_acls(&self, filters: &[AclBindingFilter])
+        -> Result<Vec<Vec<AclBinding>>, KafkaError>;
+}
+
+// kafka's acl semantics: super users may do anything, a matching DENY beats any ALLOW, and
+// a resource without any acls is only open with allow.everyone.if.no.acl.found
+pub struct StandardAuthorizer {
+    store: Arc<AclStore>,
+    super_users: Vec<String>,
+    allow_everyone_if_no_acl_found: bool,
+}
+
+impl StandardAuthorizer {
+    pub fn new(
+        store: Arc<AclStore>,
+        super_users: Vec<String>,
+        allow_everyone_if_no_acl_found: bool,
+    ) -> Arc<Self> {
+        Arc::new(StandardAuthorizer {
+            store,
+            super_users,
+            allow_everyone_if_no_acl_found,
+        })
+    }
+}
+
+// an ALLOW for any of these operations also allows `operation`
+fn implied_by(operation: i8) -> &'static [i8] {
+    match operation {
+        OPERATION_DESCRIBE => &[
+            OPERATION_DESCRIBE,
+            OPERATION_READ,
+            OPERATION_WRITE,
+            OPERATION_DELETE,
+            OPERATION_ALTER,
+        ],
+        OPERATION_DESCRIBE_CONFIGS => &[OPERATION_DESCRIBE_CONFIGS, OPERATION_ALTER_CONFIGS],
+        _ => &[],
+    }
+}
+
+impl Authorizer for StandardAuthorizer {
+    fn authorize(
+        &self,
+        principal: &str,
+        host: &str,
+        operation: i8,
+        resource_type: i8,
+        resource_name: &str,
+    ) -> bool {
+        if self.super_users.iter().any(|user| user == principal) {
+            return true;
+        }
+
+        let acls = self.store.acls.lock().unwrap();
+        let mut resource_acls = acls
+            .iter()
+            .filter(|binding| binding.applies_to(resource_type, resource_name))
+            .peekable();
+        if resource_acls.peek().is_none() {
+            return self.allow_everyone_if_no_acl_found;
+        }
+
+        let mut allowed = false;
+        for binding in resource_acls {
+            let principal_matches =
+                binding.principal == principal || binding.principal == WILDCARD_PRINCIPAL;
+            let host_matches = binding.host == host || binding.host == WILDCARD;
+            if !principal_matches || !host_matches {
+                continue;
+            }
+
+            match binding.permission_type {
+                PERMISSION_TYPE_DENY
+                    if binding.operation == operation || binding.operation == OPERATION_ALL =>
+                {
+                    return false;
+                }
+                PERMISSION_TYPE_ALLOW
+                    if binding.operation == operation
+                        || binding.operation == OPERATION_ALL
+                        || implied_by(operation).contains(&binding.operation) =>
+                {
+                    allowed = true;
+                }
+                _ => {}
+            }
+        }
+        allowed
+    }
+
+    fn acls(&self, filter: &AclBindingFilter) -> Vec<AclBinding> {
+        self.store.list(filter)
+    }
+
+    fn create_acls(&self, bindings: &[AclBinding]) -> Result<(), KafkaError> {
+        self.store.create(bindings)
+    }
+
+    fn delete_acls(
+        &self,
+        filters: &[AclBindingFilter],
+    ) -> Result<Vec<Vec<AclBinding>>, KafkaError> {
+        self.store.delete(filters)
+    }
+}
+
+// who a request is checked for, everything is allowed when no authorizer is configured
+#[derive(Clone)]
+pub struct Session {
+    pub authorizer: Option<Arc<dyn Authorizer>>,
+    pub principal: String,
+    pub host: String,
+}
+
+impl Session {
+    // `principal` is the sasl username, unauthenticated connections act as User:ANONYMOUS
+    pub fn new(
+        authorizer: Option<Arc<dyn Authorizer>>,
+        principal: Option<&str>,
+        host: &str,
+    ) -> Self {
+        Session {
+            authorizer,
+            principal: principal
+                .map(|name| format!("User:{name}"))
+                .unwrap_or_else(|| ANONYMOUS_PRINCIPAL.to_string()),
+            host: host.to_string(),
+        }
+    }
+
+    pub fn authorize(&self, operation: i8, resource_type: i8, resource_name: &str) -> bool {
+        match &self.authorizer {
+            Some(authorizer) => authorizer.authorize(
+                &self.principal,
+                &self.host,
+                operation,
+                resource_type,
+                resource_name,
+            ),
+            None => true,
+        }
+    }
+
+    pub fn authorize_cluster(&self, operation: i8) -> bool {
+        self.authorize(operation, RESOURCE_TYPE_CLUSTER, CLUSTER_RESOURCE_NAME)
+    }
+
+    // which of `operations` are allowed on the resource, as the bitmask responses report in
+    // their authorized_operations, one bit per operation code
+    pub fn authorized_operations(
+        &self,
+        resource_type: i8,
+        resource_name: &str,
+        operations: &[i8],
+    ) -> i32 {
+        operations
+            .iter()
+            .filter(|&&operation| self.authorize(operation, resource_type, resource_name))
+            .fold(0, |authorized, &operation| authorized | 1 << operation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binding(
+        resource_name: &str,
+        pattern_type: i8,
+        principal: &str,
+        operation: i8,
+        permission_type: i8,
+    ) -> AclBinding {
+        AclBinding {
+            resource_type: RESOURCE_TYPE_TOPIC,
+            resource_name: resource_name.to_string(),
+            pattern_type,
+            principal: principal.to_string(),
+            host: WILDCARD.to_string(),
+            operation,
+            permission_type,
+        }
+    }
+
+    fn session(bindings: &[AclBinding], principal: &str) -> Session {
+        let store = AclStore::in_memory();
+        store.create(bindings).unwrap();
+        let authorizer = StandardAuthorizer::new(store, vec!["User:admin".to_string()], false);
+        Session::new(Some(authorizer), Some(principal), "127.0.0.1")
+    }
+
+    #[test]
+    fn deny_beats_allow() {
+        let acls = [
+            binding(
+                "*",
+                PATTERN_TYPE_LITERAL,
+                WILDCARD_PRINCIPAL,
+                OPERATION_ALL,
+                PERMISSION_TYPE_ALLOW,
+            ),
+            binding(
+                "secret",
+                PATTERN_TYPE_LITERAL,
+                "User:alice",
+                OPERATION_READ,
+                PERMISSION_TYPE_DENY,
+            ),
+        ];
+        let alice = session(&acls, "alice");
+        assert!(!alice.authorize(OPERATION_READ, RESOURCE_TYPE_TOPIC, "secret"));
+        assert!(alice.authorize(OPERATION_WRITE, RESOURCE_TYPE_TOPIC, "secret"));
+        assert!(alice.authorize(OPERATION_READ, RESOURCE_TYPE_TOPIC, "public"));
+        assert!(session(&acls, "bob").authorize(OPERATION_READ, RESOURCE_TYPE_TOPIC, "secret"));
+    }
+
+    #[test]
+    fn describe_is_implied_by_the_operations_it_goes_with() {
+        let acls = [binding(
+            "logs-",
+            PATTERN_TYPE_PREFIXED,
+            "User:alice",
+            OPERATION_WRITE,
+            PERMISSION_TYPE_ALLOW,
+        )];
+        let alice = session(&acls, "alice");
+        assert!(alice.authorize(OPERATION_WRITE, RESOURCE_TYPE_TOPIC, "logs-app"));
+        assert!(alice.authorize(OPERATION_DESCRIBE, RESOURCE_TYPE_TOPIC, "logs-app"));
+        assert!(!alice.authorize(OPERATION_READ, RESOURCE_TYPE_TOPIC, "logs-app"));
+        assert!(!alice.authorize(OPERATION_WRITE, RESOURCE_TYPE_TOPIC, "metrics"));
+        assert_eq!(
+            alice.authorized_operations(
+                RESOURCE_TYPE_TOPIC,
+                "logs-app",
+                &[OPERATION_READ, OPERATION_WRITE, OPERATION_DESCRIBE]
+            ),
+            1 << OPERATION_WRITE | 1 << OPERATION_DESCRIBE
+        );
+    }
+
+    #[test]
+    fn resources_without_acls_and_super_users() {
+        let acls = [binding(
+            "t",
+            PATTERN_TYPE_LITERAL,
+            "User:alice",
+            OPERATION_READ,
+            PERMISSION_TYPE_ALLOW,
+        )];
+        let bob = session(&acls, "bob");
+        assert!(!bob.authorize(OPERATION_READ, RESOURCE_TYPE_TOPIC, "t"));
+        assert!(!bob.authorize(OPERATION_READ, RESOURCE_TYPE_TOPIC, "unguarded"));
+        assert!(session(&acls, "admin").authorize(OPERATION_DELETE, RESOURCE_TYPE_TOPIC, "t"));
+
+        let store = AclStore::in_memory();
+        store.create(&acls).unwrap();
+        let open = StandardAuthorizer::new(store, vec![], true);
+        let bob = Session::new(Some(open), Some("bob"), "127.0.0.1");
+        assert!(!bob.authorize(OPERATION_READ, RESOURCE_TYPE_TOPIC, "t"));
+        assert!(bob.authorize(OPERATION_READ, RESOURCE_TYPE_TOPIC, "unguarded"));
+
+        // nothing is checked without an authorizer
+        let anyone = Session::new(None, None, "127.0.0.1");
+        assert_eq!(anyone.principal, ANONYMOUS_PRINCIPAL);
+        assert!(anyone.authorize_cluster(OPERATION_ALTER));
+    }
+
+    #[test]
+    fn bindings_are_validated() {
+        let valid = binding(
+            "t",
+            PATTERN_TYPE_LITERAL,
+            "User:alice",
+            OPERATION_READ,
+            PERMISSION_TYPE_ALLOW,
+        );
+        assert_eq!(valid.validate(), Ok(()));
+        for invalid in [
+            AclBinding {
+                pattern_type: PATTERN_TYPE_MATCH,
+                ..valid.clone()
+            },
+            AclBinding {
+                permission_type: PERMISSION_TYPE_ANY,
+                ..valid.clone()
+            },
+            AclBinding {
+                principal: "alice".to_string(),
+                ..valid.clone()
+            },
+            AclBinding {
+                resource_name: "a\tb".to_string(),
+                ..valid.clone()
+            },
+            AclBinding {
+                resource_type: RESOURCE_TYPE_CLUSTER,
+                ..valid.clone()
+            },
+        ] {
+            assert!(invalid.validate().is_err(), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn acls_persist_and_delete_by_filter() {
+        let log_dir = std::env::temp_dir().join(format!("acl-test-{}", std::process::id()));
+        let read = binding(
+            "t",
+            PATTERN_TYPE_LITERAL,
+            "User:alice",
+            OPERATION_READ,
+            PERMISSION_TYPE_ALLOW,
+        );
+        let write = binding(
+            "t-",
+            PATTERN_TYPE_PREFIXED,
+            "User:bob",
+            OPERATION_WRITE,
+            PERMISSION_TYPE_ALLOW,
+        );
+        let store = AclStore::load(&log_dir).unwrap();
+        store
+            .create(&[read.clone(), write.clone(), read.clone()])
+            .unwrap();
+
+        let reloaded = AclStore::load(&log_dir).unwrap();
+        let bobs = AclBindingFilter {
+            resource_type: RESOURCE_TYPE_ANY,
+            resource_name: Some("t-1".to_string()),
+            pattern_type: PATTERN_TYPE_MATCH,
+            principal: None,
+            host: None,
+            operation: OPERATION_ANY,
+            permission_type: PERMISSION_TYPE_ANY,
+        };
+        assert_eq!(reloaded.list(&bobs), vec![write.clone()]);
+        assert_eq!(reloaded.delete(&[bobs]).unwrap(), vec![vec![write]]);
+
+        let remaining = AclStore::load(&log_dir).unwrap();
+        std::fs::remove_dir_all(&log_dir).unwrap();
+        let everything = AclBindingFilter {
+            resource_type: RESOURCE_TYPE_ANY,
+            resource_name: None,
+            pattern_type: PATTERN_TYPE_ANY,
+            principal: None,
+            host: None,
+            operation: OPERATION_ANY,
+            permission_type: PERMISSION_TYPE_ANY,
+        };
+        assert_eq!(remaining.list(&everything), vec![read]);
+    }
+}
@@ -0,0 +1,379 @@
+use crate::acl::*;
+use crate::readers::*;
+use crate::writers::*;
+use crate::{
+    KafkaError, CLUSTER_AUTHORIZATION_FAILED, INVALID_REQUEST, NONE, SECURITY_DISABLED, TAG_BUFFER,
+    UNKNOWN_SERVER_ERROR,
+};
+use std::collections::BTreeMap;
+use std::io::Cursor;
+
+const SECURITY_DISABLED_MESSAGE: &str = "No Authorizer is configured on the broker";
+
+fn read_filter(cursor: &mut Cursor<&[u8]>) -> Result<AclBindingFilter, KafkaError> {
+    let filter = AclBindingFilter {
+        resource_type: read_int8(cursor)?,
+        resource_name: read_compact_nullable_string(cursor)?,
+        pattern_type: read_int8(cursor)?,
+        principal: read_compact_nullable_string(cursor)?,
+        host: read_compact_nullable_string(cursor)?,
+        operation: read_int8(cursor)?,
+        permission_type: read_int8(cursor)?,
+    };
+    Ok(filter)
+}
+
+// ### DESCRIBE ACLS (v3) ### //
+pub struct DescribeAclsRequest {
+    pub filter: AclBindingFilter,
+}
+
+impl DescribeAclsRequest {
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+
+        let filter = read_filter(&mut cursor)?;
+
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(DescribeAclsRequest { filter })
+    }
+}
+
+pub struct DescribeAclsResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub error_message: Option<String>,
+    pub resources: Vec<DescribeAclsResource>,
+}
+
+pub struct DescribeAclsResource {
+    pub resource_type: i8,
+    pub resource_name: String,
+    pub pattern_type: i8,
+    pub acls: Vec<AclDescription>,
+}
+
+pub struct AclDescription {
+    pub principal: String,
+    pub host: String,
+    pub operation: i8,
+    pub permission_type: i8,
+}
+
+impl DescribeAclsResponse {
+    fn error(error_code: i16, error_message: &str) -> Self {
+        DescribeAclsResponse {
+            throttle_time_ms: 0,
+            error_code,
+            error_message: Some(error_message.to_string()),
+            resources: vec![],
+        }
+    }
+
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.throttle_time_ms.to_be_bytes());
+        res_buf.extend_from_slice(&self.error_code.to_be_bytes());
+        write_compact_nullable_string(res_buf, self.error_message.as_deref());
+
+        write_compact_array_len(res_buf, self.resources.len()); // [resources]
+        for resource in &self.resources {
+            res_buf.push(resource.resource_type as u8);
+            write_compact_string(res_buf, &resource.resource_name);
+            res_buf.push(resource.pattern_type as u8);
+
+            write_compact_array_len(res_buf, resource.acls.len()); // [acls]
+            for acl in &resource.acls {
+                write_compact_string(res_buf, &acl.principal);
+                write_compact_string(res_buf, &acl.host);
+                res_buf.push(acl.operation as u8);
+                res_buf.push(acl.permission_type as u8);
+                res_buf.extend_from_slice(TAG_BUFFER);
+            }
+
+            res_buf.extend_from_slice(TAG_BUFFER);
+        }
+
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+pub fn describe_acls(session: &Session, request: &DescribeAclsRequest) -> DescribeAclsResponse {
+    let Some(authorizer) = &session.authorizer else {
+        return DescribeAclsResponse::error(SECURITY_DISABLED, SECURITY_DISABLED_MESSAGE);
+    };
+    if !session.authorize_cluster(OPERATION_DESCRIBE) {
+        return DescribeAclsResponse::error(
+            CLUSTER_AUTHORIZATION_FAILED,
+            "Not authorized to describe acls",
+        );
+    }
+
+    // bindings are grouped by the resource pattern they're bound to
+    let mut resources: BTreeMap<(i8, String, i8), Vec<AclDescription>> = BTreeMap::new();
+    for binding in authorizer.acls(&request.filter) {
+        resources
+            .entry((
+                binding.resource_type,
+                binding.resource_name,
+                binding.pattern_type,
+            ))
+            .or_default()
+            .push(AclDescription {
+                principal: binding.principal,
+                host: binding.host,
+                operation: binding.operation,
+                permission_type: binding.permission_type,
+            });
+    }
+
+    DescribeAclsResponse {
+        throttle_time_ms: 0,
+        error_code: NONE,
+        error_message: None,
+        resources: resources
+            .into_iter()
+            .map(
+                |((resource_type, resource_name, pattern_type), acls)| DescribeAclsResource {
+                    resource_type,
+                    resource_name,
+                    pattern_type,
+                    acls,
+                },
+            )
+            .collect(),
+    }
+}
+
+// ### CREATE ACLS (v3) ### //
+pub struct CreateAclsRequest {
+    pub creations: Vec<AclBinding>,
+}
+
+impl CreateAclsRequest {
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+
+        let creations_size = read_compact_array_len(&mut cursor)?; // [creations]
+        let mut creations = array_with_capacity(creations_size);
+        for _ in 0..creations_size {
+            let resource_type = read_int8(&mut cursor)?;
+            let resource_name = read_compact_string(&mut cursor)?;
+            let pattern_type = read_int8(&mut cursor)?;
+            let principal = read_compact_string(&mut cursor)?;
+            let host = read_compact_string(&mut cursor)?;
+            let operation = read_int8(&mut cursor)?;
+            let permission_type = read_int8(&mut cursor)?;
+            read_tagged_fields(&mut cursor)?;
+
+            creations.push(AclBinding {
+                resource_type,
+                resource_name,
+                pattern_type,
+                principal,
+                host,
+                operation,
+                permission_type,
+            });
+        }
+
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(CreateAclsRequest { creations })
+    }
+}
+
+pub struct CreateAclsResponse {
+    pub throttle_time_ms: i32,
+    pub results: Vec<AclCreationResult>,
+}
+
+pub struct AclCreationResult {
+    pub error_code: i16,
+    pub error_message: Option<String>,
+}
+
+impl CreateAclsResponse {
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.throttle_time_ms.to_be_bytes());
+
+        write_compact_array_len(res_buf, self.results.len()); // [results]
+        for result in &self.results {
+            res_buf.extend_from_slice(&result.error_code.to_be_bytes());
+            write_compact_nullable_string(res_buf, result.error_message.as_deref());
+            res_buf.extend_from_slice(TAG_BUFFER);
+        }
+
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+// the valid creations are stored together, an invalid one doesn't hold the others back
+pub fn create_acls(session: &Session, request: &CreateAclsRequest) -> CreateAclsResponse {
+    let error = |error_code, error_message: &str| AclCreationResult {
+        error_code,
+        error_message: Some(error_message.to_string()),
+    };
+    let rejected = |error_code, error_message: &str| CreateAclsResponse {
+        throttle_time_ms: 0,
+        results: request
+            .creations
+            .iter()
+            .map(|_| error(error_code, error_message))
+            .collect(),
+    };
+
+    let Some(authorizer) = &session.authorizer else {
+        return rejected(SECURITY_DISABLED, SECURITY_DISABLED_MESSAGE);
+    };
+    if !session.authorize_cluster(OPERATION_ALTER) {
+        return rejected(
+            CLUSTER_AUTHORIZATION_FAILED,
+            "Not authorized to create acls",
+        );
+    }
+
+    let validated = request
+        .creations
+        .iter()
+        .map(|binding| binding.validate())
+        .collect::<Vec<_>>();
+    let valid = request
+        .creations
+        .iter()
+        .zip(&validated)
+        .filter(|(_, validated)| validated.is_ok())
+        .map(|(binding, _)| binding.clone())
+        .collect::<Vec<_>>();
+    let stored = authorizer.create_acls(&valid).map_err(|e| {
+        eprintln!("Error storing acls: {e}");
+        e.to_string()
+    });
+
+    let results = validated
+        .into_iter()
+        .map(|validated| match (validated, &stored) {
+            (Err(message), _) => error(INVALID_REQUEST, &message),
+            (Ok(()), Err(message)) => error(UNKNOWN_SERVER_ERROR, message),
+            (Ok(()), Ok(())) => AclCreationResult {
+                error_code: NONE,
+                error_message: None,
+            },
+        })
+        .collect();
+
+    CreateAclsResponse {
+        throttle_time_ms: 0,
+        results,
+    }
+}
+
+// ### DELETE ACLS (v3) ### //
+pub struct DeleteAclsRequest {
+    pub filters: Vec<AclBindingFilter>,
+}
+
+impl DeleteAclsRequest {
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+
+        let filters_size = read_compact_array_len(&mut cursor)?; // [filters]
+        let mut filters = array_with_capacity(filters_size);
+        for _ in 0..filters_size {
+            filters.push(read_filter(&mut cursor)?);
+            read_tagged_fields(&mut cursor)?;
+        }
+
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(DeleteAclsRequest { filters })
+    }
+}
+
+pub struct DeleteAclsResponse {
+    pub throttle_time_ms: i32,
+    pub filter_results: Vec<DeleteAclsFilterResult>,
+}
+
+pub struct DeleteAclsFilterResult {
+    pub error_code: i16,
+    pub error_message: Option<String>,
+    pub matching_acls: Vec<AclBinding>,
+}
+
+impl DeleteAclsResponse {
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.throttle_time_ms.to_be_bytes());
+
+        write_compact_array_len(res_buf, self.filter_results.len()); // [filter_results]
+        for result in &self.filter_results {
+            res_buf.extend_from_slice(&result.error_code.to_be_bytes());
+            write_compact_nullable_string(res_buf, result.error_message.as_deref());
+
+            write_compact_array_len(res_buf, result.matching_acls.len()); // [matching_acls]
+            for binding in &result.matching_acls {
+                // each deleted binding carries its own error, deletion is all or nothing here
+                res_buf.extend_from_slice(&NONE.to_be_bytes());
+                write_compact_nullable_string(res_buf, None);
+                res_buf.push(binding.resource_type as u8);
+                write_compact_string(res_buf, &binding.resource_name);
+                res_buf.push(binding.pattern_type as u8);
+                write_compact_string(res_buf, &binding.principal);
+                write_compact_string(res_buf, &binding.host);
+                res_buf.push(binding.operation as u8);
+                res_buf.push(binding.permission_type as u8);
+                res_buf.extend_from_slice(TAG_BUFFER);
+            }
+
+            res_buf.extend_from_slice(TAG_BUFFER);
+        }
+
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+pub fn delete_acls(session: &Session, request: &DeleteAclsRequest) -> DeleteAclsResponse {
+    let error = |error_code, error_message: &str| DeleteAclsResponse {
+        throttle_time_ms: 0,
+        filter_results: request
+            .filters
+            .iter()
+            .map(|_| DeleteAclsFilterResult {
+                error_code,
+                error_message: Some(error_message.to_string()),
+                matching_acls: vec![],
+            })
+            .collect(),
+    };
+
+    let Some(authorizer) = &session.authorizer else {
+        return error(SECURITY_DISABLED, SECURITY_DISABLED_MESSAGE);
+    };
+    if !session.authorize_cluster(OPERATION_ALTER) {
+        return error(
+            CLUSTER_AUTHORIZATION_FAILED,
+            "Not authorized to delete acls",
+        );
+    }
+
+    match authorizer.delete_acls(&request.filters) {
+        Ok(deleted) => DeleteAclsResponse {
+            throttle_time_ms: 0,
+            filter_results: deleted
+                .into_iter()
+                .map(|matching_acls| DeleteAclsFilterResult {
+                    error_code: NONE,
+                    error_message: None,
+                    matching_acls,
+                })
+                .collect(),
+        },
+        Err(e) => {
+            eprintln!("Error deleting acls: {e}");
+            error(UNKNOWN_SERVER_ERROR, &e.to_string())
+        }
+    }
+}
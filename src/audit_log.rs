@@ -0,0 +1,146 @@
+use crate::KafkaError;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// what the audit log records about a request, once its response has gone out
+pub struct AuditEntry<'a> {
+    pub api_key: i16,
+    // -1 when the request was rejected before its header could be read
+    pub api_version: i16,
+    pub correlation_id: i32,
+    pub client_id: &'a str,
+    pub client_host: &'a str,
+    // `None` on connections that haven't authenticated
+    pub principal: Option<&'a str>,
+    // the response's top level error code, like the request metrics
+    pub error_code: i16,
+    pub latency: Duration,
+}
+
+impl AuditEntry<'_> {
+    pub fn to_json(&self) -> String {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_millis() as i64)
+            .unwrap_or_default();
+        let principal = match self.principal {
+            Some(principal) => json_string(principal),
+            None => "null".to_string(),
+        };
+        let outcome = match self.error_code {
+            0 => "success",
+            _ => "failure",
+        };
+
+        format!(
+            "{{\"timestamp_ms\":{timestamp_ms},\"api_key\":{},\"api_version\":{},\
+             \"correlation_id\":{},\"client_id\":{},\"client_host\":{},\"principal\":{principal},\
+             \"outcome\":\"{outcome}\",\"error_code\":{},\"latency_ms\":{:.3}}}\n",
+            self.api_key,
+            self.api_version,
+            self.correlation_id,
+            json_string(self.client_id),
+            json_string(self.client_host),
+            self.error_code,
+            self.latency.as_secs_f64() * 1000.0,
+        )
+    }
+}
+
+// escapes everything json doesn't allow raw inside a string
+fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+struct AuditFile {
+    file: File,
+    size: u64,
+}
+
+// an append-only file of json lines. like log4j's RollingFileAppender, a file that would
+// grow past `max_bytes` is renamed to `<path>.1`, shifting the older ones up to
+// `<path>.<max_backups>` and deleting whatever falls off the end
+pub struct AuditLog {
+    path: PathBuf,
+    max_bytes: u64,
+    max_backups: u32,
+    file: Mutex<AuditFile>,
+}
+
+impl AuditLog {
+    pub fn open(
+        path: impl AsRef<Path>,
+        max_bytes: u64,
+        max_backups: u32,
+    ) -> Result<Arc<Self>, KafkaError> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = open_append(&path)?;
+
+        Ok(Arc::new(AuditLog {
+            path,
+            max_bytes,
+            max_backups,
+            file: Mutex::new(file),
+        }))
+    }
+
+    // blocking file io, a line is never split across two files
+    pub fn append(&self, line: &str) -> Result<(), KafkaError> {
+        let mut file = self.file.lock().unwrap();
+        if file.size > 0 && file.size + line.len() as u64 > self.max_bytes {
+            *file = self.rotate()?;
+        }
+
+        file.file.write_all(line.as_bytes())?;
+        file.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&self) -> Result<AuditFile, KafkaError> {
+        let backup = |index: u32| {
+            let mut name = self.path.clone().into_os_string();
+            name.push(format!(".{index}"));
+            PathBuf::from(name)
+        };
+
+        if self.max_backups == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            for index in (1..self.max_backups).rev() {
+                match std::fs::rename(backup(index), backup(index + 1)) {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                    _ => {}
+                }
+            }
+            std::fs::rename(&self.path, backup(1))?;
+        }
+
+        open_append(&self.path)
+    }
+}
+
+fn open_append(path: &Path) -> Result<AuditFile, KafkaError> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    Ok(AuditFile { file, size })
+}
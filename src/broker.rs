@@ -0,0 +1,396 @@
+use crate::config::LogStoreKind;
+use crate::isr::run_isr_shrinker;
+use crate::meta_properties::{load_meta_properties, MetaProperties};
+use crate::metadata_snapshot::run_metadata_snapshots;
+use crate::purgatory::run_purgatory_reaper;
+use crate::storage::{run_log_cleaner, run_partition_discovery};
+use crate::{
+    handle_connection, run_replica_fetchers, serve_metrics, BrokerConfig, BrokerState,
+    FetchInterceptor, KafkaError, LogManager, LogStore, MemoryLogStore, MetadataStores, Metrics,
+    NodeState, NoopFetchInterceptor, RequestLogs,
+};
+use std::net::SocketAddr;
+use std::os::fd::AsFd;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::{TcpListener, TcpSocket, TcpStream, UnixListener};
+use tokio::task::{JoinHandle, JoinSet};
+
+// a broker running inside the current tokio runtime, as the binary runs it or as a stand-in
+// kafka for another program's integration tests
+pub struct Broker;
+
+impl Broker {
+    pub fn builder() -> BrokerBuilder {
+        BrokerBuilder::default()
+    }
+
+    // loads the logs, binds every listener and starts serving connections
+    pub async fn start(config: BrokerConfig) -> Result<BrokerHandle, KafkaError> {
+        start_broker(config, Arc::new(NoopFetchInterceptor)).await
+    }
+}
+
+type OpenedLogs = (MetadataStores, Arc<dyn LogStore>, Option<MetaProperties>);
+
+// the partition logs and the metadata stores kept next to them, in the first log dir. the
+// stores, like meta.properties, are only read once the log dirs are locked, so a second
+// broker on the same dirs fails before touching any of them
+pub(crate) fn open_logs(config: &BrokerConfig) -> Result<OpenedLogs, KafkaError> {
+    let opened: OpenedLogs = match config.log_store {
+        LogStoreKind::File => {
+            let logs = LogManager::load(&config.log_dirs)?;
+            let loaded = load_meta_properties(&config.log_dirs, config.node_id)
+                .and_then(|meta| Ok((MetadataStores::load(&config.log_dirs[0])?, meta)));
+            match loaded {
+                Ok((stores, meta)) => (stores, logs, meta),
+                Err(e) => {
+                    logs.close()?;
+                    return Err(e);
+                }
+            }
+        }
+        LogStoreKind::Memory => (MetadataStores::in_memory(), MemoryLogStore::new(), None),
+    };
+    Ok(opened)
+}
+
+// the metrics listener comes up first, so the readiness probe answers through the log
+// recovery, and goes away again when the broker fails to start
+async fn start_broker(
+    config: BrokerConfig,
+    fetch_interceptor: Arc<dyn FetchInterceptor>,
+) -> Result<BrokerHandle, KafkaError> {
+    let started = Instant::now();
+    let metrics = Metrics::new();
+    let mut metrics_addr = None;
+    let metrics_task = match config.metrics_port {
+        Some(port) => {
+            let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+            metrics_addr = Some(listener.local_addr()?);
+            Some(tokio::spawn(serve_metrics(listener, metrics.clone())))
+        }
+        None => None,
+    };
+
+    match start_serving(config, fetch_interceptor, metrics).await {
+        Ok(mut broker) => {
+            broker.tasks.extend(metrics_task);
+            print_banner(&broker, metrics_addr, started.elapsed());
+            Ok(broker)
+        }
+        Err(e) => {
+            if let Some(task) = metrics_task {
+                task.abort();
+            }
+            Err(e)
+        }
+    }
+}
+
+async fn start_serving(
+    mut config: BrokerConfig,
+    fetch_interceptor: Arc<dyn FetchInterceptor>,
+    metrics: Arc<Metrics>,
+) -> Result<BrokerHandle, KafkaError> {
+    // off the runtime's threads, which keep answering the probe
+    metrics.set_node_state(NodeState::RecoveringLogs);
+    let log_config = config.clone();
+    let (stores, logs, meta) = tokio::task::spawn_blocking(move || open_logs(&log_config))
+        .await
+        .map_err(|e| KafkaError::Io(std::io::Error::other(e)))??;
+    metrics.set_node_state(NodeState::Starting);
+
+    let request_logs = match RequestLogs::open(&config) {
+        Ok(request_logs) => request_logs,
+        Err(e) => {
+            logs.close()?;
+            return Err(e);
+        }
+    };
+
+    // nothing has been spawned yet, so a failed bind leaves only the logs to close
+    let listeners = match bind_listeners(&mut config).await {
+        Ok(listeners) => listeners,
+        Err(e) => {
+            logs.close()?;
+            return Err(e.into());
+        }
+    };
+
+    let mut tasks = vec![];
+    tasks.push(tokio::spawn(run_log_cleaner(
+        logs.clone(),
+        stores.topic_configs.clone(),
+        Duration::from_millis(config.log_retention_check_interval_ms),
+    )));
+    tasks.push(tokio::spawn(run_partition_discovery(
+        logs.clone(),
+        Duration::from_millis(config.log_partition_discovery_interval_ms),
+    )));
+
+    let state = BrokerState::new(
+        Arc::new(config),
+        metrics,
+        logs,
+        meta,
+        stores,
+        request_logs,
+        fetch_interceptor,
+    );
+
+    tasks.push(tokio::spawn(run_replica_fetchers(state.clone())));
+    // checked twice per lag time, like kafka
+    tasks.push(tokio::spawn(run_isr_shrinker(
+        state.logs.clone(),
+        state.isr.clone(),
+        Duration::from_millis((state.config.replica_lag_time_max_ms / 2).max(1)),
+    )));
+    tasks.push(tokio::spawn(run_purgatory_reaper(
+        state.fetch_purgatory.clone(),
+    )));
+    let snapshot_interval_ms = state.config.metadata_log_max_snapshot_interval_ms;
+    tasks.push(tokio::spawn(run_metadata_snapshots(
+        state.metadata_log.clone(),
+        state.config.metadata_log_max_record_bytes_between_snapshots,
+        (snapshot_interval_ms > 0).then(|| Duration::from_millis(snapshot_interval_ms)),
+    )));
+
+    if let Some(listener) = listeners.unix {
+        tasks.push(tokio::spawn(accept_unix(listener, state.clone())));
+    }
+    let mut local_addrs = vec![];
+    for listener in listeners.tcp {
+        local_addrs.push(listener.local_addr()?);
+        tasks.push(tokio::spawn(accept_tcp(listener, state.clone())));
+    }
+    state.metrics.set_node_state(NodeState::Ready);
+
+    Ok(BrokerHandle {
+        state,
+        local_addrs,
+        tasks,
+    })
+}
+
+// server.properties settings for an embedded broker, anything not set keeps its default
+#[derive(Default)]
+pub struct BrokerBuilder {
+    properties: Vec<(String, String)>,
+    // NoopFetchInterceptor when unset
+    fetch_interceptor: Option<Arc<dyn FetchInterceptor>>,
+}
+
+impl BrokerBuilder {
+    // a single PLAINTEXT listener, port 0 binds an ephemeral one (see BrokerHandle::local_addr)
+    pub fn bind_address(self, addr: SocketAddr) -> Self {
+        self.config("listeners", format!("PLAINTEXT://{addr}"))
+    }
+
+    pub fn log_dir(self, log_dir: impl AsRef<Path>) -> Self {
+        self.config("log.dirs", log_dir.as_ref().display().to_string())
+    }
+
+    // any server.properties key, later calls win
+    pub fn config(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.properties.push((key.into(), value.into()));
+        self
+    }
+
+    pub fn fetch_interceptor(mut self, interceptor: impl FetchInterceptor + 'static) -> Self {
+        self.fetch_interceptor = Some(Arc::new(interceptor));
+        self
+    }
+
+    pub fn build(&self) -> Result<BrokerConfig, KafkaError> {
+        let contents = self
+            .properties
+            .iter()
+            .map(|(key, value)| format!("{key}={value}\n"))
+            .collect::<String>();
+        BrokerConfig::parse(&contents)
+    }
+
+    pub async fn start(self) -> Result<BrokerHandle, KafkaError> {
+        let fetch_interceptor = self
+            .fetch_interceptor
+            .clone()
+            .unwrap_or_else(|| Arc::new(NoopFetchInterceptor));
+        start_broker(self.build()?, fetch_interceptor).await
+    }
+}
+
+// dropping the handle leaves the broker running until the runtime goes away
+pub struct BrokerHandle {
+    state: Arc<BrokerState>,
+    local_addrs: Vec<SocketAddr>,
+    // accept loops (which own their connections) and background tasks
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl BrokerHandle {
+    // the address of the first tcp listener, with the port it actually bound
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addrs.first().copied()
+    }
+
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.local_addrs
+    }
+
+    pub fn state(&self) -> &Arc<BrokerState> {
+        &self.state
+    }
+
+    // stops accepting, drops every open connection, then closes the logs like a clean stop
+    pub async fn shutdown(self) -> Result<(), KafkaError> {
+        for task in &self.tasks {
+            task.abort();
+        }
+        for task in self.tasks {
+            let _ = task.await;
+        }
+
+        let logs = self.state.logs.clone();
+        tokio::task::spawn_blocking(move || logs.close())
+            .await
+            .map_err(|e| KafkaError::Io(std::io::Error::other(e)))??;
+        Ok(())
+    }
+}
+
+struct Listeners {
+    tcp: Vec<TcpListener>,
+    unix: Option<UnixListener>,
+}
+
+// listeners on port 0 get an ephemeral port, which is what gets advertised for them
+async fn bind_listeners(config: &mut BrokerConfig) -> std::io::Result<Listeners> {
+    let mut tcp = vec![];
+    if config.tcp_listener_enabled {
+        for listener in &config.listeners {
+            let bound = TcpListener::bind((listener.bind_host(), listener.port)).await?;
+            let port = bound.local_addr()?.port();
+            for advertised in &mut config.advertised_listeners {
+                if advertised.name == listener.name && advertised.port == 0 {
+                    advertised.port = port;
+                }
+            }
+            tcp.push(bound);
+        }
+    }
+
+    let unix = match &config.unix_socket_path {
+        Some(path) => {
+            // a socket file left behind by a previous run would make the bind fail
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+            Some(UnixListener::bind(path)?)
+        }
+        None => None,
+    };
+
+    Ok(Listeners { tcp, unix })
+}
+
+// one line saying what the broker came up as, and where to find it
+fn print_banner(broker: &BrokerHandle, metrics_addr: Option<SocketAddr>, elapsed: Duration) {
+    let config = &broker.state.config;
+    let mut listening = broker
+        .local_addrs
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    if let Some(path) = &config.unix_socket_path {
+        listening.push(path.display().to_string());
+    }
+    let log_dirs = match config.log_store {
+        LogStoreKind::File => config
+            .log_dirs
+            .iter()
+            .map(|dir| dir.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", "),
+        LogStoreKind::Memory => "memory".to_string(),
+    };
+    let metrics = match metrics_addr {
+        Some(addr) => format!(", metrics and probes on {addr}"),
+        None => String::new(),
+    };
+    println!(
+        "Kafka broker {} (v{}) ready in {}ms, listening on {} with logs in {log_dirs}{metrics}",
+        config.node_id,
+        env!("CARGO_PKG_VERSION"),
+        elapsed.as_millis(),
+        listening.join(", "),
+    );
+}
+
+// the buffer sizes and keepalive are only settable through a TcpSocket, which takes
+// ownership, so that gets a duplicate of the stream's fd. the options apply to the socket
+// both fds refer to
+fn configure_socket(stream: &TcpStream, config: &BrokerConfig) -> std::io::Result<()> {
+    stream.set_nodelay(config.socket_tcp_nodelay)?;
+
+    let socket = TcpSocket::from_std_stream(stream.as_fd().try_clone_to_owned()?.into());
+    socket.set_keepalive(config.socket_keepalive)?;
+    if let Some(size) = config.socket_send_buffer_bytes {
+        socket.set_send_buffer_size(size)?;
+    }
+    if let Some(size) = config.socket_receive_buffer_bytes {
+        socket.set_recv_buffer_size(size)?;
+    }
+    Ok(())
+}
+
+// connections live in the accept loop's JoinSet, so aborting the loop drops them too
+async fn accept_tcp(listener: TcpListener, state: Arc<BrokerState>) {
+    let mut connections = JoinSet::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, addr)) => {
+                    println!("New connection accepted: {}", addr);
+                    if let Err(e) = configure_socket(&stream, &state.config) {
+                        eprintln!("Error setting socket options for {addr}: {e}");
+                    }
+                    // kafka reports member hosts the way java formats an InetAddress
+                    let client_host = format!("/{}", addr.ip());
+                    let state = state.clone();
+                    connections.spawn(async move {
+                        if let Err(e) = handle_connection(stream, client_host, state).await {
+                            eprintln!("Error handling connection: {e}");
+                        }
+                    });
+                }
+                Err(e) => eprintln!("Error accepting connection: {e}"),
+            },
+            // reaps finished connections
+            Some(_) = connections.join_next() => {}
+        }
+    }
+}
+
+async fn accept_unix(listener: UnixListener, state: Arc<BrokerState>) {
+    let mut connections = JoinSet::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    println!("New unix socket connection accepted");
+                    let state = state.clone();
+                    connections.spawn(async move {
+                        let client_host = "/localhost".to_string();
+                        if let Err(e) = handle_connection(stream, client_host, state).await {
+                            eprintln!("Error handling connection: {e}");
+                        }
+                    });
+                }
+                Err(e) => eprintln!("Error accepting unix socket connection: {e}"),
+            },
+            Some(_) = connections.join_next() => {}
+        }
+    }
+}
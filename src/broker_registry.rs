@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct BrokerEndpoint {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    pub security_protocol: i16,
+}
+
+#[derive(Debug, Clone)]
+pub struct RegisteredBroker {
+    pub broker_id: i32,
+    // tells a restarted broker process apart from a retried registration of the same one
+    pub incarnation_id: i128,
+    pub epoch: i64,
+    pub endpoints: Vec<BrokerEndpoint>,
+    pub rack: Option<String>,
+    // a broker starts out fenced, and is fenced again once it misses its session timeout
+    pub fenced: bool,
+    pub shutting_down: bool,
+    last_heartbeat: Instant,
+}
+
+// what a heartbeat tells the broker back
+pub struct HeartbeatOutcome {
+    pub is_caught_up: bool,
+    pub is_fenced: bool,
+    pub should_shut_down: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RegistrationError {
+    // another incarnation of the broker id holds a live session
+    Duplicate,
+    // no registration of the broker id with that epoch
+    StaleEpoch,
+}
+
+struct Registrations {
+    brokers: BTreeMap<i32, RegisteredBroker>,
+    // epochs are handed out from one counter, so no two registrations ever share one
+    next_epoch: i64,
+}
+
+// the brokers that registered with this node acting as their controller. registrations only
+// live in memory, brokers register again once their controller restarts
+pub struct BrokerRegistry {
+    session_timeout: Duration,
+    registrations: Mutex<Registrations>,
+}
+
+impl BrokerRegistry {
+    pub fn new(session_timeout: Duration) -> Arc<Self> {
+        Arc::new(BrokerRegistry {
+            session_timeout,
+            registrations: Mutex::new(Registrations {
+                brokers: BTreeMap::new(),
+                next_epoch: 0,
+            }),
+        })
+    }
+
+    // a new epoch every time, fenced until the broker heartbeats. another incarnation can only
+    // take over the broker id once the old one's session has expired, or when it names the
+    // old one's epoch as its previous one after a clean restart
+    pub fn register(
+        &self,
+        broker_id: i32,
+        incarnation_id: i128,
+        previous_broker_epoch: i64,
+        endpoints: Vec<BrokerEndpoint>,
+        rack: Option<String>,
+    ) -> Result<i64, RegistrationError> {
+        let mut registrations = self.registrations.lock().unwrap();
+        if let Some(existing) = registrations.brokers.get(&broker_id) {
+            if existing.incarnation_id != incarnation_id
+                && existing.last_heartbeat.elapsed() < self.session_timeout
+                && existing.epoch != previous_broker_epoch
+            {
+                return Err(RegistrationError::Duplicate);
+            }
+        }
+
+        let epoch = registrations.next_epoch;
+        registrations.next_epoch += 1;
+        registrations.brokers.insert(
+            broker_id,
+            RegisteredBroker {
+                broker_id,
+                incarnation_id,
+                epoch,
+                endpoints,
+                rack,
+                fenced: true,
+                shutting_down: false,
+                last_heartbeat: Instant::now(),
+            },
+        );
+
+        Ok(epoch)
+    }
+
+    // there's no metadata log for a broker to replay yet, so it's always caught up and gets
+    // unfenced by the first heartbeat that doesn't ask to stay fenced. nothing has to move
+    // off a broker that wants to shut down, so it can go right away
+    pub fn heartbeat(
+        &self,
+        broker_id: i32,
+        broker_epoch: i64,
+        want_fence: bool,
+        want_shut_down: bool,
+    ) -> Result<HeartbeatOutcome, RegistrationError> {
+        let mut registrations = self.registrations.lock().unwrap();
+        let broker = registrations
+            .brokers
+            .get_mut(&broker_id)
+            .filter(|broker| broker.epoch == broker_epoch)
+            .ok_or(RegistrationError::StaleEpoch)?;
+
+        broker.last_heartbeat = Instant::now();
+        broker.shutting_down |= want_shut_down;
+        broker.fenced = want_fence || broker.shutting_down;
+
+        Ok(HeartbeatOutcome {
+            is_caught_up: true,
+            is_fenced: broker.fenced,
+            should_shut_down: broker.shutting_down,
+        })
+    }
+
+    // whether the broker's current registration has `broker_epoch`
+    pub fn has_epoch(&self, broker_id: i32, broker_epoch: i64) -> bool {
+        let registrations = self.registrations.lock().unwrap();
+        registrations
+            .brokers
+            .get(&broker_id)
+            .is_some_and(|broker| broker.epoch == broker_epoch)
+    }
+
+    // whether the broker can be in an isr: registered, under `broker_epoch` unless that's -1,
+    // within its session and neither fenced nor shutting down
+    pub fn is_active(&self, broker_id: i32, broker_epoch: i64) -> bool {
+        let registrations = self.registrations.lock().unwrap();
+        registrations.brokers.get(&broker_id).is_some_and(|broker| {
+            (broker_epoch == -1 || broker.epoch == broker_epoch)
+                && broker.last_heartbeat.elapsed() < self.session_timeout
+                && !broker.fenced
+                && !broker.shutting_down
+        })
+    }
+
+    // by broker id, with anyone past their session timeout fenced
+    pub fn brokers(&self) -> Vec<RegisteredBroker> {
+        let mut registrations = self.registrations.lock().unwrap();
+        registrations
+            .brokers
+            .values_mut()
+            .map(|broker| {
+                if broker.last_heartbeat.elapsed() >= self.session_timeout {
+                    broker.fenced = true;
+                }
+                broker.clone()
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,48 @@
+use crate::metrics::Metrics;
+use std::sync::Arc;
+
+// a connection only ever has a request and a response buffer in flight
+const MAX_POOLED_BUFFERS: usize = 4;
+// buffers grown past this by a large request or response are freed instead of kept around
+const MAX_POOLED_CAPACITY: usize = 1 << 20;
+const INITIAL_CAPACITY: usize = 1024;
+
+// per-connection free list of request and response buffers, so a busy connection stops
+// allocating once its buffers have grown to fit its usual traffic
+pub struct BufferPool {
+    free: Vec<Vec<u8>>,
+    metrics: Arc<Metrics>,
+}
+
+impl BufferPool {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        BufferPool {
+            free: Vec::with_capacity(MAX_POOLED_BUFFERS),
+            metrics,
+        }
+    }
+
+    // always handed out empty
+    pub fn acquire(&mut self) -> Vec<u8> {
+        match self.free.pop() {
+            Some(buf) => {
+                self.metrics.record_buffer_reused();
+                buf
+            }
+            None => {
+                self.metrics.record_buffer_allocated();
+                Vec::with_capacity(INITIAL_CAPACITY)
+            }
+        }
+    }
+
+    pub fn release(&mut self, mut buf: Vec<u8>) {
+        if buf.capacity() > MAX_POOLED_CAPACITY || self.free.len() >= MAX_POOLED_BUFFERS {
+            self.metrics.record_buffer_discarded();
+            return;
+        }
+
+        buf.clear();
+        self.free.push(buf);
+    }
+}
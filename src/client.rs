@@ -0,0 +1,110 @@
+use crate::readers::{read_int16, read_int32, read_tagged_fields};
+use crate::writers::write_compact_string;
+use crate::{
+    is_flexible_version, ApiVersionsResponse, FetchRequest, FetchResponse, KafkaError,
+    KafkaRequestHeader, APIVERSIONS, FETCH, TAG_BUFFER, UNSUPPORTED_VERSION,
+};
+use std::io::Cursor;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+const API_VERSIONS_VERSION: i16 = 4;
+const FETCH_VERSION: i16 = 16;
+
+// a bare-bones client built on the broker's own codec, for tools and for checking that what
+// the broker encodes decodes back. requests go one at a time, there's no pipelining
+pub struct KafkaClient<S> {
+    stream: S,
+    client_id: String,
+    next_correlation_id: i32,
+}
+
+impl KafkaClient<TcpStream> {
+    pub async fn connect(addr: impl ToSocketAddrs, client_id: &str) -> Result<Self, KafkaError> {
+        let stream = TcpStream::connect(addr).await?;
+        Ok(KafkaClient::new(stream, client_id))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> KafkaClient<S> {
+    pub fn new(stream: S, client_id: &str) -> Self {
+        KafkaClient {
+            stream,
+            client_id: client_id.to_string(),
+            next_correlation_id: 0,
+        }
+    }
+
+    // sends a request body and returns the correlation id along with the response body, the
+    // response header already stripped off
+    pub async fn send(
+        &mut self,
+        api_key: i16,
+        api_ver: i16,
+        body: &[u8],
+    ) -> Result<(i32, Vec<u8>), KafkaError> {
+        let correlation_id = self.next_correlation_id;
+        self.next_correlation_id = self.next_correlation_id.wrapping_add(1);
+
+        let mut frame = vec![0u8; 4];
+        KafkaRequestHeader {
+            api_key,
+            api_ver,
+            correlation_id,
+            client_id: Some(self.client_id.clone()),
+        }
+        .encode(&mut frame);
+        frame.extend_from_slice(body);
+        let size = (frame.len() - 4) as i32;
+        frame[..4].copy_from_slice(&size.to_be_bytes());
+
+        self.stream.write_all(&frame).await?;
+        self.stream.flush().await?;
+
+        let size = self.stream.read_i32().await?;
+        let size = usize::try_from(size).map_err(|_| KafkaError::InvalidMessageLength(size))?;
+        let mut response = vec![0u8; size];
+        self.stream.read_exact(&mut response).await?;
+
+        // ApiVersions answers with a v0 header even in its flexible versions
+        let mut cursor = Cursor::new(response.as_slice());
+        let response_correlation_id = read_int32(&mut cursor)?;
+        if is_flexible_version(api_key, api_ver) && api_key != APIVERSIONS {
+            read_tagged_fields(&mut cursor)?;
+        }
+        if response_correlation_id != correlation_id {
+            return Err(KafkaError::CorruptedMessage(format!(
+                "expected a response to request {correlation_id}, got {response_correlation_id}"
+            )));
+        }
+
+        let header_len = cursor.position() as usize;
+        Ok((correlation_id, response.split_off(header_len)))
+    }
+
+    pub async fn api_versions(&mut self) -> Result<ApiVersionsResponse, KafkaError> {
+        let mut body = vec![];
+        write_compact_string(&mut body, env!("CARGO_PKG_NAME"));
+        write_compact_string(&mut body, env!("CARGO_PKG_VERSION"));
+        body.extend_from_slice(TAG_BUFFER);
+
+        let (correlation_id, response) =
+            self.send(APIVERSIONS, API_VERSIONS_VERSION, &body).await?;
+
+        // a broker that doesn't know the version falls back to a v0 body
+        let error_code = read_int16(&mut Cursor::new(response.as_slice()))?;
+        let api_version = match error_code {
+            UNSUPPORTED_VERSION => 0,
+            _ => API_VERSIONS_VERSION,
+        };
+        ApiVersionsResponse::parse(&response, correlation_id, api_version)
+    }
+
+    pub async fn fetch(&mut self, request: &FetchRequest) -> Result<FetchResponse, KafkaError> {
+        let mut body = vec![];
+        request.encode(&mut body);
+
+        let (correlation_id, response) = self.send(FETCH, FETCH_VERSION, &body).await?;
+        FetchResponse::parse(&response, correlation_id)
+    }
+}
@@ -0,0 +1,192 @@
+use crate::KafkaError;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+pub const ENTITY_TYPE_USER: &str = "user";
+pub const ENTITY_TYPE_CLIENT_ID: &str = "client-id";
+
+pub const CONSUMER_BYTE_RATE: &str = "consumer_byte_rate";
+// the quotas kafka defines for users and client ids. only Fetch is throttled here, so
+// consumer_byte_rate is the only one enforced, the rest are just kept
+pub const QUOTA_KEYS: &[&str] = &[
+    CONSUMER_BYTE_RATE,
+    "producer_byte_rate",
+    "request_percentage",
+    "controller_mutation_rate",
+];
+
+// the user quotas of unauthenticated connections are looked up under this name, like kafka
+pub const ANONYMOUS_USER: &str = "ANONYMOUS";
+
+const QUOTAS_FILE_NAME: &str = "client-quotas";
+// how kafka's zookeeper layout names a default entity
+const DEFAULT_ENTITY_NAME: &str = "<default>";
+
+// entity type to name, a `None` name is the type's default entity
+pub type ClientQuotaEntity = BTreeMap<String, Option<String>>;
+// quota key to its new value, a `None` value removes the quota
+pub type QuotaOps = Vec<(String, Option<f64>)>;
+
+// quota overrides set through AlterClientQuotas, kept in a `client-quotas` file in the first
+// log dir
+pub struct ClientQuotaStore {
+    // `None` keeps the quotas in memory only
+    path: Option<PathBuf>,
+    quotas: Mutex<BTreeMap<ClientQuotaEntity, BTreeMap<String, f64>>>,
+}
+
+impl ClientQuotaStore {
+    pub fn load(log_dir: impl AsRef<Path>) -> Result<Arc<Self>, KafkaError> {
+        let path = log_dir.as_ref().join(QUOTAS_FILE_NAME);
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut quotas: BTreeMap<ClientQuotaEntity, BTreeMap<String, f64>> = BTreeMap::new();
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            let invalid = || {
+                KafkaError::InvalidConfig(format!("malformed line in {}: {line}", path.display()))
+            };
+            let (entity, key, value) = parse_line(line).ok_or_else(invalid)?;
+            quotas.entry(entity).or_default().insert(key, value);
+        }
+
+        Ok(Arc::new(ClientQuotaStore {
+            path: Some(path),
+            quotas: Mutex::new(quotas),
+        }))
+    }
+
+    pub fn in_memory() -> Arc<Self> {
+        Arc::new(ClientQuotaStore {
+            path: None,
+            quotas: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    pub fn list(&self) -> Vec<(ClientQuotaEntity, BTreeMap<String, f64>)> {
+        let quotas = self.quotas.lock().unwrap();
+        quotas
+            .iter()
+            .map(|(entity, values)| (entity.clone(), values.clone()))
+            .collect()
+    }
+
+    // the alterations have to be validated already, and are applied together once they've
+    // been written out
+    pub fn alter(&self, alterations: Vec<(ClientQuotaEntity, QuotaOps)>) -> Result<(), KafkaError> {
+        let mut quotas = self.quotas.lock().unwrap();
+        let mut updated = quotas.clone();
+        for (entity, ops) in alterations {
+            let values = updated.entry(entity.clone()).or_default();
+            for (key, value) in ops {
+                match value {
+                    Some(value) => values.insert(key, value),
+                    None => values.remove(&key),
+                };
+            }
+            if values.is_empty() {
+                updated.remove(&entity);
+            }
+        }
+
+        self.persist(&updated)?;
+        *quotas = updated;
+        Ok(())
+    }
+
+    // the consumer_byte_rate of the most specific entity matching the connection, in kafka's
+    // order of precedence. also returns the (user, client id) the usage is tracked under, which
+    // leaves out whichever the matched entity doesn't name, so clients of a shared user quota
+    // are measured together
+    pub fn consumer_byte_rate(
+        &self,
+        user: &str,
+        client_id: &str,
+    ) -> Option<(f64, (String, String))> {
+        let quotas = self.quotas.lock().unwrap();
+
+        // (user, client id), each of them a specific name, the default or left out
+        let candidates = [
+            (Some(Some(user)), Some(Some(client_id))),
+            (Some(Some(user)), Some(None)),
+            (Some(Some(user)), None),
+            (Some(None), Some(Some(client_id))),
+            (Some(None), Some(None)),
+            (Some(None), None),
+            (None, Some(Some(client_id))),
+            (None, Some(None)),
+        ];
+        candidates.into_iter().find_map(|(user_name, client_name)| {
+            let mut entity = ClientQuotaEntity::new();
+            if let Some(name) = user_name {
+                entity.insert(ENTITY_TYPE_USER.to_string(), name.map(str::to_string));
+            }
+            if let Some(name) = client_name {
+                entity.insert(ENTITY_TYPE_CLIENT_ID.to_string(), name.map(str::to_string));
+            }
+
+            let quota = *quotas.get(&entity)?.get(CONSUMER_BYTE_RATE)?;
+            let tracked_user = user_name.map(|_| user).unwrap_or_default();
+            let tracked_client = client_name.map(|_| client_id).unwrap_or_default();
+            Some((
+                quota,
+                (tracked_user.to_string(), tracked_client.to_string()),
+            ))
+        })
+    }
+
+    // written to a temp file first so a crash can't leave a half-written file behind
+    fn persist(
+        &self,
+        quotas: &BTreeMap<ClientQuotaEntity, BTreeMap<String, f64>>,
+    ) -> Result<(), KafkaError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut contents = String::new();
+        for (entity, values) in quotas {
+            for (key, value) in values {
+                contents.push_str(&format_line(entity, key, *value));
+            }
+        }
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, contents)?;
+        std::fs::rename(&tmp_path, path)?;
+
+        Ok(())
+    }
+}
+
+// `<key>\t<value>` followed by a `<entity type>\t<name>` pair per entity component
+fn format_line(entity: &ClientQuotaEntity, key: &str, value: f64) -> String {
+    let mut line = format!("{key}\t{value}");
+    for (entity_type, name) in entity {
+        let name = name.as_deref().unwrap_or(DEFAULT_ENTITY_NAME);
+        line.push_str(&format!("\t{entity_type}\t{name}"));
+    }
+    line + "\n"
+}
+
+fn parse_line(line: &str) -> Option<(ClientQuotaEntity, String, f64)> {
+    let mut fields = line.split('\t');
+    let key = fields.next()?.to_string();
+    let value = fields.next()?.parse().ok()?;
+
+    let mut entity = ClientQuotaEntity::new();
+    while let Some(entity_type) = fields.next() {
+        let name = match fields.next()? {
+            DEFAULT_ENTITY_NAME => None,
+            name => Some(name.to_string()),
+        };
+        entity.insert(entity_type.to_string(), name);
+    }
+    Some((entity, key, value))
+}
@@ -0,0 +1,369 @@
+use crate::acl::{Session, OPERATION_CREATE, OPERATION_DESCRIBE, RESOURCE_TYPE_TOPIC};
+use crate::cluster_metadata::MetadataLog;
+use crate::config::BrokerConfig;
+use crate::controller::create_topic;
+use crate::isr::IsrTracker;
+use crate::readers::*;
+use crate::storage::{LogStore, PartitionInfo};
+use crate::writers::*;
+use crate::{
+    KafkaError, NONE, TAG_BUFFER, TOPIC_AUTHORIZATION_FAILED, UNKNOWN_TOPIC_ID,
+    UNKNOWN_TOPIC_OR_PARTITION,
+};
+use std::collections::BTreeMap;
+
+// what kafka reports when authorized operations weren't asked for
+const AUTHORIZED_OPERATIONS_OMITTED: i32 = i32::MIN;
+
+// topics kafka itself keeps, flagged as internal in Metadata responses
+const INTERNAL_TOPICS: &[&str] = &["__consumer_offsets", "__transaction_state"];
+
+// ### METADATA (v12) ### //
+pub struct MetadataRequest {
+    // every topic when null
+    pub topics: Option<Vec<MetadataRequestTopic>>,
+    pub allow_auto_topic_creation: bool,
+    pub include_topic_authorized_operations: bool,
+}
+
+// looked up by id when the name is null
+pub struct MetadataRequestTopic {
+    pub topic_id: i128,
+    pub name: Option<String>,
+}
+
+impl MetadataRequest {
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+
+        let topics = match read_compact_nullable_array_len(&mut cursor)? {
+            Some(topics_size) => {
+                let mut topics = array_with_capacity(topics_size);
+                for _ in 0..topics_size {
+                    let topic_id = read_int128(&mut cursor)?;
+                    let name = read_compact_nullable_string(&mut cursor)?;
+                    read_tagged_fields(&mut cursor)?;
+
+                    topics.push(MetadataRequestTopic { topic_id, name });
+                }
+                Some(topics)
+            }
+            None => None,
+        };
+
+        let allow_auto_topic_creation = read_bool(&mut cursor)?;
+        let include_topic_authorized_operations = read_bool(&mut cursor)?;
+
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(MetadataRequest {
+            topics,
+            allow_auto_topic_creation,
+            include_topic_authorized_operations,
+        })
+    }
+}
+
+pub struct MetadataResponse {
+    pub throttle_time_ms: i32,
+    pub brokers: Vec<DescribeClusterBroker>,
+    pub cluster_id: Option<String>,
+    pub controller_id: i32,
+    pub topics: Vec<MetadataResponseTopic>,
+}
+
+pub struct MetadataResponseTopic {
+    pub error_code: i16,
+    pub name: Option<String>,
+    pub topic_id: i128,
+    pub is_internal: bool,
+    pub partitions: Vec<MetadataResponsePartition>,
+    pub topic_authorized_operations: i32,
+}
+
+pub struct MetadataResponsePartition {
+    pub error_code: i16,
+    pub partition_index: i32,
+    pub leader_id: i32,
+    pub leader_epoch: i32,
+    pub replica_nodes: Vec<i32>,
+    pub isr_nodes: Vec<i32>,
+    pub offline_replicas: Vec<i32>,
+}
+
+impl MetadataResponse {
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.throttle_time_ms.to_be_bytes());
+
+        write_compact_array_len(res_buf, self.brokers.len()); // [brokers]
+        for broker in &self.brokers {
+            res_buf.extend_from_slice(&broker.broker_id.to_be_bytes());
+            write_compact_string(res_buf, &broker.host);
+            res_buf.extend_from_slice(&broker.port.to_be_bytes());
+            write_compact_nullable_string(res_buf, broker.rack.as_deref());
+            res_buf.extend_from_slice(TAG_BUFFER);
+        }
+
+        write_compact_nullable_string(res_buf, self.cluster_id.as_deref());
+        res_buf.extend_from_slice(&self.controller_id.to_be_bytes());
+
+        write_compact_array_len(res_buf, self.topics.len()); // [topics]
+        for topic in &self.topics {
+            res_buf.extend_from_slice(&topic.error_code.to_be_bytes());
+            write_compact_nullable_string(res_buf, topic.name.as_deref());
+            res_buf.extend_from_slice(&topic.topic_id.to_be_bytes());
+            res_buf.push(topic.is_internal as u8);
+
+            write_compact_array_len(res_buf, topic.partitions.len()); // [partitions]
+            for partition in &topic.partitions {
+                res_buf.extend_from_slice(&partition.error_code.to_be_bytes());
+                res_buf.extend_from_slice(&partition.partition_index.to_be_bytes());
+                res_buf.extend_from_slice(&partition.leader_id.to_be_bytes());
+                res_buf.extend_from_slice(&partition.leader_epoch.to_be_bytes());
+                for nodes in [
+                    &partition.replica_nodes,
+                    &partition.isr_nodes,
+                    &partition.offline_replicas,
+                ] {
+                    write_compact_array_len(res_buf, nodes.len());
+                    for node in nodes {
+                        res_buf.extend_from_slice(&node.to_be_bytes());
+                    }
+                }
+                res_buf.extend_from_slice(TAG_BUFFER);
+            }
+
+            res_buf.extend_from_slice(&topic.topic_authorized_operations.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+        }
+
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+// topics asked for by name that don't exist are created when both the client and
+// auto.create.topics.enable allow it. creation here is synchronous, so rather than kafka's
+// LEADER_NOT_AVAILABLE while it propagates, the new topic is described right away
+// topics the session can't describe are left out of a listing of every topic, and answer
+// TOPIC_AUTHORIZATION_FAILED when asked for, whether or not they exist
+pub fn metadata(
+    config: &BrokerConfig,
+    logs: &dyn LogStore,
+    isr: &IsrTracker,
+    metadata_log: &MetadataLog,
+    session: &Session,
+    cluster_id: Option<&str>,
+    request: &MetadataRequest,
+) -> MetadataResponse {
+    let mut existing: BTreeMap<String, Vec<PartitionInfo>> = BTreeMap::new();
+    for partition in logs.list_partitions() {
+        existing
+            .entry(partition.topic_partition.topic.clone())
+            .or_default()
+            .push(partition);
+    }
+
+    let described = |name: &str, partitions: &[PartitionInfo]| MetadataResponseTopic {
+        error_code: NONE,
+        name: Some(name.to_string()),
+        topic_id: partitions
+            .iter()
+            .find_map(|partition| partition.topic_id)
+            .unwrap_or(0),
+        is_internal: INTERNAL_TOPICS.contains(&name),
+        partitions: partitions
+            .iter()
+            .map(|partition| {
+                // partitions without followers only have this broker
+                let (replica_nodes, isr_nodes) = partition
+                    .topic_id
+                    .and_then(|topic_id| {
+                        isr.replicas_and_isr(
+                            config.node_id,
+                            topic_id,
+                            partition.topic_partition.partition,
+                        )
+                    })
+                    .unwrap_or_else(|| (vec![config.node_id], vec![config.node_id]));
+                MetadataResponsePartition {
+                    error_code: NONE,
+                    partition_index: partition.topic_partition.partition,
+                    leader_id: config.node_id,
+                    leader_epoch: metadata_log
+                        .partition_leader_epoch(&partition.topic_partition)
+                        .unwrap_or(partition.leader_epoch),
+                    replica_nodes,
+                    isr_nodes,
+                    offline_replicas: vec![],
+                }
+            })
+            .collect(),
+        // the operations allowed on the topic aren't worked out
+        topic_authorized_operations: AUTHORIZED_OPERATIONS_OMITTED,
+    };
+    let missing = |error_code, name: Option<String>, topic_id| MetadataResponseTopic {
+        error_code,
+        name,
+        topic_id,
+        is_internal: false,
+        partitions: vec![],
+        topic_authorized_operations: AUTHORIZED_OPERATIONS_OMITTED,
+    };
+
+    let can_describe =
+        |name: &str| session.authorize(OPERATION_DESCRIBE, RESOURCE_TYPE_TOPIC, name);
+    let can_create = |name: &str| {
+        session.authorize_cluster(OPERATION_CREATE)
+            || session.authorize(OPERATION_CREATE, RESOURCE_TYPE_TOPIC, name)
+    };
+
+    let topics = match &request.topics {
+        None => existing
+            .iter()
+            .filter(|(name, _)| can_describe(name))
+            .map(|(name, partitions)| described(name, partitions))
+            .collect(),
+        Some(topics) => topics
+            .iter()
+            .map(|topic| match &topic.name {
+                Some(name) if !can_describe(name) => {
+                    missing(TOPIC_AUTHORIZATION_FAILED, Some(name.clone()), 0)
+                }
+                Some(name) => match existing.get(name) {
+                    Some(partitions) => described(name, partitions),
+                    None if request.allow_auto_topic_creation
+                        && config.auto_create_topics_enable
+                        && can_create(name) =>
+                    {
+                        match create_topic(
+                            metadata_log,
+                            logs,
+                            config.node_id,
+                            name,
+                            config.num_partitions,
+                        ) {
+                            Ok(_) => {
+                                let partitions = logs
+                                    .list_partitions()
+                                    .into_iter()
+                                    .filter(|partition| &partition.topic_partition.topic == name)
+                                    .collect::<Vec<_>>();
+                                described(name, &partitions)
+                            }
+                            Err(error_code) => missing(error_code, Some(name.clone()), 0),
+                        }
+                    }
+                    None => missing(UNKNOWN_TOPIC_OR_PARTITION, Some(name.clone()), 0),
+                },
+                None => match existing
+                    .iter()
+                    .find(|(_, partitions)| partitions[0].topic_id == Some(topic.topic_id))
+                {
+                    // an id the session can't describe doesn't give away the name
+                    Some((name, _)) if !can_describe(name) => {
+                        missing(TOPIC_AUTHORIZATION_FAILED, None, topic.topic_id)
+                    }
+                    Some((name, partitions)) => described(name, partitions),
+                    None => missing(UNKNOWN_TOPIC_ID, None, topic.topic_id),
+                },
+            })
+            .collect(),
+    };
+
+    MetadataResponse {
+        throttle_time_ms: 0,
+        brokers: cluster_brokers(config),
+        cluster_id: cluster_id.map(str::to_string),
+        controller_id: config.node_id,
+        topics,
+    }
+}
+
+// ### DESCRIBE CLUSTER (v0) ### //
+pub struct DescribeClusterRequest {
+    pub include_cluster_authorized_operations: bool,
+}
+
+impl DescribeClusterRequest {
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+
+        let include_cluster_authorized_operations = read_bool(&mut cursor)?;
+
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(DescribeClusterRequest {
+            include_cluster_authorized_operations,
+        })
+    }
+}
+
+pub struct DescribeClusterResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub error_message: Option<String>,
+    pub cluster_id: String,
+    pub controller_id: i32,
+    pub brokers: Vec<DescribeClusterBroker>,
+    pub cluster_authorized_operations: i32,
+}
+
+pub struct DescribeClusterBroker {
+    pub broker_id: i32,
+    pub host: String,
+    pub port: i32,
+    pub rack: Option<String>,
+}
+
+impl DescribeClusterResponse {
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.throttle_time_ms.to_be_bytes());
+        res_buf.extend_from_slice(&self.error_code.to_be_bytes());
+        write_compact_nullable_string(res_buf, self.error_message.as_deref());
+        write_compact_string(res_buf, &self.cluster_id);
+        res_buf.extend_from_slice(&self.controller_id.to_be_bytes());
+
+        write_compact_array_len(res_buf, self.brokers.len()); // [brokers]
+        for broker in &self.brokers {
+            res_buf.extend_from_slice(&broker.broker_id.to_be_bytes());
+            write_compact_string(res_buf, &broker.host);
+            res_buf.extend_from_slice(&broker.port.to_be_bytes());
+            write_compact_nullable_string(res_buf, broker.rack.as_deref());
+            res_buf.extend_from_slice(TAG_BUFFER);
+        }
+
+        res_buf.extend_from_slice(&self.cluster_authorized_operations.to_be_bytes());
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+// this broker is the whole cluster and its own controller. it's reachable on the first
+// advertised listener, since a connection doesn't know which listener it came in on
+pub fn describe_cluster(config: &BrokerConfig, cluster_id: &str) -> DescribeClusterResponse {
+    // there are no acls, so there's nothing to report operations against
+    DescribeClusterResponse {
+        throttle_time_ms: 0,
+        error_code: NONE,
+        error_message: None,
+        cluster_id: cluster_id.to_string(),
+        controller_id: config.node_id,
+        brokers: cluster_brokers(config),
+        cluster_authorized_operations: AUTHORIZED_OPERATIONS_OMITTED,
+    }
+}
+
+fn cluster_brokers(config: &BrokerConfig) -> Vec<DescribeClusterBroker> {
+    config
+        .advertised_listeners
+        .first()
+        .map(|listener| DescribeClusterBroker {
+            broker_id: config.node_id,
+            host: listener.host.clone(),
+            port: listener.port as i32,
+            rack: None,
+        })
+        .into_iter()
+        .collect()
+}
@@ -0,0 +1,778 @@
+use crate::broker_registry::BrokerEndpoint;
+use crate::metadata_snapshot::*;
+use crate::readers::*;
+use crate::records::{decode_records, encode_batch};
+use crate::storage::{whole_batches, TopicPartition, CLUSTER_METADATA_TOPIC};
+use crate::writers::*;
+use crate::{KafkaError, TAG_BUFFER};
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// the api keys of the metadata record schemas that are read here
+const REGISTER_BROKER_RECORD: u32 = 0;
+const TOPIC_RECORD: u32 = 2;
+const PARTITION_RECORD: u32 = 3;
+const CONFIG_RECORD: u32 = 4;
+const PARTITION_CHANGE_RECORD: u32 = 5;
+const REMOVE_TOPIC_RECORD: u32 = 9;
+const FEATURE_LEVEL_RECORD: u32 = 12;
+const UNREGISTER_BROKER_RECORD: u32 = 17;
+// what PartitionChangeRecord's leader defaults to when it leaves the leader as it was
+const NO_LEADER_CHANGE: i32 = -2;
+// metadata records are framed with this version ahead of their type, control records in the
+// same log aren't
+const METADATA_RECORD_FRAME_VERSION: u32 = 1;
+
+// the feature levels this broker can run with, (name, min, max). metadata.version 1 is
+// 3.3-IV0, 21 is 3.9-IV0
+pub const SUPPORTED_FEATURES: &[(&str, i16, i16)] = &[("metadata.version", 1, 21)];
+
+// the feature levels the controller finalized, from the FeatureLevelRecords in the cluster
+// metadata log
+#[derive(Debug, Clone)]
+pub struct FinalizedFeatures {
+    // the offset of the last FeatureLevelRecord, -1 without any
+    pub epoch: i64,
+    pub levels: BTreeMap<String, i16>,
+}
+
+impl Default for FinalizedFeatures {
+    fn default() -> Self {
+        FinalizedFeatures {
+            epoch: -1,
+            levels: BTreeMap::new(),
+        }
+    }
+}
+
+impl FinalizedFeatures {
+    // no metadata log means nothing is finalized
+    pub fn load(metadata_log_dir: impl AsRef<Path>) -> Result<Self, KafkaError> {
+        let mut features = FinalizedFeatures::default();
+        replay_metadata_log(
+            metadata_log_dir.as_ref(),
+            |offset, record_type, _, cursor| {
+                if record_type != FEATURE_LEVEL_RECORD {
+                    return None;
+                }
+                let name = read_compact_string(cursor).ok()?;
+                let level = read_int16(cursor).ok()?;
+                // level 0 takes the feature back out
+                match level {
+                    0 => features.levels.remove(&name),
+                    level => features.levels.insert(name, level),
+                };
+                features.epoch = offset;
+                Some(())
+            },
+        )?;
+
+        Ok(features)
+    }
+}
+
+// a partition as the controller assigned it
+#[derive(Debug, Clone)]
+pub struct PartitionRegistration {
+    pub topic_id: i128,
+    pub replicas: Vec<i32>,
+    pub isr: Vec<i32>,
+    // -1 without one
+    pub leader: i32,
+    pub leader_epoch: i32,
+    pub partition_epoch: i32,
+}
+
+// the topics, partition assignments, brokers, configs and feature levels in the cluster
+// metadata log, as of when it was read
+#[derive(Debug, Clone, Default)]
+pub struct MetadataImage {
+    // topic id -> name
+    pub topics: BTreeMap<i128, String>,
+    pub partitions: BTreeMap<TopicPartition, PartitionRegistration>,
+    // broker id -> the endpoints it registered
+    pub brokers: BTreeMap<i32, Vec<BrokerEndpoint>>,
+    // (resource type, resource name) -> config name -> value
+    pub configs: BTreeMap<(i8, String), BTreeMap<String, String>>,
+    pub features: BTreeMap<String, i16>,
+    // each broker's RegisterBrokerRecord as it was written, for snapshots
+    registrations: BTreeMap<i32, MetadataRecord>,
+}
+
+impl MetadataImage {
+    // the topic id's name, for requests that name topics by id
+    pub fn topic_partition(&self, topic_id: i128, partition: i32) -> Option<TopicPartition> {
+        let topic = self.topics.get(&topic_id)?.clone();
+        Some(TopicPartition { topic, partition })
+    }
+
+    // record types that aren't read here are left out of the image, and out of its snapshots
+    fn apply(&mut self, record_type: u32, version: u32, cursor: &mut Cursor<&[u8]>) -> Option<()> {
+        match record_type {
+            REGISTER_BROKER_RECORD => self.register_broker(version, cursor),
+            UNREGISTER_BROKER_RECORD => {
+                let broker_id = read_int32(cursor).ok()?;
+                self.brokers.remove(&broker_id);
+                self.registrations.remove(&broker_id);
+                Some(())
+            }
+            TOPIC_RECORD => {
+                let name = read_compact_string(cursor).ok()?;
+                self.topics.insert(read_int128(cursor).ok()?, name);
+                Some(())
+            }
+            REMOVE_TOPIC_RECORD => {
+                let topic = self.topics.remove(&read_int128(cursor).ok()?)?;
+                self.partitions.retain(|tp, _| tp.topic != topic);
+                Some(())
+            }
+            PARTITION_RECORD => self.add_partition(cursor),
+            PARTITION_CHANGE_RECORD => self.change_partition(cursor),
+            CONFIG_RECORD => {
+                let resource_type = read_int8(cursor).ok()?;
+                let resource_name = read_compact_string(cursor).ok()?;
+                let name = read_compact_string(cursor).ok()?;
+                let resource = (resource_type, resource_name);
+                match read_compact_nullable_string(cursor).ok()? {
+                    Some(value) => {
+                        self.configs
+                            .entry(resource)
+                            .or_default()
+                            .insert(name, value);
+                    }
+                    None => {
+                        let configs = self.configs.get_mut(&resource)?;
+                        configs.remove(&name);
+                        if configs.is_empty() {
+                            self.configs.remove(&resource);
+                        }
+                    }
+                }
+                Some(())
+            }
+            FEATURE_LEVEL_RECORD => {
+                let name = read_compact_string(cursor).ok()?;
+                match read_int16(cursor).ok()? {
+                    0 => self.features.remove(&name),
+                    level => self.features.insert(name, level),
+                };
+                Some(())
+            }
+            _ => None,
+        }
+    }
+
+    // the records that rebuild the image from nothing: feature levels first, like kafka
+    // puts metadata.version, then the brokers, each topic with its partitions, and the configs
+    pub fn snapshot_records(&self) -> Vec<MetadataRecord> {
+        let features = self
+            .features
+            .iter()
+            .map(|(name, level)| MetadataRecord::feature_level(name, *level));
+        let brokers = self.registrations.values().cloned();
+        let topics = self.topics.iter().flat_map(|(topic_id, name)| {
+            let first = TopicPartition {
+                topic: name.clone(),
+                partition: i32::MIN,
+            };
+            let partitions = self
+                .partitions
+                .range(first..)
+                .take_while(move |(topic_partition, _)| topic_partition.topic == *name)
+                .map(|(topic_partition, partition)| {
+                    MetadataRecord::partition_registration(topic_partition.partition, partition)
+                });
+            std::iter::once(MetadataRecord::topic(name, *topic_id)).chain(partitions)
+        });
+        let configs = self
+            .configs
+            .iter()
+            .flat_map(|((resource_type, resource_name), configs)| {
+                configs.iter().map(|(name, value)| {
+                    MetadataRecord::config(*resource_type, resource_name, name, Some(value))
+                })
+            });
+
+        features
+            .chain(brokers)
+            .chain(topics)
+            .chain(configs)
+            .collect()
+    }
+
+    // the partitions `broker_id` is a replica of but doesn't lead, by the leader to fetch
+    // them from
+    pub fn followed_partitions(
+        &self,
+        broker_id: i32,
+    ) -> BTreeMap<i32, Vec<(TopicPartition, PartitionRegistration)>> {
+        let mut followed: BTreeMap<i32, Vec<_>> = BTreeMap::new();
+        for (topic_partition, partition) in &self.partitions {
+            if partition.leader >= 0
+                && partition.leader != broker_id
+                && partition.replicas.contains(&broker_id)
+            {
+                followed
+                    .entry(partition.leader)
+                    .or_default()
+                    .push((topic_partition.clone(), partition.clone()));
+            }
+        }
+        followed
+    }
+
+    // only the endpoints are kept, the fields after them don't matter here
+    fn register_broker(&mut self, version: u32, cursor: &mut Cursor<&[u8]>) -> Option<()> {
+        let record = MetadataRecord {
+            record_type: REGISTER_BROKER_RECORD,
+            version,
+            data: cursor.get_ref()[cursor.position() as usize..].to_vec(),
+        };
+        let broker_id = read_int32(cursor).ok()?;
+        if version >= 2 {
+            let _is_migrating_zk_broker = read_bool(cursor).ok()?;
+        }
+        let _incarnation_id = read_int128(cursor).ok()?;
+        let _broker_epoch = read_int64(cursor).ok()?;
+
+        let endpoints_size = read_compact_array_len(cursor).ok()?; // [end_points]
+        let mut endpoints = array_with_capacity(endpoints_size);
+        for _ in 0..endpoints_size {
+            let name = read_compact_string(cursor).ok()?;
+            let host = read_compact_string(cursor).ok()?;
+            let port = read_int16(cursor).ok()? as u16;
+            let security_protocol = read_int16(cursor).ok()?;
+            read_tagged_fields(cursor).ok()?;
+            endpoints.push(BrokerEndpoint {
+                name,
+                host,
+                port,
+                security_protocol,
+            });
+        }
+
+        self.brokers.insert(broker_id, endpoints);
+        self.registrations.insert(broker_id, record);
+        Some(())
+    }
+
+    fn add_partition(&mut self, cursor: &mut Cursor<&[u8]>) -> Option<()> {
+        let partition = read_int32(cursor).ok()?;
+        let topic_id = read_int128(cursor).ok()?;
+        let replicas = read_broker_ids(cursor)?;
+        let isr = read_broker_ids(cursor)?;
+        let _removing_replicas = read_broker_ids(cursor)?;
+        let _adding_replicas = read_broker_ids(cursor)?;
+        let leader = read_int32(cursor).ok()?;
+        let leader_epoch = read_int32(cursor).ok()?;
+        let partition_epoch = read_int32(cursor).ok()?;
+
+        let topic = self.topics.get(&topic_id)?.clone();
+        self.partitions.insert(
+            TopicPartition { topic, partition },
+            PartitionRegistration {
+                topic_id,
+                replicas,
+                isr,
+                leader,
+                leader_epoch,
+                partition_epoch,
+            },
+        );
+        Some(())
+    }
+
+    // everything that changed is a tagged field. like kafka, a new leader bumps the leader
+    // epoch, and any change bumps the partition epoch
+    fn change_partition(&mut self, cursor: &mut Cursor<&[u8]>) -> Option<()> {
+        let partition = read_int32(cursor).ok()?;
+        let topic_id = read_int128(cursor).ok()?;
+
+        let mut isr = None;
+        let mut leader = NO_LEADER_CHANGE;
+        let mut replicas = None;
+        read_tagged_fields_with(cursor, |tag, bytes| {
+            let mut field = Cursor::new(bytes);
+            match tag {
+                0 => isr = read_broker_ids(&mut field),
+                1 => leader = read_int32(&mut field)?,
+                2 => replicas = read_broker_ids(&mut field),
+                _ => {}
+            }
+            Ok(())
+        })
+        .ok()?;
+
+        let topic_partition = self.topic_partition(topic_id, partition)?;
+        let registration = self.partitions.get_mut(&topic_partition)?;
+        if let Some(isr) = isr {
+            registration.isr = isr;
+        }
+        if let Some(replicas) = replicas {
+            registration.replicas = replicas;
+        }
+        if leader != NO_LEADER_CHANGE {
+            registration.leader = leader;
+            registration.leader_epoch += 1;
+        }
+        registration.partition_epoch += 1;
+        Some(())
+    }
+}
+
+fn read_broker_ids(cursor: &mut Cursor<&[u8]>) -> Option<Vec<i32>> {
+    let size = read_compact_array_len(cursor).ok()?;
+    let mut broker_ids = array_with_capacity(size);
+    for _ in 0..size {
+        broker_ids.push(read_int32(cursor).ok()?);
+    }
+    Some(broker_ids)
+}
+
+fn write_broker_ids(buf: &mut Vec<u8>, broker_ids: &[i32]) {
+    write_compact_array_len(buf, broker_ids.len());
+    for broker_id in broker_ids {
+        buf.extend_from_slice(&broker_id.to_be_bytes());
+    }
+}
+
+// a metadata record to append, `data` is everything after its frame header
+#[derive(Debug, Clone)]
+pub struct MetadataRecord {
+    pub record_type: u32,
+    pub version: u32,
+    pub data: Vec<u8>,
+}
+
+impl MetadataRecord {
+    // a TopicRecord (v0)
+    pub fn topic(name: &str, topic_id: i128) -> Self {
+        let mut data = vec![];
+        write_compact_string(&mut data, name);
+        data.extend_from_slice(&topic_id.to_be_bytes());
+        data.extend_from_slice(TAG_BUFFER);
+
+        MetadataRecord {
+            record_type: TOPIC_RECORD,
+            version: 0,
+            data,
+        }
+    }
+
+    // a PartitionRecord (v0) with every replica in the isr, led by the first one at epoch 0
+    pub fn partition(topic_id: i128, partition: i32, replicas: &[i32]) -> Self {
+        let registration = PartitionRegistration {
+            topic_id,
+            replicas: replicas.to_vec(),
+            isr: replicas.to_vec(),
+            leader: replicas.first().copied().unwrap_or(-1),
+            leader_epoch: 0,
+            partition_epoch: 0,
+        };
+        Self::partition_registration(partition, &registration)
+    }
+
+    // a PartitionRecord (v0) for the partition as it's registered, no reassignment is ever in
+    // progress here
+    pub fn partition_registration(partition: i32, registration: &PartitionRegistration) -> Self {
+        let mut data = vec![];
+        data.extend_from_slice(&partition.to_be_bytes());
+        data.extend_from_slice(&registration.topic_id.to_be_bytes());
+        write_broker_ids(&mut data, &registration.replicas);
+        write_broker_ids(&mut data, &registration.isr);
+        write_broker_ids(&mut data, &[]); // removing_replicas
+        write_broker_ids(&mut data, &[]); // adding_replicas
+        data.extend_from_slice(&registration.leader.to_be_bytes());
+        data.extend_from_slice(&registration.leader_epoch.to_be_bytes());
+        data.extend_from_slice(&registration.partition_epoch.to_be_bytes());
+        data.extend_from_slice(TAG_BUFFER);
+
+        MetadataRecord {
+            record_type: PARTITION_RECORD,
+            version: 0,
+            data,
+        }
+    }
+
+    // a ConfigRecord (v0), a `None` value takes the config back to its default
+    pub fn config(resource_type: i8, resource_name: &str, name: &str, value: Option<&str>) -> Self {
+        let mut data = vec![resource_type as u8];
+        write_compact_string(&mut data, resource_name);
+        write_compact_string(&mut data, name);
+        write_compact_nullable_string(&mut data, value);
+        data.extend_from_slice(TAG_BUFFER);
+
+        MetadataRecord {
+            record_type: CONFIG_RECORD,
+            version: 0,
+            data,
+        }
+    }
+
+    // a FeatureLevelRecord (v0), level 0 takes the feature back out
+    pub fn feature_level(name: &str, level: i16) -> Self {
+        let mut data = vec![];
+        write_compact_string(&mut data, name);
+        data.extend_from_slice(&level.to_be_bytes());
+        data.extend_from_slice(TAG_BUFFER);
+
+        MetadataRecord {
+            record_type: FEATURE_LEVEL_RECORD,
+            version: 0,
+            data,
+        }
+    }
+
+    // a PartitionChangeRecord (v0) that only changes the isr
+    pub fn isr_change(topic_id: i128, partition: i32, isr: &[i32]) -> Self {
+        let mut data = vec![];
+        data.extend_from_slice(&partition.to_be_bytes());
+        data.extend_from_slice(&topic_id.to_be_bytes());
+
+        let mut field = vec![];
+        write_broker_ids(&mut field, isr);
+        write_unsigned_varint(&mut data, 1);
+        write_unsigned_varint(&mut data, 0); // isr
+        write_unsigned_varint(&mut data, field.len() as u32);
+        data.extend_from_slice(&field);
+
+        MetadataRecord {
+            record_type: PARTITION_CHANGE_RECORD,
+            version: 0,
+            data,
+        }
+    }
+
+    pub(crate) fn framed(&self) -> Vec<u8> {
+        let mut value = vec![];
+        write_unsigned_varint(&mut value, METADATA_RECORD_FRAME_VERSION);
+        write_unsigned_varint(&mut value, self.record_type);
+        write_unsigned_varint(&mut value, self.version);
+        value.extend_from_slice(&self.data);
+        value
+    }
+}
+
+// the offset after the metadata log's last record, and the leader epoch of its last batch
+#[derive(Debug, Clone, Copy)]
+pub struct LogEnd {
+    pub offset: i64,
+    // -1 for an empty log
+    pub epoch: i32,
+}
+
+struct MetadataLogState {
+    image: MetadataImage,
+    end: LogEnd,
+    // the max timestamp of the last batch, -1 for an empty log
+    last_timestamp_ms: i64,
+    // the quorum epoch this node leads in, `None` while it isn't the leader and can't append
+    leader_epoch: Option<i32>,
+    snapshot: Option<SnapshotId>,
+    // the batches appended after the snapshot, and since when there have been any
+    bytes_since_snapshot: u64,
+    unsnapshotted_since: Option<Instant>,
+}
+
+// the cluster metadata log and the image replayed from it, kept in step with every append
+// this node makes as the controller
+pub struct MetadataLog {
+    // `__cluster_metadata-0`, unset when metadata only lives in memory
+    dir: Option<PathBuf>,
+    state: Mutex<MetadataLogState>,
+}
+
+impl MetadataLog {
+    // an empty image without a metadata log. records appended since the last snapshot are
+    // counted as waiting from now on
+    pub fn open(metadata_log_dir: impl AsRef<Path>) -> Result<Arc<Self>, KafkaError> {
+        let metadata_log_dir = metadata_log_dir.as_ref();
+        let mut image = MetadataImage::default();
+        let replayed = replay_metadata_log(metadata_log_dir, |_, record_type, version, cursor| {
+            image.apply(record_type, version, cursor)
+        })?;
+
+        Ok(Arc::new(MetadataLog {
+            dir: Some(metadata_log_dir.join(format!("{CLUSTER_METADATA_TOPIC}-0"))),
+            state: Mutex::new(MetadataLogState {
+                image,
+                end: replayed.end,
+                last_timestamp_ms: replayed.last_timestamp_ms,
+                leader_epoch: None,
+                snapshot: replayed.snapshot,
+                bytes_since_snapshot: replayed.bytes_since_snapshot,
+                unsnapshotted_since: (replayed.bytes_since_snapshot > 0).then(Instant::now),
+            }),
+        }))
+    }
+
+    pub fn in_memory() -> Arc<Self> {
+        Arc::new(MetadataLog {
+            dir: None,
+            state: Mutex::new(MetadataLogState {
+                image: MetadataImage::default(),
+                end: LogEnd {
+                    offset: 0,
+                    epoch: -1,
+                },
+                last_timestamp_ms: -1,
+                leader_epoch: None,
+                snapshot: None,
+                bytes_since_snapshot: 0,
+                unsnapshotted_since: None,
+            }),
+        })
+    }
+
+    // where the quorum keeps its state, `None` in memory
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    pub fn image(&self) -> MetadataImage {
+        self.state.lock().unwrap().image.clone()
+    }
+
+    // the partition's leader epoch as the metadata log has it, what requests naming an epoch
+    // are fenced against. `None` for a partition it doesn't know
+    pub fn partition_leader_epoch(&self, topic_partition: &TopicPartition) -> Option<i32> {
+        let state = self.state.lock().unwrap();
+        let partition = state.image.partitions.get(topic_partition)?;
+        Some(partition.leader_epoch)
+    }
+
+    // like MetadataImage::topic_partition, without copying the image
+    pub fn topic_partition(&self, topic_id: i128, partition: i32) -> Option<TopicPartition> {
+        self.state
+            .lock()
+            .unwrap()
+            .image
+            .topic_partition(topic_id, partition)
+    }
+
+    pub fn end(&self) -> LogEnd {
+        self.state.lock().unwrap().end
+    }
+
+    pub fn latest_snapshot(&self) -> Option<SnapshotId> {
+        self.state.lock().unwrap().snapshot
+    }
+
+    // blocking file io, see read_snapshot_chunk. `None` in memory too
+    pub fn read_snapshot(
+        &self,
+        id: SnapshotId,
+        position: u64,
+        max_bytes: u64,
+    ) -> std::io::Result<Option<(u64, Vec<u8>)>> {
+        match &self.dir {
+            Some(dir) => read_snapshot_chunk(dir, id, position, max_bytes),
+            None => Ok(None),
+        }
+    }
+
+    // blocking file io. writes the image out as a snapshot at the log end once
+    // `max_bytes_between` bytes of batches were appended since the last one, or once the
+    // oldest of them has waited `max_interval`. appends wait for it to be written
+    pub fn snapshot_if_due(
+        &self,
+        max_bytes_between: u64,
+        max_interval: Option<Duration>,
+    ) -> Result<Option<SnapshotId>, KafkaError> {
+        let mut state = self.state.lock().unwrap();
+        let (Some(dir), Some(unsnapshotted_since)) = (&self.dir, state.unsnapshotted_since) else {
+            return Ok(None);
+        };
+        let waited_long_enough =
+            max_interval.is_some_and(|max_interval| unsnapshotted_since.elapsed() >= max_interval);
+        if state.bytes_since_snapshot < max_bytes_between && !waited_long_enough {
+            return Ok(None);
+        }
+
+        let id = SnapshotId {
+            end_offset: state.end.offset,
+            epoch: state.end.epoch,
+        };
+        let records = state.image.snapshot_records();
+        write_snapshot(dir, id, state.last_timestamp_ms, &records)?;
+        state.snapshot = Some(id);
+        state.bytes_since_snapshot = 0;
+        state.unsnapshotted_since = None;
+        Ok(Some(id))
+    }
+
+    // set by the quorum as this node gains or loses the leadership
+    pub fn set_leader_epoch(&self, leader_epoch: Option<i32>) {
+        self.state.lock().unwrap().leader_epoch = leader_epoch;
+    }
+
+    // blocking file io. `update` decides what to write from the image as it is, and nothing
+    // else is appended in between. its records go out as one batch under the leader epoch,
+    // synced before they're applied to the image. only the quorum leader can append
+    pub fn update<T>(
+        &self,
+        update: impl FnOnce(&MetadataImage) -> (T, Vec<MetadataRecord>),
+    ) -> Result<T, KafkaError> {
+        let mut state = self.state.lock().unwrap();
+        let leader_epoch = state.leader_epoch.ok_or(KafkaError::NotController)?;
+        let (result, records) = update(&state.image);
+        if records.is_empty() {
+            return Ok(result);
+        }
+
+        let values: Vec<Vec<u8>> = records.iter().map(MetadataRecord::framed).collect();
+        if let Some(dir) = &self.dir {
+            let timestamp_ms = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_millis() as i64)
+                .unwrap_or(0);
+            let batch = encode_batch(state.end.offset, leader_epoch, timestamp_ms, values);
+            append_to_active_segment(dir, state.end.offset, &batch)?;
+            state.last_timestamp_ms = timestamp_ms;
+            state.bytes_since_snapshot += batch.len() as u64;
+            state.unsnapshotted_since.get_or_insert_with(Instant::now);
+        }
+
+        for record in &records {
+            let mut cursor = Cursor::new(record.data.as_slice());
+            state
+                .image
+                .apply(record.record_type, record.version, &mut cursor);
+        }
+        state.end = LogEnd {
+            offset: state.end.offset + records.len() as i64,
+            epoch: leader_epoch,
+        };
+        Ok(result)
+    }
+}
+
+// the last segment by name, a first one starting at `base_offset` is created with the
+// directory when there's none, which after a snapshot needn't be offset 0
+fn append_to_active_segment(dir: &Path, base_offset: i64, batch: &[u8]) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let mut segments: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    segments.retain(|path| path.extension().is_some_and(|extension| extension == "log"));
+    segments.sort();
+    let segment = segments
+        .pop()
+        .unwrap_or_else(|| dir.join(format!("{base_offset:020}.log")));
+
+    let mut file = OpenOptions::new().create(true).append(true).open(segment)?;
+    file.write_all(batch)?;
+    file.sync_data()
+}
+
+// where a replay of the metadata log left off
+struct Replayed {
+    end: LogEnd,
+    last_timestamp_ms: i64,
+    snapshot: Option<SnapshotId>,
+    // the batches after the snapshot, all of them without one
+    bytes_since_snapshot: u64,
+}
+
+// `__cluster_metadata-0` in the metadata log dir, which like kafka's metadata.log.dir default
+// is the first log dir. the latest snapshot is replayed first, its records all at the offset
+// before its end, then the log from the snapshot's end on. `apply` gets each metadata
+// record's offset, type and version, with the cursor past its frame header. control records
+// are skipped, as is whatever `apply` can't make sense of (it returns `None`). control
+// records count towards where the log ends
+fn replay_metadata_log(
+    metadata_log_dir: &Path,
+    mut apply: impl FnMut(i64, u32, u32, &mut Cursor<&[u8]>) -> Option<()>,
+) -> Result<Replayed, KafkaError> {
+    let dir = metadata_log_dir.join(format!("{CLUSTER_METADATA_TOPIC}-0"));
+    let mut replayed = Replayed {
+        end: LogEnd {
+            offset: 0,
+            epoch: -1,
+        },
+        last_timestamp_ms: -1,
+        snapshot: latest_snapshot(&dir)?,
+        bytes_since_snapshot: 0,
+    };
+
+    if let Some(id) = replayed.snapshot {
+        let bytes = read_snapshot(&dir, id)?.unwrap_or_default();
+        let batches = whole_batches(&bytes);
+        // snapshots are renamed into place whole, one without its footer was damaged since
+        if batches.len() < 2 || !batches.last().is_some_and(|footer| footer.is_control()) {
+            return Err(KafkaError::CorruptedMessage(format!(
+                "metadata snapshot {id:?} has no footer"
+            )));
+        }
+        replay_records(&bytes, |_, record_type, version, cursor| {
+            apply(id.end_offset - 1, record_type, version, cursor)
+        })?;
+        replayed.end = LogEnd {
+            offset: id.end_offset,
+            epoch: id.epoch,
+        };
+        // every batch is stamped with the last contained record's timestamp
+        replayed.last_timestamp_ms = batches[0].max_timestamp_ms;
+    }
+
+    let snapshot_end = replayed.end.offset;
+    let mut segments = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+        Err(e) => return Err(e.into()),
+    };
+    segments.retain(|path| path.extension().is_some_and(|extension| extension == "log"));
+    // zero padded base offsets, so by name is by offset
+    segments.sort();
+
+    for segment in segments {
+        let bytes = std::fs::read(&segment)?;
+        for batch in whole_batches(&bytes) {
+            if batch.next_offset() <= snapshot_end {
+                continue;
+            }
+            replayed.end = LogEnd {
+                offset: batch.next_offset(),
+                epoch: batch.partition_leader_epoch,
+            };
+            replayed.last_timestamp_ms = batch.max_timestamp_ms;
+            replayed.bytes_since_snapshot += batch.len;
+        }
+        replay_records(&bytes, |offset, record_type, version, cursor| {
+            match offset >= snapshot_end {
+                true => apply(offset, record_type, version, cursor),
+                false => None,
+            }
+        })?;
+    }
+
+    Ok(replayed)
+}
+
+// the metadata records in `bytes`, whether a segment's or a snapshot's
+fn replay_records(
+    bytes: &[u8],
+    mut apply: impl FnMut(i64, u32, u32, &mut Cursor<&[u8]>) -> Option<()>,
+) -> Result<(), KafkaError> {
+    for record in decode_records(bytes)? {
+        let Some(value) = &record.value else {
+            continue;
+        };
+        let mut cursor = Cursor::new(value.as_slice());
+        let header = (
+            read_unsigned_varint(&mut cursor),
+            read_unsigned_varint(&mut cursor),
+            read_unsigned_varint(&mut cursor),
+        );
+        if let (Ok(METADATA_RECORD_FRAME_VERSION), Ok(record_type), Ok(version)) = header {
+            apply(record.offset, record_type, version, &mut cursor);
+        }
+    }
+    Ok(())
+}
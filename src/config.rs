@@ -0,0 +1,1277 @@
+use crate::fault_injection::FaultConfig;
+use crate::KafkaError;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+const SUPPORTED_SASL_MECHANISMS: &[&str] = &["PLAIN", "SCRAM-SHA-256", "SCRAM-SHA-512"];
+// either name gets the one acl authorizer there is
+const SUPPORTED_AUTHORIZERS: &[&str] = &[
+    "org.apache.kafka.metadata.authorizer.StandardAuthorizer",
+    "kafka.security.authorizer.AclAuthorizer",
+];
+const DEFAULT_LOG_DIR: &str = "/tmp/kafka-logs";
+const DEFAULT_MESSAGE_MAX_BYTES: usize = 1_048_588;
+const DEFAULT_REQUEST_TIMEOUT_MS: u64 = 30_000;
+const DEFAULT_MAX_IN_FLIGHT_REQUESTS_PER_CONNECTION: usize = 5;
+const DEFAULT_BROKER_SESSION_TIMEOUT_MS: u64 = 9_000;
+const DEFAULT_REPLICA_FETCH_MAX_BYTES: i32 = 1_048_576;
+const DEFAULT_REPLICA_FETCH_WAIT_MAX_MS: i32 = 500;
+const DEFAULT_REPLICA_FETCH_BACKOFF_MS: u64 = 1_000;
+const DEFAULT_REPLICA_LAG_TIME_MAX_MS: u64 = 30_000;
+const DEFAULT_METADATA_LOG_MAX_RECORD_BYTES_BETWEEN_SNAPSHOTS: u64 = 20_971_520;
+const DEFAULT_METADATA_LOG_MAX_SNAPSHOT_INTERVAL_MS: u64 = 3_600_000;
+const DEFAULT_LISTENERS: &str = "PLAINTEXT://127.0.0.1:9092";
+const DEFAULT_AUDIT_LOG_MAX_BYTES: u64 = 104_857_600;
+const DEFAULT_AUDIT_LOG_MAX_BACKUPS: u32 = 10;
+const DEFAULT_FAULT_INJECTION_DELAY_MAX_MS: u64 = 1_000;
+// NOT_LEADER_OR_FOLLOWER, which clients answer by refreshing metadata and retrying
+const DEFAULT_FAULT_INJECTION_PARTITION_ERROR_CODES: &str = "6";
+
+// ### CONFIG REGISTRY ### //
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigType {
+    Boolean = 1,
+    String = 2,
+    Int = 3,
+    Long = 5,
+    Double = 6,
+    List = 7,
+    Password = 9,
+}
+
+pub struct ConfigDef {
+    pub name: &'static str,
+    pub config_type: ConfigType,
+    pub default: Option<&'static str>,
+    pub documentation: &'static str,
+    // whether the value can be changed without restarting the broker
+    pub read_only: bool,
+    // allowed values (or list items), anything goes when empty
+    pub valid_values: &'static [&'static str],
+    // lower bound of numeric configs
+    pub min: Option<i64>,
+}
+
+impl ConfigDef {
+    pub fn is_sensitive(&self) -> bool {
+        self.config_type == ConfigType::Password
+    }
+
+    pub fn validate(&self, value: &str) -> Result<(), String> {
+        if value.contains(['\n', '\r']) {
+            return Err(format!("{} must not contain line breaks", self.name));
+        }
+
+        let number = match self.config_type {
+            ConfigType::Int => Some(value.parse::<i32>().map(i64::from)),
+            ConfigType::Long => Some(value.parse::<i64>()),
+            _ => None,
+        };
+        match (number, self.min) {
+            (Some(Err(_)), _) => {
+                return Err(format!("{} expects a number, got {value}", self.name));
+            }
+            (Some(Ok(number)), Some(min)) if number < min => {
+                return Err(format!(
+                    "{} must be at least {min}, got {number}",
+                    self.name
+                ));
+            }
+            _ => {}
+        }
+
+        if self.config_type == ConfigType::Double && value.parse::<f64>().is_err() {
+            return Err(format!("{} expects a number, got {value}", self.name));
+        }
+        if self.config_type == ConfigType::Boolean
+            && !["true", "false"].contains(&value.to_lowercase().as_str())
+        {
+            return Err(format!("{} expects true or false, got {value}", self.name));
+        }
+
+        let items = match self.config_type {
+            ConfigType::List => value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .collect(),
+            _ => vec![value],
+        };
+        if let Some(invalid) = items
+            .iter()
+            .find(|item| !self.valid_values.is_empty() && !self.valid_values.contains(item))
+        {
+            return Err(format!(
+                "invalid value {invalid} for {}, expected one of {}",
+                self.name,
+                self.valid_values.join(", ")
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+pub fn topic_config_def(name: &str) -> Option<&'static ConfigDef> {
+    TOPIC_CONFIG_DEFS.iter().find(|def| def.name == name)
+}
+
+// every broker config this broker actually reads from server.properties
+pub const BROKER_CONFIG_DEFS: &[ConfigDef] = &[
+    ConfigDef {
+        name: "node.id",
+        config_type: ConfigType::Int,
+        default: Some("1"),
+        documentation: "The node id of this broker.",
+        read_only: true,
+        valid_values: &[],
+        min: None,
+    },
+    ConfigDef {
+        name: "log.dirs",
+        config_type: ConfigType::List,
+        default: Some(DEFAULT_LOG_DIR),
+        documentation: "Directories the log data is kept in.",
+        read_only: true,
+        valid_values: &[],
+        min: None,
+    },
+    ConfigDef {
+        name: "log.store",
+        config_type: ConfigType::String,
+        default: Some("file"),
+        documentation: "Where partitions are kept: segment files under log.dirs, or memory only.",
+        read_only: true,
+        valid_values: &["file", "memory"],
+        min: None,
+    },
+    ConfigDef {
+        name: "log.retention.check.interval.ms",
+        config_type: ConfigType::Long,
+        default: Some("300000"),
+        documentation: "How often the log cleaner checks for segments to delete.",
+        read_only: true,
+        valid_values: &[],
+        min: Some(1),
+    },
+    ConfigDef {
+        name: "log.partition.discovery.interval.ms",
+        config_type: ConfigType::Long,
+        default: Some("10000"),
+        documentation: "How often log.dirs are scanned for partitions created after startup.",
+        read_only: true,
+        valid_values: &[],
+        min: Some(1),
+    },
+    ConfigDef {
+        name: "auto.create.topics.enable",
+        config_type: ConfigType::Boolean,
+        default: Some("true"),
+        documentation: "Whether a topic a client asks for metadata on is created when it doesn't exist.",
+        read_only: true,
+        valid_values: &[],
+        min: None,
+    },
+    ConfigDef {
+        name: "num.partitions",
+        config_type: ConfigType::Int,
+        default: Some("1"),
+        documentation: "How many partitions an automatically created topic gets.",
+        read_only: true,
+        valid_values: &[],
+        min: Some(1),
+    },
+    ConfigDef {
+        name: "message.max.bytes",
+        config_type: ConfigType::Int,
+        default: Some("1048588"),
+        documentation: "Largest request the broker accepts, size prefix excluded.",
+        read_only: true,
+        valid_values: &[],
+        min: Some(0),
+    },
+    ConfigDef {
+        name: "message.max.bytes.per.api",
+        config_type: ConfigType::List,
+        default: Some(""),
+        documentation: "Per-API overrides of message.max.bytes as <api_key>:<bytes> entries.",
+        read_only: true,
+        valid_values: &[],
+        min: None,
+    },
+    ConfigDef {
+        name: "listeners",
+        config_type: ConfigType::List,
+        default: Some(DEFAULT_LISTENERS),
+        documentation: "The <name>://<host>:<port> TCP listeners to accept client connections on.",
+        read_only: true,
+        valid_values: &[],
+        min: None,
+    },
+    ConfigDef {
+        name: "advertised.listeners",
+        config_type: ConfigType::List,
+        default: None,
+        documentation: "Addresses handed to clients for each listener, when they differ from the listeners themselves.",
+        read_only: true,
+        valid_values: &[],
+        min: None,
+    },
+    ConfigDef {
+        name: "tcp.listener.enabled",
+        config_type: ConfigType::Boolean,
+        default: Some("true"),
+        documentation: "Whether to accept client connections on the TCP listener.",
+        read_only: true,
+        valid_values: &[],
+        min: None,
+    },
+    ConfigDef {
+        name: "socket.tcp.nodelay",
+        config_type: ConfigType::Boolean,
+        default: Some("true"),
+        documentation: "Whether client connections set TCP_NODELAY, sending small responses \
+            right away instead of waiting to coalesce them.",
+        read_only: true,
+        valid_values: &[],
+        min: None,
+    },
+    ConfigDef {
+        name: "socket.keepalive.enable",
+        config_type: ConfigType::Boolean,
+        default: Some("true"),
+        documentation: "Whether client connections set SO_KEEPALIVE, so dead peers are noticed.",
+        read_only: true,
+        valid_values: &[],
+        min: None,
+    },
+    ConfigDef {
+        name: "socket.send.buffer.bytes",
+        config_type: ConfigType::Int,
+        default: Some("-1"),
+        documentation: "SO_SNDBUF of client connections. The OS default when -1.",
+        read_only: true,
+        valid_values: &[],
+        min: Some(-1),
+    },
+    ConfigDef {
+        name: "socket.receive.buffer.bytes",
+        config_type: ConfigType::Int,
+        default: Some("-1"),
+        documentation: "SO_RCVBUF of client connections. The OS default when -1.",
+        read_only: true,
+        valid_values: &[],
+        min: Some(-1),
+    },
+    ConfigDef {
+        name: "unix.socket.path",
+        config_type: ConfigType::String,
+        default: None,
+        documentation: "Path of a Unix domain socket to also accept client connections on.",
+        read_only: true,
+        valid_values: &[],
+        min: None,
+    },
+    ConfigDef {
+        name: "request.timeout.ms",
+        config_type: ConfigType::Int,
+        default: Some("30000"),
+        documentation: "How long a client gets to finish sending a request once it has started, \
+            and to read back a response, before its connection is closed.",
+        read_only: true,
+        valid_values: &[],
+        min: Some(1),
+    },
+    ConfigDef {
+        name: "max.in.flight.requests.per.connection",
+        config_type: ConfigType::Int,
+        default: Some("5"),
+        documentation: "How many requests a connection can have read but not yet answered. \
+            Past that the broker stops reading from it until responses have been written.",
+        read_only: true,
+        valid_values: &[],
+        min: Some(1),
+    },
+    ConfigDef {
+        name: "broker.session.timeout.ms",
+        config_type: ConfigType::Int,
+        default: Some("9000"),
+        documentation: "How long a broker registered through BrokerRegistration can go without \
+            a heartbeat before it is fenced.",
+        read_only: true,
+        valid_values: &[],
+        min: Some(1),
+    },
+    ConfigDef {
+        name: "controller.quorum.voters",
+        config_type: ConfigType::List,
+        default: None,
+        documentation: "The <id>@<host>:<port> voters of the metadata quorum, this node alone \
+            when unset.",
+        read_only: true,
+        valid_values: &[],
+        min: None,
+    },
+    ConfigDef {
+        name: "metadata.log.max.record.bytes.between.snapshots",
+        config_type: ConfigType::Long,
+        default: Some("20971520"),
+        documentation: "How many bytes of batches the metadata log can grow by before its \
+            image is written out as a new snapshot.",
+        read_only: true,
+        valid_values: &[],
+        min: Some(1),
+    },
+    ConfigDef {
+        name: "metadata.log.max.snapshot.interval.ms",
+        config_type: ConfigType::Long,
+        default: Some("3600000"),
+        documentation: "How long the metadata log can go with records that aren't in a \
+            snapshot yet, regardless of their size. 0 disables time based snapshots.",
+        read_only: true,
+        valid_values: &[],
+        min: Some(0),
+    },
+    ConfigDef {
+        name: "metrics.port",
+        config_type: ConfigType::Int,
+        default: None,
+        documentation: "Port serving Prometheus metrics on /metrics. Disabled when unset.",
+        read_only: true,
+        valid_values: &[],
+        min: None,
+    },
+    ConfigDef {
+        name: "inter.broker.listener.name",
+        config_type: ConfigType::String,
+        default: None,
+        documentation: "The listener replica fetchers connect to on the partition leader. The \
+            first of listeners when unset.",
+        read_only: true,
+        valid_values: &[],
+        min: None,
+    },
+    ConfigDef {
+        name: "replica.fetch.max.bytes",
+        config_type: ConfigType::Int,
+        default: Some("1048576"),
+        documentation: "Bytes a replica fetcher asks for from each partition it follows.",
+        read_only: true,
+        valid_values: &[],
+        min: Some(1),
+    },
+    ConfigDef {
+        name: "replica.fetch.wait.max.ms",
+        config_type: ConfigType::Int,
+        default: Some("500"),
+        documentation: "How long the leader may hold a replica fetcher's Fetch waiting for \
+            new records.",
+        read_only: true,
+        valid_values: &[],
+        min: Some(0),
+    },
+    ConfigDef {
+        name: "replica.fetch.backoff.ms",
+        config_type: ConfigType::Int,
+        default: Some("1000"),
+        documentation: "How long a replica fetcher waits before retrying after an error.",
+        read_only: true,
+        valid_values: &[],
+        min: Some(0),
+    },
+    ConfigDef {
+        name: "replica.lag.time.max.ms",
+        config_type: ConfigType::Long,
+        default: Some("30000"),
+        documentation: "How long a follower can go without catching up to its leader before it \
+            is dropped from the isr.",
+        read_only: true,
+        valid_values: &[],
+        min: Some(1),
+    },
+    ConfigDef {
+        name: "quota.consumer.default",
+        config_type: ConfigType::Long,
+        default: None,
+        documentation: "Fetch bytes per second allowed for each client id without a client quota \
+            set through AlterClientQuotas. Unlimited when unset.",
+        read_only: true,
+        valid_values: &[],
+        min: None,
+    },
+    ConfigDef {
+        name: "sasl.enabled.mechanisms",
+        config_type: ConfigType::List,
+        default: Some(""),
+        documentation:
+            "SASL mechanisms clients have to authenticate with: PLAIN, SCRAM-SHA-256 or SCRAM-SHA-512.",
+        read_only: true,
+        valid_values: &[],
+        min: None,
+    },
+    ConfigDef {
+        name: "sasl.jaas.config",
+        config_type: ConfigType::Password,
+        default: None,
+        documentation: "JAAS login context whose user_<name> options define the PLAIN users.",
+        read_only: true,
+        valid_values: &[],
+        min: None,
+    },
+    ConfigDef {
+        name: "authorizer.class.name",
+        config_type: ConfigType::String,
+        default: Some(""),
+        documentation: "The authorizer checking requests against the acls, none when empty.",
+        read_only: true,
+        valid_values: &[],
+        min: None,
+    },
+    ConfigDef {
+        name: "super.users",
+        config_type: ConfigType::String,
+        default: Some(""),
+        documentation: "Semicolon separated principals allowed to do anything, whatever the acls say.",
+        read_only: true,
+        valid_values: &[],
+        min: None,
+    },
+    ConfigDef {
+        name: "allow.everyone.if.no.acl.found",
+        config_type: ConfigType::Boolean,
+        default: Some("false"),
+        documentation: "Whether a resource without any acls is open to everyone.",
+        read_only: true,
+        valid_values: &[],
+        min: None,
+    },
+    ConfigDef {
+        name: "audit.log.path",
+        config_type: ConfigType::String,
+        default: None,
+        documentation: "File every request is recorded in as a line of JSON. Disabled when unset.",
+        read_only: true,
+        valid_values: &[],
+        min: None,
+    },
+    ConfigDef {
+        name: "audit.log.max.bytes",
+        config_type: ConfigType::Long,
+        default: Some("104857600"),
+        documentation: "Size the audit log can grow to before it is rotated.",
+        read_only: true,
+        valid_values: &[],
+        min: Some(1),
+    },
+    ConfigDef {
+        name: "audit.log.max.backups",
+        config_type: ConfigType::Int,
+        default: Some("10"),
+        documentation: "Rotated audit logs kept next to the current one, the oldest is deleted \
+            past this many.",
+        read_only: true,
+        valid_values: &[],
+        min: Some(0),
+    },
+    ConfigDef {
+        name: "request.record.path",
+        config_type: ConfigType::String,
+        default: None,
+        documentation: "File every request frame is appended to, for the replay subcommand. \
+            Disabled when unset.",
+        read_only: true,
+        valid_values: &[],
+        min: None,
+    },
+    ConfigDef {
+        name: "fault.injection.enable",
+        config_type: ConfigType::Boolean,
+        default: Some("false"),
+        documentation: "Whether the other fault.injection configs apply. Only meant for \
+            testing clients against a misbehaving broker.",
+        read_only: true,
+        valid_values: &[],
+        min: None,
+    },
+    ConfigDef {
+        name: "fault.injection.delay.probability",
+        config_type: ConfigType::Double,
+        default: Some("0"),
+        documentation: "Chance of a response being held back before it is sent.",
+        read_only: true,
+        valid_values: &[],
+        min: None,
+    },
+    ConfigDef {
+        name: "fault.injection.delay.max.ms",
+        config_type: ConfigType::Long,
+        default: Some("1000"),
+        documentation: "Longest a delayed response is held back for.",
+        read_only: true,
+        valid_values: &[],
+        min: Some(0),
+    },
+    ConfigDef {
+        name: "fault.injection.disconnect.probability",
+        config_type: ConfigType::Double,
+        default: Some("0"),
+        documentation: "Chance of the connection being closed instead of a response being sent.",
+        read_only: true,
+        valid_values: &[],
+        min: None,
+    },
+    ConfigDef {
+        name: "fault.injection.truncate.probability",
+        config_type: ConfigType::Double,
+        default: Some("0"),
+        documentation: "Chance of only part of a response being sent before the connection \
+            is closed.",
+        read_only: true,
+        valid_values: &[],
+        min: None,
+    },
+    ConfigDef {
+        name: "fault.injection.partition.error.probability",
+        config_type: ConfigType::Double,
+        default: Some("0"),
+        documentation: "Chance of a Fetch partition failing with one of \
+            fault.injection.partition.error.codes.",
+        read_only: true,
+        valid_values: &[],
+        min: None,
+    },
+    ConfigDef {
+        name: "fault.injection.partition.error.codes",
+        config_type: ConfigType::List,
+        default: Some("6"),
+        documentation: "Error codes injected into Fetch partitions.",
+        read_only: true,
+        valid_values: &[],
+        min: None,
+    },
+];
+
+pub const TOPIC_CONFIG_DEFS: &[ConfigDef] = &[
+    ConfigDef {
+        name: "cleanup.policy",
+        config_type: ConfigType::List,
+        default: Some("delete"),
+        documentation: "Whether old log segments are deleted or compacted.",
+        read_only: false,
+        valid_values: &["delete", "compact"],
+        min: None,
+    },
+    ConfigDef {
+        name: "compression.type",
+        config_type: ConfigType::String,
+        default: Some("producer"),
+        documentation: "Final compression type of the topic's batches.",
+        read_only: false,
+        valid_values: &["uncompressed", "zstd", "lz4", "snappy", "gzip", "producer"],
+        min: None,
+    },
+    ConfigDef {
+        name: "max.message.bytes",
+        config_type: ConfigType::Int,
+        default: Some("1048588"),
+        documentation: "Largest record batch size allowed in the topic.",
+        read_only: false,
+        valid_values: &[],
+        min: Some(0),
+    },
+    ConfigDef {
+        name: "message.timestamp.type",
+        config_type: ConfigType::String,
+        default: Some("CreateTime"),
+        documentation: "Whether record timestamps are CreateTime or LogAppendTime.",
+        read_only: false,
+        valid_values: &["CreateTime", "LogAppendTime"],
+        min: None,
+    },
+    ConfigDef {
+        name: "min.insync.replicas",
+        config_type: ConfigType::Int,
+        default: Some("1"),
+        documentation: "Fewest in-sync replicas a partition needs to take an acks=-1 produce. \
+            Accepted and reported for compatibility only: there is no Produce path to enforce it, \
+            so no value has any effect.",
+        read_only: false,
+        valid_values: &[],
+        min: Some(1),
+    },
+    ConfigDef {
+        name: "retention.bytes",
+        config_type: ConfigType::Long,
+        default: Some("-1"),
+        documentation: "Maximum size a partition can grow to before old segments are discarded.",
+        read_only: false,
+        valid_values: &[],
+        min: None,
+    },
+    ConfigDef {
+        name: "retention.ms",
+        config_type: ConfigType::Long,
+        default: Some("604800000"),
+        documentation: "Maximum time a log segment is retained before it is discarded.",
+        read_only: false,
+        valid_values: &[],
+        min: Some(-1),
+    },
+    ConfigDef {
+        name: "segment.bytes",
+        config_type: ConfigType::Int,
+        default: Some("1073741824"),
+        documentation: "Size at which the active log segment is rolled.",
+        read_only: false,
+        valid_values: &[],
+        min: Some(14),
+    },
+    ConfigDef {
+        name: "segment.ms",
+        config_type: ConfigType::Long,
+        default: Some("604800000"),
+        documentation: "Time after which the active log segment is rolled.",
+        read_only: false,
+        valid_values: &[],
+        min: Some(1),
+    },
+];
+// ### ### ### //
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Listener {
+    pub name: String,
+    // empty to listen on all interfaces
+    pub host: String,
+    pub port: u16,
+}
+
+impl Listener {
+    pub fn bind_host(&self) -> &str {
+        match self.host.as_str() {
+            "" => "0.0.0.0",
+            host => host,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogStoreKind {
+    File,
+    // nothing under log.dirs is read or written
+    Memory,
+}
+
+#[derive(Debug, Clone)]
+pub struct BrokerConfig {
+    properties: HashMap<String, String>,
+    pub node_id: i32,
+    pub log_dirs: Vec<PathBuf>,
+    pub log_store: LogStoreKind,
+    pub log_retention_check_interval_ms: u64,
+    pub log_partition_discovery_interval_ms: u64,
+    pub auto_create_topics_enable: bool,
+    // partitions of an automatically created topic, each with this broker as its only replica
+    pub num_partitions: i32,
+    pub message_max_bytes: usize,
+    // api key -> request size limit, overriding message_max_bytes for that api
+    pub message_max_bytes_per_api: HashMap<i16, usize>,
+    pub request_timeout_ms: u64,
+    // requests read ahead of their responses, answered in the order they came in
+    pub max_in_flight_requests_per_connection: usize,
+    // registered brokers that haven't heartbeated for this long are fenced
+    pub broker_session_timeout_ms: u64,
+    // the ids of the metadata quorum's voters
+    pub controller_quorum_voters: Vec<i32>,
+    // when the metadata log's image is snapshotted, see run_metadata_snapshots
+    pub metadata_log_max_record_bytes_between_snapshots: u64,
+    pub metadata_log_max_snapshot_interval_ms: u64,
+    pub listeners: Vec<Listener>,
+    // what clients are told to connect to, one per listener (Metadata, DescribeCluster)
+    pub advertised_listeners: Vec<Listener>,
+    pub tcp_listener_enabled: bool,
+    // applied to each accepted tcp connection, `None` buffer sizes leave the OS defaults
+    pub socket_tcp_nodelay: bool,
+    pub socket_keepalive: bool,
+    pub socket_send_buffer_bytes: Option<u32>,
+    pub socket_receive_buffer_bytes: Option<u32>,
+    // also (or only, with the tcp listener disabled) accept connections on a unix socket
+    pub unix_socket_path: Option<PathBuf>,
+    // port for the prometheus `/metrics` http endpoint, disabled when unset
+    pub metrics_port: Option<u16>,
+    // the follower side of replication, see run_replica_fetchers
+    pub inter_broker_listener_name: String,
+    pub replica_fetch_max_bytes: i32,
+    pub replica_fetch_wait_max_ms: i32,
+    pub replica_fetch_backoff_ms: u64,
+    // the leader side, see IsrTracker
+    pub replica_lag_time_max_ms: u64,
+    // fetch byte rate allowed per client id (quota.consumer.default) that has no quota set
+    // through AlterClientQuotas, unlimited when unset
+    pub quota_consumer_default: Option<u64>,
+    pub sasl_enabled_mechanisms: Vec<String>,
+    // username -> password, taken from the `user_<name>="<password>"` JAAS entries
+    pub sasl_plain_users: HashMap<String, String>,
+    // requests are checked against the acls when set (authorizer.class.name)
+    pub authorizer_enabled: bool,
+    pub super_users: Vec<String>,
+    pub allow_everyone_if_no_acl_found: bool,
+    // each request gets a line of json here when set (audit.log.path)
+    pub audit_log_path: Option<PathBuf>,
+    // the audit log is rotated to `<path>.1` once it would grow past this many bytes
+    pub audit_log_max_bytes: u64,
+    pub audit_log_max_backups: u32,
+    // every request frame is appended here when set, see RequestRecorder
+    pub request_record_path: Option<PathBuf>,
+    // `None` unless fault.injection.enable is set
+    pub fault_injection: Option<FaultConfig>,
+    // every frame in and out is logged as an annotated hex dump, set by `--trace-wire`
+    pub trace_wire: bool,
+}
+
+impl Default for BrokerConfig {
+    fn default() -> Self {
+        BrokerConfig {
+            properties: HashMap::new(),
+            node_id: 1,
+            log_dirs: vec![PathBuf::from(DEFAULT_LOG_DIR)],
+            log_store: LogStoreKind::File,
+            log_retention_check_interval_ms: 300_000,
+            log_partition_discovery_interval_ms: 10_000,
+            auto_create_topics_enable: true,
+            num_partitions: 1,
+            message_max_bytes: DEFAULT_MESSAGE_MAX_BYTES,
+            message_max_bytes_per_api: HashMap::new(),
+            request_timeout_ms: DEFAULT_REQUEST_TIMEOUT_MS,
+            max_in_flight_requests_per_connection: DEFAULT_MAX_IN_FLIGHT_REQUESTS_PER_CONNECTION,
+            broker_session_timeout_ms: DEFAULT_BROKER_SESSION_TIMEOUT_MS,
+            controller_quorum_voters: vec![1],
+            metadata_log_max_record_bytes_between_snapshots:
+                DEFAULT_METADATA_LOG_MAX_RECORD_BYTES_BETWEEN_SNAPSHOTS,
+            metadata_log_max_snapshot_interval_ms: DEFAULT_METADATA_LOG_MAX_SNAPSHOT_INTERVAL_MS,
+            listeners: parse_listeners("listeners", DEFAULT_LISTENERS).unwrap(),
+            advertised_listeners: parse_listeners("listeners", DEFAULT_LISTENERS).unwrap(),
+            tcp_listener_enabled: true,
+            socket_tcp_nodelay: true,
+            socket_keepalive: true,
+            socket_send_buffer_bytes: None,
+            socket_receive_buffer_bytes: None,
+            unix_socket_path: None,
+            metrics_port: None,
+            inter_broker_listener_name: "PLAINTEXT".to_string(),
+            replica_fetch_max_bytes: DEFAULT_REPLICA_FETCH_MAX_BYTES,
+            replica_fetch_wait_max_ms: DEFAULT_REPLICA_FETCH_WAIT_MAX_MS,
+            replica_fetch_backoff_ms: DEFAULT_REPLICA_FETCH_BACKOFF_MS,
+            replica_lag_time_max_ms: DEFAULT_REPLICA_LAG_TIME_MAX_MS,
+            quota_consumer_default: None,
+            sasl_enabled_mechanisms: vec![],
+            sasl_plain_users: HashMap::new(),
+            authorizer_enabled: false,
+            super_users: vec![],
+            allow_everyone_if_no_acl_found: false,
+            audit_log_path: None,
+            audit_log_max_bytes: DEFAULT_AUDIT_LOG_MAX_BYTES,
+            audit_log_max_backups: DEFAULT_AUDIT_LOG_MAX_BACKUPS,
+            request_record_path: None,
+            fault_injection: None,
+            trace_wire: false,
+        }
+    }
+}
+
+impl BrokerConfig {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, KafkaError> {
+        let contents = std::fs::read_to_string(path)?;
+        Self::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, KafkaError> {
+        let properties = parse_properties(contents);
+        // checked against the registry up front, so what's parsed below already has the
+        // right type and is within its def's bounds
+        for def in BROKER_CONFIG_DEFS {
+            if let Some(value) = properties.get(def.name) {
+                def.validate(value).map_err(KafkaError::InvalidConfig)?;
+            }
+        }
+
+        let node_id = parse_number(&properties, "node.id")?.unwrap_or(1);
+        // like kafka, `log.dirs` takes precedence over the single `log.dir`
+        let log_dirs = properties
+            .get("log.dirs")
+            .or(properties.get("log.dir"))
+            .map(String::as_str)
+            .unwrap_or(DEFAULT_LOG_DIR)
+            .split(',')
+            .map(str::trim)
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .collect::<Vec<_>>();
+        if log_dirs.is_empty() {
+            return Err(KafkaError::InvalidConfig(
+                "log.dirs must name at least one directory".to_string(),
+            ));
+        }
+
+        let log_store = match properties.get("log.store").map(String::as_str) {
+            Some("memory") => LogStoreKind::Memory,
+            _ => LogStoreKind::File,
+        };
+
+        let log_retention_check_interval_ms =
+            parse_number(&properties, "log.retention.check.interval.ms")?.unwrap_or(300_000);
+
+        let log_partition_discovery_interval_ms =
+            parse_number(&properties, "log.partition.discovery.interval.ms")?.unwrap_or(10_000);
+
+        let auto_create_topics_enable = parse_bool(&properties, "auto.create.topics.enable", true)?;
+        let num_partitions = parse_number(&properties, "num.partitions")?.unwrap_or(1);
+
+        let message_max_bytes =
+            parse_number(&properties, "message.max.bytes")?.unwrap_or(DEFAULT_MESSAGE_MAX_BYTES);
+        let message_max_bytes_per_api = properties
+            .get("message.max.bytes.per.api")
+            .map(String::as_str)
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                entry
+                    .split_once(':')
+                    .and_then(|(api_key, bytes)| {
+                        Some((api_key.trim().parse().ok()?, bytes.trim().parse().ok()?))
+                    })
+                    .ok_or_else(|| {
+                        KafkaError::InvalidConfig(format!(
+                            "expected <api_key>:<bytes> in message.max.bytes.per.api, got {entry}"
+                        ))
+                    })
+            })
+            .collect::<Result<HashMap<_, _>, _>>()?;
+
+        let request_timeout_ms =
+            parse_number(&properties, "request.timeout.ms")?.unwrap_or(DEFAULT_REQUEST_TIMEOUT_MS);
+
+        let max_in_flight_requests_per_connection =
+            parse_number(&properties, "max.in.flight.requests.per.connection")?
+                .unwrap_or(DEFAULT_MAX_IN_FLIGHT_REQUESTS_PER_CONNECTION);
+
+        let broker_session_timeout_ms = parse_number(&properties, "broker.session.timeout.ms")?
+            .unwrap_or(DEFAULT_BROKER_SESSION_TIMEOUT_MS);
+        let controller_quorum_voters = match properties.get("controller.quorum.voters") {
+            Some(voters) => parse_quorum_voters(voters)?,
+            None => vec![node_id],
+        };
+        let metadata_log_max_record_bytes_between_snapshots = parse_number(
+            &properties,
+            "metadata.log.max.record.bytes.between.snapshots",
+        )?
+        .unwrap_or(DEFAULT_METADATA_LOG_MAX_RECORD_BYTES_BETWEEN_SNAPSHOTS);
+        let metadata_log_max_snapshot_interval_ms =
+            parse_number(&properties, "metadata.log.max.snapshot.interval.ms")?
+                .unwrap_or(DEFAULT_METADATA_LOG_MAX_SNAPSHOT_INTERVAL_MS);
+
+        let listeners = parse_listeners(
+            "listeners",
+            properties
+                .get("listeners")
+                .map(String::as_str)
+                .unwrap_or(DEFAULT_LISTENERS),
+        )?;
+        let advertised_listeners = match properties.get("advertised.listeners") {
+            Some(advertised) => {
+                let advertised = parse_listeners("advertised.listeners", advertised)?;
+                if let Some(unknown) = advertised
+                    .iter()
+                    .find(|advertised| !listeners.iter().any(|l| l.name == advertised.name))
+                {
+                    return Err(KafkaError::InvalidConfig(format!(
+                        "advertised.listeners names {}, which isn't in listeners",
+                        unknown.name
+                    )));
+                }
+                advertised
+            }
+            None => listeners.clone(),
+        };
+
+        let tcp_listener_enabled = parse_bool(&properties, "tcp.listener.enabled", true)?;
+        let socket_tcp_nodelay = parse_bool(&properties, "socket.tcp.nodelay", true)?;
+        let socket_keepalive = parse_bool(&properties, "socket.keepalive.enable", true)?;
+        let socket_send_buffer_bytes = parse_buffer_size(&properties, "socket.send.buffer.bytes")?;
+        let socket_receive_buffer_bytes =
+            parse_buffer_size(&properties, "socket.receive.buffer.bytes")?;
+        let unix_socket_path = properties
+            .get("unix.socket.path")
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from);
+
+        if !tcp_listener_enabled && unix_socket_path.is_none() {
+            return Err(KafkaError::InvalidConfig(
+                "tcp.listener.enabled=false requires unix.socket.path to be set".to_string(),
+            ));
+        }
+
+        let metrics_port = parse_number(&properties, "metrics.port")?;
+
+        let inter_broker_listener_name = match properties.get("inter.broker.listener.name") {
+            Some(name)
+                if !listeners
+                    .iter()
+                    .any(|listener| listener.name == name.to_uppercase()) =>
+            {
+                return Err(KafkaError::InvalidConfig(format!(
+                    "inter.broker.listener.name names {name}, which isn't in listeners"
+                )));
+            }
+            Some(name) => name.to_uppercase(),
+            None => listeners
+                .first()
+                .map_or("PLAINTEXT".to_string(), |listener| listener.name.clone()),
+        };
+        let replica_fetch_max_bytes = parse_number(&properties, "replica.fetch.max.bytes")?
+            .unwrap_or(DEFAULT_REPLICA_FETCH_MAX_BYTES);
+        let replica_fetch_wait_max_ms = parse_number(&properties, "replica.fetch.wait.max.ms")?
+            .unwrap_or(DEFAULT_REPLICA_FETCH_WAIT_MAX_MS);
+        let replica_fetch_backoff_ms = parse_number(&properties, "replica.fetch.backoff.ms")?
+            .unwrap_or(DEFAULT_REPLICA_FETCH_BACKOFF_MS);
+        let replica_lag_time_max_ms = parse_number(&properties, "replica.lag.time.max.ms")?
+            .unwrap_or(DEFAULT_REPLICA_LAG_TIME_MAX_MS);
+        let quota_consumer_default = parse_number(&properties, "quota.consumer.default")?;
+
+        let sasl_enabled_mechanisms = properties
+            .get("sasl.enabled.mechanisms")
+            .map(|mechanisms| {
+                mechanisms
+                    .split(',')
+                    .map(|mechanism| mechanism.trim().to_uppercase())
+                    .filter(|mechanism| !mechanism.is_empty())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        if let Some(unsupported) = sasl_enabled_mechanisms
+            .iter()
+            .find(|mechanism| !SUPPORTED_SASL_MECHANISMS.contains(&mechanism.as_str()))
+        {
+            return Err(KafkaError::InvalidConfig(format!(
+                "unsupported SASL mechanism in sasl.enabled.mechanisms: {unsupported}"
+            )));
+        }
+
+        let sasl_plain_users = properties
+            .iter()
+            .filter(|(key, _)| *key == "sasl.jaas.config" || key.ends_with(".sasl.jaas.config"))
+            .flat_map(|(_, jaas_config)| parse_jaas_users(jaas_config))
+            .collect();
+
+        let authorizer_enabled = match properties.get("authorizer.class.name") {
+            None => false,
+            Some(name) if name.is_empty() => false,
+            Some(name) if SUPPORTED_AUTHORIZERS.contains(&name.as_str()) => true,
+            Some(name) => {
+                return Err(KafkaError::InvalidConfig(format!(
+                    "unsupported authorizer.class.name {name}"
+                )));
+            }
+        };
+        let super_users = properties
+            .get("super.users")
+            .map(String::as_str)
+            .unwrap_or_default()
+            .split(';')
+            .map(str::trim)
+            .filter(|user| !user.is_empty())
+            .map(str::to_string)
+            .collect();
+        let allow_everyone_if_no_acl_found =
+            parse_bool(&properties, "allow.everyone.if.no.acl.found", false)?;
+
+        let audit_log_path = properties
+            .get("audit.log.path")
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from);
+        let audit_log_max_bytes = parse_number(&properties, "audit.log.max.bytes")?
+            .unwrap_or(DEFAULT_AUDIT_LOG_MAX_BYTES);
+        let audit_log_max_backups = parse_number(&properties, "audit.log.max.backups")?
+            .unwrap_or(DEFAULT_AUDIT_LOG_MAX_BACKUPS);
+
+        let request_record_path = properties
+            .get("request.record.path")
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from);
+
+        let fault_injection = match parse_bool(&properties, "fault.injection.enable", false)? {
+            true => Some(parse_fault_config(&properties)?),
+            false => None,
+        };
+
+        Ok(BrokerConfig {
+            properties,
+            node_id,
+            log_dirs,
+            log_store,
+            log_retention_check_interval_ms,
+            log_partition_discovery_interval_ms,
+            auto_create_topics_enable,
+            num_partitions,
+            message_max_bytes,
+            message_max_bytes_per_api,
+            request_timeout_ms,
+            max_in_flight_requests_per_connection,
+            broker_session_timeout_ms,
+            controller_quorum_voters,
+            metadata_log_max_record_bytes_between_snapshots,
+            metadata_log_max_snapshot_interval_ms,
+            listeners,
+            advertised_listeners,
+            tcp_listener_enabled,
+            socket_tcp_nodelay,
+            socket_keepalive,
+            socket_send_buffer_bytes,
+            socket_receive_buffer_bytes,
+            unix_socket_path,
+            metrics_port,
+            inter_broker_listener_name,
+            replica_fetch_max_bytes,
+            replica_fetch_wait_max_ms,
+            replica_fetch_backoff_ms,
+            replica_lag_time_max_ms,
+            quota_consumer_default,
+            sasl_enabled_mechanisms,
+            sasl_plain_users,
+            authorizer_enabled,
+            super_users,
+            allow_everyone_if_no_acl_found,
+            audit_log_path,
+            audit_log_max_bytes,
+            audit_log_max_backups,
+            request_record_path,
+            fault_injection,
+            trace_wire: false,
+        })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.properties.get(key).map(String::as_str)
+    }
+
+    pub fn max_request_bytes(&self, api_key: i16) -> usize {
+        self.message_max_bytes_per_api
+            .get(&api_key)
+            .copied()
+            .unwrap_or(self.message_max_bytes)
+    }
+
+    pub fn is_sasl_enabled(&self) -> bool {
+        !self.sasl_enabled_mechanisms.is_empty()
+    }
+}
+
+// parses the java `.properties` format: `key=value` lines, `#`/`!` comments, and trailing
+// backslashes continuing a value onto the next line
+pub(crate) fn parse_properties(contents: &str) -> HashMap<String, String> {
+    let mut properties = HashMap::new();
+    let mut lines = contents.lines();
+
+    while let Some(line) = lines.next() {
+        let mut line = line.trim().to_string();
+        if line.is_empty() || line.starts_with('#') || line.starts_with('!') {
+            continue;
+        }
+
+        while line.ends_with('\\') {
+            line.pop();
+            match lines.next() {
+                Some(next) => line.push_str(next.trim()),
+                None => break,
+            }
+        }
+
+        let Some((key, value)) = line.split_once(['=', ':']) else {
+            properties.insert(line, String::new());
+            continue;
+        };
+        properties.insert(key.trim().to_string(), value.trim().to_string());
+    }
+
+    properties
+}
+
+// like kafka, -1 leaves the buffer at the OS default
+fn parse_buffer_size(
+    properties: &HashMap<String, String>,
+    key: &str,
+) -> Result<Option<u32>, KafkaError> {
+    match parse_number::<i32>(properties, key)? {
+        None | Some(-1) => Ok(None),
+        Some(size) if size > 0 => Ok(Some(size as u32)),
+        Some(size) => Err(KafkaError::InvalidConfig(format!(
+            "{key} must be -1 or at least 1, got {size}"
+        ))),
+    }
+}
+
+fn parse_fault_config(properties: &HashMap<String, String>) -> Result<FaultConfig, KafkaError> {
+    let probability = |key| match parse_number::<f64>(properties, key)? {
+        None => Ok(0.0),
+        Some(probability) if (0.0..=1.0).contains(&probability) => Ok(probability),
+        Some(probability) => Err(KafkaError::InvalidConfig(format!(
+            "{key} must be between 0 and 1, got {probability}"
+        ))),
+    };
+
+    let partition_error_codes = properties
+        .get("fault.injection.partition.error.codes")
+        .map(String::as_str)
+        .unwrap_or(DEFAULT_FAULT_INJECTION_PARTITION_ERROR_CODES)
+        .split(',')
+        .map(str::trim)
+        .filter(|code| !code.is_empty())
+        .map(|code| {
+            code.parse::<i16>().map_err(|_| {
+                KafkaError::InvalidConfig(format!(
+                    "invalid error code in fault.injection.partition.error.codes: {code}"
+                ))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(FaultConfig {
+        delay_probability: probability("fault.injection.delay.probability")?,
+        delay_max_ms: parse_number(properties, "fault.injection.delay.max.ms")?
+            .unwrap_or(DEFAULT_FAULT_INJECTION_DELAY_MAX_MS),
+        disconnect_probability: probability("fault.injection.disconnect.probability")?,
+        truncate_probability: probability("fault.injection.truncate.probability")?,
+        partition_error_probability: probability("fault.injection.partition.error.probability")?,
+        partition_error_codes,
+    })
+}
+
+fn parse_bool(
+    properties: &HashMap<String, String>,
+    key: &str,
+    default: bool,
+) -> Result<bool, KafkaError> {
+    match properties.get(key).map(|value| value.to_lowercase()) {
+        None => Ok(default),
+        Some(value) if value == "true" => Ok(true),
+        Some(value) if value == "false" => Ok(false),
+        Some(value) => Err(KafkaError::InvalidConfig(format!(
+            "expected true or false for {key}, got {value}"
+        ))),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(
+    properties: &HashMap<String, String>,
+    key: &str,
+) -> Result<Option<T>, KafkaError> {
+    properties
+        .get(key)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| KafkaError::InvalidConfig(format!("invalid value for {key}: {value}")))
+        })
+        .transpose()
+}
+
+// `<name>://<host>:<port>` entries, the host may be empty or a bracketed ipv6 address
+fn parse_listeners(key: &str, value: &str) -> Result<Vec<Listener>, KafkaError> {
+    let mut listeners: Vec<Listener> = vec![];
+
+    for entry in value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        let parsed = entry.split_once("://").and_then(|(name, address)| {
+            let (host, port) = address.rsplit_once(':')?;
+            let host = host.trim_start_matches('[').trim_end_matches(']');
+            Some(Listener {
+                name: name.to_uppercase(),
+                host: host.to_string(),
+                port: port.parse().ok()?,
+            })
+        });
+        let Some(listener) = parsed.filter(|listener| !listener.name.is_empty()) else {
+            return Err(KafkaError::InvalidConfig(format!(
+                "expected <name>://<host>:<port> in {key}, got {entry}"
+            )));
+        };
+
+        if listeners.iter().any(|l| l.name == listener.name) {
+            return Err(KafkaError::InvalidConfig(format!(
+                "{key} names listener {} more than once",
+                listener.name
+            )));
+        }
+        listeners.push(listener);
+    }
+
+    Ok(listeners)
+}
+
+// `<id>@<host>:<port>` entries, only the ids are kept. nothing connects to the other voters
+// yet, they only make this node wait to be told who leads
+fn parse_quorum_voters(value: &str) -> Result<Vec<i32>, KafkaError> {
+    let mut voters = vec![];
+
+    for entry in value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        let parsed = entry.split_once('@').and_then(|(id, address)| {
+            let (_, port) = address.rsplit_once(':')?;
+            port.parse::<u16>().ok()?;
+            id.parse::<i32>().ok().filter(|id| *id >= 0)
+        });
+        let Some(voter) = parsed else {
+            return Err(KafkaError::InvalidConfig(format!(
+                "expected <id>@<host>:<port> in controller.quorum.voters, got {entry}"
+            )));
+        };
+
+        if voters.contains(&voter) {
+            return Err(KafkaError::InvalidConfig(format!(
+                "controller.quorum.voters names voter {voter} more than once"
+            )));
+        }
+        voters.push(voter);
+    }
+
+    if voters.is_empty() {
+        return Err(KafkaError::InvalidConfig(
+            "controller.quorum.voters can't be empty".to_string(),
+        ));
+    }
+    Ok(voters)
+}
+
+// pulls the `user_<name>="<password>"` options out of a PlainLoginModule JAAS entry
+fn parse_jaas_users(jaas_config: &str) -> Vec<(String, String)> {
+    let mut users = vec![];
+    let mut rest = jaas_config;
+
+    while let Some(start) = rest.find("user_") {
+        rest = &rest[start + "user_".len()..];
+
+        let Some((name, value)) = rest.split_once('=') else {
+            break;
+        };
+        let Some(value) = value.trim_start().strip_prefix('"') else {
+            continue;
+        };
+        let Some((password, remaining)) = value.split_once('"') else {
+            break;
+        };
+
+        users.push((name.trim().to_string(), password.to_string()));
+        rest = remaining;
+    }
+
+    users
+}
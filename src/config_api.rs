@@ -0,0 +1,567 @@
+use crate::acl::{self, Session, OPERATION_ALTER_CONFIGS, OPERATION_DESCRIBE_CONFIGS};
+use crate::cluster_metadata::MetadataLog;
+use crate::config::{
+    topic_config_def, BrokerConfig, ConfigDef, ConfigType, BROKER_CONFIG_DEFS, TOPIC_CONFIG_DEFS,
+};
+use crate::controller::set_topic_config_overrides;
+use crate::readers::*;
+use crate::topic_config::TopicConfigStore;
+use crate::writers::*;
+use crate::{
+    KafkaError, CLUSTER_AUTHORIZATION_FAILED, INVALID_CONFIG, INVALID_REQUEST,
+    INVALID_TOPIC_EXCEPTION, NONE, TAG_BUFFER, TOPIC_AUTHORIZATION_FAILED,
+};
+
+const RESOURCE_TYPE_TOPIC: i8 = 2;
+const RESOURCE_TYPE_BROKER: i8 = 4;
+
+// config sources as reported by DescribeConfigs
+const DYNAMIC_TOPIC_CONFIG: i8 = 1;
+const STATIC_BROKER_CONFIG: i8 = 4;
+const DEFAULT_CONFIG: i8 = 5;
+
+const OP_SET: i8 = 0;
+const OP_DELETE: i8 = 1;
+const OP_APPEND: i8 = 2;
+const OP_SUBTRACT: i8 = 3;
+
+// ### DESCRIBE CONFIGS (v4) ### //
+pub struct DescribeConfigsRequest {
+    pub resources: Vec<DescribeConfigsResource>,
+    pub include_synonyms: bool,
+    pub include_documentation: bool,
+}
+
+pub struct DescribeConfigsResource {
+    pub resource_type: i8,
+    pub resource_name: String,
+    // all configs of the resource are described when null
+    pub configuration_keys: Option<Vec<String>>,
+}
+
+impl DescribeConfigsRequest {
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+
+        let resources_size = read_compact_array_len(&mut cursor)?; // [resources]
+        let mut resources = array_with_capacity(resources_size);
+        for _ in 0..resources_size {
+            let resource_type = read_int8(&mut cursor)?;
+            let resource_name = read_compact_string(&mut cursor)?;
+
+            let configuration_keys = match read_compact_nullable_array_len(&mut cursor)? {
+                Some(keys_size) => {
+                    let mut keys = array_with_capacity(keys_size);
+                    for _ in 0..keys_size {
+                        keys.push(read_compact_string(&mut cursor)?);
+                    }
+                    Some(keys)
+                }
+                None => None,
+            };
+
+            read_tagged_fields(&mut cursor)?;
+            resources.push(DescribeConfigsResource {
+                resource_type,
+                resource_name,
+                configuration_keys,
+            });
+        }
+
+        let include_synonyms = read_bool(&mut cursor)?;
+        let include_documentation = read_bool(&mut cursor)?;
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(DescribeConfigsRequest {
+            resources,
+            include_synonyms,
+            include_documentation,
+        })
+    }
+}
+
+pub struct DescribeConfigsResponse {
+    pub throttle_time_ms: i32,
+    pub results: Vec<DescribeConfigsResult>,
+}
+
+pub struct DescribeConfigsResult {
+    pub error_code: i16,
+    pub error_message: Option<String>,
+    pub resource_type: i8,
+    pub resource_name: String,
+    pub configs: Vec<DescribedConfig>,
+}
+
+pub struct DescribedConfig {
+    pub name: String,
+    pub value: Option<String>,
+    pub read_only: bool,
+    pub config_source: i8,
+    pub is_sensitive: bool,
+    pub synonyms: Vec<ConfigSynonym>,
+    pub config_type: i8,
+    pub documentation: Option<String>,
+}
+
+pub struct ConfigSynonym {
+    pub name: String,
+    pub value: Option<String>,
+    pub source: i8,
+}
+
+impl DescribeConfigsResponse {
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.throttle_time_ms.to_be_bytes());
+
+        write_compact_array_len(res_buf, self.results.len()); // [results]
+        for result in &self.results {
+            res_buf.extend_from_slice(&result.error_code.to_be_bytes());
+            write_compact_nullable_string(res_buf, result.error_message.as_deref());
+            res_buf.push(result.resource_type as u8);
+            write_compact_string(res_buf, &result.resource_name);
+
+            write_compact_array_len(res_buf, result.configs.len()); // [configs]
+            for config in &result.configs {
+                write_compact_string(res_buf, &config.name);
+                write_compact_nullable_string(res_buf, config.value.as_deref());
+                res_buf.push(config.read_only as u8);
+                res_buf.push(config.config_source as u8);
+                res_buf.push(config.is_sensitive as u8);
+
+                write_compact_array_len(res_buf, config.synonyms.len()); // [synonyms]
+                for synonym in &config.synonyms {
+                    write_compact_string(res_buf, &synonym.name);
+                    write_compact_nullable_string(res_buf, synonym.value.as_deref());
+                    res_buf.push(synonym.source as u8);
+                    res_buf.extend_from_slice(TAG_BUFFER);
+                }
+
+                res_buf.push(config.config_type as u8);
+                write_compact_nullable_string(res_buf, config.documentation.as_deref());
+                res_buf.extend_from_slice(TAG_BUFFER);
+            }
+
+            res_buf.extend_from_slice(TAG_BUFFER);
+        }
+
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+pub fn describe_configs(
+    config: &BrokerConfig,
+    topic_configs: &TopicConfigStore,
+    session: &Session,
+    request: &DescribeConfigsRequest,
+) -> DescribeConfigsResponse {
+    let results = request
+        .resources
+        .iter()
+        .map(|resource| {
+            let described = authorize_resource(
+                session,
+                OPERATION_DESCRIBE_CONFIGS,
+                resource.resource_type,
+                &resource.resource_name,
+            )
+            .and_then(|()| match resource.resource_type {
+                RESOURCE_TYPE_BROKER => describe_broker(config, &resource.resource_name),
+                RESOURCE_TYPE_TOPIC => describe_topic(topic_configs, &resource.resource_name),
+                resource_type => Err((
+                    INVALID_REQUEST,
+                    format!("unsupported resource type {resource_type}"),
+                )),
+            });
+
+            match described {
+                Ok(entries) => DescribeConfigsResult {
+                    error_code: NONE,
+                    error_message: None,
+                    resource_type: resource.resource_type,
+                    resource_name: resource.resource_name.clone(),
+                    configs: entries
+                        .into_iter()
+                        .filter(|(def, _, _)| match &resource.configuration_keys {
+                            Some(keys) => keys.iter().any(|key| key == def.name),
+                            None => true,
+                        })
+                        .map(|(def, value, source)| described_config(def, value, source, request))
+                        .collect(),
+                },
+                Err((error_code, error_message)) => DescribeConfigsResult {
+                    error_code,
+                    error_message: Some(error_message),
+                    resource_type: resource.resource_type,
+                    resource_name: resource.resource_name.clone(),
+                    configs: vec![],
+                },
+            }
+        })
+        .collect();
+
+    DescribeConfigsResponse {
+        throttle_time_ms: 0,
+        results,
+    }
+}
+
+// (definition, current value, source) of every config of the resource
+type ConfigEntries = Vec<(&'static ConfigDef, Option<String>, i8)>;
+
+fn describe_broker(
+    config: &BrokerConfig,
+    resource_name: &str,
+) -> Result<ConfigEntries, (i16, String)> {
+    validate_broker_name(config, resource_name)?;
+
+    Ok(BROKER_CONFIG_DEFS
+        .iter()
+        .map(|def| match config.get(def.name) {
+            Some(value) => (def, Some(value.to_string()), STATIC_BROKER_CONFIG),
+            None => (def, def.default.map(str::to_string), DEFAULT_CONFIG),
+        })
+        .collect())
+}
+
+// broker configs are guarded by the cluster resource, topic configs by the topic's own
+fn authorize_resource(
+    session: &Session,
+    operation: i8,
+    resource_type: i8,
+    resource_name: &str,
+) -> Result<(), (i16, String)> {
+    let authorized = match resource_type {
+        RESOURCE_TYPE_BROKER => session.authorize_cluster(operation),
+        RESOURCE_TYPE_TOPIC => {
+            session.authorize(operation, acl::RESOURCE_TYPE_TOPIC, resource_name)
+        }
+        _ => true,
+    };
+    match (authorized, resource_type) {
+        (true, _) => Ok(()),
+        (false, RESOURCE_TYPE_BROKER) => Err((
+            CLUSTER_AUTHORIZATION_FAILED,
+            "Not authorized to access the cluster's configs".to_string(),
+        )),
+        (false, _) => Err((
+            TOPIC_AUTHORIZATION_FAILED,
+            format!("Not authorized to access the configs of topic {resource_name}"),
+        )),
+    }
+}
+
+fn validate_broker_name(config: &BrokerConfig, resource_name: &str) -> Result<(), (i16, String)> {
+    match resource_name == config.node_id.to_string() {
+        true => Ok(()),
+        false => Err((
+            INVALID_REQUEST,
+            format!("broker {resource_name} is not this broker"),
+        )),
+    }
+}
+
+fn describe_topic(
+    topic_configs: &TopicConfigStore,
+    resource_name: &str,
+) -> Result<ConfigEntries, (i16, String)> {
+    validate_topic_name(resource_name)?;
+
+    let mut overrides = topic_configs.overrides(resource_name);
+    Ok(TOPIC_CONFIG_DEFS
+        .iter()
+        .map(|def| match overrides.remove(def.name) {
+            Some(value) => (def, Some(value), DYNAMIC_TOPIC_CONFIG),
+            None => (def, def.default.map(str::to_string), DEFAULT_CONFIG),
+        })
+        .collect())
+}
+
+// any topic name is accepted for now, there's no topic registry to check it against
+fn validate_topic_name(resource_name: &str) -> Result<(), (i16, String)> {
+    match resource_name.is_empty() {
+        true => Err((
+            INVALID_TOPIC_EXCEPTION,
+            "topic name must not be empty".to_string(),
+        )),
+        false => Ok(()),
+    }
+}
+
+fn described_config(
+    def: &ConfigDef,
+    value: Option<String>,
+    source: i8,
+    request: &DescribeConfigsRequest,
+) -> DescribedConfig {
+    // sensitive values are never handed out, only whether they're set
+    let value = if def.is_sensitive() { None } else { value };
+
+    let synonyms = match request.include_synonyms {
+        true => vec![ConfigSynonym {
+            name: def.name.to_string(),
+            value: value.clone(),
+            source,
+        }],
+        false => vec![],
+    };
+
+    DescribedConfig {
+        name: def.name.to_string(),
+        value,
+        read_only: def.read_only,
+        config_source: source,
+        is_sensitive: def.is_sensitive(),
+        synonyms,
+        config_type: def.config_type as i8,
+        documentation: request
+            .include_documentation
+            .then(|| def.documentation.to_string()),
+    }
+}
+
+// ### INCREMENTAL ALTER CONFIGS (v1) ### //
+pub struct IncrementalAlterConfigsRequest {
+    pub resources: Vec<AlterConfigsResource>,
+    pub validate_only: bool,
+}
+
+pub struct AlterConfigsResource {
+    pub resource_type: i8,
+    pub resource_name: String,
+    pub configs: Vec<AlterableConfig>,
+}
+
+pub struct AlterableConfig {
+    pub name: String,
+    pub config_operation: i8,
+    pub value: Option<String>,
+}
+
+impl IncrementalAlterConfigsRequest {
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+
+        let resources_size = read_compact_array_len(&mut cursor)?; // [resources]
+        let mut resources = array_with_capacity(resources_size);
+        for _ in 0..resources_size {
+            let resource_type = read_int8(&mut cursor)?;
+            let resource_name = read_compact_string(&mut cursor)?;
+
+            let configs_size = read_compact_array_len(&mut cursor)?; // [configs]
+            let mut configs = array_with_capacity(configs_size);
+            for _ in 0..configs_size {
+                let name = read_compact_string(&mut cursor)?;
+                let config_operation = read_int8(&mut cursor)?;
+                let value = read_compact_nullable_string(&mut cursor)?;
+                read_tagged_fields(&mut cursor)?;
+
+                configs.push(AlterableConfig {
+                    name,
+                    config_operation,
+                    value,
+                });
+            }
+
+            read_tagged_fields(&mut cursor)?;
+            resources.push(AlterConfigsResource {
+                resource_type,
+                resource_name,
+                configs,
+            });
+        }
+
+        let validate_only = read_bool(&mut cursor)?;
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(IncrementalAlterConfigsRequest {
+            resources,
+            validate_only,
+        })
+    }
+}
+
+pub struct IncrementalAlterConfigsResponse {
+    pub throttle_time_ms: i32,
+    pub responses: Vec<AlterConfigsResourceResponse>,
+}
+
+pub struct AlterConfigsResourceResponse {
+    pub error_code: i16,
+    pub error_message: Option<String>,
+    pub resource_type: i8,
+    pub resource_name: String,
+}
+
+impl IncrementalAlterConfigsResponse {
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.throttle_time_ms.to_be_bytes());
+
+        write_compact_array_len(res_buf, self.responses.len()); // [responses]
+        for response in &self.responses {
+            res_buf.extend_from_slice(&response.error_code.to_be_bytes());
+            write_compact_nullable_string(res_buf, response.error_message.as_deref());
+            res_buf.push(response.resource_type as u8);
+            write_compact_string(res_buf, &response.resource_name);
+            res_buf.extend_from_slice(TAG_BUFFER);
+        }
+
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+pub fn incremental_alter_configs(
+    config: &BrokerConfig,
+    topic_configs: &TopicConfigStore,
+    metadata_log: &MetadataLog,
+    session: &Session,
+    request: &IncrementalAlterConfigsRequest,
+) -> IncrementalAlterConfigsResponse {
+    let responses = request
+        .resources
+        .iter()
+        .map(|resource| {
+            let result = authorize_resource(
+                session,
+                OPERATION_ALTER_CONFIGS,
+                resource.resource_type,
+                &resource.resource_name,
+            )
+            .and_then(|()| match resource.resource_type {
+                RESOURCE_TYPE_BROKER => validate_broker_name(config, &resource.resource_name).and(
+                    Err((INVALID_REQUEST, "broker configs are read-only".to_string())),
+                ),
+                RESOURCE_TYPE_TOPIC => {
+                    alter_topic(topic_configs, metadata_log, resource, request.validate_only)
+                }
+                resource_type => Err((
+                    INVALID_REQUEST,
+                    format!("unsupported resource type {resource_type}"),
+                )),
+            });
+
+            let (error_code, error_message) = match result {
+                Ok(()) => (NONE, None),
+                Err((error_code, error_message)) => (error_code, Some(error_message)),
+            };
+            AlterConfigsResourceResponse {
+                error_code,
+                error_message,
+                resource_type: resource.resource_type,
+                resource_name: resource.resource_name.clone(),
+            }
+        })
+        .collect();
+
+    IncrementalAlterConfigsResponse {
+        throttle_time_ms: 0,
+        responses,
+    }
+}
+
+// all operations of a resource are applied together or not at all
+fn alter_topic(
+    topic_configs: &TopicConfigStore,
+    metadata_log: &MetadataLog,
+    resource: &AlterConfigsResource,
+    validate_only: bool,
+) -> Result<(), (i16, String)> {
+    validate_topic_name(&resource.resource_name)?;
+
+    let mut overrides = topic_configs.overrides(&resource.resource_name);
+    for (i, alter) in resource.configs.iter().enumerate() {
+        if resource.configs[..i]
+            .iter()
+            .any(|other| other.name == alter.name)
+        {
+            return Err((
+                INVALID_REQUEST,
+                format!("{} is altered more than once", alter.name),
+            ));
+        }
+
+        let Some(def) = topic_config_def(&alter.name) else {
+            return Err((
+                INVALID_CONFIG,
+                format!("unknown topic config {}", alter.name),
+            ));
+        };
+
+        let value = match (alter.config_operation, &alter.value) {
+            (OP_DELETE, _) => {
+                overrides.remove(def.name);
+                continue;
+            }
+            (OP_SET | OP_APPEND | OP_SUBTRACT, None) => {
+                return Err((
+                    INVALID_REQUEST,
+                    format!("a value is required to alter {}", def.name),
+                ));
+            }
+            (OP_SET, Some(value)) => value.clone(),
+            (OP_APPEND | OP_SUBTRACT, Some(value)) => {
+                if def.config_type != ConfigType::List {
+                    return Err((
+                        INVALID_CONFIG,
+                        format!(
+                            "{} is not a list, it can't be appended to or subtracted from",
+                            def.name
+                        ),
+                    ));
+                }
+
+                let current = overrides
+                    .get(def.name)
+                    .map(String::as_str)
+                    .or(def.default)
+                    .unwrap_or_default();
+                let mut items = list_items(current);
+                let changed = list_items(value);
+
+                match alter.config_operation {
+                    OP_APPEND => {
+                        for item in changed {
+                            if !items.contains(&item) {
+                                items.push(item);
+                            }
+                        }
+                    }
+                    _ => items.retain(|item| !changed.contains(item)),
+                }
+                items.join(",")
+            }
+            (config_operation, _) => {
+                return Err((
+                    INVALID_REQUEST,
+                    format!("unknown config operation {config_operation}"),
+                ));
+            }
+        };
+
+        def.validate(&value)
+            .map_err(|message| (INVALID_CONFIG, message))?;
+        overrides.insert(def.name.to_string(), value);
+    }
+
+    if validate_only {
+        return Ok(());
+    }
+
+    set_topic_config_overrides(
+        metadata_log,
+        topic_configs,
+        &resource.resource_name,
+        overrides,
+    )
+    .map_err(|e| (e.to_error_code(), e.to_string()))
+}
+
+fn list_items(value: &str) -> Vec<&str> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .collect()
+}
@@ -0,0 +1,137 @@
+use crate::records::decode_records;
+use crate::storage::decode_uuid;
+use crate::{FetchRequest, KafkaClient, KafkaError, RequestPartition, RequestTopic, NONE};
+use std::time::Duration;
+
+const DEFAULT_BOOTSTRAP_SERVER: &str = "127.0.0.1:9092";
+// the broker answers fetches right away, so an empty one is retried after this
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+const FETCH_MAX_BYTES: i32 = 1 << 20;
+
+pub const CONSUME_USAGE: &str = "usage: consume --topic-id <uuid> [--partition <n>] \
+[--offset <n> | --from-beginning] [--bootstrap-server <host:port>]";
+
+// `consume` options, there's no Metadata api to look topics up by name so they're given
+// by id (the `topic_id` in the partition's partition.metadata)
+pub struct ConsumeOptions {
+    pub bootstrap_server: String,
+    pub topic_id: i128,
+    pub partition: i32,
+    pub offset: i64,
+}
+
+impl ConsumeOptions {
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut bootstrap_server = DEFAULT_BOOTSTRAP_SERVER.to_string();
+        let mut topic_id = None;
+        let mut partition = 0;
+        let mut offset = 0;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{arg} expects a value"));
+            match arg.as_str() {
+                "--bootstrap-server" => bootstrap_server = value()?.clone(),
+                "--topic-id" => {
+                    let value = value()?;
+                    topic_id = Some(
+                        decode_uuid(value).ok_or_else(|| format!("invalid topic id {value}"))?,
+                    );
+                }
+                "--partition" => {
+                    let value = value()?;
+                    partition = value
+                        .parse()
+                        .map_err(|_| format!("invalid partition {value}"))?;
+                }
+                "--offset" => {
+                    let value = value()?;
+                    offset = value
+                        .parse()
+                        .map_err(|_| format!("invalid offset {value}"))?;
+                }
+                // offsets start at 0 unless retention trimmed the log
+                "--from-beginning" => offset = 0,
+                other => return Err(format!("unknown option {other}")),
+            }
+        }
+
+        Ok(ConsumeOptions {
+            bootstrap_server,
+            topic_id: topic_id.ok_or("--topic-id is required")?,
+            partition,
+            offset,
+        })
+    }
+}
+
+// prints each record's value on its own line until interrupted, like
+// kafka-console-consumer.sh
+pub async fn consume(options: &ConsumeOptions) -> Result<(), KafkaError> {
+    let mut client = KafkaClient::connect(&options.bootstrap_server, "console-consumer").await?;
+    let mut offset = options.offset;
+
+    loop {
+        let response = client
+            .fetch(&FetchRequest {
+                correlation_id: 0,
+                max_wait_ms: 0,
+                min_bytes: 1,
+                max_bytes: FETCH_MAX_BYTES,
+                isolation_level: 0,
+                session_id: 0,
+                session_epoch: -1,
+                topics: vec![RequestTopic {
+                    topic_id: options.topic_id,
+                    partitions: vec![RequestPartition {
+                        partition: options.partition,
+                        current_leader_epoch: -1,
+                        fetch_offset: offset,
+                        last_fetched_epoch: -1,
+                        log_start_offset: -1,
+                        partition_max_bytes: FETCH_MAX_BYTES,
+                    }],
+                }],
+                forgotten_topics: vec![],
+                rack_id: String::new(),
+                replica_id: -1,
+                replica_epoch: -1,
+            })
+            .await?;
+
+        let partition = response
+            .responses
+            .iter()
+            .flat_map(|topic| &topic.partitions)
+            .find(|partition| partition.partition_index == options.partition)
+            .ok_or_else(|| {
+                KafkaError::CorruptedMessage("fetch response is missing the partition".into())
+            })?;
+        if partition.error_code != NONE {
+            return Err(KafkaError::CorruptedMessage(format!(
+                "fetching offset {offset} failed with error code {}",
+                partition.error_code
+            )));
+        }
+
+        let records = decode_records(partition.records.as_deref().unwrap_or_default())?;
+        let mut consumed = false;
+        // batches are returned whole, so the first may hold records before `offset`
+        let fetch_offset = offset;
+        for record in records
+            .iter()
+            .filter(|record| record.offset >= fetch_offset)
+        {
+            match &record.value {
+                Some(value) => println!("{}", String::from_utf8_lossy(value)),
+                None => println!("null"),
+            }
+            offset = record.offset + 1;
+            consumed = true;
+        }
+
+        if !consumed {
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+}
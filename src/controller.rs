@@ -0,0 +1,64 @@
+use crate::cluster_metadata::{MetadataLog, MetadataRecord};
+use crate::storage::LogStore;
+use crate::topic_config::TopicConfigStore;
+use crate::KafkaError;
+use std::collections::BTreeMap;
+
+// ConfigRecord's resource type for topics, as in DescribeConfigs
+const CONFIG_RESOURCE_TOPIC: i8 = 2;
+
+// the changes this node makes as the cluster's only controller, applied to the broker's own
+// stores and recorded in the metadata log for kraft tooling to read. the log dirs and the
+// topic config store stay what the broker goes by
+
+// blocking file io. a topic whose partitions were created but whose records couldn't be
+// written is still there, it's only missing from the metadata log
+pub fn create_topic(
+    metadata_log: &MetadataLog,
+    logs: &dyn LogStore,
+    node_id: i32,
+    topic: &str,
+    num_partitions: i32,
+) -> Result<i128, i16> {
+    let topic_id = logs.create_topic(topic, num_partitions)?;
+
+    let appended = metadata_log.update(|_| {
+        let records = std::iter::once(MetadataRecord::topic(topic, topic_id))
+            .chain(
+                (0..num_partitions)
+                    .map(|partition| MetadataRecord::partition(topic_id, partition, &[node_id])),
+            )
+            .collect();
+        ((), records)
+    });
+    if let Err(e) = appended {
+        eprintln!("Error recording topic {topic} in the metadata log: {e}");
+    }
+    Ok(topic_id)
+}
+
+// blocking file io. replaces the topic's overrides, with a ConfigRecord for every config that
+// changed. the records go out first, so the overrides aren't changed when they can't be
+pub fn set_topic_config_overrides(
+    metadata_log: &MetadataLog,
+    topic_configs: &TopicConfigStore,
+    topic: &str,
+    overrides: BTreeMap<String, String>,
+) -> Result<(), KafkaError> {
+    let current = topic_configs.overrides(topic);
+    metadata_log.update(|_| {
+        let removed = current
+            .keys()
+            .filter(|name| !overrides.contains_key(*name))
+            .map(|name| MetadataRecord::config(CONFIG_RESOURCE_TOPIC, topic, name, None));
+        let changed = overrides
+            .iter()
+            .filter(|(name, value)| current.get(*name) != Some(*value))
+            .map(|(name, value)| {
+                MetadataRecord::config(CONFIG_RESOURCE_TOPIC, topic, name, Some(value))
+            });
+        ((), removed.chain(changed).collect())
+    })?;
+
+    topic_configs.set_overrides(topic, overrides)
+}
@@ -0,0 +1,88 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// what fault.injection.* asks for. probabilities are per request (per partition for the
+// error codes), from 0 for never to 1 for always
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaultConfig {
+    pub delay_probability: f64,
+    // a delayed response waits anywhere up to this long
+    pub delay_max_ms: u64,
+    pub disconnect_probability: f64,
+    pub truncate_probability: f64,
+    pub partition_error_probability: f64,
+    // one of these is picked for each failed Fetch partition
+    pub partition_error_codes: Vec<i16>,
+}
+
+// what happens to a single response
+#[derive(Debug, Default)]
+pub struct ResponseFaults {
+    pub delay: Option<Duration>,
+    // the connection is closed without a response
+    pub disconnect: bool,
+    // only part of the response goes out before the connection is closed, never the whole
+    // frame
+    pub truncate: bool,
+}
+
+// makes a broker misbehave on purpose, so clients' retry and reconnect logic can be tested
+// against it. the config can be swapped while the broker runs
+pub struct FaultInjector {
+    config: Mutex<FaultConfig>,
+}
+
+impl FaultInjector {
+    pub fn new(config: FaultConfig) -> Arc<Self> {
+        Arc::new(FaultInjector {
+            config: Mutex::new(config),
+        })
+    }
+
+    pub fn config(&self) -> FaultConfig {
+        self.config.lock().unwrap().clone()
+    }
+
+    pub fn set_config(&self, config: FaultConfig) {
+        *self.config.lock().unwrap() = config;
+    }
+
+    pub fn response_faults(&self) -> ResponseFaults {
+        let config = self.config.lock().unwrap();
+        let delay = happens(config.delay_probability).then(|| {
+            let delay_ms = (random() * (config.delay_max_ms + 1) as f64) as u64;
+            Duration::from_millis(delay_ms.min(config.delay_max_ms))
+        });
+
+        ResponseFaults {
+            delay,
+            disconnect: happens(config.disconnect_probability),
+            truncate: happens(config.truncate_probability),
+        }
+    }
+
+    pub fn partition_error(&self) -> Option<i16> {
+        let config = self.config.lock().unwrap();
+        if config.partition_error_codes.is_empty() || !happens(config.partition_error_probability) {
+            return None;
+        }
+        let index = (random() * config.partition_error_codes.len() as f64) as usize;
+        config
+            .partition_error_codes
+            .get(index)
+            .or(config.partition_error_codes.last())
+            .copied()
+    }
+}
+
+fn happens(probability: f64) -> bool {
+    probability > 0.0 && random() < probability
+}
+
+// uniform in [0, 1). every RandomState gets fresh keys, which is random enough for this
+fn random() -> f64 {
+    let bits = RandomState::new().build_hasher().finish() >> 11;
+    bits as f64 / (1u64 << 53) as f64
+}
@@ -0,0 +1,24 @@
+use crate::replica_selector::ClientMetadata;
+
+// sees each partition's records on their way out in a Fetch response, and can replace them,
+// e.g. for a test harness embedding the broker to simulate broker-side filtering.
+// `rewrite_records` re-encodes the batches for interceptors that drop or change records
+pub trait FetchInterceptor: Send + Sync {
+    // `records` are the whole batches read for the partition, what's returned goes out in
+    // their place, or the error code the partition gets instead. the fetch's byte limits were
+    // applied to the records as read, so they aren't enforced again on what's returned
+    fn intercept(
+        &self,
+        _topic_id: i128,
+        _partition: i32,
+        _client: &ClientMetadata,
+        records: Vec<u8>,
+    ) -> Result<Vec<u8>, i16> {
+        Ok(records)
+    }
+}
+
+// sends the records exactly as they were read
+pub struct NoopFetchInterceptor;
+
+impl FetchInterceptor for NoopFetchInterceptor {}
@@ -0,0 +1,449 @@
+use crate::readers::*;
+use crate::writers::*;
+use crate::{KafkaError, TAG_BUFFER};
+
+// ### JOIN GROUP (v9) ### //
+pub struct JoinGroupRequest {
+    pub group_id: String,
+    pub session_timeout_ms: i32,
+    pub rebalance_timeout_ms: i32,
+    pub member_id: String,
+    pub group_instance_id: Option<String>,
+    pub protocol_type: String,
+    pub protocols: Vec<JoinGroupProtocol>,
+    pub reason: Option<String>,
+}
+
+#[derive(PartialEq, Eq)]
+pub struct JoinGroupProtocol {
+    pub name: String,
+    pub metadata: Vec<u8>,
+}
+
+impl JoinGroupRequest {
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+
+        let group_id = read_compact_string(&mut cursor)?;
+        let session_timeout_ms = read_int32(&mut cursor)?;
+        let rebalance_timeout_ms = read_int32(&mut cursor)?;
+        let member_id = read_compact_string(&mut cursor)?;
+        let group_instance_id = read_compact_nullable_string(&mut cursor)?;
+        let protocol_type = read_compact_string(&mut cursor)?;
+
+        let protocols_size = read_compact_array_len(&mut cursor)?; // [protocols]
+        let mut protocols = array_with_capacity(protocols_size);
+        for _ in 0..protocols_size {
+            let name = read_compact_string(&mut cursor)?;
+            let metadata = read_compact_bytes(&mut cursor)?;
+            read_tagged_fields(&mut cursor)?;
+
+            protocols.push(JoinGroupProtocol { name, metadata });
+        }
+
+        let reason = read_compact_nullable_string(&mut cursor)?;
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(JoinGroupRequest {
+            group_id,
+            session_timeout_ms,
+            rebalance_timeout_ms,
+            member_id,
+            group_instance_id,
+            protocol_type,
+            protocols,
+            reason,
+        })
+    }
+}
+
+pub struct JoinGroupResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub generation_id: i32,
+    pub protocol_type: Option<String>,
+    pub protocol_name: Option<String>,
+    pub leader: String,
+    pub skip_assignment: bool,
+    pub member_id: String,
+    pub members: Vec<JoinGroupResponseMember>,
+}
+
+#[derive(Clone)]
+pub struct JoinGroupResponseMember {
+    pub member_id: String,
+    pub group_instance_id: Option<String>,
+    pub metadata: Vec<u8>,
+}
+
+impl JoinGroupResponse {
+    pub fn error(error_code: i16, member_id: String) -> Self {
+        JoinGroupResponse {
+            throttle_time_ms: 0,
+            error_code,
+            generation_id: -1,
+            protocol_type: None,
+            protocol_name: None,
+            leader: String::new(),
+            skip_assignment: false,
+            member_id,
+            members: vec![],
+        }
+    }
+
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.throttle_time_ms.to_be_bytes());
+        res_buf.extend_from_slice(&self.error_code.to_be_bytes());
+        res_buf.extend_from_slice(&self.generation_id.to_be_bytes());
+        write_compact_nullable_string(res_buf, self.protocol_type.as_deref());
+        write_compact_nullable_string(res_buf, self.protocol_name.as_deref());
+        write_compact_string(res_buf, &self.leader);
+        res_buf.push(self.skip_assignment as u8);
+        write_compact_string(res_buf, &self.member_id);
+
+        write_compact_array_len(res_buf, self.members.len()); // [members]
+        for member in &self.members {
+            write_compact_string(res_buf, &member.member_id);
+            write_compact_nullable_string(res_buf, member.group_instance_id.as_deref());
+            write_compact_bytes(res_buf, &member.metadata);
+            res_buf.extend_from_slice(TAG_BUFFER);
+        }
+
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+// ### SYNC GROUP (v5) ### //
+pub struct SyncGroupRequest {
+    pub group_id: String,
+    pub generation_id: i32,
+    pub member_id: String,
+    pub group_instance_id: Option<String>,
+    pub protocol_type: Option<String>,
+    pub protocol_name: Option<String>,
+    pub assignments: Vec<SyncGroupAssignment>,
+}
+
+pub struct SyncGroupAssignment {
+    pub member_id: String,
+    pub assignment: Vec<u8>,
+}
+
+impl SyncGroupRequest {
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+
+        let group_id = read_compact_string(&mut cursor)?;
+        let generation_id = read_int32(&mut cursor)?;
+        let member_id = read_compact_string(&mut cursor)?;
+        let group_instance_id = read_compact_nullable_string(&mut cursor)?;
+        let protocol_type = read_compact_nullable_string(&mut cursor)?;
+        let protocol_name = read_compact_nullable_string(&mut cursor)?;
+
+        let assignments_size = read_compact_array_len(&mut cursor)?; // [assignments]
+        let mut assignments = array_with_capacity(assignments_size);
+        for _ in 0..assignments_size {
+            let member_id = read_compact_string(&mut cursor)?;
+            let assignment = read_compact_bytes(&mut cursor)?;
+            read_tagged_fields(&mut cursor)?;
+
+            assignments.push(SyncGroupAssignment {
+                member_id,
+                assignment,
+            });
+        }
+
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(SyncGroupRequest {
+            group_id,
+            generation_id,
+            member_id,
+            group_instance_id,
+            protocol_type,
+            protocol_name,
+            assignments,
+        })
+    }
+}
+
+pub struct SyncGroupResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub protocol_type: Option<String>,
+    pub protocol_name: Option<String>,
+    pub assignment: Vec<u8>,
+}
+
+impl SyncGroupResponse {
+    pub fn error(error_code: i16) -> Self {
+        SyncGroupResponse {
+            throttle_time_ms: 0,
+            error_code,
+            protocol_type: None,
+            protocol_name: None,
+            assignment: vec![],
+        }
+    }
+
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.throttle_time_ms.to_be_bytes());
+        res_buf.extend_from_slice(&self.error_code.to_be_bytes());
+        write_compact_nullable_string(res_buf, self.protocol_type.as_deref());
+        write_compact_nullable_string(res_buf, self.protocol_name.as_deref());
+        write_compact_bytes(res_buf, &self.assignment);
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+// ### HEARTBEAT (v4) ### //
+pub struct HeartbeatRequest {
+    pub group_id: String,
+    pub generation_id: i32,
+    pub member_id: String,
+    pub group_instance_id: Option<String>,
+}
+
+impl HeartbeatRequest {
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+
+        let group_id = read_compact_string(&mut cursor)?;
+        let generation_id = read_int32(&mut cursor)?;
+        let member_id = read_compact_string(&mut cursor)?;
+        let group_instance_id = read_compact_nullable_string(&mut cursor)?;
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(HeartbeatRequest {
+            group_id,
+            generation_id,
+            member_id,
+            group_instance_id,
+        })
+    }
+}
+
+pub struct HeartbeatResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+}
+
+impl HeartbeatResponse {
+    pub fn new(error_code: i16) -> Self {
+        HeartbeatResponse {
+            throttle_time_ms: 0,
+            error_code,
+        }
+    }
+
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.throttle_time_ms.to_be_bytes());
+        res_buf.extend_from_slice(&self.error_code.to_be_bytes());
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+// ### LEAVE GROUP (v5) ### //
+pub struct LeaveGroupRequest {
+    pub group_id: String,
+    pub members: Vec<LeaveGroupMember>,
+}
+
+pub struct LeaveGroupMember {
+    pub member_id: String,
+    pub group_instance_id: Option<String>,
+    pub reason: Option<String>,
+}
+
+impl LeaveGroupRequest {
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+
+        let group_id = read_compact_string(&mut cursor)?;
+
+        let members_size = read_compact_array_len(&mut cursor)?; // [members]
+        let mut members = array_with_capacity(members_size);
+        for _ in 0..members_size {
+            let member_id = read_compact_string(&mut cursor)?;
+            let group_instance_id = read_compact_nullable_string(&mut cursor)?;
+            let reason = read_compact_nullable_string(&mut cursor)?;
+            read_tagged_fields(&mut cursor)?;
+
+            members.push(LeaveGroupMember {
+                member_id,
+                group_instance_id,
+                reason,
+            });
+        }
+
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(LeaveGroupRequest { group_id, members })
+    }
+}
+
+pub struct LeaveGroupResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub members: Vec<LeaveGroupMemberResponse>,
+}
+
+pub struct LeaveGroupMemberResponse {
+    pub member_id: String,
+    pub group_instance_id: Option<String>,
+    pub error_code: i16,
+}
+
+impl LeaveGroupResponse {
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.throttle_time_ms.to_be_bytes());
+        res_buf.extend_from_slice(&self.error_code.to_be_bytes());
+
+        write_compact_array_len(res_buf, self.members.len()); // [members]
+        for member in &self.members {
+            write_compact_string(res_buf, &member.member_id);
+            write_compact_nullable_string(res_buf, member.group_instance_id.as_deref());
+            res_buf.extend_from_slice(&member.error_code.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+        }
+
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+// ### LIST GROUPS (v4) ### //
+pub struct ListGroupsRequest {
+    pub states_filter: Vec<String>,
+}
+
+impl ListGroupsRequest {
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+
+        let states_size = read_compact_array_len(&mut cursor)?; // [states_filter]
+        let mut states_filter = array_with_capacity(states_size);
+        for _ in 0..states_size {
+            states_filter.push(read_compact_string(&mut cursor)?);
+        }
+
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(ListGroupsRequest { states_filter })
+    }
+}
+
+pub struct ListGroupsResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub groups: Vec<ListedGroup>,
+}
+
+pub struct ListedGroup {
+    pub group_id: String,
+    pub protocol_type: String,
+    pub group_state: String,
+}
+
+impl ListGroupsResponse {
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.throttle_time_ms.to_be_bytes());
+        res_buf.extend_from_slice(&self.error_code.to_be_bytes());
+
+        write_compact_array_len(res_buf, self.groups.len()); // [groups]
+        for group in &self.groups {
+            write_compact_string(res_buf, &group.group_id);
+            write_compact_string(res_buf, &group.protocol_type);
+            write_compact_string(res_buf, &group.group_state);
+            res_buf.extend_from_slice(TAG_BUFFER);
+        }
+
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+// ### DESCRIBE GROUPS (v5) ### //
+pub struct DescribeGroupsRequest {
+    pub groups: Vec<String>,
+    pub include_authorized_operations: bool,
+}
+
+impl DescribeGroupsRequest {
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+
+        let groups_size = read_compact_array_len(&mut cursor)?; // [groups]
+        let mut groups = array_with_capacity(groups_size);
+        for _ in 0..groups_size {
+            groups.push(read_compact_string(&mut cursor)?);
+        }
+
+        let include_authorized_operations = read_bool(&mut cursor)?;
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(DescribeGroupsRequest {
+            groups,
+            include_authorized_operations,
+        })
+    }
+}
+
+pub struct DescribeGroupsResponse {
+    pub throttle_time_ms: i32,
+    pub groups: Vec<DescribedGroup>,
+}
+
+pub struct DescribedGroup {
+    pub error_code: i16,
+    pub group_id: String,
+    pub group_state: String,
+    pub protocol_type: String,
+    pub protocol_data: String,
+    pub members: Vec<DescribedGroupMember>,
+    pub authorized_operations: i32,
+}
+
+pub struct DescribedGroupMember {
+    pub member_id: String,
+    pub group_instance_id: Option<String>,
+    pub client_id: String,
+    pub client_host: String,
+    pub member_metadata: Vec<u8>,
+    pub member_assignment: Vec<u8>,
+}
+
+impl DescribeGroupsResponse {
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.throttle_time_ms.to_be_bytes());
+
+        write_compact_array_len(res_buf, self.groups.len()); // [groups]
+        for group in &self.groups {
+            res_buf.extend_from_slice(&group.error_code.to_be_bytes());
+            write_compact_string(res_buf, &group.group_id);
+            write_compact_string(res_buf, &group.group_state);
+            write_compact_string(res_buf, &group.protocol_type);
+            write_compact_string(res_buf, &group.protocol_data);
+
+            write_compact_array_len(res_buf, group.members.len()); // [members]
+            for member in &group.members {
+                write_compact_string(res_buf, &member.member_id);
+                write_compact_nullable_string(res_buf, member.group_instance_id.as_deref());
+                write_compact_string(res_buf, &member.client_id);
+                write_compact_string(res_buf, &member.client_host);
+                write_compact_bytes(res_buf, &member.member_metadata);
+                write_compact_bytes(res_buf, &member.member_assignment);
+                res_buf.extend_from_slice(TAG_BUFFER);
+            }
+
+            res_buf.extend_from_slice(&group.authorized_operations.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+        }
+
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
@@ -0,0 +1,1459 @@
+use crate::acl::{
+    Session, OPERATION_DELETE, OPERATION_DESCRIBE, OPERATION_READ, RESOURCE_TYPE_GROUP,
+    RESOURCE_TYPE_TOPIC,
+};
+use crate::group_api::*;
+use crate::offset_api::*;
+use crate::{
+    FENCED_INSTANCE_ID, GROUP_AUTHORIZATION_FAILED, ILLEGAL_GENERATION,
+    INCONSISTENT_GROUP_PROTOCOL, INVALID_GROUP_ID, INVALID_SESSION_TIMEOUT, MEMBER_ID_REQUIRED,
+    NONE, OFFSET_METADATA_TOO_LARGE, REBALANCE_IN_PROGRESS, TOPIC_AUTHORIZATION_FAILED,
+    UNKNOWN_MEMBER_ID,
+};
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{BuildHasher, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+// broker defaults for group.min.session.timeout.ms / group.max.session.timeout.ms
+const MIN_SESSION_TIMEOUT_MS: i32 = 6_000;
+const MAX_SESSION_TIMEOUT_MS: i32 = 1_800_000;
+// the operations kafka reports in a described group's authorized_operations
+const GROUP_OPERATIONS: &[i8] = &[OPERATION_READ, OPERATION_DELETE, OPERATION_DESCRIBE];
+// broker defaults for offsets.retention.minutes / offset.metadata.max.bytes
+const OFFSETS_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+const OFFSET_METADATA_MAX_BYTES: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupState {
+    Empty,
+    PreparingRebalance,
+    CompletingRebalance,
+    Stable,
+}
+
+impl GroupState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GroupState::Empty => "Empty",
+            GroupState::PreparingRebalance => "PreparingRebalance",
+            GroupState::CompletingRebalance => "CompletingRebalance",
+            GroupState::Stable => "Stable",
+        }
+    }
+}
+
+struct Member {
+    member_id: String,
+    group_instance_id: Option<String>,
+    client_id: String,
+    client_host: String,
+    session_timeout_ms: i32,
+    rebalance_timeout_ms: i32,
+    protocols: Vec<JoinGroupProtocol>,
+    assignment: Vec<u8>,
+    last_heartbeat: Instant,
+    awaiting_join: Option<oneshot::Sender<JoinGroupResponse>>,
+    awaiting_sync: Option<oneshot::Sender<SyncGroupResponse>>,
+}
+
+impl Member {
+    fn supports_protocol(&self, name: &str) -> bool {
+        self.protocols.iter().any(|protocol| protocol.name == name)
+    }
+
+    fn metadata(&self, protocol_name: &str) -> Vec<u8> {
+        self.protocols
+            .iter()
+            .find(|protocol| protocol.name == protocol_name)
+            .map(|protocol| protocol.metadata.clone())
+            .unwrap_or_default()
+    }
+}
+
+struct CommittedOffset {
+    offset: i64,
+    leader_epoch: i32,
+    metadata: String,
+    commit_timestamp: Instant,
+}
+
+struct Group {
+    state: GroupState,
+    // when the group last became empty, offsets only start expiring from here (KIP-211)
+    empty_since: Instant,
+    generation_id: i32,
+    protocol_type: Option<String>,
+    protocol_name: Option<String>,
+    leader_id: Option<String>,
+    members: BTreeMap<String, Member>,
+    // members handed an id through MEMBER_ID_REQUIRED that haven't rejoined yet
+    pending_members: HashSet<String>,
+    // group.instance.id -> member id of the static members (KIP-345)
+    static_members: HashMap<String, String>,
+    // bumped on every rebalance so stale rebalance timers can tell they're outdated
+    rebalance_epoch: u64,
+    offsets: BTreeMap<(String, i32), CommittedOffset>,
+}
+
+impl Group {
+    fn new() -> Self {
+        Group {
+            state: GroupState::Empty,
+            empty_since: Instant::now(),
+            generation_id: 0,
+            protocol_type: None,
+            protocol_name: None,
+            leader_id: None,
+            members: BTreeMap::new(),
+            pending_members: HashSet::new(),
+            static_members: HashMap::new(),
+            rebalance_epoch: 0,
+            offsets: BTreeMap::new(),
+        }
+    }
+
+    fn is_protocol_compatible(&self, protocol_type: &str, protocols: &[JoinGroupProtocol]) -> bool {
+        if self.members.is_empty() {
+            return !protocol_type.is_empty() && !protocols.is_empty();
+        }
+
+        self.protocol_type.as_deref() == Some(protocol_type)
+            && protocols.iter().any(|protocol| {
+                self.members
+                    .values()
+                    .all(|member| member.supports_protocol(&protocol.name))
+            })
+    }
+
+    // every member votes for its most preferred protocol out of the ones all members support
+    fn select_protocol(&self) -> Option<String> {
+        let mut votes: Vec<(&str, usize)> = vec![];
+
+        for member in self.members.values() {
+            let candidate = member.protocols.iter().find(|protocol| {
+                self.members
+                    .values()
+                    .all(|other| other.supports_protocol(&protocol.name))
+            });
+
+            if let Some(candidate) = candidate {
+                match votes.iter_mut().find(|(name, _)| *name == candidate.name) {
+                    Some((_, count)) => *count += 1,
+                    None => votes.push((&candidate.name, 1)),
+                }
+            }
+        }
+
+        votes
+            .into_iter()
+            .max_by_key(|(_, count)| *count)
+            .map(|(name, _)| name.to_string())
+    }
+
+    fn rebalance_timeout(&self) -> Duration {
+        let timeout_ms = self
+            .members
+            .values()
+            .map(|member| member.rebalance_timeout_ms)
+            .max()
+            .unwrap_or(0);
+
+        Duration::from_millis(timeout_ms.max(0) as u64)
+    }
+
+    fn expire_offsets(&mut self) {
+        if self.state != GroupState::Empty {
+            return;
+        }
+
+        let empty_since = self.empty_since;
+        self.offsets.retain(|_, committed| {
+            committed.commit_timestamp.max(empty_since).elapsed() < OFFSETS_RETENTION
+        });
+    }
+
+    // a static member's requests have to come from the member id its instance id
+    // currently maps to, older incarnations are fenced
+    fn validate_instance(&self, member_id: &str, group_instance_id: Option<&str>) -> i16 {
+        let Some(group_instance_id) = group_instance_id else {
+            return NONE;
+        };
+
+        match self.static_members.get(group_instance_id) {
+            Some(current) if current == member_id => NONE,
+            Some(_) => FENCED_INSTANCE_ID,
+            None => UNKNOWN_MEMBER_ID,
+        }
+    }
+
+    fn remove_member(&mut self, member_id: &str) -> Option<Member> {
+        let member = self.members.remove(member_id)?;
+        if let Some(group_instance_id) = &member.group_instance_id {
+            if self
+                .static_members
+                .get(group_instance_id)
+                .map(String::as_str)
+                == Some(member_id)
+            {
+                self.static_members.remove(group_instance_id);
+            }
+        }
+        Some(member)
+    }
+
+    fn all_members_joined(&self) -> bool {
+        self.pending_members.is_empty()
+            && self
+                .members
+                .values()
+                .all(|member| member.awaiting_join.is_some())
+    }
+}
+
+#[derive(Default)]
+pub struct GroupCoordinator {
+    groups: Mutex<HashMap<String, Group>>,
+}
+
+impl GroupCoordinator {
+    pub fn new() -> Arc<Self> {
+        Arc::new(GroupCoordinator::default())
+    }
+
+    pub(crate) async fn join_group(
+        self: &Arc<Self>,
+        session: &Session,
+        client_id: &str,
+        client_host: &str,
+        request: JoinGroupRequest,
+    ) -> JoinGroupResponse {
+        if !session.authorize(OPERATION_READ, RESOURCE_TYPE_GROUP, &request.group_id) {
+            return JoinGroupResponse::error(GROUP_AUTHORIZATION_FAILED, request.member_id);
+        }
+        if request.group_id.is_empty() {
+            return JoinGroupResponse::error(INVALID_GROUP_ID, request.member_id);
+        }
+
+        if !(MIN_SESSION_TIMEOUT_MS..=MAX_SESSION_TIMEOUT_MS).contains(&request.session_timeout_ms)
+        {
+            return JoinGroupResponse::error(INVALID_SESSION_TIMEOUT, request.member_id);
+        }
+
+        let receiver = {
+            let mut groups = self.groups.lock().unwrap();
+
+            if !request.member_id.is_empty() && !groups.contains_key(&request.group_id) {
+                return JoinGroupResponse::error(UNKNOWN_MEMBER_ID, request.member_id);
+            }
+
+            let group = groups
+                .entry(request.group_id.clone())
+                .or_insert_with(Group::new);
+
+            if !group.is_protocol_compatible(&request.protocol_type, &request.protocols) {
+                return JoinGroupResponse::error(INCONSISTENT_GROUP_PROTOCOL, request.member_id);
+            }
+
+            let static_member_id = request
+                .group_instance_id
+                .as_ref()
+                .and_then(|group_instance_id| group.static_members.get(group_instance_id))
+                .cloned();
+
+            let member_id = match (&request.group_instance_id, static_member_id) {
+                // a known static member restarting, it takes over its old membership
+                (Some(_), Some(old_member_id)) if request.member_id.is_empty() => {
+                    let member_id = generate_member_id(client_id);
+                    replace_static_member(group, &old_member_id, &member_id);
+                    self.schedule_session_expiry(&request.group_id, &member_id);
+
+                    let member = group.members.get_mut(&member_id).unwrap();
+                    member.client_id = client_id.to_string();
+                    member.client_host = client_host.to_string();
+                    member.session_timeout_ms = request.session_timeout_ms;
+                    member.rebalance_timeout_ms = request.rebalance_timeout_ms;
+                    member.last_heartbeat = Instant::now();
+
+                    // with nothing changed the group carries on without a rebalance
+                    if group.state == GroupState::Stable && member.protocols == request.protocols {
+                        return static_rejoin_response(group, &member_id);
+                    }
+                    member_id
+                }
+                // static members are known by their instance id, so they skip MEMBER_ID_REQUIRED
+                (Some(group_instance_id), None) if request.member_id.is_empty() => {
+                    let member_id = generate_member_id(client_id);
+                    group
+                        .static_members
+                        .insert(group_instance_id.clone(), member_id.clone());
+                    self.schedule_session_expiry(&request.group_id, &member_id);
+                    member_id
+                }
+                (Some(_), current) => {
+                    match current {
+                        Some(current) if current == request.member_id => {}
+                        Some(_) => {
+                            return JoinGroupResponse::error(FENCED_INSTANCE_ID, request.member_id)
+                        }
+                        None => {
+                            return JoinGroupResponse::error(UNKNOWN_MEMBER_ID, request.member_id)
+                        }
+                    }
+                    request.member_id.clone()
+                }
+                // new members get an id assigned first and have to rejoin with it (KIP-394)
+                (None, _) if request.member_id.is_empty() => {
+                    let member_id = generate_member_id(client_id);
+                    group.pending_members.insert(member_id.clone());
+                    self.schedule_pending_member_expiry(
+                        &request.group_id,
+                        &member_id,
+                        request.session_timeout_ms,
+                    );
+                    return JoinGroupResponse::error(MEMBER_ID_REQUIRED, member_id);
+                }
+                (None, _) => {
+                    let is_pending = group.pending_members.remove(&request.member_id);
+                    if !is_pending && !group.members.contains_key(&request.member_id) {
+                        return JoinGroupResponse::error(UNKNOWN_MEMBER_ID, request.member_id);
+                    }
+                    if is_pending {
+                        self.schedule_session_expiry(&request.group_id, &request.member_id);
+                    }
+                    request.member_id.clone()
+                }
+            };
+
+            let (sender, receiver) = oneshot::channel();
+            let member = group
+                .members
+                .entry(member_id.clone())
+                .or_insert_with(|| Member {
+                    member_id: member_id.clone(),
+                    group_instance_id: request.group_instance_id.clone(),
+                    client_id: client_id.to_string(),
+                    client_host: client_host.to_string(),
+                    session_timeout_ms: request.session_timeout_ms,
+                    rebalance_timeout_ms: request.rebalance_timeout_ms,
+                    protocols: vec![],
+                    assignment: vec![],
+                    last_heartbeat: Instant::now(),
+                    awaiting_join: None,
+                    awaiting_sync: None,
+                });
+
+            member.session_timeout_ms = request.session_timeout_ms;
+            member.rebalance_timeout_ms = request.rebalance_timeout_ms;
+            member.protocols = request.protocols;
+            member.last_heartbeat = Instant::now();
+            member.awaiting_join = Some(sender);
+
+            if group.protocol_type.is_none() {
+                group.protocol_type = Some(request.protocol_type);
+            }
+
+            if group.state != GroupState::PreparingRebalance {
+                self.prepare_rebalance(&request.group_id, group);
+            }
+            maybe_complete_join(group);
+
+            receiver
+        };
+
+        receiver
+            .await
+            .unwrap_or_else(|_| JoinGroupResponse::error(UNKNOWN_MEMBER_ID, request.member_id))
+    }
+
+    pub(crate) async fn sync_group(
+        &self,
+        session: &Session,
+        request: SyncGroupRequest,
+    ) -> SyncGroupResponse {
+        if !session.authorize(OPERATION_READ, RESOURCE_TYPE_GROUP, &request.group_id) {
+            return SyncGroupResponse::error(GROUP_AUTHORIZATION_FAILED);
+        }
+        let receiver = {
+            let mut groups = self.groups.lock().unwrap();
+
+            let Some(group) = groups.get_mut(&request.group_id) else {
+                return SyncGroupResponse::error(UNKNOWN_MEMBER_ID);
+            };
+
+            let instance_error =
+                group.validate_instance(&request.member_id, request.group_instance_id.as_deref());
+            if instance_error != NONE {
+                return SyncGroupResponse::error(instance_error);
+            }
+
+            if !group.members.contains_key(&request.member_id) {
+                return SyncGroupResponse::error(UNKNOWN_MEMBER_ID);
+            }
+
+            if request.generation_id != group.generation_id {
+                return SyncGroupResponse::error(ILLEGAL_GENERATION);
+            }
+
+            let protocol_mismatch = |requested: &Option<String>, current: &Option<String>| {
+                requested.is_some() && requested != current
+            };
+            if protocol_mismatch(&request.protocol_type, &group.protocol_type)
+                || protocol_mismatch(&request.protocol_name, &group.protocol_name)
+            {
+                return SyncGroupResponse::error(INCONSISTENT_GROUP_PROTOCOL);
+            }
+
+            match group.state {
+                GroupState::Empty => return SyncGroupResponse::error(UNKNOWN_MEMBER_ID),
+                GroupState::PreparingRebalance => {
+                    return SyncGroupResponse::error(REBALANCE_IN_PROGRESS)
+                }
+                GroupState::Stable => {
+                    let member = &group.members[&request.member_id];
+                    return SyncGroupResponse {
+                        throttle_time_ms: 0,
+                        error_code: NONE,
+                        protocol_type: group.protocol_type.clone(),
+                        protocol_name: group.protocol_name.clone(),
+                        assignment: member.assignment.clone(),
+                    };
+                }
+                GroupState::CompletingRebalance => {}
+            }
+
+            let (sender, receiver) = oneshot::channel();
+            let member = group.members.get_mut(&request.member_id).unwrap();
+            member.awaiting_sync = Some(sender);
+            member.last_heartbeat = Instant::now();
+
+            // only the leader's sync carries the assignments, everyone else waits on it
+            if group.leader_id.as_deref() == Some(request.member_id.as_str()) {
+                let mut assignments: HashMap<String, Vec<u8>> = request
+                    .assignments
+                    .into_iter()
+                    .map(|assignment| (assignment.member_id, assignment.assignment))
+                    .collect();
+
+                for member in group.members.values_mut() {
+                    member.assignment = assignments.remove(&member.member_id).unwrap_or_default();
+                }
+
+                group.state = GroupState::Stable;
+                complete_sync(group);
+            }
+
+            receiver
+        };
+
+        receiver
+            .await
+            .unwrap_or_else(|_| SyncGroupResponse::error(REBALANCE_IN_PROGRESS))
+    }
+
+    pub(crate) fn heartbeat(
+        &self,
+        session: &Session,
+        request: HeartbeatRequest,
+    ) -> HeartbeatResponse {
+        if !session.authorize(OPERATION_READ, RESOURCE_TYPE_GROUP, &request.group_id) {
+            return HeartbeatResponse::new(GROUP_AUTHORIZATION_FAILED);
+        }
+        let mut groups = self.groups.lock().unwrap();
+
+        let Some(group) = groups.get_mut(&request.group_id) else {
+            return HeartbeatResponse::new(UNKNOWN_MEMBER_ID);
+        };
+
+        let instance_error =
+            group.validate_instance(&request.member_id, request.group_instance_id.as_deref());
+        if instance_error != NONE {
+            return HeartbeatResponse::new(instance_error);
+        }
+
+        let Some(member) = group.members.get_mut(&request.member_id) else {
+            return HeartbeatResponse::new(UNKNOWN_MEMBER_ID);
+        };
+
+        if request.generation_id != group.generation_id {
+            return HeartbeatResponse::new(ILLEGAL_GENERATION);
+        }
+
+        member.last_heartbeat = Instant::now();
+
+        match group.state {
+            GroupState::Empty => HeartbeatResponse::new(UNKNOWN_MEMBER_ID),
+            GroupState::PreparingRebalance => HeartbeatResponse::new(REBALANCE_IN_PROGRESS),
+            GroupState::CompletingRebalance | GroupState::Stable => HeartbeatResponse::new(NONE),
+        }
+    }
+
+    pub(crate) fn leave_group(
+        self: &Arc<Self>,
+        session: &Session,
+        request: LeaveGroupRequest,
+    ) -> LeaveGroupResponse {
+        if !session.authorize(OPERATION_READ, RESOURCE_TYPE_GROUP, &request.group_id) {
+            return LeaveGroupResponse {
+                throttle_time_ms: 0,
+                error_code: GROUP_AUTHORIZATION_FAILED,
+                members: vec![],
+            };
+        }
+        let mut groups = self.groups.lock().unwrap();
+        let mut group = groups.get_mut(&request.group_id);
+
+        let members = request
+            .members
+            .into_iter()
+            .map(|leaving| {
+                let Some(group) = group.as_mut() else {
+                    return LeaveGroupMemberResponse {
+                        member_id: leaving.member_id,
+                        group_instance_id: leaving.group_instance_id,
+                        error_code: UNKNOWN_MEMBER_ID,
+                    };
+                };
+
+                // static members may leave by instance id alone, without knowing their member id
+                let mut member_id = leaving.member_id;
+                let mut error_code = NONE;
+                if let Some(group_instance_id) = &leaving.group_instance_id {
+                    match group.static_members.get(group_instance_id) {
+                        Some(current) if member_id.is_empty() || *current == member_id => {
+                            member_id = current.clone()
+                        }
+                        Some(_) => error_code = FENCED_INSTANCE_ID,
+                        None => error_code = UNKNOWN_MEMBER_ID,
+                    }
+                }
+                if error_code == NONE && group.remove_member(&member_id).is_none() {
+                    error_code = UNKNOWN_MEMBER_ID;
+                }
+
+                LeaveGroupMemberResponse {
+                    member_id,
+                    group_instance_id: leaving.group_instance_id,
+                    error_code,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if let Some(group) = group {
+            if members.iter().any(|member| member.error_code == NONE) {
+                self.on_members_removed(&request.group_id, group);
+            }
+        }
+
+        LeaveGroupResponse {
+            throttle_time_ms: 0,
+            error_code: NONE,
+            members,
+        }
+    }
+
+    // DESCRIBE on the cluster lists every group, otherwise only the ones the principal can
+    // describe are
+    pub(crate) fn list_groups(
+        &self,
+        session: &Session,
+        request: ListGroupsRequest,
+    ) -> ListGroupsResponse {
+        let groups = self.groups.lock().unwrap();
+        let describe_all = session.authorize_cluster(OPERATION_DESCRIBE);
+
+        let groups = groups
+            .iter()
+            .filter(|(group_id, _)| {
+                describe_all || session.authorize(OPERATION_DESCRIBE, RESOURCE_TYPE_GROUP, group_id)
+            })
+            .filter(|(_, group)| {
+                request.states_filter.is_empty()
+                    || request
+                        .states_filter
+                        .iter()
+                        .any(|state| state.eq_ignore_ascii_case(group.state.as_str()))
+            })
+            .map(|(group_id, group)| ListedGroup {
+                group_id: group_id.clone(),
+                protocol_type: group.protocol_type.clone().unwrap_or_default(),
+                group_state: group.state.as_str().to_string(),
+            })
+            .collect();
+
+        ListGroupsResponse {
+            throttle_time_ms: 0,
+            error_code: NONE,
+            groups,
+        }
+    }
+
+    pub(crate) fn describe_groups(
+        &self,
+        session: &Session,
+        request: DescribeGroupsRequest,
+    ) -> DescribeGroupsResponse {
+        let groups = self.groups.lock().unwrap();
+
+        let groups = request
+            .groups
+            .into_iter()
+            .map(|group_id| {
+                if !session.authorize(OPERATION_DESCRIBE, RESOURCE_TYPE_GROUP, &group_id) {
+                    return DescribedGroup {
+                        error_code: GROUP_AUTHORIZATION_FAILED,
+                        group_id,
+                        group_state: String::new(),
+                        protocol_type: String::new(),
+                        protocol_data: String::new(),
+                        members: vec![],
+                        authorized_operations: i32::MIN,
+                    };
+                }
+                // i32::MIN when they weren't asked for, like kafka
+                let authorized_operations = match request.include_authorized_operations {
+                    true => session.authorized_operations(
+                        RESOURCE_TYPE_GROUP,
+                        &group_id,
+                        GROUP_OPERATIONS,
+                    ),
+                    false => i32::MIN,
+                };
+
+                let Some(group) = groups.get(&group_id) else {
+                    return DescribedGroup {
+                        error_code: NONE,
+                        group_id,
+                        group_state: "Dead".to_string(),
+                        protocol_type: String::new(),
+                        protocol_data: String::new(),
+                        members: vec![],
+                        authorized_operations,
+                    };
+                };
+
+                let protocol_name = group.protocol_name.clone().unwrap_or_default();
+                // metadata and assignments are only meaningful once the group has settled
+                let is_stable = group.state == GroupState::Stable;
+
+                let members = group
+                    .members
+                    .values()
+                    .map(|member| DescribedGroupMember {
+                        member_id: member.member_id.clone(),
+                        group_instance_id: member.group_instance_id.clone(),
+                        client_id: member.client_id.clone(),
+                        client_host: member.client_host.clone(),
+                        member_metadata: if is_stable {
+                            member.metadata(&protocol_name)
+                        } else {
+                            vec![]
+                        },
+                        member_assignment: if is_stable {
+                            member.assignment.clone()
+                        } else {
+                            vec![]
+                        },
+                    })
+                    .collect();
+
+                DescribedGroup {
+                    error_code: NONE,
+                    group_id,
+                    group_state: group.state.as_str().to_string(),
+                    protocol_type: group.protocol_type.clone().unwrap_or_default(),
+                    protocol_data: protocol_name,
+                    members,
+                    authorized_operations,
+                }
+            })
+            .collect();
+
+        DescribeGroupsResponse {
+            throttle_time_ms: 0,
+            groups,
+        }
+    }
+
+    // READ on the group is needed to commit anything, and READ on each topic to commit to it
+    pub(crate) fn commit_offsets(
+        &self,
+        session: &Session,
+        request: OffsetCommitRequest,
+    ) -> OffsetCommitResponse {
+        let mut groups = self.groups.lock().unwrap();
+        let group_error =
+            match session.authorize(OPERATION_READ, RESOURCE_TYPE_GROUP, &request.group_id) {
+                true => validate_offset_commit(&mut groups, &request),
+                false => GROUP_AUTHORIZATION_FAILED,
+            };
+
+        let group = groups.get_mut(&request.group_id);
+        let mut group = group.filter(|_| group_error == NONE);
+
+        let topics = request
+            .topics
+            .into_iter()
+            .map(|topic| {
+                let topic_denied =
+                    !session.authorize(OPERATION_READ, RESOURCE_TYPE_TOPIC, &topic.name);
+                let partitions = topic
+                    .partitions
+                    .into_iter()
+                    .map(|partition| {
+                        let metadata = partition.committed_metadata.unwrap_or_default();
+                        let error_code = match group.as_mut() {
+                            None => group_error,
+                            Some(_) if topic_denied => TOPIC_AUTHORIZATION_FAILED,
+                            Some(_) if metadata.len() > OFFSET_METADATA_MAX_BYTES => {
+                                OFFSET_METADATA_TOO_LARGE
+                            }
+                            Some(group) => {
+                                group.offsets.insert(
+                                    (topic.name.clone(), partition.partition_index),
+                                    CommittedOffset {
+                                        offset: partition.committed_offset,
+                                        leader_epoch: partition.committed_leader_epoch,
+                                        metadata,
+                                        commit_timestamp: Instant::now(),
+                                    },
+                                );
+                                NONE
+                            }
+                        };
+
+                        OffsetCommitResponsePartition {
+                            partition_index: partition.partition_index,
+                            error_code,
+                        }
+                    })
+                    .collect();
+
+                OffsetCommitResponseTopic {
+                    name: topic.name,
+                    partitions,
+                }
+            })
+            .collect();
+
+        OffsetCommitResponse {
+            throttle_time_ms: 0,
+            topics,
+        }
+    }
+
+    // DESCRIBE on a group is needed to fetch its offsets, and READ on a topic to see the
+    // topic's. fetching every topic leaves the unreadable ones out
+    pub(crate) fn fetch_offsets(
+        &self,
+        session: &Session,
+        request: OffsetFetchRequest,
+    ) -> OffsetFetchResponse {
+        let mut groups = self.groups.lock().unwrap();
+        let can_read = |topic: &str| session.authorize(OPERATION_READ, RESOURCE_TYPE_TOPIC, topic);
+
+        let groups = request
+            .groups
+            .into_iter()
+            .map(|requested| {
+                if !session.authorize(OPERATION_DESCRIBE, RESOURCE_TYPE_GROUP, &requested.group_id)
+                {
+                    return OffsetFetchResponseGroup {
+                        group_id: requested.group_id,
+                        topics: vec![],
+                        error_code: GROUP_AUTHORIZATION_FAILED,
+                    };
+                }
+                let mut group = groups.get_mut(&requested.group_id);
+                if let Some(group) = group.as_mut() {
+                    group.expire_offsets();
+                }
+
+                let lookup = |topic: &str, partition_index: i32| {
+                    let committed = group
+                        .as_ref()
+                        .and_then(|group| group.offsets.get(&(topic.to_string(), partition_index)));
+
+                    OffsetFetchResponsePartition {
+                        partition_index,
+                        committed_offset: committed.map_or(-1, |committed| committed.offset),
+                        committed_leader_epoch: committed
+                            .map_or(-1, |committed| committed.leader_epoch),
+                        metadata: Some(
+                            committed
+                                .map(|committed| committed.metadata.clone())
+                                .unwrap_or_default(),
+                        ),
+                        error_code: NONE,
+                    }
+                };
+
+                let topics = match requested.topics {
+                    Some(topics) => topics
+                        .into_iter()
+                        .map(|topic| {
+                            let readable = can_read(&topic.name);
+                            OffsetFetchResponseTopic {
+                                partitions: topic
+                                    .partition_indexes
+                                    .iter()
+                                    .map(|&partition_index| match readable {
+                                        true => lookup(&topic.name, partition_index),
+                                        false => OffsetFetchResponsePartition {
+                                            partition_index,
+                                            committed_offset: -1,
+                                            committed_leader_epoch: -1,
+                                            metadata: Some(String::new()),
+                                            error_code: TOPIC_AUTHORIZATION_FAILED,
+                                        },
+                                    })
+                                    .collect(),
+                                name: topic.name,
+                            }
+                        })
+                        .collect(),
+                    None => {
+                        let mut topics: Vec<OffsetFetchResponseTopic> = vec![];
+                        let committed = group
+                            .iter()
+                            .flat_map(|group| group.offsets.keys())
+                            .filter(|(name, _)| can_read(name));
+
+                        for (name, partition_index) in committed {
+                            let partition = lookup(name, *partition_index);
+                            match topics.last_mut() {
+                                Some(topic) if topic.name == *name => {
+                                    topic.partitions.push(partition)
+                                }
+                                _ => topics.push(OffsetFetchResponseTopic {
+                                    name: name.clone(),
+                                    partitions: vec![partition],
+                                }),
+                            }
+                        }
+                        topics
+                    }
+                };
+
+                OffsetFetchResponseGroup {
+                    group_id: requested.group_id,
+                    topics,
+                    error_code: NONE,
+                }
+            })
+            .collect();
+
+        OffsetFetchResponse {
+            throttle_time_ms: 0,
+            groups,
+        }
+    }
+
+    // evicts the member once it goes a whole session timeout without a heartbeat. members
+    // waiting on a join or sync response count as alive, they can't heartbeat meanwhile
+    fn schedule_session_expiry(self: &Arc<Self>, group_id: &str, member_id: &str) {
+        let coordinator = Arc::clone(self);
+        let group_id = group_id.to_string();
+        let member_id = member_id.to_string();
+
+        tokio::spawn(async move {
+            let mut deadline = Instant::now();
+            loop {
+                tokio::time::sleep_until(deadline.into()).await;
+
+                let mut groups = coordinator.groups.lock().unwrap();
+                let Some(group) = groups.get_mut(&group_id) else {
+                    return;
+                };
+                let Some(member) = group.members.get_mut(&member_id) else {
+                    return;
+                };
+
+                let now = Instant::now();
+                let session_timeout = Duration::from_millis(member.session_timeout_ms as u64);
+                if member.awaiting_join.is_some() || member.awaiting_sync.is_some() {
+                    member.last_heartbeat = now;
+                }
+
+                deadline = member.last_heartbeat + session_timeout;
+                if deadline > now {
+                    continue;
+                }
+
+                println!("Member {member_id} of group {group_id} missed its session timeout");
+                group.remove_member(&member_id);
+                coordinator.on_members_removed(&group_id, group);
+                return;
+            }
+        });
+    }
+
+    // a member handed an id that never rejoins with it mustn't hold up the rebalance forever
+    fn schedule_pending_member_expiry(
+        self: &Arc<Self>,
+        group_id: &str,
+        member_id: &str,
+        session_timeout_ms: i32,
+    ) {
+        let coordinator = Arc::clone(self);
+        let group_id = group_id.to_string();
+        let member_id = member_id.to_string();
+        let session_timeout = Duration::from_millis(session_timeout_ms as u64);
+
+        tokio::spawn(async move {
+            tokio::time::sleep(session_timeout).await;
+
+            let mut groups = coordinator.groups.lock().unwrap();
+            if let Some(group) = groups.get_mut(&group_id) {
+                if group.pending_members.remove(&member_id) {
+                    maybe_complete_join(group);
+                }
+            }
+        });
+    }
+
+    fn on_members_removed(self: &Arc<Self>, group_id: &str, group: &mut Group) {
+        if group
+            .leader_id
+            .as_ref()
+            .is_some_and(|leader_id| !group.members.contains_key(leader_id))
+        {
+            group.leader_id = None;
+        }
+
+        match group.state {
+            GroupState::Stable | GroupState::CompletingRebalance => {
+                self.prepare_rebalance(group_id, group);
+                maybe_complete_join(group);
+            }
+            GroupState::PreparingRebalance => maybe_complete_join(group),
+            GroupState::Empty => {}
+        }
+    }
+
+    fn prepare_rebalance(self: &Arc<Self>, group_id: &str, group: &mut Group) {
+        // members still waiting on an assignment from the old generation have to rejoin
+        for member in group.members.values_mut() {
+            if let Some(sender) = member.awaiting_sync.take() {
+                let _ = sender.send(SyncGroupResponse::error(REBALANCE_IN_PROGRESS));
+            }
+        }
+
+        group.state = GroupState::PreparingRebalance;
+        group.rebalance_epoch += 1;
+
+        // members that don't rejoin within the rebalance timeout get dropped from the group
+        let coordinator = Arc::clone(self);
+        let group_id = group_id.to_string();
+        let rebalance_epoch = group.rebalance_epoch;
+        let timeout = group.rebalance_timeout();
+
+        tokio::spawn(async move {
+            tokio::time::sleep(timeout).await;
+
+            let mut groups = coordinator.groups.lock().unwrap();
+            if let Some(group) = groups.get_mut(&group_id) {
+                if group.state == GroupState::PreparingRebalance
+                    && group.rebalance_epoch == rebalance_epoch
+                {
+                    complete_join(group);
+                }
+            }
+        });
+    }
+}
+
+fn maybe_complete_join(group: &mut Group) {
+    if group.state == GroupState::PreparingRebalance && group.all_members_joined() {
+        complete_join(group);
+    }
+}
+
+fn complete_join(group: &mut Group) {
+    group
+        .members
+        .retain(|_, member| member.awaiting_join.is_some());
+    let members = &group.members;
+    group
+        .static_members
+        .retain(|_, member_id| members.contains_key(member_id));
+    group.pending_members.clear();
+    group.generation_id += 1;
+
+    if group.members.is_empty() {
+        group.state = GroupState::Empty;
+        group.empty_since = Instant::now();
+        group.protocol_type = None;
+        group.protocol_name = None;
+        group.leader_id = None;
+        return;
+    }
+
+    group.state = GroupState::CompletingRebalance;
+    group.protocol_name = group.select_protocol();
+
+    let leader_id = match group.leader_id.take() {
+        Some(leader_id) if group.members.contains_key(&leader_id) => leader_id,
+        _ => group.members.keys().next().cloned().unwrap(),
+    };
+    group.leader_id = Some(leader_id.clone());
+
+    let protocol_name = group.protocol_name.clone().unwrap_or_default();
+    let leader_members = group
+        .members
+        .values()
+        .map(|member| JoinGroupResponseMember {
+            member_id: member.member_id.clone(),
+            group_instance_id: member.group_instance_id.clone(),
+            metadata: member.metadata(&protocol_name),
+        })
+        .collect::<Vec<_>>();
+
+    for member in group.members.values_mut() {
+        let Some(sender) = member.awaiting_join.take() else {
+            continue;
+        };
+
+        let members = if member.member_id == leader_id {
+            leader_members.clone()
+        } else {
+            vec![]
+        };
+
+        let _ = sender.send(JoinGroupResponse {
+            throttle_time_ms: 0,
+            error_code: NONE,
+            generation_id: group.generation_id,
+            protocol_type: group.protocol_type.clone(),
+            protocol_name: group.protocol_name.clone(),
+            leader: leader_id.clone(),
+            skip_assignment: false,
+            member_id: member.member_id.clone(),
+            members,
+        });
+    }
+}
+
+// hands the old member's place over to its new incarnation, the old one gets fenced
+fn replace_static_member(group: &mut Group, old_member_id: &str, member_id: &str) {
+    let mut member = group.members.remove(old_member_id).unwrap();
+    if let Some(sender) = member.awaiting_join.take() {
+        let _ = sender.send(JoinGroupResponse::error(
+            FENCED_INSTANCE_ID,
+            old_member_id.to_string(),
+        ));
+    }
+    if let Some(sender) = member.awaiting_sync.take() {
+        let _ = sender.send(SyncGroupResponse::error(FENCED_INSTANCE_ID));
+    }
+
+    if group.leader_id.as_deref() == Some(old_member_id) {
+        group.leader_id = Some(member_id.to_string());
+    }
+    if let Some(group_instance_id) = &member.group_instance_id {
+        group
+            .static_members
+            .insert(group_instance_id.clone(), member_id.to_string());
+    }
+
+    member.member_id = member_id.to_string();
+    group.members.insert(member_id.to_string(), member);
+}
+
+// the current generation for a static member that rejoined a stable group, its assignment
+// stays as it was so the leader doesn't have to compute a new one
+fn static_rejoin_response(group: &Group, member_id: &str) -> JoinGroupResponse {
+    let protocol_name = group.protocol_name.clone().unwrap_or_default();
+    let is_leader = group.leader_id.as_deref() == Some(member_id);
+
+    let members = if is_leader {
+        group
+            .members
+            .values()
+            .map(|member| JoinGroupResponseMember {
+                member_id: member.member_id.clone(),
+                group_instance_id: member.group_instance_id.clone(),
+                metadata: member.metadata(&protocol_name),
+            })
+            .collect()
+    } else {
+        vec![]
+    };
+
+    JoinGroupResponse {
+        throttle_time_ms: 0,
+        error_code: NONE,
+        generation_id: group.generation_id,
+        protocol_type: group.protocol_type.clone(),
+        protocol_name: group.protocol_name.clone(),
+        leader: group.leader_id.clone().unwrap_or_default(),
+        skip_assignment: is_leader,
+        member_id: member_id.to_string(),
+        members,
+    }
+}
+
+fn complete_sync(group: &mut Group) {
+    for member in group.members.values_mut() {
+        if let Some(sender) = member.awaiting_sync.take() {
+            let _ = sender.send(SyncGroupResponse {
+                throttle_time_ms: 0,
+                error_code: NONE,
+                protocol_type: group.protocol_type.clone(),
+                protocol_name: group.protocol_name.clone(),
+                assignment: member.assignment.clone(),
+            });
+        }
+    }
+}
+
+// standalone commits (no member id, negative generation) are only allowed while the group is empty
+fn validate_offset_commit(
+    groups: &mut HashMap<String, Group>,
+    request: &OffsetCommitRequest,
+) -> i16 {
+    let is_standalone = request.generation_id < 0 && request.member_id.is_empty();
+
+    let Some(group) = groups.get_mut(&request.group_id) else {
+        if !is_standalone {
+            return ILLEGAL_GENERATION;
+        }
+        groups.insert(request.group_id.clone(), Group::new());
+        return NONE;
+    };
+
+    if is_standalone {
+        return if group.state == GroupState::Empty {
+            NONE
+        } else {
+            UNKNOWN_MEMBER_ID
+        };
+    }
+
+    let instance_error =
+        group.validate_instance(&request.member_id, request.group_instance_id.as_deref());
+    if instance_error != NONE {
+        return instance_error;
+    }
+
+    let Some(member) = group.members.get_mut(&request.member_id) else {
+        return UNKNOWN_MEMBER_ID;
+    };
+
+    if request.generation_id != group.generation_id {
+        return ILLEGAL_GENERATION;
+    }
+
+    member.last_heartbeat = Instant::now();
+
+    match group.state {
+        GroupState::CompletingRebalance => REBALANCE_IN_PROGRESS,
+        GroupState::Empty => UNKNOWN_MEMBER_ID,
+        GroupState::PreparingRebalance | GroupState::Stable => NONE,
+    }
+}
+
+fn generate_member_id(client_id: &str) -> String {
+    let random = || RandomState::new().build_hasher().finish();
+    format!("{client_id}-{:016x}{:016x}", random(), random())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acl::{
+        AclBinding, AclStore, StandardAuthorizer, PATTERN_TYPE_LITERAL, PERMISSION_TYPE_ALLOW,
+    };
+
+    const GROUP_ID: &str = "g";
+
+    fn session() -> Session {
+        Session::new(None, None, "127.0.0.1")
+    }
+
+    fn join_request(member_id: &str) -> JoinGroupRequest {
+        JoinGroupRequest {
+            group_id: GROUP_ID.to_string(),
+            session_timeout_ms: 10_000,
+            rebalance_timeout_ms: 10_000,
+            member_id: member_id.to_string(),
+            group_instance_id: None,
+            protocol_type: "consumer".to_string(),
+            protocols: vec![JoinGroupProtocol {
+                name: "range".to_string(),
+                metadata: vec![],
+            }],
+            reason: None,
+        }
+    }
+
+    async fn join(coordinator: &Arc<GroupCoordinator>, member_id: &str) -> JoinGroupResponse {
+        coordinator
+            .join_group(&session(), "client", "/127.0.0.1", join_request(member_id))
+            .await
+    }
+
+    // the id a new member is handed through MEMBER_ID_REQUIRED, to join with
+    async fn new_member_id(coordinator: &Arc<GroupCoordinator>) -> String {
+        let response = join(coordinator, "").await;
+        assert_eq!(response.error_code, MEMBER_ID_REQUIRED);
+        response.member_id
+    }
+
+    fn sync_request(
+        member_id: &str,
+        generation_id: i32,
+        assignments: &[(&str, &[u8])],
+    ) -> SyncGroupRequest {
+        SyncGroupRequest {
+            group_id: GROUP_ID.to_string(),
+            generation_id,
+            member_id: member_id.to_string(),
+            group_instance_id: None,
+            protocol_type: Some("consumer".to_string()),
+            protocol_name: Some("range".to_string()),
+            assignments: assignments
+                .iter()
+                .map(|(member_id, assignment)| SyncGroupAssignment {
+                    member_id: member_id.to_string(),
+                    assignment: assignment.to_vec(),
+                })
+                .collect(),
+        }
+    }
+
+    fn heartbeat(coordinator: &GroupCoordinator, member_id: &str, generation_id: i32) -> i16 {
+        let request = HeartbeatRequest {
+            group_id: GROUP_ID.to_string(),
+            generation_id,
+            member_id: member_id.to_string(),
+            group_instance_id: None,
+        };
+        coordinator.heartbeat(&session(), request).error_code
+    }
+
+    fn state(coordinator: &GroupCoordinator) -> (GroupState, i32) {
+        let groups = coordinator.groups.lock().unwrap();
+        (groups[GROUP_ID].state, groups[GROUP_ID].generation_id)
+    }
+
+    // a group of the one member, stable in generation 1
+    async fn stable_group(coordinator: &Arc<GroupCoordinator>) -> String {
+        let member_id = new_member_id(coordinator).await;
+        let joined = join(coordinator, &member_id).await;
+        assert_eq!((joined.error_code, joined.generation_id), (NONE, 1));
+        let synced = coordinator
+            .sync_group(
+                &session(),
+                sync_request(&member_id, 1, &[(&member_id, b"a")]),
+            )
+            .await;
+        assert_eq!(synced.assignment, b"a");
+        member_id
+    }
+
+    #[tokio::test]
+    async fn a_lone_member_leads_the_first_generation() {
+        let coordinator = GroupCoordinator::new();
+        let member_id = new_member_id(&coordinator).await;
+
+        let joined = join(&coordinator, &member_id).await;
+        assert_eq!(joined.error_code, NONE);
+        assert_eq!(joined.generation_id, 1);
+        assert_eq!(joined.leader, member_id);
+        assert_eq!(joined.protocol_name.as_deref(), Some("range"));
+        assert_eq!(joined.members.len(), 1);
+        assert_eq!(state(&coordinator), (GroupState::CompletingRebalance, 1));
+
+        let synced = coordinator
+            .sync_group(
+                &session(),
+                sync_request(&member_id, 1, &[(&member_id, b"a")]),
+            )
+            .await;
+        assert_eq!(synced.error_code, NONE);
+        assert_eq!(synced.assignment, b"a");
+        assert_eq!(state(&coordinator), (GroupState::Stable, 1));
+        assert_eq!(heartbeat(&coordinator, &member_id, 1), NONE);
+    }
+
+    #[tokio::test]
+    async fn a_new_member_rebalances_into_the_next_generation() {
+        let coordinator = GroupCoordinator::new();
+        let leader = stable_group(&coordinator).await;
+
+        let follower = new_member_id(&coordinator).await;
+        let follower_join = tokio::spawn({
+            let coordinator = coordinator.clone();
+            let follower = follower.clone();
+            async move { join(&coordinator, &follower).await }
+        });
+        tokio::task::yield_now().await;
+
+        // the leader hears about the rebalance through its heartbeat, and its old generation
+        // is done for
+        assert_eq!(state(&coordinator), (GroupState::PreparingRebalance, 1));
+        assert_eq!(heartbeat(&coordinator, &leader, 1), REBALANCE_IN_PROGRESS);
+        let stale_sync = coordinator
+            .sync_group(&session(), sync_request(&leader, 1, &[]))
+            .await;
+        assert_eq!(stale_sync.error_code, REBALANCE_IN_PROGRESS);
+
+        let leader_joined = join(&coordinator, &leader).await;
+        let follower_joined = follower_join.await.unwrap();
+        assert_eq!(leader_joined.generation_id, 2);
+        assert_eq!(follower_joined.generation_id, 2);
+        assert_eq!(follower_joined.leader, leader);
+        // only the leader is sent the members to assign partitions to
+        assert_eq!(leader_joined.members.len(), 2);
+        assert!(follower_joined.members.is_empty());
+        assert_eq!(heartbeat(&coordinator, &leader, 1), ILLEGAL_GENERATION);
+
+        // the follower's sync waits on the leader's, which carries everyone's assignment
+        let follower_sync = tokio::spawn({
+            let coordinator = coordinator.clone();
+            let follower = follower.clone();
+            async move {
+                coordinator
+                    .sync_group(&session(), sync_request(&follower, 2, &[]))
+                    .await
+            }
+        });
+        tokio::task::yield_now().await;
+        assert!(!follower_sync.is_finished());
+
+        let assignments: [(&str, &[u8]); 2] = [(&leader, b"a"), (&follower, b"b")];
+        let leader_synced = coordinator
+            .sync_group(&session(), sync_request(&leader, 2, &assignments))
+            .await;
+        assert_eq!(leader_synced.assignment, b"a");
+        let follower_synced = follower_sync.await.unwrap();
+        assert_eq!(follower_synced.error_code, NONE);
+        assert_eq!(follower_synced.assignment, b"b");
+        assert_eq!(state(&coordinator), (GroupState::Stable, 2));
+    }
+
+    #[tokio::test]
+    async fn a_leaving_member_empties_the_group() {
+        let coordinator = GroupCoordinator::new();
+        let member_id = stable_group(&coordinator).await;
+
+        let request = LeaveGroupRequest {
+            group_id: GROUP_ID.to_string(),
+            members: vec![LeaveGroupMember {
+                member_id: member_id.clone(),
+                group_instance_id: None,
+                reason: None,
+            }],
+        };
+        let left = coordinator.leave_group(&session(), request);
+        assert_eq!(left.error_code, NONE);
+        assert_eq!(left.members[0].error_code, NONE);
+
+        assert_eq!(state(&coordinator), (GroupState::Empty, 2));
+        assert_eq!(heartbeat(&coordinator, &member_id, 1), UNKNOWN_MEMBER_ID);
+    }
+
+    #[tokio::test]
+    async fn a_member_missing_its_session_timeout_is_evicted() {
+        let coordinator = GroupCoordinator::new();
+        let member_id = stable_group(&coordinator).await;
+
+        // the expiry task hasn't had its first look yet, so this is what it finds
+        {
+            let mut groups = coordinator.groups.lock().unwrap();
+            let member = groups
+                .get_mut(GROUP_ID)
+                .unwrap()
+                .members
+                .get_mut(&member_id)
+                .unwrap();
+            member.last_heartbeat = Instant::now() - Duration::from_secs(11);
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        assert_eq!(state(&coordinator), (GroupState::Empty, 2));
+        assert_eq!(heartbeat(&coordinator, &member_id, 1), UNKNOWN_MEMBER_ID);
+    }
+
+    #[tokio::test]
+    async fn joins_need_a_valid_group_and_session_timeout() {
+        let coordinator = GroupCoordinator::new();
+
+        let mut request = join_request("");
+        request.group_id = String::new();
+        let joined = coordinator
+            .join_group(&session(), "client", "/127.0.0.1", request)
+            .await;
+        assert_eq!(joined.error_code, INVALID_GROUP_ID);
+
+        let mut request = join_request("");
+        request.session_timeout_ms = MIN_SESSION_TIMEOUT_MS - 1;
+        let joined = coordinator
+            .join_group(&session(), "client", "/127.0.0.1", request)
+            .await;
+        assert_eq!(joined.error_code, INVALID_SESSION_TIMEOUT);
+
+        // an id the group never handed out
+        assert_eq!(
+            join(&coordinator, "stranger").await.error_code,
+            UNKNOWN_MEMBER_ID
+        );
+    }
+
+    #[tokio::test]
+    async fn group_acls_are_enforced() {
+        let store = AclStore::in_memory();
+        let read = AclBinding {
+            resource_type: RESOURCE_TYPE_GROUP,
+            resource_name: GROUP_ID.to_string(),
+            pattern_type: PATTERN_TYPE_LITERAL,
+            principal: "User:alice".to_string(),
+            host: "*".to_string(),
+            operation: OPERATION_READ,
+            permission_type: PERMISSION_TYPE_ALLOW,
+        };
+        store.create(&[read]).unwrap();
+        let authorizer = StandardAuthorizer::new(store, vec![], false);
+        let alice = Session::new(Some(authorizer.clone()), Some("alice"), "127.0.0.1");
+        let bob = Session::new(Some(authorizer), Some("bob"), "127.0.0.1");
+        let coordinator = GroupCoordinator::new();
+
+        let joined = coordinator
+            .join_group(&bob, "client", "/127.0.0.1", join_request(""))
+            .await;
+        assert_eq!(joined.error_code, GROUP_AUTHORIZATION_FAILED);
+        let joined = coordinator
+            .join_group(&alice, "client", "/127.0.0.1", join_request(""))
+            .await;
+        assert_eq!(joined.error_code, MEMBER_ID_REQUIRED);
+
+        let describe = |session: &Session| {
+            let request = DescribeGroupsRequest {
+                groups: vec![GROUP_ID.to_string()],
+                include_authorized_operations: true,
+            };
+            let group = coordinator
+                .describe_groups(session, request)
+                .groups
+                .remove(0);
+            (group.error_code, group.authorized_operations)
+        };
+        // READ implies DESCRIBE, but not DELETE
+        assert_eq!(
+            describe(&alice),
+            (NONE, 1 << OPERATION_READ | 1 << OPERATION_DESCRIBE)
+        );
+        assert_eq!(describe(&bob), (GROUP_AUTHORIZATION_FAILED, i32::MIN));
+
+        let listed = |session: &Session| {
+            let request = ListGroupsRequest {
+                states_filter: vec![],
+            };
+            coordinator.list_groups(session, request).groups.len()
+        };
+        assert_eq!((listed(&alice), listed(&bob)), (1, 0));
+    }
+}
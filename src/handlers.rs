@@ -0,0 +1,1416 @@
+use crate::acl::{Session, OPERATION_CLUSTER_ACTION, OPERATION_READ, RESOURCE_TYPE_TOPIC};
+use crate::acl_api::*;
+use crate::cluster_api::*;
+use crate::cluster_metadata::MetadataLog;
+use crate::config_api::*;
+use crate::group_api::*;
+use crate::offset_api::*;
+use crate::partition_api::*;
+use crate::purgatory::Completion;
+use crate::quorum_api::*;
+use crate::quota_api::*;
+use crate::registration_api::*;
+use crate::replica_selector::{ClientMetadata, LeaderSelector, ReplicaSelector};
+use crate::sasl::*;
+use crate::scram_api::*;
+use crate::storage::{FetchLimits, LogStore, TopicPartition};
+use crate::{
+    run_blocking, ApiKeyVerInfo, ApiVersionsRequest, ApiVersionsResponse, BrokerState,
+    FetchRequest, FetchResponse, FinalizedFeature, KafkaError, KafkaRequestHeader, KafkaResponse,
+    ResponsePartition, ResponseTopic, SupportedFeature, ALTER_CLIENT_QUOTAS, ALTER_PARTITION,
+    ALTER_PARTITION_REASSIGNMENTS, ALTER_USER_SCRAM_CREDENTIALS, APIVERSIONS, BEGIN_QUORUM_EPOCH,
+    BROKER_HEARTBEAT, BROKER_REGISTRATION, CREATE_ACLS, DELETE_ACLS, DESCRIBE_ACLS,
+    DESCRIBE_CLIENT_QUOTAS, DESCRIBE_CLUSTER, DESCRIBE_CONFIGS, DESCRIBE_GROUPS, DESCRIBE_LOG_DIRS,
+    DESCRIBE_PRODUCERS, DESCRIBE_QUORUM, ELECT_LEADERS, END_QUORUM_EPOCH, FETCH, FETCH_SNAPSHOT,
+    HEARTBEAT, INCREMENTAL_ALTER_CONFIGS, INVALID_REQUEST, JOIN_GROUP, LEAVE_GROUP, LIST_GROUPS,
+    LIST_PARTITION_REASSIGNMENTS, METADATA, NONE, OFFSET_COMMIT, OFFSET_FETCH,
+    OFFSET_FOR_LEADER_EPOCH, OFFSET_OUT_OF_RANGE, SASL_AUTHENTICATE, SASL_HANDSHAKE,
+    SUPPORTED_FEATURES, SYNC_GROUP, TOPIC_AUTHORIZATION_FAILED, UNSUPPORTED_VERSION, VOTE,
+};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::ops::RangeInclusive;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub(crate) type HandlerFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<KafkaResponse, KafkaError>> + Send + 'a>>;
+
+// the request along with the connection it arrived on
+pub(crate) struct RequestContext<'a> {
+    pub header: &'a KafkaRequestHeader,
+    pub body: &'a [u8],
+    // what group member descriptions report for the connection's peer
+    pub client_host: &'a str,
+    pub sasl_state: &'a mut SaslState,
+    pub state: &'a Arc<BrokerState>,
+    // when the request was read off the socket, which time budgets count from
+    pub received: Instant,
+}
+
+impl RequestContext<'_> {
+    fn client_id(&self) -> &str {
+        self.header.client_id.as_deref().unwrap_or_default()
+    }
+
+    // who acls are checked against for this request
+    fn session(&self) -> Session {
+        Session::new(
+            self.state.authorizer.clone(),
+            self.sasl_state.principal(),
+            self.client_host.trim_start_matches('/'),
+        )
+    }
+}
+
+pub(crate) trait ApiHandler: Send + Sync {
+    fn api_key(&self) -> i16;
+    // advertised through ApiVersions, requests for any other version are rejected up front
+    fn version_range(&self) -> RangeInclusive<i16>;
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a>;
+
+    // answers a request for a version outside version_range, a bare error frame by default
+    fn reject_version(&self, ctx: &RequestContext<'_>) -> Result<KafkaResponse, KafkaError> {
+        Err(KafkaError::UnsupportedApiVersion(ctx.header.api_ver))
+    }
+}
+
+pub struct ApiRegistry {
+    handlers: BTreeMap<i16, Box<dyn ApiHandler>>,
+}
+
+impl ApiRegistry {
+    // every api the broker implements
+    pub fn builtin() -> Self {
+        let mut registry = ApiRegistry {
+            handlers: BTreeMap::new(),
+        };
+
+        registry.register(ApiVersionsHandler);
+        registry.register(FetchHandler {
+            selector: Box::new(LeaderSelector),
+        });
+        registry.register(MetadataHandler);
+        registry.register(OffsetCommitHandler);
+        registry.register(OffsetFetchHandler);
+        registry.register(JoinGroupHandler);
+        registry.register(HeartbeatHandler);
+        registry.register(LeaveGroupHandler);
+        registry.register(SyncGroupHandler);
+        registry.register(DescribeGroupsHandler);
+        registry.register(ListGroupsHandler);
+        registry.register(SaslHandshakeHandler);
+        registry.register(SaslAuthenticateHandler);
+        registry.register(OffsetForLeaderEpochHandler);
+        registry.register(DescribeConfigsHandler);
+        registry.register(DescribeLogDirsHandler);
+        registry.register(ElectLeadersHandler);
+        registry.register(IncrementalAlterConfigsHandler);
+        registry.register(AlterPartitionReassignmentsHandler);
+        registry.register(ListPartitionReassignmentsHandler);
+        registry.register(DescribeProducersHandler);
+        registry.register(DescribeClusterHandler);
+        registry.register(DescribeAclsHandler);
+        registry.register(CreateAclsHandler);
+        registry.register(DeleteAclsHandler);
+        registry.register(AlterUserScramCredentialsHandler);
+        registry.register(DescribeClientQuotasHandler);
+        registry.register(AlterClientQuotasHandler);
+        registry.register(BrokerRegistrationHandler);
+        registry.register(BrokerHeartbeatHandler);
+        registry.register(AlterPartitionHandler);
+        registry.register(VoteHandler);
+        registry.register(BeginQuorumEpochHandler);
+        registry.register(EndQuorumEpochHandler);
+        registry.register(DescribeQuorumHandler);
+        registry.register(FetchSnapshotHandler);
+
+        registry
+    }
+
+    // replaces any handler already registered for the same api key
+    pub(crate) fn register(&mut self, handler: impl ApiHandler + 'static) {
+        self.handlers.insert(handler.api_key(), Box::new(handler));
+    }
+
+    pub(crate) fn version_range(&self, api_key: i16) -> Option<RangeInclusive<i16>> {
+        self.handlers
+            .get(&api_key)
+            .map(|handler| handler.version_range())
+    }
+
+    pub(crate) fn api_versions(&self) -> Vec<ApiKeyVerInfo> {
+        self.handlers
+            .values()
+            .map(|handler| ApiKeyVerInfo {
+                id: handler.api_key(),
+                min: *handler.version_range().start(),
+                max: *handler.version_range().end(),
+            })
+            .collect()
+    }
+
+    pub(crate) async fn dispatch(
+        &self,
+        ctx: RequestContext<'_>,
+    ) -> Result<KafkaResponse, KafkaError> {
+        let Some(handler) = self.handlers.get(&ctx.header.api_key) else {
+            return Err(KafkaError::UnsupportedApiKey(ctx.header.api_key));
+        };
+        if !handler.version_range().contains(&ctx.header.api_ver) {
+            return handler.reject_version(&ctx);
+        }
+
+        handler.handle(ctx).await
+    }
+}
+
+// ### API VERSIONS (v0-v4) ### //
+struct ApiVersionsHandler;
+
+impl ApiHandler for ApiVersionsHandler {
+    fn api_key(&self) -> i16 {
+        APIVERSIONS
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        0..=4
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let invalid = KafkaResponse::ApiVersions(ApiVersionsResponse::new(
+                ctx.header.correlation_id,
+                ctx.header.api_ver,
+                INVALID_REQUEST,
+            ));
+            let request = match ApiVersionsRequest::parse(ctx.body, ctx.header.api_ver) {
+                Ok(request) => request,
+                Err(e) => {
+                    eprintln!(
+                        "Rejecting ApiVersions request from client {:?}: {e}",
+                        ctx.client_id()
+                    );
+                    return Ok(invalid);
+                }
+            };
+
+            // only v3 and up say what the client is
+            if ctx.header.api_ver >= 3 {
+                if !request.is_valid() {
+                    eprintln!(
+                        "Rejecting ApiVersions request from client {:?}: invalid client software {:?} version {:?}",
+                        ctx.client_id(),
+                        request.client_software_name,
+                        request.client_software_version
+                    );
+                    return Ok(invalid);
+                }
+                println!(
+                    "Client {:?} from {} is {} {}",
+                    ctx.client_id(),
+                    ctx.client_host,
+                    request.client_software_name,
+                    request.client_software_version
+                );
+                ctx.state.metrics.record_client_software(
+                    ctx.client_id(),
+                    &request.client_software_name,
+                    &request.client_software_version,
+                );
+            }
+
+            let mut response =
+                ApiVersionsResponse::new(ctx.header.correlation_id, ctx.header.api_ver, NONE);
+            response.api_key_versions = ctx.state.handlers.api_versions();
+            response.supported_features = SUPPORTED_FEATURES
+                .iter()
+                .map(|(name, min_version, max_version)| SupportedFeature {
+                    name: name.to_string(),
+                    min_version: *min_version,
+                    max_version: *max_version,
+                })
+                .collect();
+            let finalized = &ctx.state.finalized_features;
+            response.finalized_features_epoch = finalized.epoch;
+            response.finalized_features = finalized
+                .levels
+                .iter()
+                .map(|(name, level)| FinalizedFeature {
+                    name: name.clone(),
+                    max_version_level: *level,
+                    min_version_level: *level,
+                })
+                .collect();
+            Ok(KafkaResponse::ApiVersions(response))
+        })
+    }
+
+    // clients can't know our versions before asking, so like kafka we answer newer requests
+    // in v0 (which every client can decode) with our own version range, letting them downgrade
+    fn reject_version(&self, ctx: &RequestContext<'_>) -> Result<KafkaResponse, KafkaError> {
+        let mut response =
+            ApiVersionsResponse::new(ctx.header.correlation_id, 0, UNSUPPORTED_VERSION);
+        response.api_key_versions = vec![ApiKeyVerInfo {
+            id: APIVERSIONS,
+            min: *self.version_range().start(),
+            max: *self.version_range().end(),
+        }];
+        Ok(KafkaResponse::ApiVersions(response))
+    }
+}
+
+// ### FETCH (v16) ### //
+struct FetchHandler {
+    selector: Box<dyn ReplicaSelector>,
+}
+
+// reads every requested partition in order, sharing the request's max_bytes between them
+fn read_fetch_topics(
+    logs: &dyn LogStore,
+    metadata_log: &MetadataLog,
+    session: &Session,
+    request: &FetchRequest,
+    deadline: Option<Instant>,
+) -> Vec<ResponseTopic> {
+    // acls name the topic, fetches only carry its id
+    let topic_names: BTreeMap<i128, String> = match &session.authorizer {
+        Some(_) => logs
+            .list_partitions()
+            .into_iter()
+            .filter_map(|partition| Some((partition.topic_id?, partition.topic_partition.topic)))
+            .collect(),
+        None => BTreeMap::new(),
+    };
+    // unknown ids are left for the log to report
+    let denied = |topic_id| {
+        topic_names
+            .get(&topic_id)
+            .is_some_and(|name| !session.authorize(OPERATION_READ, RESOURCE_TYPE_TOPIC, name))
+    };
+
+    let mut remaining_bytes = request.max_bytes.max(0) as usize;
+    let mut records_sent = false;
+    // nothing is ever transactional, so read_committed fetches have nothing to skip
+    let read_committed = request.isolation_level == 1;
+    let from_follower =
+        request.replica_id >= 0 && session.authorize_cluster(OPERATION_CLUSTER_ACTION);
+
+    request
+        .topics
+        .iter()
+        .map(|topic| {
+            let denied = denied(topic.topic_id);
+            ResponseTopic {
+                topic_id: topic.topic_id,
+                partitions: topic
+                    .partitions
+                    .iter()
+                    .map(|partition| {
+                        let limits = FetchLimits {
+                            max_bytes: (partition.partition_max_bytes.max(0) as usize)
+                                .min(remaining_bytes),
+                            // the response's first batch goes out even when it exceeds the limits
+                            min_one: !records_sent,
+                            deadline,
+                            read_past_high_watermark: from_follower,
+                        };
+
+                        let fetched = match denied {
+                            true => Err(TOPIC_AUTHORIZATION_FAILED),
+                            false => logs.fetch(
+                                topic.topic_id,
+                                partition.partition,
+                                partition.current_leader_epoch,
+                                metadata_log
+                                    .topic_partition(topic.topic_id, partition.partition)
+                                    .and_then(|tp| metadata_log.partition_leader_epoch(&tp)),
+                                partition.fetch_offset,
+                                &limits,
+                            ),
+                        };
+                        match fetched {
+                            Ok(fetched) => {
+                                remaining_bytes =
+                                    remaining_bytes.saturating_sub(fetched.records.len());
+                                records_sent |= !fetched.records.is_empty();
+                                ResponsePartition {
+                                    partition_index: partition.partition,
+                                    error_code: NONE,
+                                    high_watermark: fetched.high_watermark,
+                                    last_stable_offset: fetched.high_watermark,
+                                    log_start_offset: fetched.log_start_offset,
+                                    aborted_transactions: read_committed.then(Vec::new),
+                                    preferred_read_replica: -1,
+                                    records: Some(fetched.records),
+                                }
+                            }
+                            // offsets are only reported alongside a successful read, apart from
+                            // where the log starts for a fetch out of range, which a follower that
+                            // fell behind starts over from
+                            Err(error_code) => ResponsePartition {
+                                partition_index: partition.partition,
+                                error_code,
+                                high_watermark: -1,
+                                last_stable_offset: -1,
+                                log_start_offset: match error_code {
+                                    OFFSET_OUT_OF_RANGE => logs
+                                        .log_start_offset(topic.topic_id, partition.partition)
+                                        .unwrap_or(-1),
+                                    _ => -1,
+                                },
+                                aborted_transactions: None,
+                                preferred_read_replica: -1,
+                                records: None,
+                            },
+                        }
+                    })
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+// a partition's (high watermark, log end offset), either moving is what a waiting fetch is
+// woken for
+fn partition_offsets(logs: &dyn LogStore, topic_partition: &TopicPartition) -> Option<(i64, i64)> {
+    logs.high_watermark(topic_partition)
+        .zip(logs.log_end_offset(topic_partition))
+}
+
+// the offsets of every partition the fetch names, taken before it reads them so anything
+// appended during the read still counts as a change
+fn watched_offsets(
+    logs: &dyn LogStore,
+    request: &FetchRequest,
+) -> Vec<(TopicPartition, (i64, i64))> {
+    let requested = |topic_id, partition| {
+        request.topics.iter().any(|topic| {
+            topic.topic_id == topic_id
+                && topic
+                    .partitions
+                    .iter()
+                    .any(|requested| requested.partition == partition)
+        })
+    };
+    logs.list_partitions()
+        .into_iter()
+        .filter(|info| {
+            info.topic_id
+                .is_some_and(|topic_id| requested(topic_id, info.topic_partition.partition))
+        })
+        .filter_map(|info| {
+            let offsets = partition_offsets(logs, &info.topic_partition)?;
+            Some((info.topic_partition, offsets))
+        })
+        .collect()
+}
+
+// like kafka, a fetch is answered once it has min_bytes, or a partition error to report
+fn fetch_satisfied(request: &FetchRequest, responses: &[ResponseTopic]) -> bool {
+    let partitions = || responses.iter().flat_map(|topic| &topic.partitions);
+    let read: usize = partitions()
+        .map(|partition| partition.records.as_ref().map_or(0, Vec::len))
+        .sum();
+    read >= request.min_bytes.max(0) as usize
+        || partitions().any(|partition| partition.error_code != NONE)
+        || partitions().next().is_none()
+}
+
+impl ApiHandler for FetchHandler {
+    fn api_key(&self) -> i16 {
+        FETCH
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        16..=16
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = Arc::new(FetchRequest::parse(ctx.body, ctx.header.correlation_id)?);
+            // partitions read once max_wait_ms is up come back without records, for the
+            // client to fetch again. a max_wait_ms of 0 asks not to wait rather than for an
+            // empty budget, so like kafka it gets everything that's there
+            let deadline = (request.max_wait_ms > 0)
+                .then(|| ctx.received + Duration::from_millis(request.max_wait_ms as u64));
+            // records are read off the segment files
+            let logs = ctx.state.logs.clone();
+            let metadata_log = ctx.state.metadata_log.clone();
+            let session = ctx.session();
+            let fetch_request = request.clone();
+            let isr = ctx.state.isr.clone();
+            let (mut responses, mut watched) = run_blocking(move || {
+                // a follower's fetch offset moves the isr along before the read, so the high
+                // watermark it gets back already counts what it has replicated
+                if fetch_request.replica_id >= 0
+                    && session.authorize_cluster(OPERATION_CLUSTER_ACTION)
+                {
+                    for topic in &fetch_request.topics {
+                        for partition in &topic.partitions {
+                            isr.record_fetch(
+                                &*logs,
+                                fetch_request.replica_id,
+                                topic.topic_id,
+                                partition.partition,
+                                partition.fetch_offset,
+                            );
+                        }
+                    }
+                }
+                let watched = watched_offsets(&*logs, &fetch_request);
+                let responses =
+                    read_fetch_topics(&*logs, &metadata_log, &session, &fetch_request, deadline);
+                (responses, watched)
+            })
+            .await?;
+
+            // one short of min_bytes waits in the purgatory until a partition it reads moves,
+            // then reads everything again, until max_wait_ms is up. what was read last is the
+            // response then
+            if let Some(deadline) = deadline {
+                while !fetch_satisfied(&request, &responses) {
+                    let logs = ctx.state.logs.clone();
+                    let keys = watched.iter().map(|(key, _)| key.clone()).collect();
+                    let waited = ctx.state.fetch_purgatory.wait(keys, deadline, move || {
+                        watched.iter().any(|(topic_partition, seen)| {
+                            partition_offsets(&*logs, topic_partition) != Some(*seen)
+                        })
+                    });
+                    if waited.await == Completion::Expired {
+                        break;
+                    }
+
+                    let logs = ctx.state.logs.clone();
+                    let metadata_log = ctx.state.metadata_log.clone();
+                    let session = ctx.session();
+                    let fetch_request = request.clone();
+                    (responses, watched) = run_blocking(move || {
+                        let watched = watched_offsets(&*logs, &fetch_request);
+                        let responses = read_fetch_topics(
+                            &*logs,
+                            &metadata_log,
+                            &session,
+                            &fetch_request,
+                            Some(deadline),
+                        );
+                        (responses, watched)
+                    })
+                    .await?;
+                }
+            }
+
+            let client = ClientMetadata {
+                rack_id: &request.rack_id,
+                client_id: ctx.client_id(),
+                client_host: ctx.client_host,
+            };
+            for topic in &mut responses {
+                for partition in &mut topic.partitions {
+                    if partition.error_code != NONE {
+                        continue;
+                    }
+                    let records = partition.records.take().unwrap_or_default();
+                    let injected_error = ctx
+                        .state
+                        .fault_injector
+                        .as_ref()
+                        .and_then(|fault_injector| fault_injector.partition_error());
+                    let intercepted = match injected_error {
+                        Some(error_code) => Err(error_code),
+                        None => ctx.state.fetch_interceptor.intercept(
+                            topic.topic_id,
+                            partition.partition_index,
+                            &client,
+                            records,
+                        ),
+                    };
+                    match intercepted {
+                        Ok(records) => partition.records = Some(records),
+                        Err(error_code) => {
+                            *partition = ResponsePartition {
+                                partition_index: partition.partition_index,
+                                error_code,
+                                high_watermark: -1,
+                                last_stable_offset: -1,
+                                log_start_offset: -1,
+                                aborted_transactions: None,
+                                preferred_read_replica: -1,
+                                records: None,
+                            };
+                            continue;
+                        }
+                    }
+
+                    partition.preferred_read_replica = self
+                        .selector
+                        .select(topic.topic_id, partition.partition_index, &client)
+                        .unwrap_or(-1);
+                }
+            }
+
+            Ok(KafkaResponse::Fetch(FetchResponse {
+                correlation_id: request.correlation_id,
+                throttle_time_ms: 0,
+                error_code: NONE,
+                session_id: request.session_id,
+                responses,
+            }))
+        })
+    }
+}
+
+// ### GROUP MEMBERSHIP ### //
+// group requests may have to wait on other members, the coordinator drives those
+struct JoinGroupHandler;
+
+impl ApiHandler for JoinGroupHandler {
+    fn api_key(&self) -> i16 {
+        JOIN_GROUP
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        9..=9
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = JoinGroupRequest::parse(ctx.body)?;
+            Ok(KafkaResponse::JoinGroup(
+                ctx.state
+                    .coordinator
+                    .join_group(&ctx.session(), ctx.client_id(), ctx.client_host, request)
+                    .await,
+            ))
+        })
+    }
+}
+
+struct SyncGroupHandler;
+
+impl ApiHandler for SyncGroupHandler {
+    fn api_key(&self) -> i16 {
+        SYNC_GROUP
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        5..=5
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = SyncGroupRequest::parse(ctx.body)?;
+            Ok(KafkaResponse::SyncGroup(
+                ctx.state
+                    .coordinator
+                    .sync_group(&ctx.session(), request)
+                    .await,
+            ))
+        })
+    }
+}
+
+struct HeartbeatHandler;
+
+impl ApiHandler for HeartbeatHandler {
+    fn api_key(&self) -> i16 {
+        HEARTBEAT
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        4..=4
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = HeartbeatRequest::parse(ctx.body)?;
+            Ok(KafkaResponse::Heartbeat(
+                ctx.state.coordinator.heartbeat(&ctx.session(), request),
+            ))
+        })
+    }
+}
+
+struct LeaveGroupHandler;
+
+impl ApiHandler for LeaveGroupHandler {
+    fn api_key(&self) -> i16 {
+        LEAVE_GROUP
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        5..=5
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = LeaveGroupRequest::parse(ctx.body)?;
+            Ok(KafkaResponse::LeaveGroup(
+                ctx.state.coordinator.leave_group(&ctx.session(), request),
+            ))
+        })
+    }
+}
+
+struct DescribeGroupsHandler;
+
+impl ApiHandler for DescribeGroupsHandler {
+    fn api_key(&self) -> i16 {
+        DESCRIBE_GROUPS
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        5..=5
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = DescribeGroupsRequest::parse(ctx.body)?;
+            Ok(KafkaResponse::DescribeGroups(
+                ctx.state
+                    .coordinator
+                    .describe_groups(&ctx.session(), request),
+            ))
+        })
+    }
+}
+
+struct ListGroupsHandler;
+
+impl ApiHandler for ListGroupsHandler {
+    fn api_key(&self) -> i16 {
+        LIST_GROUPS
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        4..=4
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = ListGroupsRequest::parse(ctx.body)?;
+            Ok(KafkaResponse::ListGroups(
+                ctx.state.coordinator.list_groups(&ctx.session(), request),
+            ))
+        })
+    }
+}
+
+// ### GROUP OFFSETS ### //
+struct OffsetCommitHandler;
+
+impl ApiHandler for OffsetCommitHandler {
+    fn api_key(&self) -> i16 {
+        OFFSET_COMMIT
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        8..=8
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = OffsetCommitRequest::parse(ctx.body)?;
+            Ok(KafkaResponse::OffsetCommit(
+                ctx.state
+                    .coordinator
+                    .commit_offsets(&ctx.session(), request),
+            ))
+        })
+    }
+}
+
+struct OffsetFetchHandler;
+
+impl ApiHandler for OffsetFetchHandler {
+    fn api_key(&self) -> i16 {
+        OFFSET_FETCH
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        8..=8
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = OffsetFetchRequest::parse(ctx.body)?;
+            Ok(KafkaResponse::OffsetFetch(
+                ctx.state.coordinator.fetch_offsets(&ctx.session(), request),
+            ))
+        })
+    }
+}
+
+// ### SASL ### //
+struct SaslHandshakeHandler;
+
+impl ApiHandler for SaslHandshakeHandler {
+    fn api_key(&self) -> i16 {
+        SASL_HANDSHAKE
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        1..=1
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = SaslHandshakeRequest::parse(ctx.body)?;
+            Ok(KafkaResponse::SaslHandshake(
+                ctx.sasl_state.handshake(&ctx.state.config, request),
+            ))
+        })
+    }
+}
+
+struct SaslAuthenticateHandler;
+
+impl ApiHandler for SaslAuthenticateHandler {
+    fn api_key(&self) -> i16 {
+        SASL_AUTHENTICATE
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        2..=2
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = SaslAuthenticateRequest::parse(ctx.body)?;
+            Ok(KafkaResponse::SaslAuthenticate(
+                ctx.sasl_state.authenticate(
+                    &ctx.state.config,
+                    &ctx.state.scram_credentials,
+                    request,
+                ),
+            ))
+        })
+    }
+}
+
+struct AlterUserScramCredentialsHandler;
+
+impl ApiHandler for AlterUserScramCredentialsHandler {
+    fn api_key(&self) -> i16 {
+        ALTER_USER_SCRAM_CREDENTIALS
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        0..=0
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = AlterUserScramCredentialsRequest::parse(ctx.body)?;
+            // the credentials file is rewritten on every change
+            let state = ctx.state.clone();
+            let session = ctx.session();
+            let response = run_blocking(move || {
+                alter_user_scram_credentials(&state.scram_credentials, &session, &request)
+            })
+            .await?;
+            Ok(KafkaResponse::AlterUserScramCredentials(response))
+        })
+    }
+}
+
+// ### CONFIGS ### //
+struct DescribeConfigsHandler;
+
+impl ApiHandler for DescribeConfigsHandler {
+    fn api_key(&self) -> i16 {
+        DESCRIBE_CONFIGS
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        4..=4
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = DescribeConfigsRequest::parse(ctx.body)?;
+            Ok(KafkaResponse::DescribeConfigs(describe_configs(
+                &ctx.state.config,
+                &ctx.state.topic_configs,
+                &ctx.session(),
+                &request,
+            )))
+        })
+    }
+}
+
+struct IncrementalAlterConfigsHandler;
+
+impl ApiHandler for IncrementalAlterConfigsHandler {
+    fn api_key(&self) -> i16 {
+        INCREMENTAL_ALTER_CONFIGS
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        1..=1
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = IncrementalAlterConfigsRequest::parse(ctx.body)?;
+            // persisting the overrides is blocking file io
+            let state = ctx.state.clone();
+            let session = ctx.session();
+            let response = run_blocking(move || {
+                incremental_alter_configs(
+                    &state.config,
+                    &state.topic_configs,
+                    &state.metadata_log,
+                    &session,
+                    &request,
+                )
+            })
+            .await?;
+            Ok(KafkaResponse::IncrementalAlterConfigs(response))
+        })
+    }
+}
+
+// ### CLIENT QUOTAS ### //
+struct DescribeClientQuotasHandler;
+
+impl ApiHandler for DescribeClientQuotasHandler {
+    fn api_key(&self) -> i16 {
+        DESCRIBE_CLIENT_QUOTAS
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        1..=1
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = DescribeClientQuotasRequest::parse(ctx.body)?;
+            Ok(KafkaResponse::DescribeClientQuotas(describe_client_quotas(
+                &ctx.state.client_quotas,
+                &ctx.session(),
+                &request,
+            )))
+        })
+    }
+}
+
+struct AlterClientQuotasHandler;
+
+impl ApiHandler for AlterClientQuotasHandler {
+    fn api_key(&self) -> i16 {
+        ALTER_CLIENT_QUOTAS
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        1..=1
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = AlterClientQuotasRequest::parse(ctx.body)?;
+            // the quotas file is rewritten on every change
+            let state = ctx.state.clone();
+            let session = ctx.session();
+            let response =
+                run_blocking(move || alter_client_quotas(&state.client_quotas, &session, &request))
+                    .await?;
+            Ok(KafkaResponse::AlterClientQuotas(response))
+        })
+    }
+}
+
+// ### BROKER REGISTRATION ### //
+struct BrokerRegistrationHandler;
+
+impl ApiHandler for BrokerRegistrationHandler {
+    fn api_key(&self) -> i16 {
+        BROKER_REGISTRATION
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        0..=3
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = BrokerRegistrationRequest::parse(ctx.body, ctx.header.api_ver)?;
+            let state = &ctx.state;
+            Ok(KafkaResponse::BrokerRegistration(register_broker(
+                &state.broker_registry,
+                &state.finalized_features,
+                state.meta.as_ref().map(|meta| meta.cluster_id.as_str()),
+                &ctx.session(),
+                request,
+            )))
+        })
+    }
+}
+
+struct BrokerHeartbeatHandler;
+
+impl ApiHandler for BrokerHeartbeatHandler {
+    fn api_key(&self) -> i16 {
+        BROKER_HEARTBEAT
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        0..=1
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = BrokerHeartbeatRequest::parse(ctx.body)?;
+            Ok(KafkaResponse::BrokerHeartbeat(broker_heartbeat(
+                &ctx.state.broker_registry,
+                &ctx.session(),
+                &request,
+            )))
+        })
+    }
+}
+
+struct AlterPartitionHandler;
+
+impl ApiHandler for AlterPartitionHandler {
+    fn api_key(&self) -> i16 {
+        ALTER_PARTITION
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        0..=3
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = AlterPartitionRequest::parse(ctx.body, ctx.header.api_ver)?;
+            // accepted changes are synced to the metadata log
+            let state = ctx.state.clone();
+            let session = ctx.session();
+            let response = run_blocking(move || {
+                alter_partition(
+                    &state.metadata_log,
+                    &state.broker_registry,
+                    &session,
+                    request,
+                )
+            })
+            .await?;
+            Ok(KafkaResponse::AlterPartition(response))
+        })
+    }
+}
+
+// ### QUORUM ### //
+struct VoteHandler;
+
+impl ApiHandler for VoteHandler {
+    fn api_key(&self) -> i16 {
+        VOTE
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        0..=0
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = VoteRequest::parse(ctx.body)?;
+            // granted votes are synced to the quorum state
+            let state = ctx.state.clone();
+            let session = ctx.session();
+            let response = run_blocking(move || {
+                let cluster_id = state.meta.as_ref().map(|meta| meta.cluster_id.as_str());
+                vote(&state.quorum, cluster_id, &session, request)
+            })
+            .await?;
+            Ok(KafkaResponse::Vote(response))
+        })
+    }
+}
+
+struct BeginQuorumEpochHandler;
+
+impl ApiHandler for BeginQuorumEpochHandler {
+    fn api_key(&self) -> i16 {
+        BEGIN_QUORUM_EPOCH
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        0..=0
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = QuorumEpochRequest::parse(ctx.body, false)?;
+            let state = ctx.state.clone();
+            let session = ctx.session();
+            let response = run_blocking(move || {
+                let cluster_id = state.meta.as_ref().map(|meta| meta.cluster_id.as_str());
+                quorum_epoch(&state.quorum, cluster_id, &session, request, false)
+            })
+            .await?;
+            Ok(KafkaResponse::BeginQuorumEpoch(response))
+        })
+    }
+}
+
+struct EndQuorumEpochHandler;
+
+impl ApiHandler for EndQuorumEpochHandler {
+    fn api_key(&self) -> i16 {
+        END_QUORUM_EPOCH
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        0..=0
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = QuorumEpochRequest::parse(ctx.body, true)?;
+            let state = ctx.state.clone();
+            let session = ctx.session();
+            let response = run_blocking(move || {
+                let cluster_id = state.meta.as_ref().map(|meta| meta.cluster_id.as_str());
+                quorum_epoch(&state.quorum, cluster_id, &session, request, true)
+            })
+            .await?;
+            Ok(KafkaResponse::EndQuorumEpoch(response))
+        })
+    }
+}
+
+struct DescribeQuorumHandler;
+
+impl ApiHandler for DescribeQuorumHandler {
+    fn api_key(&self) -> i16 {
+        DESCRIBE_QUORUM
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        0..=1
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = DescribeQuorumRequest::parse(ctx.body)?;
+            let state = &ctx.state;
+            Ok(KafkaResponse::DescribeQuorum(describe_quorum(
+                &state.quorum,
+                &state.metadata_log,
+                &ctx.session(),
+                ctx.header.api_ver,
+                request,
+            )))
+        })
+    }
+}
+
+struct FetchSnapshotHandler;
+
+impl ApiHandler for FetchSnapshotHandler {
+    fn api_key(&self) -> i16 {
+        FETCH_SNAPSHOT
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        0..=0
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = FetchSnapshotRequest::parse(ctx.body)?;
+            let state = ctx.state.clone();
+            let session = ctx.session();
+            let response = run_blocking(move || {
+                let cluster_id = state.meta.as_ref().map(|meta| meta.cluster_id.as_str());
+                fetch_snapshot(
+                    &state.quorum,
+                    &state.metadata_log,
+                    cluster_id,
+                    &session,
+                    request,
+                )
+            })
+            .await?;
+            Ok(KafkaResponse::FetchSnapshot(response))
+        })
+    }
+}
+
+// ### PARTITIONS ### //
+struct OffsetForLeaderEpochHandler;
+
+impl ApiHandler for OffsetForLeaderEpochHandler {
+    fn api_key(&self) -> i16 {
+        OFFSET_FOR_LEADER_EPOCH
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        4..=4
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = OffsetForLeaderEpochRequest::parse(ctx.body)?;
+            Ok(KafkaResponse::OffsetForLeaderEpoch(
+                offsets_for_leader_epoch(
+                    &*ctx.state.logs,
+                    &ctx.state.metadata_log,
+                    &ctx.session(),
+                    &request,
+                ),
+            ))
+        })
+    }
+}
+
+struct ElectLeadersHandler;
+
+impl ApiHandler for ElectLeadersHandler {
+    fn api_key(&self) -> i16 {
+        ELECT_LEADERS
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        2..=2
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = ElectLeadersRequest::parse(ctx.body)?;
+            Ok(KafkaResponse::ElectLeaders(elect_leaders(
+                &*ctx.state.logs,
+                &ctx.session(),
+                &request,
+            )))
+        })
+    }
+}
+
+struct AlterPartitionReassignmentsHandler;
+
+impl ApiHandler for AlterPartitionReassignmentsHandler {
+    fn api_key(&self) -> i16 {
+        ALTER_PARTITION_REASSIGNMENTS
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        0..=0
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = AlterPartitionReassignmentsRequest::parse(ctx.body)?;
+            Ok(KafkaResponse::AlterPartitionReassignments(
+                alter_partition_reassignments(
+                    &*ctx.state.logs,
+                    ctx.state.config.node_id,
+                    &ctx.session(),
+                    &request,
+                ),
+            ))
+        })
+    }
+}
+
+struct ListPartitionReassignmentsHandler;
+
+impl ApiHandler for ListPartitionReassignmentsHandler {
+    fn api_key(&self) -> i16 {
+        LIST_PARTITION_REASSIGNMENTS
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        0..=0
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = ListPartitionReassignmentsRequest::parse(ctx.body)?;
+            Ok(KafkaResponse::ListPartitionReassignments(
+                list_partition_reassignments(&ctx.session(), &request),
+            ))
+        })
+    }
+}
+
+struct DescribeProducersHandler;
+
+impl ApiHandler for DescribeProducersHandler {
+    fn api_key(&self) -> i16 {
+        DESCRIBE_PRODUCERS
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        0..=0
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = DescribeProducersRequest::parse(ctx.body)?;
+            Ok(KafkaResponse::DescribeProducers(describe_producers(
+                &*ctx.state.logs,
+                &ctx.session(),
+                &request,
+            )))
+        })
+    }
+}
+
+struct DescribeLogDirsHandler;
+
+impl ApiHandler for DescribeLogDirsHandler {
+    fn api_key(&self) -> i16 {
+        DESCRIBE_LOG_DIRS
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        4..=4
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = DescribeLogDirsRequest::parse(ctx.body)?;
+            // sizes are read off the segment files
+            let logs = ctx.state.logs.clone();
+            let session = ctx.session();
+            let response =
+                run_blocking(move || describe_log_dirs(&*logs, &session, &request)).await?;
+            Ok(KafkaResponse::DescribeLogDirs(response))
+        })
+    }
+}
+
+// ### CLUSTER ### //
+struct MetadataHandler;
+
+impl ApiHandler for MetadataHandler {
+    fn api_key(&self) -> i16 {
+        METADATA
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        12..=12
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = MetadataRequest::parse(ctx.body)?;
+            // auto-creating a topic writes its partition directories
+            let state = ctx.state.clone();
+            let session = ctx.session();
+            let response = run_blocking(move || {
+                let cluster_id = state.meta.as_ref().map(|meta| meta.cluster_id.as_str());
+                metadata(
+                    &state.config,
+                    &*state.logs,
+                    &state.isr,
+                    &state.metadata_log,
+                    &session,
+                    cluster_id,
+                    &request,
+                )
+            })
+            .await?;
+            Ok(KafkaResponse::Metadata(response))
+        })
+    }
+}
+
+struct DescribeClusterHandler;
+
+impl ApiHandler for DescribeClusterHandler {
+    fn api_key(&self) -> i16 {
+        DESCRIBE_CLUSTER
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        0..=0
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            DescribeClusterRequest::parse(ctx.body)?;
+            // unformatted log dirs leave the cluster without an id
+            let cluster_id = ctx
+                .state
+                .meta
+                .as_ref()
+                .map(|meta| meta.cluster_id.as_str())
+                .unwrap_or_default();
+            Ok(KafkaResponse::DescribeCluster(describe_cluster(
+                &ctx.state.config,
+                cluster_id,
+            )))
+        })
+    }
+}
+
+// ### ACLS ### //
+struct DescribeAclsHandler;
+
+impl ApiHandler for DescribeAclsHandler {
+    fn api_key(&self) -> i16 {
+        DESCRIBE_ACLS
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        3..=3
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = DescribeAclsRequest::parse(ctx.body)?;
+            Ok(KafkaResponse::DescribeAcls(describe_acls(
+                &ctx.session(),
+                &request,
+            )))
+        })
+    }
+}
+
+struct CreateAclsHandler;
+
+impl ApiHandler for CreateAclsHandler {
+    fn api_key(&self) -> i16 {
+        CREATE_ACLS
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        3..=3
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = CreateAclsRequest::parse(ctx.body)?;
+            // the acls file is rewritten on every change
+            let session = ctx.session();
+            let response = run_blocking(move || create_acls(&session, &request)).await?;
+            Ok(KafkaResponse::CreateAcls(response))
+        })
+    }
+}
+
+struct DeleteAclsHandler;
+
+impl ApiHandler for DeleteAclsHandler {
+    fn api_key(&self) -> i16 {
+        DELETE_ACLS
+    }
+
+    fn version_range(&self) -> RangeInclusive<i16> {
+        3..=3
+    }
+
+    fn handle<'a>(&'a self, ctx: RequestContext<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let request = DeleteAclsRequest::parse(ctx.body)?;
+            let session = ctx.session();
+            let response = run_blocking(move || delete_acls(&session, &request)).await?;
+            Ok(KafkaResponse::DeleteAcls(response))
+        })
+    }
+}
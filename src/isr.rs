@@ -0,0 +1,254 @@
+use crate::cluster_metadata::MetadataImage;
+use crate::purgatory::Purgatory;
+use crate::storage::{LogStore, TopicPartition};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+struct FollowerProgress {
+    // the offset its last fetch started at, everything before it has been replicated
+    log_end_offset: i64,
+    // when a fetch of its last reached the leader's log end offset
+    last_caught_up: Instant,
+}
+
+struct PartitionIsr {
+    topic_partition: TopicPartition,
+    // the followers in the isr, the leader always is
+    isr: BTreeSet<i32>,
+    followers: BTreeMap<i32, FollowerProgress>,
+}
+
+impl PartitionIsr {
+    // returns whether any follower was dropped
+    fn shrink(&mut self, lag_time_max: Duration) -> bool {
+        let lagging: Vec<i32> = self
+            .isr
+            .iter()
+            .filter(|replica_id| self.followers[replica_id].last_caught_up.elapsed() > lag_time_max)
+            .copied()
+            .collect();
+        for replica_id in &lagging {
+            self.isr.remove(replica_id);
+            println!(
+                "Replica {replica_id} fell out of the isr of {}-{}",
+                self.topic_partition.topic, self.topic_partition.partition
+            );
+        }
+        !lagging.is_empty()
+    }
+
+    // as far as every follower in the isr has replicated, the log end offset once the leader
+    // is on its own. it only ever moves up, like kafka's maybeIncrementLeaderHW, so a follower
+    // that starts over lower down can't take back what consumers were already given. fetches
+    // waiting on the partition get another look
+    fn update_high_watermark(&self, logs: &dyn LogStore, purgatory: &Purgatory<TopicPartition>) {
+        let high_watermark = self
+            .isr
+            .iter()
+            .map(|replica_id| self.followers[replica_id].log_end_offset)
+            .min();
+        let current = logs.high_watermark(&self.topic_partition);
+        if high_watermark.is_some_and(|high_watermark| current >= Some(high_watermark)) {
+            return;
+        }
+        logs.set_high_watermark(&self.topic_partition, high_watermark);
+        purgatory.check(&self.topic_partition);
+    }
+}
+
+// the in-sync replicas of the partitions the cluster metadata log makes this broker the
+// leader of, keyed by topic id and partition like fetches name them. membership only lives
+// in memory, so the isr starts out as the leader alone and followers join it as they catch up.
+// the leader side of AlterPartition is left for later: brokers don't register with the
+// controller, so there's no broker epoch to send shrinks and expansions under, and the isr in
+// the metadata log stays whatever it was assigned rather than following this one
+pub struct IsrTracker {
+    lag_time_max: Duration,
+    partitions: Mutex<BTreeMap<(i128, i32), PartitionIsr>>,
+    fetch_purgatory: Arc<Purgatory<TopicPartition>>,
+}
+
+impl IsrTracker {
+    // partitions without followers are left out, their leader is all of their isr
+    pub fn new(
+        node_id: i32,
+        image: &MetadataImage,
+        lag_time_max: Duration,
+        fetch_purgatory: Arc<Purgatory<TopicPartition>>,
+    ) -> Arc<Self> {
+        let now = Instant::now();
+        let partitions = image
+            .partitions
+            .iter()
+            .filter(|(_, partition)| partition.leader == node_id && partition.replicas.len() > 1)
+            .map(|(topic_partition, partition)| {
+                let followers = partition
+                    .replicas
+                    .iter()
+                    .filter(|&&replica_id| replica_id != node_id)
+                    .map(|&replica_id| {
+                        let progress = FollowerProgress {
+                            log_end_offset: 0,
+                            last_caught_up: now,
+                        };
+                        (replica_id, progress)
+                    })
+                    .collect();
+                let tracked = PartitionIsr {
+                    topic_partition: topic_partition.clone(),
+                    isr: BTreeSet::new(),
+                    followers,
+                };
+                ((partition.topic_id, topic_partition.partition), tracked)
+            })
+            .collect();
+
+        Arc::new(IsrTracker {
+            lag_time_max,
+            partitions: Mutex::new(partitions),
+            fetch_purgatory,
+        })
+    }
+
+    // blocking, reads the leader's log end offset. a follower's fetch offset is its own log
+    // end offset, and one that reaches the leader's (re)joins the isr. fetches from anyone
+    // who isn't a follower of the partition are left alone
+    pub fn record_fetch(
+        &self,
+        logs: &dyn LogStore,
+        replica_id: i32,
+        topic_id: i128,
+        partition: i32,
+        fetch_offset: i64,
+    ) {
+        let mut partitions = self.partitions.lock().unwrap();
+        let Some(tracked) = partitions.get_mut(&(topic_id, partition)) else {
+            return;
+        };
+        let Some(log_end_offset) = logs.log_end_offset(&tracked.topic_partition) else {
+            return;
+        };
+        let Some(follower) = tracked.followers.get_mut(&replica_id) else {
+            return;
+        };
+
+        follower.log_end_offset = fetch_offset;
+        if fetch_offset >= log_end_offset {
+            follower.last_caught_up = Instant::now();
+            if tracked.isr.insert(replica_id) {
+                println!(
+                    "Replica {replica_id} joined the isr of {}-{}",
+                    tracked.topic_partition.topic, tracked.topic_partition.partition
+                );
+            }
+        }
+        tracked.shrink(self.lag_time_max);
+        tracked.update_high_watermark(logs, &self.fetch_purgatory);
+    }
+
+    // blocking. drops the followers that haven't caught up for replica.lag.time.max.ms,
+    // including ones that stopped fetching altogether
+    pub fn shrink_lagging(&self, logs: &dyn LogStore) {
+        let mut partitions = self.partitions.lock().unwrap();
+        for tracked in partitions.values_mut() {
+            if tracked.shrink(self.lag_time_max) {
+                tracked.update_high_watermark(logs, &self.fetch_purgatory);
+            }
+        }
+    }
+
+    // (replicas, isr), the leader first in both. `None` for a partition not tracked here
+    pub fn replicas_and_isr(
+        &self,
+        leader: i32,
+        topic_id: i128,
+        partition: i32,
+    ) -> Option<(Vec<i32>, Vec<i32>)> {
+        let partitions = self.partitions.lock().unwrap();
+        let tracked = partitions.get(&(topic_id, partition))?;
+        let with_leader = |followers: Vec<i32>| [vec![leader], followers].concat();
+        Some((
+            with_leader(tracked.followers.keys().copied().collect()),
+            with_leader(tracked.isr.iter().copied().collect()),
+        ))
+    }
+}
+
+pub async fn run_isr_shrinker(
+    logs: Arc<dyn LogStore>,
+    isr: Arc<IsrTracker>,
+    check_interval: Duration,
+) {
+    let mut interval = tokio::time::interval(check_interval);
+    loop {
+        interval.tick().await;
+
+        let logs = logs.clone();
+        let isr = isr.clone();
+        if let Err(e) = tokio::task::spawn_blocking(move || isr.shrink_lagging(&*logs)).await {
+            eprintln!("Error shrinking isrs: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_log::MemoryLogStore;
+    use crate::records::encode_batch;
+
+    // a partition with offsets 0 to 2 and the one follower, 2, in its isr
+    fn partition(logs: &MemoryLogStore) -> PartitionIsr {
+        let topic_partition = TopicPartition {
+            topic: "t".into(),
+            partition: 0,
+        };
+        logs.open_replica(&topic_partition, 1).unwrap();
+        let batch = encode_batch(0, 0, 0, vec![vec![]; 3]);
+        logs.append_replica(&topic_partition, &batch, u64::MAX)
+            .unwrap();
+
+        let progress = FollowerProgress {
+            log_end_offset: 0,
+            last_caught_up: Instant::now(),
+        };
+        PartitionIsr {
+            topic_partition,
+            isr: BTreeSet::from([2]),
+            followers: BTreeMap::from([(2, progress)]),
+        }
+    }
+
+    #[test]
+    fn high_watermark_follows_the_isr() {
+        let logs = MemoryLogStore::new();
+        let purgatory = Purgatory::new();
+        let mut tracked = partition(&logs);
+
+        tracked.followers.get_mut(&2).unwrap().log_end_offset = 2;
+        tracked.update_high_watermark(&*logs, &purgatory);
+        assert_eq!(logs.high_watermark(&tracked.topic_partition), Some(2));
+
+        // on its own the leader has everything committed
+        tracked.isr.clear();
+        tracked.update_high_watermark(&*logs, &purgatory);
+        assert_eq!(logs.high_watermark(&tracked.topic_partition), Some(3));
+    }
+
+    #[test]
+    fn high_watermark_never_moves_back() {
+        let logs = MemoryLogStore::new();
+        let purgatory = Purgatory::new();
+        let mut tracked = partition(&logs);
+
+        tracked.followers.get_mut(&2).unwrap().log_end_offset = 3;
+        tracked.update_high_watermark(&*logs, &purgatory);
+        assert_eq!(logs.high_watermark(&tracked.topic_partition), Some(3));
+
+        // like a follower that restarted, or was reset, fetching from further back
+        tracked.followers.get_mut(&2).unwrap().log_end_offset = 1;
+        tracked.update_high_watermark(&*logs, &purgatory);
+        assert_eq!(logs.high_watermark(&tracked.topic_partition), Some(3));
+    }
+}
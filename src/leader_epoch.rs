@@ -0,0 +1,161 @@
+use crate::KafkaError;
+use std::path::{Path, PathBuf};
+
+const CHECKPOINT_FILE_NAME: &str = "leader-epoch-checkpoint";
+const CHECKPOINT_VERSION: i32 = 0;
+
+pub const UNDEFINED_EPOCH: i32 = -1;
+pub const UNDEFINED_EPOCH_OFFSET: i64 = -1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct EpochEntry {
+    epoch: i32,
+    start_offset: i64,
+}
+
+// the offset each leader epoch of a partition started at, backed by the partition's
+// `leader-epoch-checkpoint` file (a version line, an entry count, then `<epoch> <offset>` lines)
+pub struct LeaderEpochCache {
+    // unset for partitions that only live in memory
+    path: Option<PathBuf>,
+    // ascending in both epoch and start offset
+    entries: Vec<EpochEntry>,
+}
+
+impl LeaderEpochCache {
+    pub fn load(partition_dir: &Path) -> Result<Self, KafkaError> {
+        let path = partition_dir.join(CHECKPOINT_FILE_NAME);
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(LeaderEpochCache {
+                    path: Some(path),
+                    entries: vec![],
+                })
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let malformed = || {
+            KafkaError::CorruptedMessage(format!("malformed checkpoint file {}", path.display()))
+        };
+        let mut lines = contents.lines();
+
+        let version = lines
+            .next()
+            .and_then(|line| line.trim().parse::<i32>().ok());
+        if version != Some(CHECKPOINT_VERSION) {
+            return Err(malformed());
+        }
+        let count = lines
+            .next()
+            .and_then(|line| line.trim().parse::<usize>().ok())
+            .ok_or_else(malformed)?;
+
+        let mut cache = LeaderEpochCache {
+            path: Some(path.clone()),
+            entries: Vec::with_capacity(count),
+        };
+        for _ in 0..count {
+            let entry = lines
+                .next()
+                .and_then(|line| line.split_once(' '))
+                .and_then(|(epoch, offset)| Some((epoch.parse().ok()?, offset.parse().ok()?)))
+                .ok_or_else(malformed)?;
+            cache.assign(entry.0, entry.1);
+        }
+
+        Ok(cache)
+    }
+
+    pub fn in_memory() -> Self {
+        LeaderEpochCache {
+            path: None,
+            entries: vec![],
+        }
+    }
+
+    pub fn latest_epoch(&self) -> Option<i32> {
+        self.entries.last().map(|entry| entry.epoch)
+    }
+
+    // records that `epoch` starts at `start_offset`, returns whether that's news to the cache.
+    // epochs older than the latest one are ignored, they're already accounted for
+    pub fn assign(&mut self, epoch: i32, start_offset: i64) -> bool {
+        if epoch < 0 {
+            return false;
+        }
+        match self.entries.last() {
+            Some(latest) if latest.epoch >= epoch || latest.start_offset > start_offset => false,
+            _ => {
+                self.entries.push(EpochEntry {
+                    epoch,
+                    start_offset,
+                });
+                true
+            }
+        }
+    }
+
+    // drops the epochs ending before the new log start offset, the epoch covering it now
+    // starts there
+    pub fn truncate_from_start(&mut self, log_start_offset: i64) -> bool {
+        let covered = self
+            .entries
+            .iter()
+            .take_while(|entry| entry.start_offset <= log_start_offset)
+            .count();
+        if covered == 0 {
+            return false;
+        }
+
+        let changed = covered > 1 || self.entries[0].start_offset != log_start_offset;
+        self.entries.drain(..covered - 1);
+        self.entries[0].start_offset = log_start_offset;
+        changed
+    }
+
+    // the largest epoch <= `requested_epoch` this log knows of, along with the offset the
+    // epoch after it starts at (the log end offset when it's the latest one)
+    pub fn end_offset_for(&self, requested_epoch: i32, log_end_offset: i64) -> (i32, i64) {
+        if requested_epoch == UNDEFINED_EPOCH {
+            return (UNDEFINED_EPOCH, UNDEFINED_EPOCH_OFFSET);
+        }
+        if self.latest_epoch() == Some(requested_epoch) {
+            return (requested_epoch, log_end_offset);
+        }
+
+        let Some(higher) = self
+            .entries
+            .iter()
+            .find(|entry| entry.epoch > requested_epoch)
+        else {
+            return (UNDEFINED_EPOCH, UNDEFINED_EPOCH_OFFSET);
+        };
+
+        let floor = self
+            .entries
+            .iter()
+            .rev()
+            .find(|entry| entry.epoch <= requested_epoch);
+        match floor {
+            Some(floor) => (floor.epoch, higher.start_offset),
+            None => (requested_epoch, higher.start_offset),
+        }
+    }
+
+    // written to a temp file first so a crash can't leave a half-written checkpoint behind
+    pub fn flush(&self) -> std::io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut contents = format!("{CHECKPOINT_VERSION}\n{}\n", self.entries.len());
+        for entry in &self.entries {
+            contents.push_str(&format!("{} {}\n", entry.epoch, entry.start_offset));
+        }
+
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, contents)?;
+        std::fs::rename(&tmp_path, path)
+    }
+}
@@ -1,20 +1,183 @@
 #![allow(dead_code)]
+use std::convert::Infallible;
 use std::io::Cursor;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
 use thiserror::Error;
-use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::TcpStream,
-};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
 
+mod acl;
+mod acl_api;
+mod audit_log;
+mod broker;
+mod broker_registry;
+mod buffer_pool;
+mod client;
+mod client_quota;
+mod cluster_api;
+mod cluster_metadata;
+mod config;
+mod config_api;
+mod console;
+mod controller;
+mod crypto;
+mod fault_injection;
+mod fetch_interceptor;
+mod group_api;
+mod group_coordinator;
+mod handlers;
+mod isr;
+mod leader_epoch;
+mod memory_log;
+mod meta_properties;
+mod metadata_snapshot;
+mod metrics;
+mod offset_api;
+mod partition_api;
+mod producer_state;
+mod purgatory;
+mod quorum_api;
+mod quota;
+mod quota_api;
+mod raft;
 mod readers;
+mod records;
+mod registration_api;
+mod replay;
+mod replica_fetcher;
+mod replica_selector;
+mod sasl;
+mod scram;
+mod scram_api;
+mod storage;
+mod topic_config;
+mod wire_trace;
+mod writers;
+pub use acl::{AclBinding, AclBindingFilter, AclStore, Authorizer, Session, StandardAuthorizer};
+use acl_api::*;
+pub use acl_api::{CreateAclsRequest, DeleteAclsRequest, DescribeAclsRequest};
+use audit_log::AuditEntry;
+pub use audit_log::AuditLog;
+pub use broker::{Broker, BrokerBuilder, BrokerHandle};
+pub use broker_registry::BrokerRegistry;
+use buffer_pool::BufferPool;
+pub use client::KafkaClient;
+pub use client_quota::ClientQuotaStore;
+use client_quota::ANONYMOUS_USER;
+use cluster_api::*;
+pub use cluster_api::{DescribeClusterRequest, MetadataRequest};
+pub use cluster_metadata::{
+    FinalizedFeatures, MetadataImage, MetadataLog, MetadataRecord, PartitionRegistration,
+    SUPPORTED_FEATURES,
+};
+pub use config::{BrokerConfig, LogStoreKind};
+use config_api::*;
+pub use config_api::{DescribeConfigsRequest, IncrementalAlterConfigsRequest};
+pub use console::{consume, ConsumeOptions, CONSUME_USAGE};
+pub use fault_injection::{FaultConfig, FaultInjector};
+pub use fetch_interceptor::{FetchInterceptor, NoopFetchInterceptor};
+use group_api::*;
+pub use group_api::{
+    DescribeGroupsRequest, HeartbeatRequest, JoinGroupRequest, LeaveGroupRequest,
+    ListGroupsRequest, SyncGroupRequest,
+};
+pub use group_coordinator::GroupCoordinator;
+use handlers::{ApiRegistry, RequestContext};
+use isr::IsrTracker;
+pub use memory_log::MemoryLogStore;
+pub use meta_properties::{load_meta_properties, MetaProperties};
+pub use metrics::{serve_metrics, ClientSoftware, Metrics, NodeState};
+use offset_api::*;
+pub use offset_api::{OffsetCommitRequest, OffsetFetchRequest};
+use partition_api::*;
+pub use partition_api::{
+    AlterPartitionReassignmentsRequest, DescribeLogDirsRequest, DescribeProducersRequest,
+    ElectLeadersRequest, ListPartitionReassignmentsRequest, OffsetForLeaderEpochRequest,
+};
+pub use producer_state::ProducerState;
+use purgatory::Purgatory;
+use quorum_api::*;
+pub use quorum_api::{
+    DescribeQuorumRequest, FetchSnapshotRequest, QuorumEpochRequest, VoteRequest,
+};
+pub use quota::QuotaManager;
+use quota_api::*;
+pub use quota_api::{AlterClientQuotasRequest, DescribeClientQuotasRequest};
+use raft::{QuorumState, RaftQuorum};
 use readers::*;
+pub use records::{decode_records, encode_batch, rewrite_records, Record, RecordHeader};
+use registration_api::*;
+pub use registration_api::{
+    AlterPartitionRequest, BrokerHeartbeatRequest, BrokerRegistrationRequest,
+};
+pub use replay::{
+    read_recording, replay, RecordedRequest, ReplayOptions, RequestRecorder, REPLAY_USAGE,
+};
+pub use replica_fetcher::run_replica_fetchers;
+pub use replica_selector::ClientMetadata;
+use sasl::*;
+pub use sasl::{SaslAuthenticateRequest, SaslHandshakeRequest};
+pub use scram::ScramCredentialStore;
+pub use scram_api::AlterUserScramCredentialsRequest;
+use scram_api::*;
+pub use storage::{run_log_cleaner, run_partition_discovery, LogManager, LogStore, TopicPartition};
+pub use topic_config::TopicConfigStore;
+use wire_trace::{trace_request, trace_response};
+use writers::*;
 
 // ### ERRORS ### //
 const UNKNOWN_SERVER_ERROR: i16 = -1;
 const NONE: i16 = 0;
+const OFFSET_OUT_OF_RANGE: i16 = 1;
 const CORRUPT_MESSAGE: i16 = 2;
+const UNKNOWN_TOPIC_OR_PARTITION: i16 = 3;
+const NOT_LEADER_OR_FOLLOWER: i16 = 6;
+const REQUEST_TIMED_OUT: i16 = 7;
+const MESSAGE_TOO_LARGE: i16 = 10;
+const OFFSET_METADATA_TOO_LARGE: i16 = 12;
+const INVALID_TOPIC_EXCEPTION: i16 = 17;
+const ILLEGAL_GENERATION: i16 = 22;
+const INCONSISTENT_GROUP_PROTOCOL: i16 = 23;
+const INVALID_GROUP_ID: i16 = 24;
+const UNKNOWN_MEMBER_ID: i16 = 25;
+const INVALID_SESSION_TIMEOUT: i16 = 26;
+const REBALANCE_IN_PROGRESS: i16 = 27;
+const UNSUPPORTED_SASL_MECHANISM: i16 = 33;
+const ILLEGAL_SASL_STATE: i16 = 34;
 const UNSUPPORTED_VERSION: i16 = 35;
+const TOPIC_ALREADY_EXISTS: i16 = 36;
+const INVALID_PARTITIONS: i16 = 37;
+const TOPIC_AUTHORIZATION_FAILED: i16 = 29;
+const GROUP_AUTHORIZATION_FAILED: i16 = 30;
+const CLUSTER_AUTHORIZATION_FAILED: i16 = 31;
+const INVALID_REPLICA_ASSIGNMENT: i16 = 39;
+const INVALID_CONFIG: i16 = 40;
+const NOT_CONTROLLER: i16 = 41;
 const INVALID_REQUEST: i16 = 42;
+const SECURITY_DISABLED: i16 = 54;
+const OPERATION_NOT_ATTEMPTED: i16 = 55;
+const KAFKA_STORAGE_ERROR: i16 = 56;
+const SASL_AUTHENTICATION_FAILED: i16 = 58;
+const FENCED_LEADER_EPOCH: i16 = 74;
+const UNKNOWN_LEADER_EPOCH: i16 = 76;
+const STALE_BROKER_EPOCH: i16 = 77;
+const MEMBER_ID_REQUIRED: i16 = 79;
+const FENCED_INSTANCE_ID: i16 = 82;
+const ELECTION_NOT_NEEDED: i16 = 84;
+const NO_REASSIGNMENT_IN_PROGRESS: i16 = 85;
+const RESOURCE_NOT_FOUND: i16 = 91;
+const DUPLICATE_RESOURCE: i16 = 92;
+const UNACCEPTABLE_CREDENTIAL: i16 = 93;
+const INCONSISTENT_VOTER_SET: i16 = 94;
+const INVALID_UPDATE_VERSION: i16 = 95;
+const SNAPSHOT_NOT_FOUND: i16 = 98;
+const POSITION_OUT_OF_RANGE: i16 = 99;
+const UNKNOWN_TOPIC_ID: i16 = 100;
+const DUPLICATE_BROKER_REGISTRATION: i16 = 101;
+const INCONSISTENT_TOPIC_ID: i16 = 103;
+const INCONSISTENT_CLUSTER_ID: i16 = 104;
+const INELIGIBLE_REPLICA: i16 = 107;
 
 #[derive(Debug, Error)]
 pub enum KafkaError {
@@ -22,6 +185,13 @@ pub enum KafkaError {
     Io(#[from] std::io::Error),
     #[error("Invalid message length: {0}")]
     InvalidMessageLength(i32),
+    #[error("Request of {size} bytes for api key {api_key} exceeds the {limit} byte limit")]
+    RequestTooLarge {
+        api_key: i16,
+        correlation_id: i32,
+        size: usize,
+        limit: usize,
+    },
     #[error("Unsupported API version: {0}")]
     UnsupportedApiVersion(i16),
     #[error("Invalid string data: {0}")]
@@ -30,6 +200,22 @@ pub enum KafkaError {
     UnsupportedApiKey(i16),
     #[error("Received corrupted message data: {0}")]
     CorruptedMessage(String),
+    #[error("Invalid broker config: {0}")]
+    InvalidConfig(String),
+    #[error("Request not allowed in the connection's SASL state: api key {0}")]
+    IllegalSaslState(i16),
+    #[error("Timed out {operation} after {timeout_ms}ms")]
+    Timeout {
+        operation: &'static str,
+        timeout_ms: u64,
+    },
+    #[error("Log dir {} is in use by another broker process (pid {pid})", log_dir.display())]
+    LogDirLocked {
+        log_dir: std::path::PathBuf,
+        pid: u32,
+    },
+    #[error("Not the leader of the metadata quorum")]
+    NotController,
 }
 
 impl KafkaError {
@@ -38,72 +224,188 @@ impl KafkaError {
             KafkaError::Io(_) => UNKNOWN_SERVER_ERROR,
             KafkaError::UnsupportedApiKey(_) => INVALID_REQUEST,
             KafkaError::InvalidMessageLength(_) => CORRUPT_MESSAGE,
+            KafkaError::RequestTooLarge { .. } => MESSAGE_TOO_LARGE,
             KafkaError::InvalidString(_) => CORRUPT_MESSAGE,
             KafkaError::UnsupportedApiVersion(_) => UNSUPPORTED_VERSION,
             KafkaError::CorruptedMessage(_) => CORRUPT_MESSAGE,
+            KafkaError::InvalidConfig(_) => UNKNOWN_SERVER_ERROR,
+            KafkaError::IllegalSaslState(_) => ILLEGAL_SASL_STATE,
+            KafkaError::Timeout { .. } => REQUEST_TIMED_OUT,
+            KafkaError::LogDirLocked { .. } => UNKNOWN_SERVER_ERROR,
+            KafkaError::NotController => NOT_CONTROLLER,
         }
     }
 }
 
 // ### CONSTANTS ### //
 const FETCH: i16 = 1;
+const METADATA: i16 = 3;
+const OFFSET_COMMIT: i16 = 8;
+const OFFSET_FETCH: i16 = 9;
+const JOIN_GROUP: i16 = 11;
+const HEARTBEAT: i16 = 12;
+const LEAVE_GROUP: i16 = 13;
+const SYNC_GROUP: i16 = 14;
+const DESCRIBE_GROUPS: i16 = 15;
+const LIST_GROUPS: i16 = 16;
+const SASL_HANDSHAKE: i16 = 17;
 const APIVERSIONS: i16 = 18;
+const OFFSET_FOR_LEADER_EPOCH: i16 = 23;
+const DESCRIBE_ACLS: i16 = 29;
+const CREATE_ACLS: i16 = 30;
+const DELETE_ACLS: i16 = 31;
+const DESCRIBE_CONFIGS: i16 = 32;
+const DESCRIBE_LOG_DIRS: i16 = 35;
+const SASL_AUTHENTICATE: i16 = 36;
+const ELECT_LEADERS: i16 = 43;
+const INCREMENTAL_ALTER_CONFIGS: i16 = 44;
+const ALTER_PARTITION_REASSIGNMENTS: i16 = 45;
+const LIST_PARTITION_REASSIGNMENTS: i16 = 46;
+const DESCRIBE_CLIENT_QUOTAS: i16 = 48;
+const ALTER_CLIENT_QUOTAS: i16 = 49;
+const ALTER_USER_SCRAM_CREDENTIALS: i16 = 51;
+const VOTE: i16 = 52;
+const BEGIN_QUORUM_EPOCH: i16 = 53;
+const END_QUORUM_EPOCH: i16 = 54;
+const DESCRIBE_QUORUM: i16 = 55;
+const ALTER_PARTITION: i16 = 56;
+const FETCH_SNAPSHOT: i16 = 59;
+const DESCRIBE_CLUSTER: i16 = 60;
+const DESCRIBE_PRODUCERS: i16 = 61;
+const BROKER_REGISTRATION: i16 = 62;
+const BROKER_HEARTBEAT: i16 = 63;
 
-const API_VERS_INFO: &[ApiKeyVerInfo] = &[
-    ApiKeyVerInfo {
-        id: APIVERSIONS,
-        min: 4,
-        max: 4,
-    },
-    ApiKeyVerInfo {
-        id: FETCH,
-        min: 16,
-        max: 16,
-    },
-];
 const TAG_BUFFER: &[u8] = &[0];
+// the tagged field a follower's Fetch carries its replica id and epoch in
+const REPLICA_STATE_TAG: u32 = 1;
 // ### ### ### //
 
-struct KafkaRequestHeader {
-    api_key: i16,
-    api_ver: i16,
-    correlation_id: i32,
-    _client_id: Option<String>,
+// flexible versions use request header v2, which adds a TAG_BUFFER after the client id
+fn is_flexible_version(api_key: i16, api_ver: i16) -> bool {
+    match api_key {
+        APIVERSIONS => api_ver >= 3,
+        FETCH => api_ver >= 12,
+        METADATA => api_ver >= 9,
+        OFFSET_COMMIT => api_ver >= 8,
+        OFFSET_FETCH => api_ver >= 6,
+        DESCRIBE_GROUPS => api_ver >= 5,
+        LIST_GROUPS => api_ver >= 3,
+        SASL_AUTHENTICATE => api_ver >= 2,
+        OFFSET_FOR_LEADER_EPOCH => api_ver >= 4,
+        DESCRIBE_CONFIGS => api_ver >= 4,
+        DESCRIBE_ACLS | CREATE_ACLS | DELETE_ACLS => api_ver >= 2,
+        DESCRIBE_LOG_DIRS => api_ver >= 2,
+        ELECT_LEADERS => api_ver >= 2,
+        INCREMENTAL_ALTER_CONFIGS => api_ver >= 1,
+        DESCRIBE_CLIENT_QUOTAS | ALTER_CLIENT_QUOTAS => api_ver >= 1,
+        JOIN_GROUP => api_ver >= 6,
+        HEARTBEAT | LEAVE_GROUP | SYNC_GROUP => api_ver >= 4,
+        BEGIN_QUORUM_EPOCH | END_QUORUM_EPOCH => api_ver >= 1,
+        ALTER_PARTITION_REASSIGNMENTS
+        | LIST_PARTITION_REASSIGNMENTS
+        | ALTER_USER_SCRAM_CREDENTIALS
+        | VOTE
+        | DESCRIBE_QUORUM
+        | ALTER_PARTITION
+        | FETCH_SNAPSHOT
+        | DESCRIBE_CLUSTER
+        | DESCRIBE_PRODUCERS
+        | BROKER_REGISTRATION
+        | BROKER_HEARTBEAT => api_ver >= 0,
+        _ => false,
+    }
+}
+
+pub struct KafkaRequestHeader {
+    pub api_key: i16,
+    pub api_ver: i16,
+    pub correlation_id: i32,
+    pub client_id: Option<String>,
 }
 
 impl KafkaRequestHeader {
-    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+    // returns the header along with the remaining request body
+    pub fn parse(buffer: &[u8]) -> Result<(Self, &[u8]), KafkaError> {
         let mut cursor = Cursor::new(buffer);
         let api_key = read_int16(&mut cursor)?;
         let api_ver = read_int16(&mut cursor)?;
         let correlation_id = read_int32(&mut cursor)?;
-        let _client_id = read_nullable_string(&mut cursor)?;
+        let client_id = read_nullable_string(&mut cursor)?;
+        if is_flexible_version(api_key, api_ver) {
+            read_tagged_fields(&mut cursor)?;
+        }
 
-        Ok(KafkaRequestHeader {
-            api_key,
-            api_ver,
-            correlation_id,
-            _client_id,
-        })
+        let body = buffer
+            .get(cursor.position() as usize..)
+            .ok_or_else(|| KafkaError::CorruptedMessage("request header overruns frame".into()))?;
+
+        Ok((
+            KafkaRequestHeader {
+                api_key,
+                api_ver,
+                correlation_id,
+                client_id,
+            },
+            body,
+        ))
+    }
+
+    pub fn encode(&self, req_buf: &mut Vec<u8>) {
+        req_buf.extend_from_slice(&self.api_key.to_be_bytes());
+        req_buf.extend_from_slice(&self.api_ver.to_be_bytes());
+        req_buf.extend_from_slice(&self.correlation_id.to_be_bytes());
+        match &self.client_id {
+            Some(client_id) => {
+                req_buf.extend_from_slice(&(client_id.len() as i16).to_be_bytes());
+                req_buf.extend_from_slice(client_id.as_bytes());
+            }
+            None => req_buf.extend_from_slice(&(-1i16).to_be_bytes()),
+        }
+        if is_flexible_version(self.api_key, self.api_ver) {
+            req_buf.extend_from_slice(TAG_BUFFER);
+        }
+    }
+
+    fn audit_entry<'a>(
+        &'a self,
+        client_host: &'a str,
+        sasl_state: &'a SaslState,
+        response: &KafkaResponse,
+        request_start: Instant,
+    ) -> AuditEntry<'a> {
+        AuditEntry {
+            api_key: self.api_key,
+            api_version: self.api_ver,
+            correlation_id: self.correlation_id,
+            client_id: self.client_id.as_deref().unwrap_or_default(),
+            client_host,
+            principal: sasl_state.principal(),
+            error_code: response.error_code(),
+            latency: request_start.elapsed(),
+        }
     }
 }
 
-struct FetchRequest {
-    correlation_id: i32,
-    max_wait_ms: i32,
-    min_bytes: i32,
-    max_bytes: i32,
-    isolation_level: i8,
-    session_id: i32,
-    session_epoch: i32,
-    topics: Vec<RequestTopic>,
-    forgotten_topics: Vec<ForgottenTopic>,
-    rack_id: String,
+pub struct FetchRequest {
+    pub correlation_id: i32,
+    pub max_wait_ms: i32,
+    pub min_bytes: i32,
+    pub max_bytes: i32,
+    pub isolation_level: i8,
+    pub session_id: i32,
+    pub session_epoch: i32,
+    pub topics: Vec<RequestTopic>,
+    pub forgotten_topics: Vec<ForgottenTopic>,
+    pub rack_id: String,
+    // -1 for consumers. a follower's broker id and epoch, which from v15 come in the
+    // ReplicaState tagged field
+    pub replica_id: i32,
+    pub replica_epoch: i64,
 }
 
 impl FetchRequest {
     fn parse(buffer: &[u8], correlation_id: i32) -> Result<FetchRequest, KafkaError> {
-        let mut cursor = Cursor::new(buffer);
+        let mut cursor = RequestReader::new(buffer);
 
         let max_wait_ms = read_int32(&mut cursor)?;
         let min_bytes = read_int32(&mut cursor)?;
@@ -112,30 +414,22 @@ impl FetchRequest {
         let session_id = read_int32(&mut cursor)?;
         let session_epoch = read_int32(&mut cursor)?;
 
-        let topics_size = read_int32(&mut cursor)?; // [topics]
-        if topics_size < 0 {
-            return Err(KafkaError::CorruptedMessage(format!(
-                "expected request's fetched topics size value to be greater than 0, got {topics_size}"
-            )));
-        }
-        let mut topics = Vec::with_capacity(topics_size as usize);
+        let topics_size = read_compact_array_len(&mut cursor)?; // [topics]
+        let mut topics = array_with_capacity(topics_size);
 
         for _ in 0..topics_size {
             let topic_id = read_int128(&mut cursor)?;
-            let partitions_size = read_int32(&mut cursor)?; // [partitions]
-            if partitions_size < 0 {
-                return Err(KafkaError::CorruptedMessage(format!("expected request's fetched partitions size value to be greater than 0, got {partitions_size}")));
-            }
-            let mut partitions = Vec::with_capacity(partitions_size as usize);
+            let partitions_size = read_compact_array_len(&mut cursor)?; // [partitions]
+            let mut partitions = array_with_capacity(partitions_size);
 
             for _ in 0..partitions_size {
-                let partition = read_int32(&mut cursor)?; // not sure if here or above
+                let partition = read_int32(&mut cursor)?;
                 let current_leader_epoch = read_int32(&mut cursor)?;
                 let fetch_offset = read_int64(&mut cursor)?;
                 let last_fetched_epoch = read_int32(&mut cursor)?;
                 let log_start_offset = read_int64(&mut cursor)?;
                 let partition_max_bytes = read_int32(&mut cursor)?;
-                let _ = read_int8(&mut cursor)?; // TAG_BUFFER?
+                read_tagged_fields(&mut cursor)?;
 
                 partitions.push(RequestPartition {
                     partition,
@@ -147,36 +441,27 @@ impl FetchRequest {
                 });
             }
 
+            read_tagged_fields(&mut cursor)?;
+
             topics.push(RequestTopic {
                 topic_id,
                 partitions,
             })
         }
 
-        let _ = read_int8(&mut cursor)?; // TAG_BUFFER?
-
-        let forgotten_size = read_int32(&mut cursor)?; // [forgotten_topics]
-        if forgotten_size < 0 {
-            return Err(KafkaError::CorruptedMessage(format!(
-                "expected request's fetched forgotten topics size value to be greater than 0, got {forgotten_size}"
-            )));
-        }
-        let mut forgotten_topics = Vec::with_capacity(forgotten_size as usize);
+        let forgotten_size = read_compact_array_len(&mut cursor)?; // [forgotten_topics]
+        let mut forgotten_topics = array_with_capacity(forgotten_size);
 
         for _ in 0..forgotten_size {
             let topic_id = read_int128(&mut cursor)?;
-            let partitions_size = read_int32(&mut cursor)?;
-
-            if partitions_size < 0 {
-                return Err(KafkaError::CorruptedMessage(format!("expected request's fetched partitions size value to be greater than 0, got {partitions_size}")));
-            }
-
-            let mut partitions = Vec::with_capacity(partitions_size as usize);
+            let partitions_size = read_compact_array_len(&mut cursor)?; // [partitions]
+            let mut partitions = array_with_capacity(partitions_size);
 
             for _ in 0..partitions_size {
                 let partition = read_int32(&mut cursor)?;
                 partitions.push(partition);
             }
+            read_tagged_fields(&mut cursor)?;
 
             forgotten_topics.push(ForgottenTopic {
                 topic_id,
@@ -184,8 +469,19 @@ impl FetchRequest {
             })
         }
 
-        let _ = read_int8(&mut cursor)?; // TAG_BUFFER?
-        let rack_id = read_nullable_string(&mut cursor)?.unwrap_or_default();
+        let rack_id = read_compact_string(&mut cursor)?;
+        let mut replica_id = -1;
+        let mut replica_epoch = -1;
+        read_tagged_fields_with(&mut cursor, |tag, bytes| {
+            if tag == REPLICA_STATE_TAG {
+                let mut field = Cursor::new(bytes);
+                replica_id = read_int32(&mut field)?;
+                replica_epoch = read_int64(&mut field)?;
+                read_tagged_fields(&mut field)?;
+            }
+            Ok(())
+        })?;
+        cursor.finish()?;
 
         Ok(FetchRequest {
             correlation_id,
@@ -198,196 +494,1525 @@ impl FetchRequest {
             topics,
             forgotten_topics,
             rack_id,
+            replica_id,
+            replica_epoch,
         })
     }
+
+    // the client side of parse
+    pub fn encode(&self, req_buf: &mut Vec<u8>) {
+        req_buf.extend_from_slice(&self.max_wait_ms.to_be_bytes());
+        req_buf.extend_from_slice(&self.min_bytes.to_be_bytes());
+        req_buf.extend_from_slice(&self.max_bytes.to_be_bytes());
+        req_buf.extend_from_slice(&self.isolation_level.to_be_bytes());
+        req_buf.extend_from_slice(&self.session_id.to_be_bytes());
+        req_buf.extend_from_slice(&self.session_epoch.to_be_bytes());
+
+        write_compact_array_len(req_buf, self.topics.len()); // [topics]
+        for topic in &self.topics {
+            req_buf.extend_from_slice(&topic.topic_id.to_be_bytes());
+
+            write_compact_array_len(req_buf, topic.partitions.len()); // [partitions]
+            for partition in &topic.partitions {
+                req_buf.extend_from_slice(&partition.partition.to_be_bytes());
+                req_buf.extend_from_slice(&partition.current_leader_epoch.to_be_bytes());
+                req_buf.extend_from_slice(&partition.fetch_offset.to_be_bytes());
+                req_buf.extend_from_slice(&partition.last_fetched_epoch.to_be_bytes());
+                req_buf.extend_from_slice(&partition.log_start_offset.to_be_bytes());
+                req_buf.extend_from_slice(&partition.partition_max_bytes.to_be_bytes());
+                req_buf.extend_from_slice(TAG_BUFFER);
+            }
+            req_buf.extend_from_slice(TAG_BUFFER);
+        }
+
+        write_compact_array_len(req_buf, self.forgotten_topics.len()); // [forgotten_topics]
+        for topic in &self.forgotten_topics {
+            req_buf.extend_from_slice(&topic.topic_id.to_be_bytes());
+
+            write_compact_array_len(req_buf, topic.partitions.len()); // [partitions]
+            for partition in &topic.partitions {
+                req_buf.extend_from_slice(&partition.to_be_bytes());
+            }
+            req_buf.extend_from_slice(TAG_BUFFER);
+        }
+
+        write_compact_string(req_buf, &self.rack_id);
+        match self.replica_id {
+            replica_id if replica_id >= 0 => {
+                let mut replica_state = replica_id.to_be_bytes().to_vec();
+                replica_state.extend_from_slice(&self.replica_epoch.to_be_bytes());
+                replica_state.extend_from_slice(TAG_BUFFER);
+
+                write_unsigned_varint(req_buf, 1);
+                write_unsigned_varint(req_buf, REPLICA_STATE_TAG);
+                write_unsigned_varint(req_buf, replica_state.len() as u32);
+                req_buf.extend_from_slice(&replica_state);
+            }
+            _ => req_buf.extend_from_slice(TAG_BUFFER),
+        }
+    }
 }
 
-struct RequestTopic {
-    topic_id: i128,
-    partitions: Vec<RequestPartition>,
+pub struct RequestTopic {
+    pub topic_id: i128,
+    pub partitions: Vec<RequestPartition>,
 }
 
-struct ForgottenTopic {
-    topic_id: i128,
-    partitions: Vec<i32>,
+pub struct ForgottenTopic {
+    pub topic_id: i128,
+    pub partitions: Vec<i32>,
 }
 
-struct RequestPartition {
-    partition: i32,
-    current_leader_epoch: i32,
-    fetch_offset: i64,
-    last_fetched_epoch: i32,
-    log_start_offset: i64,
-    partition_max_bytes: i32,
+pub struct RequestPartition {
+    pub partition: i32,
+    pub current_leader_epoch: i32,
+    pub fetch_offset: i64,
+    pub last_fetched_epoch: i32,
+    pub log_start_offset: i64,
+    pub partition_max_bytes: i32,
+}
+
+// a parsed request body, see parse_request
+pub enum KafkaRequest {
+    ApiVersions(ApiVersionsRequest),
+    Fetch(FetchRequest),
+    JoinGroup(JoinGroupRequest),
+    SyncGroup(SyncGroupRequest),
+    Heartbeat(HeartbeatRequest),
+    LeaveGroup(LeaveGroupRequest),
+    OffsetCommit(OffsetCommitRequest),
+    OffsetFetch(OffsetFetchRequest),
+    ListGroups(ListGroupsRequest),
+    DescribeGroups(DescribeGroupsRequest),
+    SaslHandshake(SaslHandshakeRequest),
+    SaslAuthenticate(SaslAuthenticateRequest),
+    DescribeConfigs(DescribeConfigsRequest),
+    IncrementalAlterConfigs(IncrementalAlterConfigsRequest),
+    OffsetForLeaderEpoch(OffsetForLeaderEpochRequest),
+    ElectLeaders(ElectLeadersRequest),
+    DescribeLogDirs(DescribeLogDirsRequest),
+    DescribeCluster(DescribeClusterRequest),
+    Metadata(MetadataRequest),
+    AlterPartitionReassignments(AlterPartitionReassignmentsRequest),
+    ListPartitionReassignments(ListPartitionReassignmentsRequest),
+    DescribeProducers(DescribeProducersRequest),
+    DescribeAcls(DescribeAclsRequest),
+    CreateAcls(CreateAclsRequest),
+    DeleteAcls(DeleteAclsRequest),
+    AlterUserScramCredentials(AlterUserScramCredentialsRequest),
+    DescribeClientQuotas(DescribeClientQuotasRequest),
+    AlterClientQuotas(AlterClientQuotasRequest),
+    Vote(VoteRequest),
+    BeginQuorumEpoch(QuorumEpochRequest),
+    EndQuorumEpoch(QuorumEpochRequest),
+    DescribeQuorum(DescribeQuorumRequest),
+    FetchSnapshot(FetchSnapshotRequest),
+    BrokerRegistration(BrokerRegistrationRequest),
+    BrokerHeartbeat(BrokerHeartbeatRequest),
+    AlterPartition(AlterPartitionRequest),
+}
+
+// parses a request body (everything after the request header) the way its handler would,
+// without a broker to hand it to. malformed input of any kind comes back as an error, never
+// a panic, so this is the entry point for fuzzing the codec
+pub fn parse_request(
+    api_key: i16,
+    api_version: i16,
+    body: &[u8],
+) -> Result<KafkaRequest, KafkaError> {
+    static HANDLERS: OnceLock<ApiRegistry> = OnceLock::new();
+    let versions = HANDLERS
+        .get_or_init(ApiRegistry::builtin)
+        .version_range(api_key)
+        .ok_or(KafkaError::UnsupportedApiKey(api_key))?;
+    if !versions.contains(&api_version) {
+        return Err(KafkaError::UnsupportedApiVersion(api_version));
+    }
+
+    let request = match api_key {
+        APIVERSIONS => KafkaRequest::ApiVersions(ApiVersionsRequest::parse(body, api_version)?),
+        // there's no header to take a correlation id from
+        FETCH => KafkaRequest::Fetch(FetchRequest::parse(body, 0)?),
+        JOIN_GROUP => KafkaRequest::JoinGroup(JoinGroupRequest::parse(body)?),
+        SYNC_GROUP => KafkaRequest::SyncGroup(SyncGroupRequest::parse(body)?),
+        HEARTBEAT => KafkaRequest::Heartbeat(HeartbeatRequest::parse(body)?),
+        LEAVE_GROUP => KafkaRequest::LeaveGroup(LeaveGroupRequest::parse(body)?),
+        OFFSET_COMMIT => KafkaRequest::OffsetCommit(OffsetCommitRequest::parse(body)?),
+        OFFSET_FETCH => KafkaRequest::OffsetFetch(OffsetFetchRequest::parse(body)?),
+        LIST_GROUPS => KafkaRequest::ListGroups(ListGroupsRequest::parse(body)?),
+        DESCRIBE_GROUPS => KafkaRequest::DescribeGroups(DescribeGroupsRequest::parse(body)?),
+        SASL_HANDSHAKE => KafkaRequest::SaslHandshake(SaslHandshakeRequest::parse(body)?),
+        SASL_AUTHENTICATE => KafkaRequest::SaslAuthenticate(SaslAuthenticateRequest::parse(body)?),
+        DESCRIBE_CONFIGS => KafkaRequest::DescribeConfigs(DescribeConfigsRequest::parse(body)?),
+        INCREMENTAL_ALTER_CONFIGS => {
+            KafkaRequest::IncrementalAlterConfigs(IncrementalAlterConfigsRequest::parse(body)?)
+        }
+        OFFSET_FOR_LEADER_EPOCH => {
+            KafkaRequest::OffsetForLeaderEpoch(OffsetForLeaderEpochRequest::parse(body)?)
+        }
+        ELECT_LEADERS => KafkaRequest::ElectLeaders(ElectLeadersRequest::parse(body)?),
+        DESCRIBE_LOG_DIRS => KafkaRequest::DescribeLogDirs(DescribeLogDirsRequest::parse(body)?),
+        DESCRIBE_CLUSTER => KafkaRequest::DescribeCluster(DescribeClusterRequest::parse(body)?),
+        METADATA => KafkaRequest::Metadata(MetadataRequest::parse(body)?),
+        DESCRIBE_ACLS => KafkaRequest::DescribeAcls(DescribeAclsRequest::parse(body)?),
+        CREATE_ACLS => KafkaRequest::CreateAcls(CreateAclsRequest::parse(body)?),
+        DELETE_ACLS => KafkaRequest::DeleteAcls(DeleteAclsRequest::parse(body)?),
+        ALTER_PARTITION_REASSIGNMENTS => KafkaRequest::AlterPartitionReassignments(
+            AlterPartitionReassignmentsRequest::parse(body)?,
+        ),
+        LIST_PARTITION_REASSIGNMENTS => KafkaRequest::ListPartitionReassignments(
+            ListPartitionReassignmentsRequest::parse(body)?,
+        ),
+        DESCRIBE_PRODUCERS => {
+            KafkaRequest::DescribeProducers(DescribeProducersRequest::parse(body)?)
+        }
+        ALTER_USER_SCRAM_CREDENTIALS => {
+            KafkaRequest::AlterUserScramCredentials(AlterUserScramCredentialsRequest::parse(body)?)
+        }
+        DESCRIBE_CLIENT_QUOTAS => {
+            KafkaRequest::DescribeClientQuotas(DescribeClientQuotasRequest::parse(body)?)
+        }
+        ALTER_CLIENT_QUOTAS => {
+            KafkaRequest::AlterClientQuotas(AlterClientQuotasRequest::parse(body)?)
+        }
+        VOTE => KafkaRequest::Vote(VoteRequest::parse(body)?),
+        BEGIN_QUORUM_EPOCH => {
+            KafkaRequest::BeginQuorumEpoch(QuorumEpochRequest::parse(body, false)?)
+        }
+        END_QUORUM_EPOCH => KafkaRequest::EndQuorumEpoch(QuorumEpochRequest::parse(body, true)?),
+        DESCRIBE_QUORUM => KafkaRequest::DescribeQuorum(DescribeQuorumRequest::parse(body)?),
+        FETCH_SNAPSHOT => KafkaRequest::FetchSnapshot(FetchSnapshotRequest::parse(body)?),
+        BROKER_REGISTRATION => {
+            KafkaRequest::BrokerRegistration(BrokerRegistrationRequest::parse(body, api_version)?)
+        }
+        BROKER_HEARTBEAT => KafkaRequest::BrokerHeartbeat(BrokerHeartbeatRequest::parse(body)?),
+        ALTER_PARTITION => {
+            KafkaRequest::AlterPartition(AlterPartitionRequest::parse(body, api_version)?)
+        }
+        _ => return Err(KafkaError::UnsupportedApiKey(api_key)),
+    };
+
+    Ok(request)
 }
 
 enum KafkaResponse {
     ApiVersions(ApiVersionsResponse),
     Error(ErrorResponse),
     Fetch(FetchResponse),
+    JoinGroup(JoinGroupResponse),
+    SyncGroup(SyncGroupResponse),
+    Heartbeat(HeartbeatResponse),
+    LeaveGroup(LeaveGroupResponse),
+    OffsetCommit(OffsetCommitResponse),
+    OffsetFetch(OffsetFetchResponse),
+    ListGroups(ListGroupsResponse),
+    DescribeGroups(DescribeGroupsResponse),
+    SaslHandshake(SaslHandshakeResponse),
+    SaslAuthenticate(SaslAuthenticateResponse),
+    DescribeConfigs(DescribeConfigsResponse),
+    IncrementalAlterConfigs(IncrementalAlterConfigsResponse),
+    OffsetForLeaderEpoch(OffsetForLeaderEpochResponse),
+    ElectLeaders(ElectLeadersResponse),
+    DescribeLogDirs(DescribeLogDirsResponse),
+    DescribeCluster(DescribeClusterResponse),
+    Metadata(MetadataResponse),
+    AlterPartitionReassignments(AlterPartitionReassignmentsResponse),
+    ListPartitionReassignments(ListPartitionReassignmentsResponse),
+    DescribeProducers(DescribeProducersResponse),
+    DescribeAcls(DescribeAclsResponse),
+    CreateAcls(CreateAclsResponse),
+    DeleteAcls(DeleteAclsResponse),
+    AlterUserScramCredentials(AlterUserScramCredentialsResponse),
+    DescribeClientQuotas(DescribeClientQuotasResponse),
+    AlterClientQuotas(AlterClientQuotasResponse),
+    Vote(VoteResponse),
+    BeginQuorumEpoch(QuorumEpochResponse),
+    EndQuorumEpoch(QuorumEpochResponse),
+    DescribeQuorum(DescribeQuorumResponse),
+    FetchSnapshot(FetchSnapshotResponse),
+    BrokerRegistration(BrokerRegistrationResponse),
+    BrokerHeartbeat(BrokerHeartbeatResponse),
+    AlterPartition(AlterPartitionResponse),
 }
 
-struct ApiVersionsResponse {
+impl KafkaResponse {
+    // the response-level error code, per-partition/per-member codes aren't considered
+    fn error_code(&self) -> i16 {
+        match self {
+            KafkaResponse::Error(err_res) => err_res.error_code,
+            KafkaResponse::JoinGroup(join_group) => join_group.error_code,
+            KafkaResponse::SyncGroup(sync_group) => sync_group.error_code,
+            KafkaResponse::Heartbeat(heartbeat) => heartbeat.error_code,
+            KafkaResponse::LeaveGroup(leave_group) => leave_group.error_code,
+            KafkaResponse::ListGroups(list_groups) => list_groups.error_code,
+            KafkaResponse::SaslHandshake(sasl_handshake) => sasl_handshake.error_code,
+            KafkaResponse::SaslAuthenticate(sasl_authenticate) => sasl_authenticate.error_code,
+            KafkaResponse::ElectLeaders(elect_leaders) => elect_leaders.error_code,
+            KafkaResponse::DescribeLogDirs(describe_log_dirs) => describe_log_dirs.error_code,
+            KafkaResponse::DescribeCluster(describe_cluster) => describe_cluster.error_code,
+            KafkaResponse::DescribeAcls(describe_acls) => describe_acls.error_code,
+            KafkaResponse::AlterPartitionReassignments(alter_reassignments) => {
+                alter_reassignments.error_code
+            }
+            KafkaResponse::ListPartitionReassignments(list_reassignments) => {
+                list_reassignments.error_code
+            }
+            KafkaResponse::ApiVersions(api_versions) => api_versions.error_code,
+            KafkaResponse::Fetch(fetch) => fetch.error_code,
+            KafkaResponse::DescribeClientQuotas(describe_client_quotas) => {
+                describe_client_quotas.error_code
+            }
+            KafkaResponse::BrokerRegistration(broker_registration) => {
+                broker_registration.error_code
+            }
+            KafkaResponse::BrokerHeartbeat(broker_heartbeat) => broker_heartbeat.error_code,
+            KafkaResponse::AlterPartition(alter_partition) => alter_partition.error_code,
+            KafkaResponse::Vote(vote) => vote.error_code,
+            KafkaResponse::BeginQuorumEpoch(quorum_epoch)
+            | KafkaResponse::EndQuorumEpoch(quorum_epoch) => quorum_epoch.error_code,
+            KafkaResponse::DescribeQuorum(describe_quorum) => describe_quorum.error_code,
+            KafkaResponse::FetchSnapshot(fetch_snapshot) => fetch_snapshot.error_code,
+            KafkaResponse::OffsetCommit(_)
+            | KafkaResponse::OffsetFetch(_)
+            | KafkaResponse::DescribeGroups(_)
+            | KafkaResponse::DescribeConfigs(_)
+            | KafkaResponse::IncrementalAlterConfigs(_)
+            | KafkaResponse::OffsetForLeaderEpoch(_)
+            | KafkaResponse::DescribeProducers(_)
+            | KafkaResponse::Metadata(_)
+            | KafkaResponse::CreateAcls(_)
+            | KafkaResponse::DeleteAcls(_)
+            | KafkaResponse::AlterUserScramCredentials(_)
+            | KafkaResponse::AlterClientQuotas(_) => NONE,
+        }
+    }
+}
+
+// ### API VERSIONS REQUEST (v4) ### //
+// empty before v3
+pub struct ApiVersionsRequest {
+    pub client_software_name: String,
+    pub client_software_version: String,
+}
+
+impl ApiVersionsRequest {
+    pub fn parse(buffer: &[u8], api_version: i16) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+
+        let mut request = ApiVersionsRequest {
+            client_software_name: String::new(),
+            client_software_version: String::new(),
+        };
+        if api_version >= 3 {
+            request.client_software_name = read_compact_string(&mut cursor)?;
+            request.client_software_version = read_compact_string(&mut cursor)?;
+            read_tagged_fields(&mut cursor)?;
+        }
+        cursor.finish()?;
+
+        Ok(request)
+    }
+
+    // like kafka, letters and digits with dots and dashes in between
+    pub fn is_valid(&self) -> bool {
+        let valid = |value: &str| {
+            let bytes = value.as_bytes();
+            match (bytes.first(), bytes.last()) {
+                (Some(first), Some(last)) => {
+                    first.is_ascii_alphanumeric()
+                        && last.is_ascii_alphanumeric()
+                        && bytes
+                            .iter()
+                            .all(|b| b.is_ascii_alphanumeric() || *b == b'-' || *b == b'.')
+                }
+                _ => false,
+            }
+        };
+        valid(&self.client_software_name) && valid(&self.client_software_version)
+    }
+}
+
+pub struct ApiVersionsResponse {
     pub correlation_id: i32,
-    pub api_key_versions: &'static [ApiKeyVerInfo],
+    // the version the body is encoded in
+    pub api_version: i16,
+    pub error_code: i16,
+    pub api_key_versions: Vec<ApiKeyVerInfo>,
+    // tagged fields from v3, each left out while empty (or -1 for the epoch)
+    pub supported_features: Vec<SupportedFeature>,
+    pub finalized_features_epoch: i64,
+    pub finalized_features: Vec<FinalizedFeature>,
 }
 
-struct ApiKeyVerInfo {
+pub struct SupportedFeature {
+    pub name: String,
+    pub min_version: i16,
+    pub max_version: i16,
+}
+
+// kraft finalizes a single level, so both bounds are the same
+pub struct FinalizedFeature {
+    pub name: String,
+    pub max_version_level: i16,
+    pub min_version_level: i16,
+}
+
+impl ApiVersionsResponse {
+    // without any api keys or features, which is how the error responses go out
+    pub fn new(correlation_id: i32, api_version: i16, error_code: i16) -> Self {
+        ApiVersionsResponse {
+            correlation_id,
+            api_version,
+            error_code,
+            api_key_versions: vec![],
+            supported_features: vec![],
+            finalized_features_epoch: -1,
+            finalized_features: vec![],
+        }
+    }
+
+    fn encode_tagged_fields(&self, res_buf: &mut Vec<u8>) {
+        let mut fields: Vec<(u32, Vec<u8>)> = vec![];
+        if !self.supported_features.is_empty() {
+            let mut field = vec![];
+            write_compact_array_len(&mut field, self.supported_features.len());
+            for feature in &self.supported_features {
+                write_compact_string(&mut field, &feature.name);
+                field.extend_from_slice(&feature.min_version.to_be_bytes());
+                field.extend_from_slice(&feature.max_version.to_be_bytes());
+                field.extend_from_slice(TAG_BUFFER);
+            }
+            fields.push((0, field));
+        }
+        if self.finalized_features_epoch != -1 {
+            fields.push((1, self.finalized_features_epoch.to_be_bytes().to_vec()));
+        }
+        if !self.finalized_features.is_empty() {
+            let mut field = vec![];
+            write_compact_array_len(&mut field, self.finalized_features.len());
+            for feature in &self.finalized_features {
+                write_compact_string(&mut field, &feature.name);
+                field.extend_from_slice(&feature.max_version_level.to_be_bytes());
+                field.extend_from_slice(&feature.min_version_level.to_be_bytes());
+                field.extend_from_slice(TAG_BUFFER);
+            }
+            fields.push((2, field));
+        }
+
+        write_unsigned_varint(res_buf, fields.len() as u32);
+        for (tag, field) in fields {
+            write_unsigned_varint(res_buf, tag);
+            write_unsigned_varint(res_buf, field.len() as u32);
+            res_buf.extend_from_slice(&field);
+        }
+    }
+
+    // the client side of the ApiVersions arm of encode_response, `buffer` starts after the
+    // correlation id
+    pub fn parse(buffer: &[u8], correlation_id: i32, api_version: i16) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+        let flexible = api_version >= 3;
+
+        let error_code = read_int16(&mut cursor)?;
+        let api_keys_size = match flexible {
+            true => read_compact_array_len(&mut cursor)?,
+            false => read_array_len(&mut cursor)?,
+        }; // [api_keys]
+        let mut api_key_versions = array_with_capacity(api_keys_size);
+        for _ in 0..api_keys_size {
+            api_key_versions.push(ApiKeyVerInfo {
+                id: read_int16(&mut cursor)?,
+                min: read_int16(&mut cursor)?,
+                max: read_int16(&mut cursor)?,
+            });
+            if flexible {
+                read_tagged_fields(&mut cursor)?;
+            }
+        }
+
+        if api_version >= 1 {
+            let _throttle_time_ms = read_int32(&mut cursor)?;
+        }
+        let mut response = ApiVersionsResponse::new(correlation_id, api_version, error_code);
+        response.api_key_versions = api_key_versions;
+        if flexible {
+            read_tagged_fields_with(&mut cursor, |tag, field| {
+                let mut field = Cursor::new(field);
+                match tag {
+                    0 => {
+                        let features_size = read_compact_array_len(&mut field)?; // [supported_features]
+                        for _ in 0..features_size {
+                            response.supported_features.push(SupportedFeature {
+                                name: read_compact_string(&mut field)?,
+                                min_version: read_int16(&mut field)?,
+                                max_version: read_int16(&mut field)?,
+                            });
+                            read_tagged_fields(&mut field)?;
+                        }
+                    }
+                    1 => response.finalized_features_epoch = read_int64(&mut field)?,
+                    2 => {
+                        let features_size = read_compact_array_len(&mut field)?; // [finalized_features]
+                        for _ in 0..features_size {
+                            response.finalized_features.push(FinalizedFeature {
+                                name: read_compact_string(&mut field)?,
+                                max_version_level: read_int16(&mut field)?,
+                                min_version_level: read_int16(&mut field)?,
+                            });
+                            read_tagged_fields(&mut field)?;
+                        }
+                    }
+                    _ => {}
+                }
+                Ok(())
+            })?;
+        }
+        cursor.finish()?;
+
+        Ok(response)
+    }
+}
+
+pub struct ApiKeyVerInfo {
     pub id: i16,
     pub min: i16,
     pub max: i16,
 }
 
-struct FetchResponse {
-    correlation_id: i32,
-    throttle_time_ms: i32,
-    session_id: i32,
-    responses: Vec<ResponseTopic>,
+pub struct FetchResponse {
+    pub correlation_id: i32,
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub session_id: i32,
+    pub responses: Vec<ResponseTopic>,
 }
 
-struct ResponseTopic {
-    topic_id: i128,
-    partitions: Vec<ResponsePartition>,
+pub struct ResponseTopic {
+    pub topic_id: i128,
+    pub partitions: Vec<ResponsePartition>,
+}
+
+pub struct ResponsePartition {
+    pub partition_index: i32,
+    pub error_code: i16,
+    pub high_watermark: i64,
+    pub last_stable_offset: i64,
+    pub log_start_offset: i64,
+    // null for read_uncommitted fetches, which don't filter anything out
+    pub aborted_transactions: Option<Vec<AbortedTransaction>>,
+    // -1 unless a replica selector sent the client elsewhere
+    pub preferred_read_replica: i32,
+    pub records: Option<Vec<u8>>,
 }
 
-struct ResponsePartition {
-    partition_index: i32,
-    error_code: i16,
-    // high_watermark: i64,
-    // last_stable_offset: i64,
-    // log_start_offset: i64,
-    // aborted_transactions: Vec<AbortedTransactions>,
-    // preferred_read_replica: i32,
-    // records: Option<Vec<u8>>,
+pub struct AbortedTransaction {
+    pub producer_id: i64,
+    pub first_offset: i64,
 }
 
-// struct AbortedTransactions {
-//     producer_id: i64,
-//     first_offset: i64,
-// }
+impl FetchResponse {
+    // the client side of encode, `buffer` starts after the response header
+    pub fn parse(buffer: &[u8], correlation_id: i32) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+
+        let throttle_time_ms = read_int32(&mut cursor)?;
+        let error_code = read_int16(&mut cursor)?;
+        let session_id = read_int32(&mut cursor)?;
+
+        let responses_size = read_compact_array_len(&mut cursor)?; // [responses]
+        let mut responses = array_with_capacity(responses_size);
+        for _ in 0..responses_size {
+            let topic_id = read_int128(&mut cursor)?;
+
+            let partitions_size = read_compact_array_len(&mut cursor)?; // [partitions]
+            let mut partitions = array_with_capacity(partitions_size);
+            for _ in 0..partitions_size {
+                let partition_index = read_int32(&mut cursor)?;
+                let error_code = read_int16(&mut cursor)?;
+                let high_watermark = read_int64(&mut cursor)?;
+                let last_stable_offset = read_int64(&mut cursor)?;
+                let log_start_offset = read_int64(&mut cursor)?;
+
+                let aborted_transactions = match read_compact_nullable_array_len(&mut cursor)? {
+                    Some(aborted_size) => {
+                        let mut aborted_transactions = array_with_capacity(aborted_size);
+                        for _ in 0..aborted_size {
+                            aborted_transactions.push(AbortedTransaction {
+                                producer_id: read_int64(&mut cursor)?,
+                                first_offset: read_int64(&mut cursor)?,
+                            });
+                            read_tagged_fields(&mut cursor)?;
+                        }
+                        Some(aborted_transactions)
+                    }
+                    None => None,
+                };
+
+                let preferred_read_replica = read_int32(&mut cursor)?;
+                let records = read_compact_nullable_bytes(&mut cursor)?;
+                read_tagged_fields(&mut cursor)?;
+
+                partitions.push(ResponsePartition {
+                    partition_index,
+                    error_code,
+                    high_watermark,
+                    last_stable_offset,
+                    log_start_offset,
+                    aborted_transactions,
+                    preferred_read_replica,
+                    records,
+                });
+            }
+            read_tagged_fields(&mut cursor)?;
+
+            responses.push(ResponseTopic {
+                topic_id,
+                partitions,
+            });
+        }
+
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(FetchResponse {
+            correlation_id,
+            throttle_time_ms,
+            error_code,
+            session_id,
+            responses,
+        })
+    }
+
+    fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.throttle_time_ms.to_be_bytes());
+        res_buf.extend_from_slice(&self.error_code.to_be_bytes());
+        res_buf.extend_from_slice(&self.session_id.to_be_bytes());
+
+        write_compact_array_len(res_buf, self.responses.len()); // [responses]
+        for response in &self.responses {
+            res_buf.extend_from_slice(&response.topic_id.to_be_bytes());
+
+            write_compact_array_len(res_buf, response.partitions.len()); // [partitions]
+            for partition in &response.partitions {
+                res_buf.extend_from_slice(&partition.partition_index.to_be_bytes());
+                res_buf.extend_from_slice(&partition.error_code.to_be_bytes());
+                res_buf.extend_from_slice(&partition.high_watermark.to_be_bytes());
+                res_buf.extend_from_slice(&partition.last_stable_offset.to_be_bytes());
+                res_buf.extend_from_slice(&partition.log_start_offset.to_be_bytes());
+
+                match &partition.aborted_transactions {
+                    Some(aborted_transactions) => {
+                        write_compact_array_len(res_buf, aborted_transactions.len());
+                        for aborted in aborted_transactions {
+                            res_buf.extend_from_slice(&aborted.producer_id.to_be_bytes());
+                            res_buf.extend_from_slice(&aborted.first_offset.to_be_bytes());
+                            res_buf.extend_from_slice(TAG_BUFFER);
+                        }
+                    }
+                    None => write_unsigned_varint(res_buf, 0),
+                }
+
+                res_buf.extend_from_slice(&partition.preferred_read_replica.to_be_bytes());
+                match &partition.records {
+                    Some(records) => write_compact_bytes(res_buf, records),
+                    None => write_unsigned_varint(res_buf, 0),
+                }
+                res_buf.extend_from_slice(TAG_BUFFER);
+            }
+            res_buf.extend_from_slice(TAG_BUFFER);
+        }
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
 
 struct ErrorResponse {
     pub correlation_id: i32,
     pub error_code: i16,
 }
 
-pub async fn handle_connection(mut stream: TcpStream) -> Result<(), KafkaError> {
+// everything connections share, built once at startup
+pub struct BrokerState {
+    pub config: Arc<BrokerConfig>,
+    pub coordinator: Arc<GroupCoordinator>,
+    pub metrics: Arc<Metrics>,
+    pub fetch_quotas: Arc<QuotaManager>,
+    pub topic_configs: Arc<TopicConfigStore>,
+    pub logs: Arc<dyn LogStore>,
+    // from the log dirs' meta.properties, `None` when they haven't been formatted
+    pub meta: Option<MetaProperties>,
+    // `None` without authorizer.class.name, which lets every request through
+    pub authorizer: Option<Arc<dyn Authorizer>>,
+    pub scram_credentials: Arc<ScramCredentialStore>,
+    pub client_quotas: Arc<ClientQuotaStore>,
+    pub finalized_features: Arc<FinalizedFeatures>,
+    pub metadata_log: Arc<MetadataLog>,
+    // this node's vote in the quorum of controllers behind the metadata log
+    pub quorum: Arc<RaftQuorum>,
+    // the isrs of the partitions led here that have followers
+    pub isr: Arc<IsrTracker>,
+    // fetches waiting on more data, checked as partitions are appended to or their high
+    // watermark moves
+    pub fetch_purgatory: Arc<Purgatory<TopicPartition>>,
+    // the brokers that registered with this node as their controller
+    pub broker_registry: Arc<BrokerRegistry>,
+    // `None` without audit.log.path
+    pub audit_log: Option<Arc<AuditLog>>,
+    // `None` without request.record.path
+    pub request_recorder: Option<Arc<RequestRecorder>>,
+    pub fetch_interceptor: Arc<dyn FetchInterceptor>,
+    // `None` without fault.injection.enable
+    pub fault_injector: Option<Arc<FaultInjector>>,
+    handlers: ApiRegistry,
+}
+
+impl BrokerState {
+    // groups and broker registrations start out empty, and the authorizer and fetch quotas
+    // follow from the config and the stores, so those are set up here
+    pub fn new(
+        config: Arc<BrokerConfig>,
+        metrics: Arc<Metrics>,
+        logs: Arc<dyn LogStore>,
+        meta: Option<MetaProperties>,
+        stores: MetadataStores,
+        request_logs: RequestLogs,
+        fetch_interceptor: Arc<dyn FetchInterceptor>,
+    ) -> Arc<Self> {
+        let authorizer: Option<Arc<dyn Authorizer>> = match config.authorizer_enabled {
+            true => Some(StandardAuthorizer::new(
+                stores.acls,
+                config.super_users.clone(),
+                config.allow_everyone_if_no_acl_found,
+            )),
+            false => None,
+        };
+        let fetch_purgatory = Purgatory::new();
+
+        Arc::new(BrokerState {
+            coordinator: GroupCoordinator::new(),
+            fetch_quotas: QuotaManager::new(
+                config.quota_consumer_default,
+                stores.client_quotas.clone(),
+            ),
+            fault_injector: config.fault_injection.clone().map(FaultInjector::new),
+            isr: IsrTracker::new(
+                config.node_id,
+                &stores.metadata_log.image(),
+                Duration::from_millis(config.replica_lag_time_max_ms),
+                fetch_purgatory.clone(),
+            ),
+            fetch_purgatory,
+            broker_registry: BrokerRegistry::new(Duration::from_millis(
+                config.broker_session_timeout_ms,
+            )),
+            quorum: RaftQuorum::new(
+                config.node_id,
+                config.controller_quorum_voters.clone(),
+                stores.metadata_log.clone(),
+                stores.quorum_state,
+            ),
+            config,
+            metrics,
+            topic_configs: stores.topic_configs,
+            logs,
+            meta,
+            authorizer,
+            scram_credentials: stores.scram_credentials,
+            client_quotas: stores.client_quotas,
+            finalized_features: stores.finalized_features,
+            metadata_log: stores.metadata_log,
+            audit_log: request_logs.audit_log,
+            request_recorder: request_logs.recorder,
+            fetch_interceptor,
+            handlers: ApiRegistry::builtin(),
+        })
+    }
+}
+
+// the cluster metadata the broker keeps next to the logs, in the first log dir
+pub struct MetadataStores {
+    pub topic_configs: Arc<TopicConfigStore>,
+    pub acls: Arc<AclStore>,
+    pub scram_credentials: Arc<ScramCredentialStore>,
+    pub client_quotas: Arc<ClientQuotaStore>,
+    pub finalized_features: Arc<FinalizedFeatures>,
+    pub metadata_log: Arc<MetadataLog>,
+    // what the quorum last persisted, which it picks up from
+    pub quorum_state: QuorumState,
+}
+
+impl MetadataStores {
+    pub fn load(log_dir: impl AsRef<std::path::Path>) -> Result<Self, KafkaError> {
+        let log_dir = log_dir.as_ref();
+        let metadata_log = MetadataLog::open(log_dir)?;
+        let quorum_state = metadata_log
+            .dir()
+            .map_or(Ok(QuorumState::default()), QuorumState::load)?;
+        Ok(MetadataStores {
+            topic_configs: TopicConfigStore::load(log_dir)?,
+            acls: AclStore::load(log_dir)?,
+            scram_credentials: ScramCredentialStore::load(log_dir)?,
+            client_quotas: ClientQuotaStore::load(log_dir)?,
+            finalized_features: Arc::new(FinalizedFeatures::load(log_dir)?),
+            metadata_log,
+            quorum_state,
+        })
+    }
+
+    pub fn in_memory() -> Self {
+        MetadataStores {
+            topic_configs: TopicConfigStore::in_memory(),
+            acls: AclStore::in_memory(),
+            scram_credentials: ScramCredentialStore::in_memory(),
+            client_quotas: ClientQuotaStore::in_memory(),
+            finalized_features: Arc::new(FinalizedFeatures::default()),
+            metadata_log: MetadataLog::in_memory(),
+            quorum_state: QuorumState::default(),
+        }
+    }
+}
+
+// the files requests are written out to as they're handled
+#[derive(Default)]
+pub struct RequestLogs {
+    pub audit_log: Option<Arc<AuditLog>>,
+    pub recorder: Option<Arc<RequestRecorder>>,
+}
+
+impl RequestLogs {
+    pub fn open(config: &BrokerConfig) -> Result<Self, KafkaError> {
+        let audit_log = match &config.audit_log_path {
+            Some(path) => Some(AuditLog::open(
+                path,
+                config.audit_log_max_bytes,
+                config.audit_log_max_backups,
+            )?),
+            None => None,
+        };
+        let recorder = match &config.request_record_path {
+            Some(path) => Some(RequestRecorder::open(path)?),
+            None => None,
+        };
+
+        Ok(RequestLogs {
+            audit_log,
+            recorder,
+        })
+    }
+}
+
+// `client_host` is what group member descriptions report for this connection's peer
+pub async fn handle_connection<S>(
+    stream: S,
+    client_host: String,
+    state: Arc<BrokerState>,
+) -> Result<(), KafkaError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let _connection = state.metrics.connection_opened();
+    let buffers = Mutex::new(BufferPool::new(state.metrics.clone()));
+    let (mut reader, mut writer) = tokio::io::split(stream);
+    let (read_tx, mut reads) = mpsc::unbounded_channel();
+
+    // the read side only ever stops by waiting forever, so this ends when the responses do
+    tokio::select! {
+        result = serve_requests(&mut writer, &mut reads, &buffers, client_host, &state) => result,
+        never = read_ahead(&mut reader, &buffers, &state.config, read_tx) => match never {},
+    }
+}
+
+// a request read off the connection, holding its place among the in-flight ones until it's
+// answered
+struct ReadRequest {
+    read: Result<(), KafkaError>,
+    buffer: Vec<u8>,
+    received: Instant,
+    _in_flight: OwnedSemaphorePermit,
+}
+
+// reads requests as long as fewer than max.in.flight.requests.per.connection are waiting on
+// a response, so a client pipelining requests can't make the broker buffer them without
+// bound. it stops for good after a read error the connection can't carry on from
+async fn read_ahead(
+    reader: &mut (impl AsyncRead + Unpin),
+    buffers: &Mutex<BufferPool>,
+    config: &BrokerConfig,
+    reads: mpsc::UnboundedSender<ReadRequest>,
+) -> Infallible {
+    let in_flight = Arc::new(Semaphore::new(
+        config
+            .max_in_flight_requests_per_connection
+            .min(Semaphore::MAX_PERMITS),
+    ));
     loop {
-        let request_buffer = read_request(&mut stream).await?;
-        let request_header = match KafkaRequestHeader::parse(&request_buffer) {
-            Ok(header) => header,
-            Err(e) => {
-                eprintln!("Error parsing incoming request header: {:?}", e);
+        let Ok(permit) = in_flight.clone().acquire_owned().await else {
+            break;
+        };
+        let mut buffer = buffers.lock().unwrap().acquire();
+        let read = read_request(reader, &mut buffer, config).await;
+        let carries_on = matches!(read, Ok(()) | Err(KafkaError::RequestTooLarge { .. }));
+
+        let request = ReadRequest {
+            read,
+            buffer,
+            received: Instant::now(),
+            _in_flight: permit,
+        };
+        if reads.send(request).is_err() || !carries_on {
+            break;
+        }
+    }
+    std::future::pending().await
+}
+
+// answers requests one at a time, in the order they were read
+async fn serve_requests(
+    stream: &mut (impl AsyncWrite + Unpin),
+    reads: &mut mpsc::UnboundedReceiver<ReadRequest>,
+    buffers: &Mutex<BufferPool>,
+    client_host: String,
+    state: &Arc<BrokerState>,
+) -> Result<(), KafkaError> {
+    let BrokerState {
+        config,
+        metrics,
+        fetch_quotas,
+        fault_injector,
+        request_recorder,
+        ..
+    } = &**state;
+    let connection_id = request_recorder
+        .as_ref()
+        .map(|recorder| recorder.connection_id());
+    let mut sasl_state = SaslState::new(config);
+
+    loop {
+        let Some(ReadRequest {
+            read,
+            buffer: request_buffer,
+            received: request_start,
+            _in_flight,
+        }) = reads.recv().await
+        else {
+            return Ok(());
+        };
+
+        match read {
+            Ok(()) => {}
+            // the oversized frame has been skipped, so the connection can carry on after the error
+            Err(
+                e @ KafkaError::RequestTooLarge {
+                    api_key,
+                    correlation_id,
+                    size,
+                    ..
+                },
+            ) => {
+                eprintln!("Rejecting request: {e}");
+                metrics.record_bytes_in(size + 4);
+                let response = KafkaResponse::Error(ErrorResponse {
+                    correlation_id,
+                    error_code: MESSAGE_TOO_LARGE,
+                });
+                let written =
+                    send_response(stream, &client_host, correlation_id, &response, config).await?;
+                metrics.record_bytes_out(written);
+                metrics.record_request(api_key, MESSAGE_TOO_LARGE, request_start.elapsed());
+                audit(
+                    state,
+                    AuditEntry {
+                        api_key,
+                        api_version: -1,
+                        correlation_id,
+                        client_id: "",
+                        client_host: &client_host,
+                        principal: sasl_state.principal(),
+                        error_code: MESSAGE_TOO_LARGE,
+                        latency: request_start.elapsed(),
+                    },
+                )
+                .await;
+                buffers.lock().unwrap().release(request_buffer);
+                continue;
+            }
+            // without a usable size there's no telling where the next frame starts
+            Err(e @ KafkaError::InvalidMessageLength(_)) => {
+                eprintln!("Closing connection from {client_host}: {e}");
                 return Ok(());
             }
+            // a frame cut off partway (eof or the request timeout) leaves nothing to pick up from
+            Err(e) => return Err(e),
+        }
+
+        let bytes_in = request_buffer.len() + 4;
+        metrics.record_bytes_in(bytes_in);
+        if config.trace_wire {
+            eprintln!("{}", trace_request(&client_host, &request_buffer));
+        }
+        if let (Some(recorder), Some(connection_id)) = (request_recorder, connection_id) {
+            record_request(recorder, connection_id, &request_buffer).await;
+        }
+        let (request_header, request_body) = match KafkaRequestHeader::parse(&request_buffer) {
+            Ok(parsed) => parsed,
+            // the whole frame has been read, so the next one starts right after it whatever
+            // is wrong with this one. only a frame too short to hold a correlation id can't be
+            // answered
+            Err(e) => match request_buffer.get(..8) {
+                Some(prefix) => {
+                    let api_key = i16::from_be_bytes([prefix[0], prefix[1]]);
+                    let api_version = i16::from_be_bytes([prefix[2], prefix[3]]);
+                    let correlation_id =
+                        i32::from_be_bytes([prefix[4], prefix[5], prefix[6], prefix[7]]);
+                    eprintln!(
+                        "Rejecting request {correlation_id} from {client_host} with a malformed header: {e}"
+                    );
+                    let response = KafkaResponse::Error(ErrorResponse {
+                        correlation_id,
+                        error_code: INVALID_REQUEST,
+                    });
+                    let written =
+                        send_response(stream, &client_host, correlation_id, &response, config)
+                            .await?;
+                    metrics.record_bytes_out(written);
+                    metrics.record_request(api_key, INVALID_REQUEST, request_start.elapsed());
+                    audit(
+                        state,
+                        AuditEntry {
+                            api_key,
+                            api_version,
+                            correlation_id,
+                            client_id: "",
+                            client_host: &client_host,
+                            principal: sasl_state.principal(),
+                            error_code: INVALID_REQUEST,
+                            latency: request_start.elapsed(),
+                        },
+                    )
+                    .await;
+                    buffers.lock().unwrap().release(request_buffer);
+                    continue;
+                }
+                None => {
+                    eprintln!(
+                        "Closing connection from {client_host}: a {}-byte request is too short for a request header",
+                        request_buffer.len()
+                    );
+                    return Ok(());
+                }
+            },
         };
+        let client_id = request_header.client_id.as_deref().unwrap_or_default();
 
-        let response = match process_request(&request_header, &request_buffer) {
-            Ok(response) => response,
-            Err(e) => KafkaResponse::Error(ErrorResponse {
+        if !sasl_state.allows(request_header.api_key) {
+            let response = KafkaResponse::Error(ErrorResponse {
                 correlation_id: request_header.correlation_id,
-                error_code: e.to_error_code(),
-            }),
+                error_code: KafkaError::IllegalSaslState(request_header.api_key).to_error_code(),
+            });
+            eprintln!(
+                "Rejecting api key {} request from client {client_id:?} in SASL state {sasl_state:?}",
+                request_header.api_key
+            );
+            let written = send_response(
+                stream,
+                &client_host,
+                request_header.correlation_id,
+                &response,
+                config,
+            )
+            .await?;
+            metrics.record_bytes_out(written);
+            metrics.record_client_request(client_id, bytes_in, written);
+            metrics.record_request(
+                request_header.api_key,
+                response.error_code(),
+                request_start.elapsed(),
+            );
+            audit(
+                state,
+                request_header.audit_entry(&client_host, &sasl_state, &response, request_start),
+            )
+            .await;
+            return Ok(());
+        }
+
+        // requests are handled one at a time, so responses go out in request order
+        let result = state
+            .handlers
+            .dispatch(RequestContext {
+                header: &request_header,
+                body: request_body,
+                client_host: &client_host,
+                sasl_state: &mut sasl_state,
+                state,
+                received: request_start,
+            })
+            .await;
+
+        let mut response = match result {
+            Ok(response) => response,
+            Err(e) => {
+                eprintln!(
+                    "Error handling api key {} v{} request {} from client {client_id:?}: {e}",
+                    request_header.api_key, request_header.api_ver, request_header.correlation_id
+                );
+                KafkaResponse::Error(ErrorResponse {
+                    correlation_id: request_header.correlation_id,
+                    error_code: e.to_error_code(),
+                })
+            }
         };
 
-        send_response(&mut stream, &response).await?;
+        let mut res_buf = buffers.lock().unwrap().acquire();
+        encode_response_frame(request_header.correlation_id, &response, &mut res_buf);
+
+        // the response's own size counts towards the quota it reports a throttle time for
+        if let KafkaResponse::Fetch(fetch) = &mut response {
+            let throttle = fetch_quotas.record(
+                sasl_state.principal().unwrap_or(ANONYMOUS_USER),
+                client_id,
+                res_buf.len(),
+            );
+
+            if !throttle.is_zero() {
+                fetch.throttle_time_ms = throttle.as_millis().min(i32::MAX as u128) as i32;
+                res_buf.clear();
+                encode_response_frame(request_header.correlation_id, &response, &mut res_buf);
+                tokio::time::sleep(throttle).await;
+            }
+        }
+
+        if config.trace_wire {
+            // ApiVersions keeps the v0 response header even in its flexible versions
+            let header_tagged = request_header.api_key != APIVERSIONS
+                && !matches!(response, KafkaResponse::Error(_))
+                && is_flexible_version(request_header.api_key, request_header.api_ver);
+            eprintln!("{}", trace_response(&client_host, &res_buf, header_tagged));
+        }
+
+        if let Some(fault_injector) = fault_injector {
+            let injected = inject_response_faults(
+                stream,
+                fault_injector,
+                request_header.correlation_id,
+                &res_buf,
+                config,
+            )
+            .await?;
+            if injected {
+                return Ok(());
+            }
+        }
+
+        let written = write_response(stream, &res_buf, config).await?;
+        metrics.record_bytes_out(written);
+        metrics.record_client_request(client_id, bytes_in, written);
+        metrics.record_request(
+            request_header.api_key,
+            response.error_code(),
+            request_start.elapsed(),
+        );
+        audit(
+            state,
+            request_header.audit_entry(&client_host, &sasl_state, &response, request_start),
+        )
+        .await;
+        buffers.lock().unwrap().release(res_buf);
+        buffers.lock().unwrap().release(request_buffer);
+
+        if sasl_state == SaslState::Failed {
+            return Ok(());
+        }
     }
 }
 
-async fn read_request(stream: &mut TcpStream) -> Result<Vec<u8>, KafkaError> {
+// reads the next request frame into `buf`, which is expected to be empty. frames over the
+// size limit of their api are skipped rather than read into memory
+async fn read_request(
+    stream: &mut (impl AsyncRead + Unpin),
+    buf: &mut Vec<u8>,
+    config: &BrokerConfig,
+) -> Result<(), KafkaError> {
     let mut size_buf = [0u8; 4];
     stream.read_exact(&mut size_buf).await?;
     let size = i32::from_be_bytes(size_buf);
 
-    if size <= 0 || size > 1_000_000 {
+    if size <= 0 {
         return Err(KafkaError::InvalidMessageLength(size));
     }
 
-    let mut buf = vec![0u8; size as usize];
-    stream.read_exact(&mut buf).await?;
-
-    Ok(buf)
+    // a connection can sit idle between requests for as long as it likes, but once a size
+    // prefix is in the rest of the frame has to follow within the request timeout
+    let timeout = Duration::from_millis(config.request_timeout_ms);
+    match tokio::time::timeout(
+        timeout,
+        read_request_frame(stream, buf, size as usize, config),
+    )
+    .await
+    {
+        Ok(read) => read,
+        Err(_) => Err(KafkaError::Timeout {
+            operation: "reading a request",
+            timeout_ms: config.request_timeout_ms,
+        }),
+    }
 }
 
-fn process_request(
-    request_header: &KafkaRequestHeader,
-    request_buffer: &[u8],
-) -> Result<KafkaResponse, KafkaError> {
-    match request_header.api_key {
-        APIVERSIONS => {
-            if !(0..=4).contains(&request_header.api_ver) {
-                Err(KafkaError::UnsupportedApiVersion(request_header.api_ver))
-            } else {
-                Ok(KafkaResponse::ApiVersions(ApiVersionsResponse {
-                    correlation_id: request_header.correlation_id,
-                    api_key_versions: API_VERS_INFO,
-                }))
-            }
-        }
-        FETCH => {
-            if !(0..=4).contains(&request_header.api_ver) {
-                Err(KafkaError::UnsupportedApiVersion(request_header.api_ver))
-            } else {
-                let request = FetchRequest::parse(request_buffer, request_header.correlation_id)?;
-                Ok(KafkaResponse::Fetch(FetchResponse {
-                    correlation_id: request.correlation_id,
-                    throttle_time_ms: 0,
-                    session_id: request.session_id,
-                    responses: vec![],
-                }))
-            }
+async fn read_request_frame(
+    stream: &mut (impl AsyncRead + Unpin),
+    buf: &mut Vec<u8>,
+    size: usize,
+    config: &BrokerConfig,
+) -> Result<(), KafkaError> {
+    // api key, api version and correlation id, enough to answer an oversized request
+    let prefix_len = size.min(8);
+    buf.resize(prefix_len, 0);
+    stream.read_exact(buf).await?;
+
+    if prefix_len == 8 {
+        let api_key = i16::from_be_bytes([buf[0], buf[1]]);
+        let limit = config.max_request_bytes(api_key);
+
+        if size > limit {
+            let remaining = (size - prefix_len) as u64;
+            tokio::io::copy(&mut (&mut *stream).take(remaining), &mut tokio::io::sink()).await?;
+
+            return Err(KafkaError::RequestTooLarge {
+                api_key,
+                correlation_id: i32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]),
+                size,
+                limit,
+            });
         }
-        _ => todo!(), // Fetch, Produce, etc?
     }
+
+    buf.resize(size, 0);
+    stream.read_exact(&mut buf[prefix_len..]).await?;
+
+    Ok(())
 }
 
-async fn send_response(stream: &mut TcpStream, response: &KafkaResponse) -> Result<(), KafkaError> {
+// holds back the response or cuts the connection short, it's closed when this returns true
+async fn inject_response_faults(
+    stream: &mut (impl AsyncWrite + Unpin),
+    fault_injector: &FaultInjector,
+    correlation_id: i32,
+    frame: &[u8],
+    config: &BrokerConfig,
+) -> Result<bool, KafkaError> {
+    let faults = fault_injector.response_faults();
+    if let Some(delay) = faults.delay {
+        tokio::time::sleep(delay).await;
+    }
+
+    if faults.disconnect {
+        eprintln!(
+            "Injected fault: closing the connection instead of answering request {correlation_id}"
+        );
+        return Ok(true);
+    }
+    if faults.truncate {
+        eprintln!("Injected fault: truncating the response to request {correlation_id}");
+        write_response(stream, &frame[..frame.len() / 2], config).await?;
+        return Ok(true);
+    }
+
+    Ok(false)
+}
+
+// filesystem work goes to the blocking pool so it doesn't stall other connections
+// a failed write is reported but doesn't fail the request
+async fn audit(state: &BrokerState, entry: AuditEntry<'_>) {
+    let Some(audit_log) = state.audit_log.clone() else {
+        return;
+    };
+    let line = entry.to_json();
+    let appended = run_blocking(move || audit_log.append(&line)).await;
+    if let Err(e) = appended.and_then(|appended| appended) {
+        eprintln!("Error writing audit log: {e}");
+    }
+}
+
+// like the audit log, a failed write doesn't fail the request
+async fn record_request(recorder: &Arc<RequestRecorder>, connection_id: u64, frame: &[u8]) {
+    let recorder = recorder.clone();
+    let frame = frame.to_vec();
+    let recorded = run_blocking(move || recorder.record(connection_id, &frame)).await;
+    if let Err(e) = recorded.and_then(|recorded| recorded) {
+        eprintln!("Error recording request: {e}");
+    }
+}
+
+async fn run_blocking<T: Send + 'static>(
+    f: impl FnOnce() -> T + Send + 'static,
+) -> Result<T, KafkaError> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| KafkaError::Io(std::io::Error::other(e)))
+}
+
+// for the bare error responses, which never have a flexible header
+async fn send_response(
+    stream: &mut (impl AsyncWrite + Unpin),
+    client_host: &str,
+    request_correlation_id: i32,
+    response: &KafkaResponse,
+    config: &BrokerConfig,
+) -> Result<usize, KafkaError> {
     let mut res_buf = vec![];
+    encode_response_frame(request_correlation_id, response, &mut res_buf);
+    if config.trace_wire {
+        eprintln!("{}", trace_response(client_host, &res_buf, false));
+    }
+    write_response(stream, &res_buf, config).await
+}
+
+// the size prefix is reserved up front and filled in once the response is encoded,
+// so the whole frame goes out in a single write
+fn encode_response_frame(
+    request_correlation_id: i32,
+    response: &KafkaResponse,
+    res_buf: &mut Vec<u8>,
+) {
+    res_buf.extend_from_slice(&[0u8; 4]);
+    encode_response(request_correlation_id, response, res_buf);
+
+    let size = (res_buf.len() - 4) as i32;
+    res_buf[..4].copy_from_slice(&size.to_be_bytes());
+}
 
+fn encode_response(request_correlation_id: i32, response: &KafkaResponse, res_buf: &mut Vec<u8>) {
     match response {
+        // always response header v0, even for the flexible versions
         KafkaResponse::ApiVersions(api_versions) => {
+            let flexible = api_versions.api_version >= 3;
             res_buf.extend_from_slice(&api_versions.correlation_id.to_be_bytes());
-            res_buf.extend_from_slice(&NONE.to_be_bytes());
-            // [api_keys] len
-            res_buf
-                .extend_from_slice(&(api_versions.api_key_versions.len() as u8 + 1).to_be_bytes());
-            for api_key in api_versions.api_key_versions {
+            res_buf.extend_from_slice(&api_versions.error_code.to_be_bytes());
+
+            // [api_keys]
+            if flexible {
+                write_compact_array_len(res_buf, api_versions.api_key_versions.len());
+            } else {
+                res_buf
+                    .extend_from_slice(&(api_versions.api_key_versions.len() as i32).to_be_bytes());
+            }
+            for api_key in &api_versions.api_key_versions {
                 res_buf.extend_from_slice(&api_key.id.to_be_bytes());
                 res_buf.extend_from_slice(&api_key.min.to_be_bytes());
                 res_buf.extend_from_slice(&api_key.max.to_be_bytes());
-                res_buf.extend_from_slice(TAG_BUFFER);
+                if flexible {
+                    res_buf.extend_from_slice(TAG_BUFFER);
+                }
+            }
+
+            if api_versions.api_version >= 1 {
+                res_buf.extend_from_slice(&[0u8; 4]); // throttle_time_ms (i32)
+            }
+            if flexible {
+                api_versions.encode_tagged_fields(res_buf);
             }
+        }
 
-            res_buf.extend_from_slice(&[0u8; 4]); // throttle_time_ms (i32)
+        KafkaResponse::Fetch(fetch) => {
+            res_buf.extend_from_slice(&fetch.correlation_id.to_be_bytes());
             res_buf.extend_from_slice(TAG_BUFFER);
+            fetch.encode(res_buf);
         }
 
-        KafkaResponse::Fetch(FetchResponse {
-            correlation_id,
-            throttle_time_ms,
-            session_id,
-            responses,
-        }) => {
-            // unsure of ordering here
-            res_buf.extend_from_slice(&throttle_time_ms.to_be_bytes()); // throttle
-            res_buf.extend_from_slice(&correlation_id.to_be_bytes()); // correlation
-            res_buf.extend_from_slice(&NONE.to_be_bytes()); // error code
-            res_buf.extend_from_slice(&session_id.to_be_bytes()); // session id
-
-            res_buf.extend_from_slice(&(responses.len() as u8 + 1).to_be_bytes()); // [responses]
-            for response in responses {
-                res_buf.extend_from_slice(&response.topic_id.to_be_bytes()); // topic_id
-                res_buf.extend_from_slice(&(response.partitions.len() as u8 + 1).to_be_bytes()); // [partitions]
-
-                for partition in &response.partitions {
-                    res_buf.extend_from_slice(&partition.partition_index.to_be_bytes()); // partition_idx
-                    res_buf.extend_from_slice(&partition.error_code.to_be_bytes()); // error_code
-                    res_buf.extend_from_slice(TAG_BUFFER); // TAG_BUFFER?
-                }
+        KafkaResponse::JoinGroup(join_group) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            join_group.encode(res_buf);
+        }
 
-                res_buf.extend_from_slice(TAG_BUFFER); // TAG_BUFFER?
-            }
-            res_buf.extend_from_slice(TAG_BUFFER); // TAG_BUFFER?
+        KafkaResponse::SyncGroup(sync_group) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            sync_group.encode(res_buf);
+        }
+
+        KafkaResponse::Heartbeat(heartbeat) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            heartbeat.encode(res_buf);
+        }
+
+        KafkaResponse::LeaveGroup(leave_group) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            leave_group.encode(res_buf);
+        }
+
+        KafkaResponse::OffsetCommit(offset_commit) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            offset_commit.encode(res_buf);
+        }
+
+        KafkaResponse::OffsetFetch(offset_fetch) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            offset_fetch.encode(res_buf);
+        }
+
+        KafkaResponse::ListGroups(list_groups) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            list_groups.encode(res_buf);
+        }
+
+        KafkaResponse::DescribeGroups(describe_groups) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            describe_groups.encode(res_buf);
+        }
+
+        KafkaResponse::SaslHandshake(sasl_handshake) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            sasl_handshake.encode(res_buf);
+        }
+
+        KafkaResponse::SaslAuthenticate(sasl_authenticate) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            sasl_authenticate.encode(res_buf);
+        }
+
+        KafkaResponse::DescribeConfigs(describe_configs) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            describe_configs.encode(res_buf);
+        }
+
+        KafkaResponse::IncrementalAlterConfigs(incremental_alter_configs) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            incremental_alter_configs.encode(res_buf);
+        }
+
+        KafkaResponse::OffsetForLeaderEpoch(offset_for_leader_epoch) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            offset_for_leader_epoch.encode(res_buf);
+        }
+
+        KafkaResponse::ElectLeaders(elect_leaders) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            elect_leaders.encode(res_buf);
+        }
+
+        KafkaResponse::DescribeLogDirs(describe_log_dirs) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            describe_log_dirs.encode(res_buf);
+        }
+
+        KafkaResponse::Metadata(metadata) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            metadata.encode(res_buf);
+        }
+
+        KafkaResponse::DescribeAcls(describe_acls) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            describe_acls.encode(res_buf);
+        }
+
+        KafkaResponse::CreateAcls(create_acls) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            create_acls.encode(res_buf);
+        }
+
+        KafkaResponse::DeleteAcls(delete_acls) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            delete_acls.encode(res_buf);
+        }
+
+        KafkaResponse::AlterUserScramCredentials(alter_user_scram_credentials) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            alter_user_scram_credentials.encode(res_buf);
+        }
+
+        KafkaResponse::DescribeClientQuotas(describe_client_quotas) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            describe_client_quotas.encode(res_buf);
+        }
+
+        KafkaResponse::AlterClientQuotas(alter_client_quotas) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            alter_client_quotas.encode(res_buf);
+        }
+
+        KafkaResponse::Vote(vote) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            vote.encode(res_buf);
+        }
+
+        KafkaResponse::BeginQuorumEpoch(quorum_epoch)
+        | KafkaResponse::EndQuorumEpoch(quorum_epoch) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            quorum_epoch.encode(res_buf);
+        }
+
+        KafkaResponse::DescribeQuorum(describe_quorum) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            describe_quorum.encode(res_buf);
+        }
+
+        KafkaResponse::FetchSnapshot(fetch_snapshot) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            fetch_snapshot.encode(res_buf);
+        }
+
+        KafkaResponse::DescribeCluster(describe_cluster) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            describe_cluster.encode(res_buf);
+        }
+
+        KafkaResponse::AlterPartitionReassignments(alter_reassignments) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            alter_reassignments.encode(res_buf);
+        }
+
+        KafkaResponse::ListPartitionReassignments(list_reassignments) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            list_reassignments.encode(res_buf);
+        }
+
+        KafkaResponse::DescribeProducers(describe_producers) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            describe_producers.encode(res_buf);
+        }
+
+        KafkaResponse::BrokerRegistration(broker_registration) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            broker_registration.encode(res_buf);
+        }
+
+        KafkaResponse::BrokerHeartbeat(broker_heartbeat) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            broker_heartbeat.encode(res_buf);
+        }
+
+        KafkaResponse::AlterPartition(alter_partition) => {
+            res_buf.extend_from_slice(&request_correlation_id.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+            alter_partition.encode(res_buf);
         }
 
         KafkaResponse::Error(err_res) => {
@@ -395,21 +2020,226 @@ async fn send_response(stream: &mut TcpStream, response: &KafkaResponse) -> Resu
             res_buf.extend_from_slice(&err_res.error_code.to_be_bytes());
         }
     };
+}
+
+// `frame` already starts with its size prefix
+// a client that stops reading would otherwise leave the write pending forever once the
+// socket buffers fill up
+async fn write_response(
+    stream: &mut (impl AsyncWrite + Unpin),
+    frame: &[u8],
+    config: &BrokerConfig,
+) -> Result<usize, KafkaError> {
+    let write = async {
+        stream.write_all(frame).await?;
+        stream.flush().await
+    };
 
-    write_response_with_len(stream, &res_buf).await
+    match tokio::time::timeout(Duration::from_millis(config.request_timeout_ms), write).await {
+        Ok(Ok(())) => Ok(frame.len()),
+        Ok(Err(e)) => Err(KafkaError::Io(e)),
+        Err(_) => Err(KafkaError::Timeout {
+            operation: "writing a response",
+            timeout_ms: config.request_timeout_ms,
+        }),
+    }
 }
 
-async fn write_response_with_len(
-    stream: &mut TcpStream,
-    response_buffer: &[u8],
-) -> Result<(), KafkaError> {
-    let size = response_buffer.len() as i32;
-    stream.write_all(&size.to_be_bytes()).await?;
-    stream.write_all(response_buffer).await?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if let Err(e) = stream.flush().await {
-        return Err(KafkaError::Io(e));
+    fn fetch_request(replica_id: i32) -> FetchRequest {
+        FetchRequest {
+            correlation_id: 7,
+            max_wait_ms: 500,
+            min_bytes: 1,
+            max_bytes: 52_428_800,
+            isolation_level: 1,
+            session_id: 0,
+            session_epoch: -1,
+            topics: vec![RequestTopic {
+                topic_id: 0x0102_0304_0506_0708_090a_0b0c_0d0e_0f10,
+                partitions: vec![
+                    RequestPartition {
+                        partition: 0,
+                        current_leader_epoch: 3,
+                        fetch_offset: 42,
+                        last_fetched_epoch: 2,
+                        log_start_offset: -1,
+                        partition_max_bytes: 1_048_576,
+                    },
+                    RequestPartition {
+                        partition: 1,
+                        current_leader_epoch: -1,
+                        fetch_offset: 0,
+                        last_fetched_epoch: -1,
+                        log_start_offset: -1,
+                        partition_max_bytes: 1_048_576,
+                    },
+                ],
+            }],
+            forgotten_topics: vec![ForgottenTopic {
+                topic_id: 99,
+                partitions: vec![4, 5],
+            }],
+            rack_id: "rack-a".into(),
+            replica_id,
+            replica_epoch: if replica_id >= 0 { 12 } else { -1 },
+        }
     }
 
-    Ok(())
+    #[test]
+    fn request_header_round_trips() {
+        // ApiVersions v3 has a flexible header, v2 doesn't
+        for (api_ver, client_id) in [(3, Some("console".to_string())), (2, None)] {
+            let header = KafkaRequestHeader {
+                api_key: APIVERSIONS,
+                api_ver,
+                correlation_id: 11,
+                client_id,
+            };
+            let mut req_buf = vec![];
+            header.encode(&mut req_buf);
+            req_buf.extend_from_slice(b"body");
+
+            let (parsed, body) = KafkaRequestHeader::parse(&req_buf).unwrap();
+            assert_eq!(parsed.api_key, APIVERSIONS);
+            assert_eq!(parsed.api_ver, api_ver);
+            assert_eq!(parsed.correlation_id, 11);
+            assert_eq!(parsed.client_id, header.client_id);
+            assert_eq!(body, b"body");
+        }
+    }
+
+    #[test]
+    fn fetch_request_round_trips() {
+        // a consumer, and a follower whose id rides in the ReplicaState tagged field
+        for replica_id in [-1, 2] {
+            let request = fetch_request(replica_id);
+            let mut req_buf = vec![];
+            request.encode(&mut req_buf);
+
+            let parsed = FetchRequest::parse(&req_buf, request.correlation_id).unwrap();
+            assert_eq!(parsed.replica_id, replica_id);
+            assert_eq!(parsed.replica_epoch, request.replica_epoch);
+            assert_eq!(parsed.rack_id, "rack-a");
+            assert_eq!(parsed.topics[0].partitions[0].fetch_offset, 42);
+            assert_eq!(parsed.forgotten_topics[0].partitions, vec![4, 5]);
+
+            let mut reencoded = vec![];
+            parsed.encode(&mut reencoded);
+            assert_eq!(reencoded, req_buf);
+        }
+    }
+
+    #[test]
+    fn fetch_request_rejects_trailing_bytes() {
+        let mut req_buf = vec![];
+        fetch_request(-1).encode(&mut req_buf);
+        req_buf.push(0);
+        assert!(FetchRequest::parse(&req_buf, 7).is_err());
+    }
+
+    #[test]
+    fn fetch_response_round_trips() {
+        let response = FetchResponse {
+            correlation_id: 7,
+            throttle_time_ms: 0,
+            error_code: NONE,
+            session_id: 0,
+            responses: vec![ResponseTopic {
+                topic_id: 1,
+                partitions: vec![
+                    ResponsePartition {
+                        partition_index: 0,
+                        error_code: NONE,
+                        high_watermark: 5,
+                        last_stable_offset: 5,
+                        log_start_offset: 0,
+                        aborted_transactions: Some(vec![AbortedTransaction {
+                            producer_id: 1000,
+                            first_offset: 2,
+                        }]),
+                        preferred_read_replica: -1,
+                        records: Some(vec![1, 2, 3]),
+                    },
+                    ResponsePartition {
+                        partition_index: 1,
+                        error_code: OFFSET_OUT_OF_RANGE,
+                        high_watermark: -1,
+                        last_stable_offset: -1,
+                        log_start_offset: 3,
+                        aborted_transactions: None,
+                        preferred_read_replica: 2,
+                        records: None,
+                    },
+                ],
+            }],
+        };
+        let mut res_buf = vec![];
+        response.encode(&mut res_buf);
+
+        let parsed = FetchResponse::parse(&res_buf, 7).unwrap();
+        let partitions = &parsed.responses[0].partitions;
+        assert_eq!(partitions[0].records.as_deref(), Some(&[1, 2, 3][..]));
+        assert_eq!(
+            partitions[0].aborted_transactions.as_ref().unwrap()[0].producer_id,
+            1000
+        );
+        assert!(partitions[1].aborted_transactions.is_none());
+        assert!(partitions[1].records.is_none());
+        assert_eq!(partitions[1].preferred_read_replica, 2);
+
+        let mut reencoded = vec![];
+        parsed.encode(&mut reencoded);
+        assert_eq!(reencoded, res_buf);
+    }
+
+    #[test]
+    fn api_versions_response_round_trips() {
+        // v0 has neither throttle_time_ms nor tagged fields, v3 all of them
+        for api_version in [0, 3] {
+            let mut response = ApiVersionsResponse::new(5, api_version, NONE);
+            response.api_key_versions = vec![
+                ApiKeyVerInfo {
+                    id: FETCH,
+                    min: 16,
+                    max: 16,
+                },
+                ApiKeyVerInfo {
+                    id: APIVERSIONS,
+                    min: 0,
+                    max: 4,
+                },
+            ];
+            if api_version >= 3 {
+                response.supported_features = vec![SupportedFeature {
+                    name: "metadata.version".into(),
+                    min_version: 1,
+                    max_version: 21,
+                }];
+                response.finalized_features_epoch = 9;
+                response.finalized_features = vec![FinalizedFeature {
+                    name: "metadata.version".into(),
+                    max_version_level: 21,
+                    min_version_level: 21,
+                }];
+            }
+            let mut res_buf = vec![];
+            encode_response(5, &KafkaResponse::ApiVersions(response), &mut res_buf);
+
+            let parsed = ApiVersionsResponse::parse(&res_buf[4..], 5, api_version).unwrap();
+            assert_eq!(parsed.api_key_versions.len(), 2);
+            assert_eq!(parsed.api_key_versions[1].max, 4);
+            assert_eq!(
+                parsed.finalized_features_epoch,
+                if api_version >= 3 { 9 } else { -1 }
+            );
+
+            let mut reencoded = vec![];
+            encode_response(5, &KafkaResponse::ApiVersions(parsed), &mut reencoded);
+            assert_eq!(reencoded, res_buf);
+        }
+    }
 }
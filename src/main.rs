@@ -1,21 +1,76 @@
-use redis_starter_rust::handle_connection;
-use tokio::net::TcpListener;
+use redis_starter_rust::{
+    consume, replay, Broker, BrokerConfig, ConsumeOptions, ReplayOptions, CONSUME_USAGE,
+    REPLAY_USAGE,
+};
+use tokio::signal::unix::{signal, SignalKind};
 
 #[tokio::main]
 async fn main() -> tokio::io::Result<()> {
-    let listener = TcpListener::bind("127.0.0.1:9092").await?;
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.get(1).map(String::as_str) == Some("consume") {
+        let options = match ConsumeOptions::parse(&args[2..]) {
+            Ok(options) => options,
+            Err(e) => {
+                eprintln!("{e}\n{CONSUME_USAGE}");
+                std::process::exit(2);
+            }
+        };
+        if let Err(e) = consume(&options).await {
+            eprintln!("Error consuming: {e}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if args.get(1).map(String::as_str) == Some("replay") {
+        let options = match ReplayOptions::parse(&args[2..]) {
+            Ok(options) => options,
+            Err(e) => {
+                eprintln!("{e}\n{REPLAY_USAGE}");
+                std::process::exit(2);
+            }
+        };
+        if let Err(e) = replay(&options).await {
+            eprintln!("Error replaying {}: {e}", options.recording.display());
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
 
-    loop {
-        match listener.accept().await {
-            Ok((stream, addr)) => {
-                println!("New connection accepted: {}", addr);
-                tokio::spawn(async move {
-                    if let Err(e) = handle_connection(stream).await {
-                        eprintln!("Error handling connection: {e}");
-                    }
-                });
+    // the broker is started as `your_program.sh /tmp/server.properties`, optionally with
+    // `--trace-wire` to dump every frame
+    let trace_wire = args[1..].iter().any(|arg| arg == "--trace-wire");
+    let mut config = match args[1..].iter().find(|arg| *arg != "--trace-wire") {
+        Some(path) => match BrokerConfig::load(path) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("Error loading broker config from {path}: {e}");
+                std::process::exit(1);
             }
-            Err(e) => eprintln!("Error accepting connection: {e}"),
+        },
+        None => BrokerConfig::default(),
+    };
+    config.trace_wire = trace_wire;
+    let broker = match Broker::start(config).await {
+        Ok(broker) => broker,
+        Err(e) => {
+            eprintln!("Error starting broker: {e}");
+            std::process::exit(1);
         }
+    };
+
+    shutdown_signal().await?;
+    println!("Shutting down");
+    if let Err(e) = broker.shutdown().await {
+        eprintln!("Error closing partition logs: {e}");
+    }
+    Ok(())
+}
+
+// ctrl-c when run by hand, SIGTERM from anything supervising the broker
+async fn shutdown_signal() -> tokio::io::Result<()> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result,
+        _ = terminate.recv() => Ok(()),
     }
 }
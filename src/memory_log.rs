@@ -0,0 +1,492 @@
+use crate::leader_epoch::LeaderEpochCache;
+use crate::producer_state::{ProducerState, ProducerStateTable};
+use crate::purgatory::Purgatory;
+use crate::storage::{
+    check_leader_epoch, check_new_topic, generate_topic_id, whole_batches, BatchHeader,
+    EpochEndOffset, FetchLimits, FetchedPartition, LogDirUsage, LogStore, PartitionInfo,
+    TopicPartition,
+};
+use crate::topic_config::{CleanupConfig, TopicConfigStore};
+use crate::{
+    KafkaError, INCONSISTENT_TOPIC_ID, OFFSET_OUT_OF_RANGE, TOPIC_ALREADY_EXISTS, UNKNOWN_TOPIC_ID,
+    UNKNOWN_TOPIC_OR_PARTITION,
+};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+struct MemoryBatch {
+    header: BatchHeader,
+    // the whole batch, laid out as it would be in a segment file
+    bytes: Vec<u8>,
+}
+
+struct MemoryPartition {
+    topic_id: i128,
+    // ordered by base offset
+    batches: Vec<MemoryBatch>,
+    // moved up as retention drops batches
+    log_start_offset: i64,
+    leader_epochs: LeaderEpochCache,
+    producers: ProducerStateTable,
+    // set for partitions followed by the replica fetcher, and for led ones with followers in
+    // their isr. the log end offset is the high watermark otherwise
+    replicated_high_watermark: Option<i64>,
+}
+
+impl MemoryPartition {
+    fn new(topic_id: i128) -> Self {
+        MemoryPartition {
+            topic_id,
+            batches: vec![],
+            log_start_offset: 0,
+            leader_epochs: LeaderEpochCache::in_memory(),
+            producers: ProducerStateTable::default(),
+            replicated_high_watermark: None,
+        }
+    }
+
+    fn log_end_offset(&self) -> i64 {
+        self.batches
+            .last()
+            .map(|batch| batch.header.next_offset())
+            .unwrap_or(self.log_start_offset)
+    }
+
+    // see PartitionLog::leader_epoch
+    fn leader_epoch(&self, metadata_epoch: Option<i32>) -> i32 {
+        metadata_epoch.unwrap_or_else(|| self.leader_epochs.latest_epoch().unwrap_or(0))
+    }
+
+    fn high_watermark(&self) -> i64 {
+        let log_end_offset = self.log_end_offset();
+        self.replicated_high_watermark
+            .map_or(log_end_offset, |high_watermark| {
+                high_watermark.min(log_end_offset)
+            })
+    }
+}
+
+// `log.store=memory`: partitions that never touch the filesystem, for tests and the early
+// protocol stages. there's no produce path, so they're filled in through `append`
+#[derive(Default)]
+pub struct MemoryLogStore {
+    partitions: Mutex<BTreeMap<TopicPartition, MemoryPartition>>,
+}
+
+impl MemoryLogStore {
+    pub fn new() -> Arc<Self> {
+        Arc::new(MemoryLogStore::default())
+    }
+
+    pub fn create_partition(&self, topic_partition: TopicPartition, topic_id: i128) {
+        self.partitions
+            .lock()
+            .unwrap()
+            .entry(topic_partition)
+            .or_insert_with(|| MemoryPartition::new(topic_id));
+    }
+
+    // `records` are whole record batches back to back, each starting where the one before it
+    // ends and the first at the log end offset like append_replica. nothing is appended when
+    // any of them is incomplete or out of place. fetches waiting on the partition are checked
+    // after
+    pub fn append(
+        &self,
+        topic_partition: &TopicPartition,
+        records: &[u8],
+        purgatory: &Purgatory<TopicPartition>,
+    ) -> Result<(), KafkaError> {
+        let mut batches = vec![];
+        let mut position = 0;
+        while position < records.len() {
+            let header = BatchHeader::parse(&records[position..])
+                .filter(|header| position + header.len as usize <= records.len())
+                .ok_or_else(|| {
+                    KafkaError::CorruptedMessage(format!(
+                        "incomplete record batch at byte {position}"
+                    ))
+                })?;
+            let end = position + header.len as usize;
+            batches.push(MemoryBatch {
+                header,
+                bytes: records[position..end].to_vec(),
+            });
+            position = end;
+        }
+
+        let mut partitions = self.partitions.lock().unwrap();
+        let partition = partitions.get_mut(topic_partition).ok_or_else(|| {
+            KafkaError::CorruptedMessage(format!("no partition {topic_partition:?} to append to"))
+        })?;
+        let mut next_offset = partition.log_end_offset();
+        for batch in &batches {
+            if batch.header.base_offset != next_offset {
+                return Err(KafkaError::CorruptedMessage(format!(
+                    "record batch at offset {} where offset {next_offset} was expected",
+                    batch.header.base_offset
+                )));
+            }
+            next_offset = batch.header.next_offset();
+        }
+        for batch in batches {
+            partition.leader_epochs.assign(
+                batch.header.partition_leader_epoch,
+                batch.header.base_offset,
+            );
+            partition.producers.apply(&batch.header);
+            partition.batches.push(batch);
+        }
+        drop(partitions);
+
+        purgatory.check(topic_partition);
+        Ok(())
+    }
+}
+
+impl LogStore for MemoryLogStore {
+    // there are no log dirs to report on
+    fn log_dir_usage(&self) -> Vec<LogDirUsage> {
+        vec![]
+    }
+
+    fn close(&self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn has_partition(&self, topic_partition: &TopicPartition) -> bool {
+        self.partitions
+            .lock()
+            .unwrap()
+            .contains_key(topic_partition)
+    }
+
+    fn list_partitions(&self) -> Vec<PartitionInfo> {
+        self.partitions
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(topic_partition, log)| PartitionInfo {
+                topic_partition: topic_partition.clone(),
+                topic_id: Some(log.topic_id),
+                leader_epoch: log.leader_epoch(None),
+            })
+            .collect()
+    }
+
+    fn fetch(
+        &self,
+        topic_id: i128,
+        partition: i32,
+        current_leader_epoch: i32,
+        metadata_epoch: Option<i32>,
+        fetch_offset: i64,
+        limits: &FetchLimits,
+    ) -> Result<FetchedPartition, i16> {
+        let partitions = self.partitions.lock().unwrap();
+        let mut topic_known = false;
+
+        for (topic_partition, log) in partitions.iter() {
+            if log.topic_id != topic_id {
+                continue;
+            }
+            topic_known = true;
+
+            if topic_partition.partition == partition {
+                check_leader_epoch(current_leader_epoch, log.leader_epoch(metadata_epoch))?;
+                let end_offset = limits.end_offset(log.high_watermark(), log.log_end_offset());
+                if !(log.log_start_offset..=log.log_end_offset()).contains(&fetch_offset) {
+                    return Err(OFFSET_OUT_OF_RANGE);
+                }
+
+                let mut records = vec![];
+                for batch in &log.batches {
+                    if batch.header.next_offset() <= fetch_offset {
+                        continue;
+                    }
+                    if batch.header.next_offset() > end_offset {
+                        break;
+                    }
+                    if limits.stops_before(records.len(), batch.bytes.len()) {
+                        break;
+                    }
+                    records.extend_from_slice(&batch.bytes);
+                }
+
+                return Ok(FetchedPartition {
+                    high_watermark: log.high_watermark(),
+                    log_start_offset: log.log_start_offset,
+                    records,
+                });
+            }
+        }
+
+        match topic_known {
+            true => Err(UNKNOWN_TOPIC_OR_PARTITION),
+            false => Err(UNKNOWN_TOPIC_ID),
+        }
+    }
+
+    fn end_offset_for_epoch(
+        &self,
+        topic_partition: &TopicPartition,
+        current_leader_epoch: i32,
+        metadata_epoch: Option<i32>,
+        leader_epoch: i32,
+    ) -> EpochEndOffset {
+        let partitions = self.partitions.lock().unwrap();
+        let Some(log) = partitions.get(topic_partition) else {
+            return EpochEndOffset::UnknownPartition;
+        };
+
+        // -1 means the client doesn't know the current epoch, which skips fencing
+        if current_leader_epoch >= 0 {
+            match current_leader_epoch.cmp(&log.leader_epoch(metadata_epoch)) {
+                std::cmp::Ordering::Less => return EpochEndOffset::FencedLeaderEpoch,
+                std::cmp::Ordering::Greater => return EpochEndOffset::UnknownLeaderEpoch,
+                std::cmp::Ordering::Equal => {}
+            }
+        }
+
+        let (leader_epoch, end_offset) = log
+            .leader_epochs
+            .end_offset_for(leader_epoch, log.log_end_offset());
+        EpochEndOffset::Found {
+            leader_epoch,
+            end_offset,
+        }
+    }
+
+    // drops whole batches the way the file store drops segments, the last one is always kept
+    fn enforce_retention(&self, topic_configs: &TopicConfigStore) {
+        let now_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_millis() as i64)
+            .unwrap_or_default();
+        let mut partitions = self.partitions.lock().unwrap();
+
+        for (topic_partition, log) in partitions.iter_mut() {
+            let CleanupConfig {
+                delete,
+                retention_ms,
+                retention_bytes,
+            } = topic_configs.cleanup_config(&topic_partition.topic);
+            if !delete {
+                continue;
+            }
+
+            let mut size: u64 = log.batches.iter().map(|batch| batch.header.len).sum();
+            let mut deletable = 0;
+            for batch in &log.batches[..log.batches.len().saturating_sub(1)] {
+                let expired =
+                    retention_ms >= 0 && now_ms - batch.header.max_timestamp_ms > retention_ms;
+                let oversized =
+                    retention_bytes >= 0 && size - batch.header.len >= retention_bytes as u64;
+                if !expired && !oversized {
+                    break;
+                }
+                size -= batch.header.len;
+                deletable += 1;
+            }
+
+            if deletable > 0 {
+                log.batches.drain(..deletable);
+                log.log_start_offset = log.batches[0].header.base_offset;
+                log.leader_epochs.truncate_from_start(log.log_start_offset);
+            }
+        }
+    }
+
+    // partitions only come from `create_partition`
+    fn discover_partitions(&self) -> Vec<TopicPartition> {
+        vec![]
+    }
+
+    fn producers(&self, topic_partition: &TopicPartition) -> Option<Vec<ProducerState>> {
+        let partitions = self.partitions.lock().unwrap();
+        let log = partitions.get(topic_partition)?;
+        Some(log.producers.active(log.log_start_offset))
+    }
+
+    fn create_topic(&self, topic: &str, num_partitions: i32) -> Result<i128, i16> {
+        check_new_topic(topic, num_partitions)?;
+        let mut partitions = self.partitions.lock().unwrap();
+        if partitions.keys().any(|tp| tp.topic == topic) {
+            return Err(TOPIC_ALREADY_EXISTS);
+        }
+
+        let topic_id = generate_topic_id();
+        for partition in 0..num_partitions {
+            partitions.insert(
+                TopicPartition {
+                    topic: topic.to_string(),
+                    partition,
+                },
+                MemoryPartition::new(topic_id),
+            );
+        }
+        Ok(topic_id)
+    }
+
+    fn open_replica(&self, topic_partition: &TopicPartition, topic_id: i128) -> Result<i64, i16> {
+        let mut partitions = self.partitions.lock().unwrap();
+        let log = partitions
+            .entry(topic_partition.clone())
+            .or_insert_with(|| MemoryPartition::new(topic_id));
+        if log.topic_id != topic_id {
+            return Err(INCONSISTENT_TOPIC_ID);
+        }
+        log.replicated_high_watermark.get_or_insert(0);
+        Ok(log.log_end_offset())
+    }
+
+    // batches aren't grouped into segments, so there's nothing to roll
+    fn append_replica(
+        &self,
+        topic_partition: &TopicPartition,
+        records: &[u8],
+        _segment_bytes: u64,
+    ) -> Result<i64, i16> {
+        let batches = whole_batches(records);
+        let mut partitions = self.partitions.lock().unwrap();
+        let log = partitions
+            .get_mut(topic_partition)
+            .ok_or(UNKNOWN_TOPIC_OR_PARTITION)?;
+        if batches
+            .first()
+            .is_some_and(|first| first.base_offset != log.log_end_offset())
+        {
+            return Err(OFFSET_OUT_OF_RANGE);
+        }
+
+        let mut position = 0;
+        for header in batches {
+            let end = position + header.len as usize;
+            log.leader_epochs
+                .assign(header.partition_leader_epoch, header.base_offset);
+            log.producers.apply(&header);
+            log.batches.push(MemoryBatch {
+                header,
+                bytes: records[position..end].to_vec(),
+            });
+            position = end;
+        }
+
+        Ok(log.log_end_offset())
+    }
+
+    fn reset_replica(
+        &self,
+        topic_partition: &TopicPartition,
+        log_start_offset: i64,
+    ) -> Result<i64, i16> {
+        let mut partitions = self.partitions.lock().unwrap();
+        let log = partitions
+            .get_mut(topic_partition)
+            .ok_or(UNKNOWN_TOPIC_OR_PARTITION)?;
+        if log_start_offset <= log.log_end_offset() {
+            return Err(OFFSET_OUT_OF_RANGE);
+        }
+
+        log.batches.clear();
+        log.log_start_offset = log_start_offset;
+        log.leader_epochs.truncate_from_start(log_start_offset);
+        log.producers = ProducerStateTable::default();
+        log.replicated_high_watermark = Some(log_start_offset);
+        Ok(log_start_offset)
+    }
+
+    fn set_high_watermark(&self, topic_partition: &TopicPartition, high_watermark: Option<i64>) {
+        if let Some(log) = self.partitions.lock().unwrap().get_mut(topic_partition) {
+            log.replicated_high_watermark = high_watermark;
+        }
+    }
+
+    fn high_watermark(&self, topic_partition: &TopicPartition) -> Option<i64> {
+        let partitions = self.partitions.lock().unwrap();
+        partitions
+            .get(topic_partition)
+            .map(|log| log.high_watermark())
+    }
+
+    fn log_end_offset(&self, topic_partition: &TopicPartition) -> Option<i64> {
+        let partitions = self.partitions.lock().unwrap();
+        partitions
+            .get(topic_partition)
+            .map(|log| log.log_end_offset())
+    }
+
+    fn log_start_offset(&self, topic_id: i128, partition: i32) -> Option<i64> {
+        let partitions = self.partitions.lock().unwrap();
+        partitions
+            .iter()
+            .find(|(topic_partition, log)| {
+                log.topic_id == topic_id && topic_partition.partition == partition
+            })
+            .map(|(_, log)| log.log_start_offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::purgatory::Completion;
+    use crate::records::encode_batch;
+    use std::time::{Duration, Instant};
+
+    fn partition(logs: &MemoryLogStore) -> TopicPartition {
+        let topic_partition = TopicPartition {
+            topic: "t".into(),
+            partition: 0,
+        };
+        logs.create_partition(topic_partition.clone(), 1);
+        topic_partition
+    }
+
+    #[test]
+    fn append_keeps_offsets_contiguous() {
+        let logs = MemoryLogStore::new();
+        let purgatory = Purgatory::new();
+        let topic_partition = partition(&logs);
+
+        let gapped = [
+            encode_batch(0, 0, 0, vec![vec![]; 2]),
+            encode_batch(3, 0, 0, vec![vec![]]),
+        ]
+        .concat();
+        assert!(logs.append(&topic_partition, &gapped, &purgatory).is_err());
+        assert_eq!(logs.log_end_offset(&topic_partition), Some(0));
+
+        let batch = encode_batch(0, 0, 0, vec![vec![]; 2]);
+        logs.append(&topic_partition, &batch, &purgatory).unwrap();
+        assert!(logs.append(&topic_partition, &batch, &purgatory).is_err());
+        logs.append(
+            &topic_partition,
+            &encode_batch(2, 0, 0, vec![vec![]]),
+            &purgatory,
+        )
+        .unwrap();
+        assert_eq!(logs.log_end_offset(&topic_partition), Some(3));
+    }
+
+    #[tokio::test]
+    async fn append_wakes_waiting_fetches() {
+        let logs = MemoryLogStore::new();
+        let purgatory = Purgatory::new();
+        let topic_partition = partition(&logs);
+
+        let (waiting, watched) = (purgatory.clone(), logs.clone());
+        let (key, watched_key) = (topic_partition.clone(), topic_partition.clone());
+        let deadline = Instant::now() + Duration::from_secs(60);
+        let fetch = tokio::spawn(async move {
+            let appended = move || watched.log_end_offset(&watched_key) > Some(0);
+            waiting.wait(vec![key], deadline, appended).await
+        });
+        tokio::task::yield_now().await;
+        assert_eq!(purgatory.len(), 1);
+
+        let batch = encode_batch(0, 0, 0, vec![vec![]]);
+        logs.append(&topic_partition, &batch, &purgatory).unwrap();
+        assert_eq!(fetch.await.unwrap(), Completion::Completed);
+    }
+}
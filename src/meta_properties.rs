@@ -0,0 +1,102 @@
+use crate::config::parse_properties;
+use crate::KafkaError;
+use std::path::{Path, PathBuf};
+
+const META_PROPERTIES_FILE: &str = "meta.properties";
+
+// what `kafka-storage.sh format` writes into each log dir. version 0 is the zookeeper era
+// layout, which names the node `broker.id` and has no directory id
+#[derive(Debug, Clone)]
+pub struct MetaProperties {
+    pub version: i32,
+    pub cluster_id: String,
+    pub node_id: i32,
+    pub directory_id: Option<String>,
+}
+
+impl MetaProperties {
+    // `None` for a log dir that hasn't been formatted
+    pub fn load(log_dir: &Path) -> Result<Option<Self>, KafkaError> {
+        let path = log_dir.join(META_PROPERTIES_FILE);
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let properties = parse_properties(&contents);
+        let invalid =
+            |reason: &str| KafkaError::InvalidConfig(format!("{} {reason}", path.display()));
+
+        let version = match properties.get("version").map(String::as_str) {
+            Some("0") => 0,
+            Some("1") => 1,
+            _ => return Err(invalid("has no supported version")),
+        };
+        let cluster_id = properties
+            .get("cluster.id")
+            .filter(|cluster_id| !cluster_id.is_empty())
+            .ok_or_else(|| invalid("has no cluster.id"))?
+            .clone();
+        let node_id = match version {
+            0 => properties.get("broker.id"),
+            _ => properties.get("node.id"),
+        }
+        .and_then(|node_id| node_id.parse().ok())
+        .ok_or_else(|| invalid("has no valid node id"))?;
+
+        Ok(Some(MetaProperties {
+            version,
+            cluster_id,
+            node_id,
+            directory_id: properties.get("directory.id").cloned(),
+        }))
+    }
+}
+
+// reads every log dir's meta.properties and checks they all belong to this node of one
+// cluster, like kafka does before it touches any of them. unformatted dirs are skipped,
+// `None` when there are only unformatted ones
+pub fn load_meta_properties(
+    log_dirs: &[PathBuf],
+    node_id: i32,
+) -> Result<Option<MetaProperties>, KafkaError> {
+    let mut loaded: Vec<(&PathBuf, MetaProperties)> = vec![];
+
+    for log_dir in log_dirs {
+        let Some(meta) = MetaProperties::load(log_dir)? else {
+            continue;
+        };
+
+        if meta.node_id != node_id {
+            return Err(KafkaError::InvalidConfig(format!(
+                "{} was formatted for node {}, but node.id is {node_id}",
+                log_dir.display(),
+                meta.node_id
+            )));
+        }
+        if let Some((other_dir, other)) = loaded.first() {
+            if other.cluster_id != meta.cluster_id {
+                return Err(KafkaError::InvalidConfig(format!(
+                    "{} belongs to cluster {}, but {} belongs to cluster {}",
+                    log_dir.display(),
+                    meta.cluster_id,
+                    other_dir.display(),
+                    other.cluster_id
+                )));
+            }
+        }
+        if let Some((other_dir, _)) = loaded.iter().find(|(_, other)| {
+            meta.directory_id.is_some() && other.directory_id == meta.directory_id
+        }) {
+            return Err(KafkaError::InvalidConfig(format!(
+                "{} and {} have the same directory.id",
+                other_dir.display(),
+                log_dir.display()
+            )));
+        }
+
+        loaded.push((log_dir, meta));
+    }
+
+    Ok(loaded.into_iter().next().map(|(_, meta)| meta))
+}
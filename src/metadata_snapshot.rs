@@ -0,0 +1,170 @@
+use crate::cluster_metadata::{MetadataLog, MetadataRecord};
+use crate::records::{encode_batch, encode_control_batch};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+// the control record types a snapshot starts and ends with
+const SNAPSHOT_HEADER: i16 = 4;
+const SNAPSHOT_FOOTER: i16 = 5;
+const SNAPSHOT_EXTENSION: &str = "checkpoint";
+// how many records go in each of a snapshot's batches
+const SNAPSHOT_BATCH_RECORDS: usize = 1_000;
+// how often run_metadata_snapshots checks whether one is due
+const SNAPSHOT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// a snapshot holds the metadata log's image as of `end_offset`, the offset after the last
+// record it contains, whose batch was written in `epoch`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SnapshotId {
+    pub end_offset: i64,
+    pub epoch: i32,
+}
+
+impl SnapshotId {
+    // `<end offset>-<epoch>.checkpoint`, zero padded like kafka's
+    fn path(&self, dir: &Path) -> PathBuf {
+        dir.join(format!(
+            "{:020}-{:010}.{SNAPSHOT_EXTENSION}",
+            self.end_offset, self.epoch
+        ))
+    }
+
+    fn parse(path: &Path) -> Option<Self> {
+        if path.extension()? != SNAPSHOT_EXTENSION {
+            return None;
+        }
+        let (end_offset, epoch) = path.file_stem()?.to_str()?.split_once('-')?;
+        Some(SnapshotId {
+            end_offset: end_offset.parse().ok()?,
+            epoch: epoch.parse().ok()?,
+        })
+    }
+}
+
+// `None` without any, or without the directory
+pub fn latest_snapshot(dir: &Path) -> std::io::Result<Option<SnapshotId>> {
+    Ok(list_snapshots(dir)?.into_iter().max())
+}
+
+fn list_snapshots(dir: &Path) -> std::io::Result<Vec<SnapshotId>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    let mut snapshots = vec![];
+    for entry in entries {
+        if let Some(id) = SnapshotId::parse(&entry?.path()) {
+            snapshots.push(id);
+        }
+    }
+    Ok(snapshots)
+}
+
+// a header control batch stamped with the last record's timestamp, the records, and a footer
+// control batch, all in the snapshot's epoch. it's written to a `.part` file and renamed into
+// place once synced, which replaces whatever snapshots came before it
+pub fn write_snapshot(
+    dir: &Path,
+    id: SnapshotId,
+    last_contained_timestamp_ms: i64,
+    records: &[MetadataRecord],
+) -> std::io::Result<()> {
+    let timestamp_ms = last_contained_timestamp_ms;
+    let mut header = 0i16.to_be_bytes().to_vec(); // version
+    header.extend_from_slice(&last_contained_timestamp_ms.to_be_bytes());
+    header.push(0); // tagged fields
+    let mut footer = 0i16.to_be_bytes().to_vec(); // version
+    footer.push(0); // tagged fields
+
+    let mut contents = encode_control_batch(0, id.epoch, timestamp_ms, SNAPSHOT_HEADER, header);
+    let mut offset = 1;
+    for chunk in records.chunks(SNAPSHOT_BATCH_RECORDS) {
+        let values = chunk.iter().map(MetadataRecord::framed).collect();
+        contents.extend_from_slice(&encode_batch(offset, id.epoch, timestamp_ms, values));
+        offset += chunk.len() as i64;
+    }
+    contents.extend_from_slice(&encode_control_batch(
+        offset,
+        id.epoch,
+        timestamp_ms,
+        SNAPSHOT_FOOTER,
+        footer,
+    ));
+
+    std::fs::create_dir_all(dir)?;
+    let path = id.path(dir);
+    let part_path = path.with_extension(format!("{SNAPSHOT_EXTENSION}.part"));
+    let mut file = File::create(&part_path)?;
+    file.write_all(&contents)?;
+    file.sync_all()?;
+    std::fs::rename(&part_path, &path)?;
+
+    for older in list_snapshots(dir)?.into_iter().filter(|older| *older < id) {
+        std::fs::remove_file(older.path(dir))?;
+    }
+    Ok(())
+}
+
+// the whole snapshot, `None` when there's no such snapshot
+pub fn read_snapshot(dir: &Path, id: SnapshotId) -> std::io::Result<Option<Vec<u8>>> {
+    match std::fs::read(id.path(dir)) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// (snapshot size, up to `max_bytes` from `position`), `None` when there's no such snapshot.
+// a position at or past the end reads nothing
+pub fn read_snapshot_chunk(
+    dir: &Path,
+    id: SnapshotId,
+    position: u64,
+    max_bytes: u64,
+) -> std::io::Result<Option<(u64, Vec<u8>)>> {
+    let mut file = match File::open(id.path(dir)) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let size = file.metadata()?.len();
+
+    let mut chunk = vec![];
+    if position < size {
+        file.seek(SeekFrom::Start(position))?;
+        file.take(max_bytes).read_to_end(&mut chunk)?;
+    }
+    Ok(Some((size, chunk)))
+}
+
+// snapshots the metadata log whenever it's due, see MetadataLog::snapshot_if_due. a
+// `max_interval` of `None` leaves it to how much was appended
+pub async fn run_metadata_snapshots(
+    metadata_log: Arc<MetadataLog>,
+    max_bytes_between: u64,
+    max_interval: Option<Duration>,
+) {
+    let mut interval = tokio::time::interval(SNAPSHOT_CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let metadata_log = metadata_log.clone();
+        let snapshot = tokio::task::spawn_blocking(move || {
+            metadata_log.snapshot_if_due(max_bytes_between, max_interval)
+        })
+        .await;
+        match snapshot {
+            Ok(Ok(Some(id))) => println!(
+                "Wrote a metadata snapshot at offset {} in epoch {}",
+                id.end_offset, id.epoch
+            ),
+            Ok(Ok(None)) => {}
+            Ok(Err(e)) => eprintln!("Error writing a metadata snapshot: {e}"),
+            Err(e) => eprintln!("Error writing a metadata snapshot: {e}"),
+        }
+    }
+}
@@ -0,0 +1,402 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, AtomicU64, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+// upper bounds of the request latency histogram buckets, in seconds
+const LATENCY_BUCKETS: &[f64] = &[0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+// client ids are picked by clients, so past this many every new one is folded into OTHER_CLIENTS
+const MAX_TRACKED_CLIENTS: usize = 1024;
+const OTHER_CLIENTS: &str = "<other>";
+
+#[derive(Default)]
+struct ApiMetrics {
+    requests: u64,
+    errors: BTreeMap<i16, u64>,
+    // cumulative counts per LATENCY_BUCKETS entry, the +Inf bucket is `requests`
+    latency_buckets: [u64; LATENCY_BUCKETS.len()],
+    latency_sum_secs: f64,
+}
+
+// per client id totals, rates are left to whatever scrapes them
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ClientStats {
+    pub requests: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+}
+
+// metric name, help text and the field it reports
+type ClientCounter = (&'static str, &'static str, fn(&ClientStats) -> u64);
+
+const CLIENT_COUNTERS: &[ClientCounter] = &[
+    (
+        "kafka_client_requests_total",
+        "Requests handled, by client id.",
+        |client| client.requests,
+    ),
+    (
+        "kafka_client_bytes_in_total",
+        "Request bytes read, including size prefixes, by client id.",
+        |client| client.bytes_in,
+    ),
+    (
+        "kafka_client_bytes_out_total",
+        "Response bytes written, including size prefixes, by client id.",
+        |client| client.bytes_out,
+    ),
+];
+
+// what a client id last reported itself as through ApiVersions v3+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientSoftware {
+    pub name: String,
+    pub version: String,
+}
+
+// what the readiness probe reports, brokers only ever move forward through these
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeState {
+    // binding listeners, opening request logs and everything else around the log recovery
+    Starting,
+    RecoveringLogs,
+    Ready,
+}
+
+impl NodeState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            NodeState::Starting => "starting",
+            NodeState::RecoveringLogs => "recovering logs",
+            NodeState::Ready => "ready",
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    apis: Mutex<BTreeMap<i16, ApiMetrics>>,
+    clients: Mutex<BTreeMap<String, ClientStats>>,
+    // capped at MAX_TRACKED_CLIENTS like `clients`, past that new client ids aren't recorded
+    client_software: Mutex<BTreeMap<String, ClientSoftware>>,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+    active_connections: AtomicI64,
+    buffers_allocated: AtomicU64,
+    buffers_reused: AtomicU64,
+    buffers_discarded: AtomicU64,
+    // a NodeState as its discriminant, the metrics listener is up before the broker is
+    node_state: AtomicU8,
+}
+
+impl Metrics {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Metrics::default())
+    }
+
+    pub fn node_state(&self) -> NodeState {
+        match self.node_state.load(Ordering::Relaxed) {
+            0 => NodeState::Starting,
+            1 => NodeState::RecoveringLogs,
+            _ => NodeState::Ready,
+        }
+    }
+
+    pub fn set_node_state(&self, state: NodeState) {
+        self.node_state.store(state as u8, Ordering::Relaxed);
+    }
+
+    pub fn record_request(&self, api_key: i16, error_code: i16, latency: Duration) {
+        let mut apis = self.apis.lock().unwrap();
+        let api = apis.entry(api_key).or_default();
+
+        api.requests += 1;
+        if error_code != crate::NONE {
+            *api.errors.entry(error_code).or_default() += 1;
+        }
+
+        let latency_secs = latency.as_secs_f64();
+        api.latency_sum_secs += latency_secs;
+        for (bucket, upper_bound) in api.latency_buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if latency_secs <= *upper_bound {
+                *bucket += 1;
+            }
+        }
+    }
+
+    pub fn record_client_request(&self, client_id: &str, bytes_in: usize, bytes_out: usize) {
+        let mut clients = self.clients.lock().unwrap();
+        let key = if clients.contains_key(client_id) || clients.len() < MAX_TRACKED_CLIENTS {
+            client_id
+        } else {
+            OTHER_CLIENTS
+        };
+        let client = clients.entry(key.to_string()).or_default();
+
+        client.requests += 1;
+        client.bytes_in += bytes_in as u64;
+        client.bytes_out += bytes_out as u64;
+    }
+
+    pub fn client_stats(&self, client_id: &str) -> Option<ClientStats> {
+        self.clients.lock().unwrap().get(client_id).copied()
+    }
+
+    pub fn record_client_software(&self, client_id: &str, name: &str, version: &str) {
+        let mut client_software = self.client_software.lock().unwrap();
+        if !client_software.contains_key(client_id) && client_software.len() >= MAX_TRACKED_CLIENTS
+        {
+            return;
+        }
+        client_software.insert(
+            client_id.to_string(),
+            ClientSoftware {
+                name: name.to_string(),
+                version: version.to_string(),
+            },
+        );
+    }
+
+    pub fn client_software(&self, client_id: &str) -> Option<ClientSoftware> {
+        self.client_software.lock().unwrap().get(client_id).cloned()
+    }
+
+    pub fn record_bytes_in(&self, bytes: usize) {
+        self.bytes_in.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_bytes_out(&self, bytes: usize) {
+        self.bytes_out.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_buffer_allocated(&self) {
+        self.buffers_allocated.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_buffer_reused(&self) {
+        self.buffers_reused.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_buffer_discarded(&self) {
+        self.buffers_discarded.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // the returned guard counts as an active connection until it's dropped
+    pub fn connection_opened(self: &Arc<Self>) -> ConnectionGuard {
+        self.active_connections.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard {
+            metrics: Arc::clone(self),
+        }
+    }
+
+    // renders everything in the prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let apis = self.apis.lock().unwrap();
+
+        out.push_str("# HELP kafka_requests_total Requests handled, by API key.\n");
+        out.push_str("# TYPE kafka_requests_total counter\n");
+        for (api_key, api) in apis.iter() {
+            let _ = writeln!(
+                out,
+                "kafka_requests_total{{api_key=\"{api_key}\"}} {}",
+                api.requests
+            );
+        }
+
+        out.push_str("# HELP kafka_request_errors_total Responses carrying an error code, by API key and error code.\n");
+        out.push_str("# TYPE kafka_request_errors_total counter\n");
+        for (api_key, api) in apis.iter() {
+            for (error_code, count) in &api.errors {
+                let _ = writeln!(
+                    out,
+                    "kafka_request_errors_total{{api_key=\"{api_key}\",error_code=\"{error_code}\"}} {count}"
+                );
+            }
+        }
+
+        out.push_str("# HELP kafka_request_latency_seconds Time from reading a request to writing its response.\n");
+        out.push_str("# TYPE kafka_request_latency_seconds histogram\n");
+        for (api_key, api) in apis.iter() {
+            for (count, upper_bound) in api.latency_buckets.iter().zip(LATENCY_BUCKETS) {
+                let _ = writeln!(
+                    out,
+                    "kafka_request_latency_seconds_bucket{{api_key=\"{api_key}\",le=\"{upper_bound}\"}} {count}"
+                );
+            }
+            let _ = writeln!(
+                out,
+                "kafka_request_latency_seconds_bucket{{api_key=\"{api_key}\",le=\"+Inf\"}} {}",
+                api.requests
+            );
+            let _ = writeln!(
+                out,
+                "kafka_request_latency_seconds_sum{{api_key=\"{api_key}\"}} {}",
+                api.latency_sum_secs
+            );
+            let _ = writeln!(
+                out,
+                "kafka_request_latency_seconds_count{{api_key=\"{api_key}\"}} {}",
+                api.requests
+            );
+        }
+
+        out.push_str("# HELP kafka_bytes_in_total Request bytes read, including size prefixes.\n");
+        out.push_str("# TYPE kafka_bytes_in_total counter\n");
+        let _ = writeln!(
+            out,
+            "kafka_bytes_in_total {}",
+            self.bytes_in.load(Ordering::Relaxed)
+        );
+
+        out.push_str(
+            "# HELP kafka_bytes_out_total Response bytes written, including size prefixes.\n",
+        );
+        out.push_str("# TYPE kafka_bytes_out_total counter\n");
+        let _ = writeln!(
+            out,
+            "kafka_bytes_out_total {}",
+            self.bytes_out.load(Ordering::Relaxed)
+        );
+
+        out.push_str("# HELP kafka_active_connections Currently open client connections.\n");
+        out.push_str("# TYPE kafka_active_connections gauge\n");
+        let _ = writeln!(
+            out,
+            "kafka_active_connections {}",
+            self.active_connections.load(Ordering::Relaxed)
+        );
+
+        let clients = self.clients.lock().unwrap();
+        for (name, help, value) in CLIENT_COUNTERS {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} counter");
+            for (client_id, client) in clients.iter() {
+                let _ = writeln!(
+                    out,
+                    "{name}{{client_id=\"{}\"}} {}",
+                    escape_label(client_id),
+                    value(client)
+                );
+            }
+        }
+        drop(clients);
+
+        out.push_str("# HELP kafka_client_software_info Client software last reported through ApiVersions, by client id.\n");
+        out.push_str("# TYPE kafka_client_software_info gauge\n");
+        for (client_id, software) in self.client_software.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "kafka_client_software_info{{client_id=\"{}\",client_software_name=\"{}\",client_software_version=\"{}\"}} 1",
+                escape_label(client_id),
+                escape_label(&software.name),
+                escape_label(&software.version)
+            );
+        }
+
+        out.push_str("# HELP kafka_buffer_pool_acquired_total Request and response buffers handed out, by whether they came from the pool.\n");
+        out.push_str("# TYPE kafka_buffer_pool_acquired_total counter\n");
+        let _ = writeln!(
+            out,
+            "kafka_buffer_pool_acquired_total{{source=\"allocated\"}} {}",
+            self.buffers_allocated.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(
+            out,
+            "kafka_buffer_pool_acquired_total{{source=\"reused\"}} {}",
+            self.buffers_reused.load(Ordering::Relaxed)
+        );
+
+        out.push_str("# HELP kafka_buffer_pool_discarded_total Buffers freed instead of returned to a full pool or for being too large.\n");
+        out.push_str("# TYPE kafka_buffer_pool_discarded_total counter\n");
+        let _ = writeln!(
+            out,
+            "kafka_buffer_pool_discarded_total {}",
+            self.buffers_discarded.load(Ordering::Relaxed)
+        );
+
+        out
+    }
+}
+
+// label values are quoted, so backslashes, quotes and newlines need escaping
+fn escape_label(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub struct ConnectionGuard {
+    metrics: Arc<Metrics>,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.metrics
+            .active_connections
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// a deliberately tiny http server: prometheus only ever needs `GET /metrics`
+pub async fn serve_metrics(listener: TcpListener, metrics: Arc<Metrics>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let metrics = metrics.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle_metrics_request(stream, &metrics).await {
+                        eprintln!("Error serving metrics request: {e}");
+                    }
+                });
+            }
+            Err(e) => eprintln!("Error accepting metrics connection: {e}"),
+        }
+    }
+}
+
+async fn handle_metrics_request(mut stream: TcpStream, metrics: &Metrics) -> std::io::Result<()> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 || request.len() > 8192 {
+            return Ok(());
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let request_line = request.lines().next().unwrap_or_default();
+    let mut parts = request_line.split_whitespace();
+
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", metrics.render()),
+        // answered whenever the process is, for a liveness probe
+        (Some("GET"), Some("/livez")) => ("200 OK", format!("{}\n", metrics.node_state().name())),
+        // 503 until the logs are recovered and every listener accepts connections
+        (Some("GET"), Some("/readyz")) => match metrics.node_state() {
+            NodeState::Ready => ("200 OK", "ready\n".to_string()),
+            state => ("503 Service Unavailable", format!("{}\n", state.name())),
+        },
+        _ => ("404 Not Found", "not found\n".to_string()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
@@ -0,0 +1,228 @@
+use crate::readers::*;
+use crate::writers::*;
+use crate::{KafkaError, TAG_BUFFER};
+
+// ### OFFSET COMMIT (v8) ### //
+pub struct OffsetCommitRequest {
+    pub group_id: String,
+    pub generation_id: i32,
+    pub member_id: String,
+    pub group_instance_id: Option<String>,
+    pub topics: Vec<OffsetCommitTopic>,
+}
+
+pub struct OffsetCommitTopic {
+    pub name: String,
+    pub partitions: Vec<OffsetCommitPartition>,
+}
+
+pub struct OffsetCommitPartition {
+    pub partition_index: i32,
+    pub committed_offset: i64,
+    pub committed_leader_epoch: i32,
+    pub committed_metadata: Option<String>,
+}
+
+impl OffsetCommitRequest {
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+
+        let group_id = read_compact_string(&mut cursor)?;
+        let generation_id = read_int32(&mut cursor)?;
+        let member_id = read_compact_string(&mut cursor)?;
+        let group_instance_id = read_compact_nullable_string(&mut cursor)?;
+
+        let topics_size = read_compact_array_len(&mut cursor)?; // [topics]
+        let mut topics = array_with_capacity(topics_size);
+        for _ in 0..topics_size {
+            let name = read_compact_string(&mut cursor)?;
+
+            let partitions_size = read_compact_array_len(&mut cursor)?; // [partitions]
+            let mut partitions = array_with_capacity(partitions_size);
+            for _ in 0..partitions_size {
+                let partition_index = read_int32(&mut cursor)?;
+                let committed_offset = read_int64(&mut cursor)?;
+                let committed_leader_epoch = read_int32(&mut cursor)?;
+                let committed_metadata = read_compact_nullable_string(&mut cursor)?;
+                read_tagged_fields(&mut cursor)?;
+
+                partitions.push(OffsetCommitPartition {
+                    partition_index,
+                    committed_offset,
+                    committed_leader_epoch,
+                    committed_metadata,
+                });
+            }
+            read_tagged_fields(&mut cursor)?;
+
+            topics.push(OffsetCommitTopic { name, partitions });
+        }
+
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(OffsetCommitRequest {
+            group_id,
+            generation_id,
+            member_id,
+            group_instance_id,
+            topics,
+        })
+    }
+}
+
+pub struct OffsetCommitResponse {
+    pub throttle_time_ms: i32,
+    pub topics: Vec<OffsetCommitResponseTopic>,
+}
+
+pub struct OffsetCommitResponseTopic {
+    pub name: String,
+    pub partitions: Vec<OffsetCommitResponsePartition>,
+}
+
+pub struct OffsetCommitResponsePartition {
+    pub partition_index: i32,
+    pub error_code: i16,
+}
+
+impl OffsetCommitResponse {
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.throttle_time_ms.to_be_bytes());
+
+        write_compact_array_len(res_buf, self.topics.len()); // [topics]
+        for topic in &self.topics {
+            write_compact_string(res_buf, &topic.name);
+
+            write_compact_array_len(res_buf, topic.partitions.len()); // [partitions]
+            for partition in &topic.partitions {
+                res_buf.extend_from_slice(&partition.partition_index.to_be_bytes());
+                res_buf.extend_from_slice(&partition.error_code.to_be_bytes());
+                res_buf.extend_from_slice(TAG_BUFFER);
+            }
+            res_buf.extend_from_slice(TAG_BUFFER);
+        }
+
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+// ### OFFSET FETCH (v8) ### //
+pub struct OffsetFetchRequest {
+    pub groups: Vec<OffsetFetchGroup>,
+    pub require_stable: bool,
+}
+
+pub struct OffsetFetchGroup {
+    pub group_id: String,
+    // None asks for every committed offset in the group
+    pub topics: Option<Vec<OffsetFetchTopic>>,
+}
+
+pub struct OffsetFetchTopic {
+    pub name: String,
+    pub partition_indexes: Vec<i32>,
+}
+
+impl OffsetFetchRequest {
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+
+        let groups_size = read_compact_array_len(&mut cursor)?; // [groups]
+        let mut groups = array_with_capacity(groups_size);
+        for _ in 0..groups_size {
+            let group_id = read_compact_string(&mut cursor)?;
+
+            let topics = match read_compact_nullable_array_len(&mut cursor)? {
+                None => None,
+                Some(topics_size) => {
+                    let mut topics = array_with_capacity(topics_size);
+                    for _ in 0..topics_size {
+                        let name = read_compact_string(&mut cursor)?;
+
+                        let partitions_size = read_compact_array_len(&mut cursor)?;
+                        let mut partition_indexes = array_with_capacity(partitions_size);
+                        for _ in 0..partitions_size {
+                            partition_indexes.push(read_int32(&mut cursor)?);
+                        }
+                        read_tagged_fields(&mut cursor)?;
+
+                        topics.push(OffsetFetchTopic {
+                            name,
+                            partition_indexes,
+                        });
+                    }
+                    Some(topics)
+                }
+            };
+            read_tagged_fields(&mut cursor)?;
+
+            groups.push(OffsetFetchGroup { group_id, topics });
+        }
+
+        let require_stable = read_bool(&mut cursor)?;
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(OffsetFetchRequest {
+            groups,
+            require_stable,
+        })
+    }
+}
+
+pub struct OffsetFetchResponse {
+    pub throttle_time_ms: i32,
+    pub groups: Vec<OffsetFetchResponseGroup>,
+}
+
+pub struct OffsetFetchResponseGroup {
+    pub group_id: String,
+    pub topics: Vec<OffsetFetchResponseTopic>,
+    pub error_code: i16,
+}
+
+pub struct OffsetFetchResponseTopic {
+    pub name: String,
+    pub partitions: Vec<OffsetFetchResponsePartition>,
+}
+
+pub struct OffsetFetchResponsePartition {
+    pub partition_index: i32,
+    pub committed_offset: i64,
+    pub committed_leader_epoch: i32,
+    pub metadata: Option<String>,
+    pub error_code: i16,
+}
+
+impl OffsetFetchResponse {
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.throttle_time_ms.to_be_bytes());
+
+        write_compact_array_len(res_buf, self.groups.len()); // [groups]
+        for group in &self.groups {
+            write_compact_string(res_buf, &group.group_id);
+
+            write_compact_array_len(res_buf, group.topics.len()); // [topics]
+            for topic in &group.topics {
+                write_compact_string(res_buf, &topic.name);
+
+                write_compact_array_len(res_buf, topic.partitions.len()); // [partitions]
+                for partition in &topic.partitions {
+                    res_buf.extend_from_slice(&partition.partition_index.to_be_bytes());
+                    res_buf.extend_from_slice(&partition.committed_offset.to_be_bytes());
+                    res_buf.extend_from_slice(&partition.committed_leader_epoch.to_be_bytes());
+                    write_compact_nullable_string(res_buf, partition.metadata.as_deref());
+                    res_buf.extend_from_slice(&partition.error_code.to_be_bytes());
+                    res_buf.extend_from_slice(TAG_BUFFER);
+                }
+                res_buf.extend_from_slice(TAG_BUFFER);
+            }
+
+            res_buf.extend_from_slice(&group.error_code.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+        }
+
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
@@ -0,0 +1,960 @@
+use crate::acl::{
+    Session, OPERATION_ALTER, OPERATION_DESCRIBE, OPERATION_READ, RESOURCE_TYPE_TOPIC,
+};
+use crate::cluster_metadata::MetadataLog;
+use crate::readers::*;
+use crate::storage::{EpochEndOffset, LogStore, TopicPartition};
+use crate::writers::*;
+use crate::{
+    KafkaError, CLUSTER_AUTHORIZATION_FAILED, ELECTION_NOT_NEEDED, FENCED_LEADER_EPOCH,
+    INVALID_REPLICA_ASSIGNMENT, INVALID_REQUEST, KAFKA_STORAGE_ERROR, NONE,
+    NO_REASSIGNMENT_IN_PROGRESS, TAG_BUFFER, TOPIC_AUTHORIZATION_FAILED, UNKNOWN_LEADER_EPOCH,
+    UNKNOWN_TOPIC_OR_PARTITION,
+};
+use std::collections::BTreeMap;
+
+const ELECTION_TYPE_PREFERRED: i8 = 0;
+const ELECTION_TYPE_UNCLEAN: i8 = 1;
+
+// volume sizes can't be queried without statvfs, kafka reports -1 when they're unknown
+const UNKNOWN_VOLUME_BYTES: i64 = -1;
+
+// ### OFFSET FOR LEADER EPOCH (v4) ### //
+pub struct OffsetForLeaderEpochRequest {
+    pub replica_id: i32,
+    pub topics: Vec<OffsetForLeaderTopic>,
+}
+
+pub struct OffsetForLeaderTopic {
+    pub topic: String,
+    pub partitions: Vec<OffsetForLeaderPartition>,
+}
+
+pub struct OffsetForLeaderPartition {
+    pub partition: i32,
+    pub current_leader_epoch: i32,
+    pub leader_epoch: i32,
+}
+
+impl OffsetForLeaderEpochRequest {
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+        let replica_id = read_int32(&mut cursor)?;
+
+        let topics_size = read_compact_array_len(&mut cursor)?; // [topics]
+        let mut topics = array_with_capacity(topics_size);
+        for _ in 0..topics_size {
+            let topic = read_compact_string(&mut cursor)?;
+
+            let partitions_size = read_compact_array_len(&mut cursor)?; // [partitions]
+            let mut partitions = array_with_capacity(partitions_size);
+            for _ in 0..partitions_size {
+                let partition = read_int32(&mut cursor)?;
+                let current_leader_epoch = read_int32(&mut cursor)?;
+                let leader_epoch = read_int32(&mut cursor)?;
+                read_tagged_fields(&mut cursor)?;
+
+                partitions.push(OffsetForLeaderPartition {
+                    partition,
+                    current_leader_epoch,
+                    leader_epoch,
+                });
+            }
+
+            read_tagged_fields(&mut cursor)?;
+            topics.push(OffsetForLeaderTopic { topic, partitions });
+        }
+
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(OffsetForLeaderEpochRequest { replica_id, topics })
+    }
+}
+
+pub struct OffsetForLeaderEpochResponse {
+    pub throttle_time_ms: i32,
+    pub topics: Vec<OffsetForLeaderTopicResult>,
+}
+
+pub struct OffsetForLeaderTopicResult {
+    pub topic: String,
+    pub partitions: Vec<EpochEndOffsetResult>,
+}
+
+pub struct EpochEndOffsetResult {
+    pub error_code: i16,
+    pub partition: i32,
+    pub leader_epoch: i32,
+    pub end_offset: i64,
+}
+
+impl OffsetForLeaderEpochResponse {
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.throttle_time_ms.to_be_bytes());
+
+        write_compact_array_len(res_buf, self.topics.len()); // [topics]
+        for topic in &self.topics {
+            write_compact_string(res_buf, &topic.topic);
+
+            write_compact_array_len(res_buf, topic.partitions.len()); // [partitions]
+            for partition in &topic.partitions {
+                res_buf.extend_from_slice(&partition.error_code.to_be_bytes());
+                res_buf.extend_from_slice(&partition.partition.to_be_bytes());
+                res_buf.extend_from_slice(&partition.leader_epoch.to_be_bytes());
+                res_buf.extend_from_slice(&partition.end_offset.to_be_bytes());
+                res_buf.extend_from_slice(TAG_BUFFER);
+            }
+
+            res_buf.extend_from_slice(TAG_BUFFER);
+        }
+
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+pub fn offsets_for_leader_epoch(
+    logs: &dyn LogStore,
+    metadata_log: &MetadataLog,
+    session: &Session,
+    request: &OffsetForLeaderEpochRequest,
+) -> OffsetForLeaderEpochResponse {
+    let topics = request
+        .topics
+        .iter()
+        .map(|topic| OffsetForLeaderTopicResult {
+            topic: topic.topic.clone(),
+            partitions: topic
+                .partitions
+                .iter()
+                .map(|partition| {
+                    if !session.authorize(OPERATION_DESCRIBE, RESOURCE_TYPE_TOPIC, &topic.topic) {
+                        return EpochEndOffsetResult {
+                            error_code: TOPIC_AUTHORIZATION_FAILED,
+                            partition: partition.partition,
+                            leader_epoch: -1,
+                            end_offset: -1,
+                        };
+                    }
+
+                    let topic_partition = TopicPartition {
+                        topic: topic.topic.clone(),
+                        partition: partition.partition,
+                    };
+                    let end_offset = logs.end_offset_for_epoch(
+                        &topic_partition,
+                        partition.current_leader_epoch,
+                        metadata_log.partition_leader_epoch(&topic_partition),
+                        partition.leader_epoch,
+                    );
+
+                    let (error_code, leader_epoch, end_offset) = match end_offset {
+                        EpochEndOffset::Found {
+                            leader_epoch,
+                            end_offset,
+                        } => (NONE, leader_epoch, end_offset),
+                        EpochEndOffset::UnknownPartition => (UNKNOWN_TOPIC_OR_PARTITION, -1, -1),
+                        EpochEndOffset::FencedLeaderEpoch => (FENCED_LEADER_EPOCH, -1, -1),
+                        EpochEndOffset::UnknownLeaderEpoch => (UNKNOWN_LEADER_EPOCH, -1, -1),
+                    };
+                    EpochEndOffsetResult {
+                        error_code,
+                        partition: partition.partition,
+                        leader_epoch,
+                        end_offset,
+                    }
+                })
+                .collect(),
+        })
+        .collect();
+
+    OffsetForLeaderEpochResponse {
+        throttle_time_ms: 0,
+        topics,
+    }
+}
+
+// ### ELECT LEADERS (v2) ### //
+pub struct ElectLeadersRequest {
+    pub election_type: i8,
+    // every partition when null
+    pub topic_partitions: Option<Vec<ElectLeadersTopic>>,
+    pub timeout_ms: i32,
+}
+
+pub struct ElectLeadersTopic {
+    pub topic: String,
+    pub partitions: Vec<i32>,
+}
+
+impl ElectLeadersRequest {
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+        let election_type = read_int8(&mut cursor)?;
+
+        let topic_partitions = match read_compact_nullable_array_len(&mut cursor)? {
+            Some(topics_size) => {
+                let mut topics = array_with_capacity(topics_size);
+                for _ in 0..topics_size {
+                    let topic = read_compact_string(&mut cursor)?;
+
+                    let partitions_size = read_compact_array_len(&mut cursor)?; // [partitions]
+                    let mut partitions = array_with_capacity(partitions_size);
+                    for _ in 0..partitions_size {
+                        partitions.push(read_int32(&mut cursor)?);
+                    }
+
+                    read_tagged_fields(&mut cursor)?;
+                    topics.push(ElectLeadersTopic { topic, partitions });
+                }
+                Some(topics)
+            }
+            None => None,
+        };
+
+        let timeout_ms = read_int32(&mut cursor)?;
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(ElectLeadersRequest {
+            election_type,
+            topic_partitions,
+            timeout_ms,
+        })
+    }
+}
+
+pub struct ElectLeadersResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub replica_election_results: Vec<ReplicaElectionResult>,
+}
+
+pub struct ReplicaElectionResult {
+    pub topic: String,
+    pub partition_result: Vec<PartitionElectionResult>,
+}
+
+pub struct PartitionElectionResult {
+    pub partition_id: i32,
+    pub error_code: i16,
+    pub error_message: Option<String>,
+}
+
+impl ElectLeadersResponse {
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.throttle_time_ms.to_be_bytes());
+        res_buf.extend_from_slice(&self.error_code.to_be_bytes());
+
+        write_compact_array_len(res_buf, self.replica_election_results.len()); // [replica_election_results]
+        for result in &self.replica_election_results {
+            write_compact_string(res_buf, &result.topic);
+
+            write_compact_array_len(res_buf, result.partition_result.len()); // [partition_result]
+            for partition in &result.partition_result {
+                res_buf.extend_from_slice(&partition.partition_id.to_be_bytes());
+                res_buf.extend_from_slice(&partition.error_code.to_be_bytes());
+                write_compact_nullable_string(res_buf, partition.error_message.as_deref());
+                res_buf.extend_from_slice(TAG_BUFFER);
+            }
+
+            res_buf.extend_from_slice(TAG_BUFFER);
+        }
+
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+// this broker is the only replica of every partition, so it already leads all of them and
+// there's never an election to run
+pub fn elect_leaders(
+    logs: &dyn LogStore,
+    session: &Session,
+    request: &ElectLeadersRequest,
+) -> ElectLeadersResponse {
+    if !session.authorize_cluster(OPERATION_ALTER) {
+        return ElectLeadersResponse {
+            throttle_time_ms: 0,
+            error_code: CLUSTER_AUTHORIZATION_FAILED,
+            replica_election_results: vec![],
+        };
+    }
+    if ![ELECTION_TYPE_PREFERRED, ELECTION_TYPE_UNCLEAN].contains(&request.election_type) {
+        return ElectLeadersResponse {
+            throttle_time_ms: 0,
+            error_code: INVALID_REQUEST,
+            replica_election_results: vec![],
+        };
+    }
+
+    let mut results: BTreeMap<String, Vec<PartitionElectionResult>> = BTreeMap::new();
+    // like kafka, electing every partition only reports the ones an election was run for
+    if let Some(topics) = &request.topic_partitions {
+        for topic in topics {
+            for &partition_id in &topic.partitions {
+                let topic_partition = TopicPartition {
+                    topic: topic.topic.clone(),
+                    partition: partition_id,
+                };
+
+                let (error_code, error_message) = match logs.has_partition(&topic_partition) {
+                    true => (
+                        ELECTION_NOT_NEEDED,
+                        "Leader election not needed, the partition has a single replica",
+                    ),
+                    false => (UNKNOWN_TOPIC_OR_PARTITION, "The partition does not exist"),
+                };
+                results
+                    .entry(topic.topic.clone())
+                    .or_default()
+                    .push(PartitionElectionResult {
+                        partition_id,
+                        error_code,
+                        error_message: Some(error_message.to_string()),
+                    });
+            }
+        }
+    }
+
+    ElectLeadersResponse {
+        throttle_time_ms: 0,
+        error_code: NONE,
+        replica_election_results: results
+            .into_iter()
+            .map(|(topic, partition_result)| ReplicaElectionResult {
+                topic,
+                partition_result,
+            })
+            .collect(),
+    }
+}
+
+// ### ALTER PARTITION REASSIGNMENTS (v0) ### //
+pub struct AlterPartitionReassignmentsRequest {
+    pub timeout_ms: i32,
+    pub topics: Vec<ReassignableTopic>,
+}
+
+pub struct ReassignableTopic {
+    pub name: String,
+    pub partitions: Vec<ReassignablePartition>,
+}
+
+pub struct ReassignablePartition {
+    pub partition_index: i32,
+    // null cancels the partition's pending reassignment
+    pub replicas: Option<Vec<i32>>,
+}
+
+impl AlterPartitionReassignmentsRequest {
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+        let timeout_ms = read_int32(&mut cursor)?;
+
+        let topics_size = read_compact_array_len(&mut cursor)?; // [topics]
+        let mut topics = array_with_capacity(topics_size);
+        for _ in 0..topics_size {
+            let name = read_compact_string(&mut cursor)?;
+
+            let partitions_size = read_compact_array_len(&mut cursor)?; // [partitions]
+            let mut partitions = array_with_capacity(partitions_size);
+            for _ in 0..partitions_size {
+                let partition_index = read_int32(&mut cursor)?;
+
+                let replicas = match read_compact_nullable_array_len(&mut cursor)? {
+                    Some(replicas_size) => {
+                        let mut replicas = array_with_capacity(replicas_size);
+                        for _ in 0..replicas_size {
+                            replicas.push(read_int32(&mut cursor)?);
+                        }
+                        Some(replicas)
+                    }
+                    None => None,
+                };
+
+                read_tagged_fields(&mut cursor)?;
+                partitions.push(ReassignablePartition {
+                    partition_index,
+                    replicas,
+                });
+            }
+
+            read_tagged_fields(&mut cursor)?;
+            topics.push(ReassignableTopic { name, partitions });
+        }
+
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(AlterPartitionReassignmentsRequest { timeout_ms, topics })
+    }
+}
+
+pub struct AlterPartitionReassignmentsResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub error_message: Option<String>,
+    pub responses: Vec<ReassignableTopicResponse>,
+}
+
+pub struct ReassignableTopicResponse {
+    pub name: String,
+    pub partitions: Vec<ReassignablePartitionResponse>,
+}
+
+pub struct ReassignablePartitionResponse {
+    pub partition_index: i32,
+    pub error_code: i16,
+    pub error_message: Option<String>,
+}
+
+impl AlterPartitionReassignmentsResponse {
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.throttle_time_ms.to_be_bytes());
+        res_buf.extend_from_slice(&self.error_code.to_be_bytes());
+        write_compact_nullable_string(res_buf, self.error_message.as_deref());
+
+        write_compact_array_len(res_buf, self.responses.len()); // [responses]
+        for topic in &self.responses {
+            write_compact_string(res_buf, &topic.name);
+
+            write_compact_array_len(res_buf, topic.partitions.len()); // [partitions]
+            for partition in &topic.partitions {
+                res_buf.extend_from_slice(&partition.partition_index.to_be_bytes());
+                res_buf.extend_from_slice(&partition.error_code.to_be_bytes());
+                write_compact_nullable_string(res_buf, partition.error_message.as_deref());
+                res_buf.extend_from_slice(TAG_BUFFER);
+            }
+
+            res_buf.extend_from_slice(TAG_BUFFER);
+        }
+
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+// every partition's only replica is this broker, so the one assignment there is to accept is
+// `[node_id]`, which is already in place. a reassignment never has anything to move and
+// completes as soon as it's accepted, leaving none in progress to cancel
+pub fn alter_partition_reassignments(
+    logs: &dyn LogStore,
+    node_id: i32,
+    session: &Session,
+    request: &AlterPartitionReassignmentsRequest,
+) -> AlterPartitionReassignmentsResponse {
+    if !session.authorize_cluster(OPERATION_ALTER) {
+        return AlterPartitionReassignmentsResponse {
+            throttle_time_ms: 0,
+            error_code: CLUSTER_AUTHORIZATION_FAILED,
+            error_message: Some("Not authorized to alter partition reassignments".to_string()),
+            responses: vec![],
+        };
+    }
+
+    let responses = request
+        .topics
+        .iter()
+        .map(|topic| ReassignableTopicResponse {
+            name: topic.name.clone(),
+            partitions: topic
+                .partitions
+                .iter()
+                .map(|partition| {
+                    let topic_partition = TopicPartition {
+                        topic: topic.name.clone(),
+                        partition: partition.partition_index,
+                    };
+
+                    let error = if !logs.has_partition(&topic_partition) {
+                        Some((UNKNOWN_TOPIC_OR_PARTITION, "The partition does not exist"))
+                    } else {
+                        match partition.replicas.as_deref() {
+                            None => Some((
+                                NO_REASSIGNMENT_IN_PROGRESS,
+                                "No reassignment is in progress for the partition",
+                            )),
+                            Some([replica]) if *replica == node_id => None,
+                            Some([]) => Some((
+                                INVALID_REPLICA_ASSIGNMENT,
+                                "The replica list must not be empty",
+                            )),
+                            Some(_) => Some((
+                                INVALID_REPLICA_ASSIGNMENT,
+                                "Replicas can only be assigned to this broker",
+                            )),
+                        }
+                    };
+
+                    ReassignablePartitionResponse {
+                        partition_index: partition.partition_index,
+                        error_code: error.map(|(error_code, _)| error_code).unwrap_or(NONE),
+                        error_message: error.map(|(_, message)| message.to_string()),
+                    }
+                })
+                .collect(),
+        })
+        .collect();
+
+    AlterPartitionReassignmentsResponse {
+        throttle_time_ms: 0,
+        error_code: NONE,
+        error_message: None,
+        responses,
+    }
+}
+
+// ### LIST PARTITION REASSIGNMENTS (v0) ### //
+pub struct ListPartitionReassignmentsRequest {
+    pub timeout_ms: i32,
+    // every reassigning partition when null
+    pub topics: Option<Vec<ListPartitionReassignmentsTopic>>,
+}
+
+pub struct ListPartitionReassignmentsTopic {
+    pub name: String,
+    pub partition_indexes: Vec<i32>,
+}
+
+impl ListPartitionReassignmentsRequest {
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+        let timeout_ms = read_int32(&mut cursor)?;
+
+        let topics = match read_compact_nullable_array_len(&mut cursor)? {
+            Some(topics_size) => {
+                let mut topics = array_with_capacity(topics_size);
+                for _ in 0..topics_size {
+                    let name = read_compact_string(&mut cursor)?;
+
+                    let partitions_size = read_compact_array_len(&mut cursor)?; // [partition_indexes]
+                    let mut partition_indexes = array_with_capacity(partitions_size);
+                    for _ in 0..partitions_size {
+                        partition_indexes.push(read_int32(&mut cursor)?);
+                    }
+
+                    read_tagged_fields(&mut cursor)?;
+                    topics.push(ListPartitionReassignmentsTopic {
+                        name,
+                        partition_indexes,
+                    });
+                }
+                Some(topics)
+            }
+            None => None,
+        };
+
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(ListPartitionReassignmentsRequest { timeout_ms, topics })
+    }
+}
+
+pub struct ListPartitionReassignmentsResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub error_message: Option<String>,
+    pub topics: Vec<OngoingTopicReassignment>,
+}
+
+pub struct OngoingTopicReassignment {
+    pub name: String,
+    pub partitions: Vec<OngoingPartitionReassignment>,
+}
+
+pub struct OngoingPartitionReassignment {
+    pub partition_index: i32,
+    pub replicas: Vec<i32>,
+    pub adding_replicas: Vec<i32>,
+    pub removing_replicas: Vec<i32>,
+}
+
+impl ListPartitionReassignmentsResponse {
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.throttle_time_ms.to_be_bytes());
+        res_buf.extend_from_slice(&self.error_code.to_be_bytes());
+        write_compact_nullable_string(res_buf, self.error_message.as_deref());
+
+        write_compact_array_len(res_buf, self.topics.len()); // [topics]
+        for topic in &self.topics {
+            write_compact_string(res_buf, &topic.name);
+
+            write_compact_array_len(res_buf, topic.partitions.len()); // [partitions]
+            for partition in &topic.partitions {
+                res_buf.extend_from_slice(&partition.partition_index.to_be_bytes());
+                for replicas in [
+                    &partition.replicas,
+                    &partition.adding_replicas,
+                    &partition.removing_replicas,
+                ] {
+                    write_compact_array_len(res_buf, replicas.len());
+                    for replica in replicas {
+                        res_buf.extend_from_slice(&replica.to_be_bytes());
+                    }
+                }
+                res_buf.extend_from_slice(TAG_BUFFER);
+            }
+
+            res_buf.extend_from_slice(TAG_BUFFER);
+        }
+
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+// reassignments complete the moment they're accepted (see alter_partition_reassignments),
+// so like kafka with nothing in flight, there's never an ongoing one to list
+pub fn list_partition_reassignments(
+    session: &Session,
+    _request: &ListPartitionReassignmentsRequest,
+) -> ListPartitionReassignmentsResponse {
+    if !session.authorize_cluster(OPERATION_DESCRIBE) {
+        return ListPartitionReassignmentsResponse {
+            throttle_time_ms: 0,
+            error_code: CLUSTER_AUTHORIZATION_FAILED,
+            error_message: Some("Not authorized to list partition reassignments".to_string()),
+            topics: vec![],
+        };
+    }
+
+    ListPartitionReassignmentsResponse {
+        throttle_time_ms: 0,
+        error_code: NONE,
+        error_message: None,
+        topics: vec![],
+    }
+}
+
+// ### DESCRIBE PRODUCERS (v0) ### //
+pub struct DescribeProducersRequest {
+    pub topics: Vec<TopicRequest>,
+}
+
+pub struct TopicRequest {
+    pub name: String,
+    pub partition_indexes: Vec<i32>,
+}
+
+impl DescribeProducersRequest {
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+
+        let topics_size = read_compact_array_len(&mut cursor)?; // [topics]
+        let mut topics = array_with_capacity(topics_size);
+        for _ in 0..topics_size {
+            let name = read_compact_string(&mut cursor)?;
+
+            let partitions_size = read_compact_array_len(&mut cursor)?; // [partition_indexes]
+            let mut partition_indexes = array_with_capacity(partitions_size);
+            for _ in 0..partitions_size {
+                partition_indexes.push(read_int32(&mut cursor)?);
+            }
+
+            read_tagged_fields(&mut cursor)?;
+            topics.push(TopicRequest {
+                name,
+                partition_indexes,
+            });
+        }
+
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(DescribeProducersRequest { topics })
+    }
+}
+
+pub struct DescribeProducersResponse {
+    pub throttle_time_ms: i32,
+    pub topics: Vec<TopicResponse>,
+}
+
+pub struct TopicResponse {
+    pub name: String,
+    pub partitions: Vec<PartitionResponse>,
+}
+
+pub struct PartitionResponse {
+    pub partition_index: i32,
+    pub error_code: i16,
+    pub error_message: Option<String>,
+    pub active_producers: Vec<ProducerStateResult>,
+}
+
+pub struct ProducerStateResult {
+    pub producer_id: i64,
+    pub producer_epoch: i32,
+    pub last_sequence: i32,
+    pub last_timestamp: i64,
+    pub coordinator_epoch: i32,
+    pub current_txn_start_offset: i64,
+}
+
+impl DescribeProducersResponse {
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.throttle_time_ms.to_be_bytes());
+
+        write_compact_array_len(res_buf, self.topics.len()); // [topics]
+        for topic in &self.topics {
+            write_compact_string(res_buf, &topic.name);
+
+            write_compact_array_len(res_buf, topic.partitions.len()); // [partitions]
+            for partition in &topic.partitions {
+                res_buf.extend_from_slice(&partition.partition_index.to_be_bytes());
+                res_buf.extend_from_slice(&partition.error_code.to_be_bytes());
+                write_compact_nullable_string(res_buf, partition.error_message.as_deref());
+
+                write_compact_array_len(res_buf, partition.active_producers.len()); // [active_producers]
+                for producer in &partition.active_producers {
+                    res_buf.extend_from_slice(&producer.producer_id.to_be_bytes());
+                    res_buf.extend_from_slice(&producer.producer_epoch.to_be_bytes());
+                    res_buf.extend_from_slice(&producer.last_sequence.to_be_bytes());
+                    res_buf.extend_from_slice(&producer.last_timestamp.to_be_bytes());
+                    res_buf.extend_from_slice(&producer.coordinator_epoch.to_be_bytes());
+                    res_buf.extend_from_slice(&producer.current_txn_start_offset.to_be_bytes());
+                    res_buf.extend_from_slice(TAG_BUFFER);
+                }
+
+                res_buf.extend_from_slice(TAG_BUFFER);
+            }
+
+            res_buf.extend_from_slice(TAG_BUFFER);
+        }
+
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+// the producer state comes from the batches in the log (see ProducerStateTable). there's no
+// transaction coordinator to have written the markers, so its epoch is never known
+pub fn describe_producers(
+    logs: &dyn LogStore,
+    session: &Session,
+    request: &DescribeProducersRequest,
+) -> DescribeProducersResponse {
+    let topics = request
+        .topics
+        .iter()
+        .map(|topic| TopicResponse {
+            name: topic.name.clone(),
+            partitions: topic
+                .partition_indexes
+                .iter()
+                .map(|&partition_index| {
+                    if !session.authorize(OPERATION_READ, RESOURCE_TYPE_TOPIC, &topic.name) {
+                        return PartitionResponse {
+                            partition_index,
+                            error_code: TOPIC_AUTHORIZATION_FAILED,
+                            error_message: Some("Not authorized to read the topic".to_string()),
+                            active_producers: vec![],
+                        };
+                    }
+
+                    let topic_partition = TopicPartition {
+                        topic: topic.name.clone(),
+                        partition: partition_index,
+                    };
+
+                    match logs.producers(&topic_partition) {
+                        Some(producers) => PartitionResponse {
+                            partition_index,
+                            error_code: NONE,
+                            error_message: None,
+                            active_producers: producers
+                                .into_iter()
+                                .map(|producer| ProducerStateResult {
+                                    producer_id: producer.producer_id,
+                                    producer_epoch: producer.producer_epoch as i32,
+                                    last_sequence: producer.last_sequence,
+                                    last_timestamp: producer.last_timestamp_ms,
+                                    coordinator_epoch: -1,
+                                    current_txn_start_offset: producer
+                                        .current_txn_start_offset
+                                        .unwrap_or(-1),
+                                })
+                                .collect(),
+                        },
+                        None => PartitionResponse {
+                            partition_index,
+                            error_code: UNKNOWN_TOPIC_OR_PARTITION,
+                            error_message: Some("The partition does not exist".to_string()),
+                            active_producers: vec![],
+                        },
+                    }
+                })
+                .collect(),
+        })
+        .collect();
+
+    DescribeProducersResponse {
+        throttle_time_ms: 0,
+        topics,
+    }
+}
+
+// ### DESCRIBE LOG DIRS (v4) ### //
+pub struct DescribeLogDirsRequest {
+    // every partition when null
+    pub topics: Option<Vec<DescribableLogDirTopic>>,
+}
+
+pub struct DescribableLogDirTopic {
+    pub topic: String,
+    pub partitions: Vec<i32>,
+}
+
+impl DescribeLogDirsRequest {
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+
+        let topics = match read_compact_nullable_array_len(&mut cursor)? {
+            Some(topics_size) => {
+                let mut topics = array_with_capacity(topics_size);
+                for _ in 0..topics_size {
+                    let topic = read_compact_string(&mut cursor)?;
+
+                    let partitions_size = read_compact_array_len(&mut cursor)?; // [partitions]
+                    let mut partitions = array_with_capacity(partitions_size);
+                    for _ in 0..partitions_size {
+                        partitions.push(read_int32(&mut cursor)?);
+                    }
+
+                    read_tagged_fields(&mut cursor)?;
+                    topics.push(DescribableLogDirTopic { topic, partitions });
+                }
+                Some(topics)
+            }
+            None => None,
+        };
+
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(DescribeLogDirsRequest { topics })
+    }
+}
+
+pub struct DescribeLogDirsResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub results: Vec<DescribeLogDirsResult>,
+}
+
+pub struct DescribeLogDirsResult {
+    pub error_code: i16,
+    pub log_dir: String,
+    pub topics: Vec<DescribeLogDirsTopic>,
+    pub total_bytes: i64,
+    pub usable_bytes: i64,
+}
+
+pub struct DescribeLogDirsTopic {
+    pub name: String,
+    pub partitions: Vec<DescribeLogDirsPartition>,
+}
+
+pub struct DescribeLogDirsPartition {
+    pub partition_index: i32,
+    pub partition_size: i64,
+    pub offset_lag: i64,
+    pub is_future_key: bool,
+}
+
+impl DescribeLogDirsResponse {
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.throttle_time_ms.to_be_bytes());
+        res_buf.extend_from_slice(&self.error_code.to_be_bytes());
+
+        write_compact_array_len(res_buf, self.results.len()); // [results]
+        for result in &self.results {
+            res_buf.extend_from_slice(&result.error_code.to_be_bytes());
+            write_compact_string(res_buf, &result.log_dir);
+
+            write_compact_array_len(res_buf, result.topics.len()); // [topics]
+            for topic in &result.topics {
+                write_compact_string(res_buf, &topic.name);
+
+                write_compact_array_len(res_buf, topic.partitions.len()); // [partitions]
+                for partition in &topic.partitions {
+                    res_buf.extend_from_slice(&partition.partition_index.to_be_bytes());
+                    res_buf.extend_from_slice(&partition.partition_size.to_be_bytes());
+                    res_buf.extend_from_slice(&partition.offset_lag.to_be_bytes());
+                    res_buf.push(partition.is_future_key as u8);
+                    res_buf.extend_from_slice(TAG_BUFFER);
+                }
+
+                res_buf.extend_from_slice(TAG_BUFFER);
+            }
+
+            res_buf.extend_from_slice(&result.total_bytes.to_be_bytes());
+            res_buf.extend_from_slice(&result.usable_bytes.to_be_bytes());
+            res_buf.extend_from_slice(TAG_BUFFER);
+        }
+
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+pub fn describe_log_dirs(
+    logs: &dyn LogStore,
+    session: &Session,
+    request: &DescribeLogDirsRequest,
+) -> DescribeLogDirsResponse {
+    if !session.authorize_cluster(OPERATION_DESCRIBE) {
+        return DescribeLogDirsResponse {
+            throttle_time_ms: 0,
+            error_code: CLUSTER_AUTHORIZATION_FAILED,
+            results: vec![],
+        };
+    }
+
+    let wanted = |topic_partition: &TopicPartition| match &request.topics {
+        Some(topics) => topics.iter().any(|topic| {
+            topic.topic == topic_partition.topic
+                && topic.partitions.contains(&topic_partition.partition)
+        }),
+        None => true,
+    };
+
+    let results =
+        logs.log_dir_usage()
+            .into_iter()
+            .map(|usage| {
+                let mut topics: BTreeMap<String, Vec<DescribeLogDirsPartition>> = BTreeMap::new();
+                for (topic_partition, size) in usage.partition_sizes {
+                    if !wanted(&topic_partition) {
+                        continue;
+                    }
+                    // there are no followers, the only replica is always caught up with itself
+                    topics.entry(topic_partition.topic).or_default().push(
+                        DescribeLogDirsPartition {
+                            partition_index: topic_partition.partition,
+                            partition_size: size as i64,
+                            offset_lag: 0,
+                            is_future_key: false,
+                        },
+                    );
+                }
+
+                DescribeLogDirsResult {
+                    error_code: match usage.error {
+                        Some(_) => KAFKA_STORAGE_ERROR,
+                        None => NONE,
+                    },
+                    log_dir: usage.log_dir.to_string_lossy().into_owned(),
+                    topics: topics
+                        .into_iter()
+                        .map(|(name, partitions)| DescribeLogDirsTopic { name, partitions })
+                        .collect(),
+                    total_bytes: UNKNOWN_VOLUME_BYTES,
+                    usable_bytes: UNKNOWN_VOLUME_BYTES,
+                }
+            })
+            .collect();
+
+    DescribeLogDirsResponse {
+        throttle_time_ms: 0,
+        error_code: NONE,
+        results,
+    }
+}
@@ -0,0 +1,73 @@
+use crate::storage::BatchHeader;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProducerState {
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+    // -1 for producers that don't send sequence numbers
+    pub last_sequence: i32,
+    pub last_timestamp_ms: i64,
+    // the last offset the producer wrote, its state goes once retention deletes that
+    pub last_offset: i64,
+    // the first offset of the producer's open transaction
+    pub current_txn_start_offset: Option<i64>,
+}
+
+// the latest batch of every producer that wrote to a partition, rebuilt from the batch
+// headers each time the log is loaded. kafka keeps this in `.snapshot` files so it doesn't
+// have to rescan, but reading every header is already how the log is loaded here
+#[derive(Default)]
+pub struct ProducerStateTable {
+    producers: BTreeMap<i64, ProducerState>,
+}
+
+impl ProducerStateTable {
+    // batches have to be applied in offset order
+    pub fn apply(&mut self, batch: &BatchHeader) {
+        // batches without a producer id aren't idempotent, there's nothing to remember
+        if batch.producer_id < 0 {
+            return;
+        }
+
+        let previous = self.producers.get(&batch.producer_id);
+        // a control batch (commit or abort marker) ends the transaction, a transactional data
+        // batch opens one unless it's already open
+        let current_txn_start_offset = match (batch.is_control(), batch.is_transactional()) {
+            (true, _) => None,
+            (false, true) => previous
+                .and_then(|previous| previous.current_txn_start_offset)
+                .or(Some(batch.base_offset)),
+            (false, false) => None,
+        };
+        // markers don't carry a sequence, so they keep the data batches' one
+        let last_sequence = match (batch.is_control(), batch.base_sequence) {
+            (true, _) => previous
+                .map(|previous| previous.last_sequence)
+                .unwrap_or(-1),
+            (false, -1) => -1,
+            (false, base_sequence) => base_sequence.wrapping_add(batch.last_offset_delta),
+        };
+
+        self.producers.insert(
+            batch.producer_id,
+            ProducerState {
+                producer_id: batch.producer_id,
+                producer_epoch: batch.producer_epoch,
+                last_sequence,
+                last_timestamp_ms: batch.max_timestamp_ms,
+                last_offset: batch.next_offset() - 1,
+                current_txn_start_offset,
+            },
+        );
+    }
+
+    // producers whose last batch is still in the log, like kafka's eviction on retention
+    pub fn active(&self, log_start_offset: i64) -> Vec<ProducerState> {
+        self.producers
+            .values()
+            .filter(|producer| producer.last_offset >= log_start_offset)
+            .copied()
+            .collect()
+    }
+}
@@ -0,0 +1,411 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, Notify};
+
+// kafka's timer defaults, a 1ms tick and 20 buckets per wheel. each level up covers 20 times
+// the span of the one below, so a few levels cover any max_wait_ms
+const TICK_MS: u64 = 1;
+const WHEEL_SIZE: usize = 20;
+// how often run_purgatory_reaper advances the timer while something is waiting
+const REAPER_INTERVAL: Duration = Duration::from_millis(5);
+
+// how a wait ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Completion {
+    // its condition held when one of its keys was checked
+    Completed,
+    // the deadline passed first
+    Expired,
+}
+
+// a hierarchical timing wheel like kafka's: WHEEL_SIZE buckets of `tick_ms` each, with
+// anything further out than that handed to an overflow wheel whose tick is this wheel's
+// whole span. entries cascade down a level as their overflow bucket comes up, so adding and
+// expiring stay cheap with thousands of entries pending
+struct TimingWheel<T> {
+    tick_ms: u64,
+    // the start of the current tick, a multiple of `tick_ms`
+    current_ms: u64,
+    // (expiration, entry), bucket `i` holds the ticks that are `i` modulo WHEEL_SIZE
+    buckets: Vec<Vec<(u64, T)>>,
+    overflow: Option<Box<TimingWheel<T>>>,
+}
+
+impl<T> TimingWheel<T> {
+    fn new(tick_ms: u64, start_ms: u64) -> Self {
+        TimingWheel {
+            tick_ms,
+            current_ms: start_ms - start_ms % tick_ms,
+            buckets: (0..WHEEL_SIZE).map(|_| vec![]).collect(),
+            overflow: None,
+        }
+    }
+
+    fn span_ms(&self) -> u64 {
+        self.tick_ms * WHEEL_SIZE as u64
+    }
+
+    // hands the entry back when it's due within the current tick
+    fn add(&mut self, expiration_ms: u64, entry: T) -> Result<(), T> {
+        if expiration_ms < self.current_ms + self.tick_ms {
+            return Err(entry);
+        }
+        if expiration_ms < self.current_ms + self.span_ms() {
+            let bucket = (expiration_ms / self.tick_ms) as usize % WHEEL_SIZE;
+            self.buckets[bucket].push((expiration_ms, entry));
+            return Ok(());
+        }
+        let (span_ms, current_ms) = (self.span_ms(), self.current_ms);
+        self.overflow
+            .get_or_insert_with(|| Box::new(TimingWheel::new(span_ms, current_ms)))
+            .add(expiration_ms, entry)
+    }
+
+    // moves the wheel up to the tick `now_ms` is in, returning every entry due by the end of
+    // it with its expiration. the overflow wheel only moves once this one has, so what
+    // cascades out of it always fits back in here unless it's due
+    fn advance(&mut self, now_ms: u64) -> Vec<(u64, T)> {
+        let mut due = vec![];
+        while self.current_ms + self.tick_ms <= now_ms {
+            self.current_ms += self.tick_ms;
+            let bucket = (self.current_ms / self.tick_ms) as usize % WHEEL_SIZE;
+            due.append(&mut self.buckets[bucket]);
+        }
+
+        let cascaded = match &mut self.overflow {
+            Some(overflow) => overflow.advance(now_ms),
+            None => vec![],
+        };
+        for (expiration_ms, entry) in cascaded {
+            if let Err(entry) = self.add(expiration_ms, entry) {
+                due.push((expiration_ms, entry));
+            }
+        }
+        due
+    }
+}
+
+type Condition = Arc<dyn Fn() -> bool + Send + Sync>;
+
+struct DelayedOperation<K> {
+    keys: Vec<K>,
+    is_complete: Condition,
+    done: oneshot::Sender<Completion>,
+}
+
+struct PurgatoryState<K> {
+    next_id: u64,
+    operations: HashMap<u64, DelayedOperation<K>>,
+    // the operations watching each key
+    watchers: BTreeMap<K, BTreeSet<u64>>,
+    // operation ids by deadline. completed operations are only dropped from `operations`,
+    // and skipped when their deadline comes up
+    timer: TimingWheel<u64>,
+}
+
+impl<K: Ord> PurgatoryState<K> {
+    fn remove(&mut self, id: u64) -> Option<DelayedOperation<K>> {
+        let operation = self.operations.remove(&id)?;
+        for key in &operation.keys {
+            if let Some(watchers) = self.watchers.get_mut(key) {
+                watchers.remove(&id);
+                if watchers.is_empty() {
+                    self.watchers.remove(key);
+                }
+            }
+        }
+        Some(operation)
+    }
+}
+
+// requests that can't be answered yet, like a fetch waiting on min_bytes, parked until a
+// change to one of the keys they watch lets them complete or their deadline passes. whatever
+// changes a key calls `check` with it, which re-evaluates the conditions of the operations
+// watching it, and run_purgatory_reaper expires the rest
+pub struct Purgatory<K> {
+    // deadlines are kept as milliseconds since this
+    started: Instant,
+    state: Mutex<PurgatoryState<K>>,
+    // wakes the reaper once something is waiting
+    waiting: Notify,
+}
+
+impl<K: Ord + Clone + Send + 'static> Purgatory<K> {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Purgatory {
+            started: Instant::now(),
+            state: Mutex::new(PurgatoryState {
+                next_id: 0,
+                operations: HashMap::new(),
+                watchers: BTreeMap::new(),
+                timer: TimingWheel::new(TICK_MS, 0),
+            }),
+            waiting: Notify::new(),
+        })
+    }
+
+    fn elapsed_ms(&self, at: Instant) -> u64 {
+        at.saturating_duration_since(self.started).as_millis() as u64
+    }
+
+    // waits until `is_complete` holds when one of `keys` is checked, or `deadline` passes.
+    // the condition is checked once right after the operation starts watching, so a change
+    // that lands between the caller's last look and this call isn't missed. dropping the
+    // future gives the wait up
+    pub async fn wait(
+        &self,
+        keys: Vec<K>,
+        deadline: Instant,
+        is_complete: impl Fn() -> bool + Send + Sync + 'static,
+    ) -> Completion {
+        let is_complete: Condition = Arc::new(is_complete);
+        let (done, completion) = oneshot::channel();
+        let id = {
+            let mut state = self.state.lock().unwrap();
+            let now_ms = self.elapsed_ms(Instant::now());
+            // with nothing waiting there's nothing in the timer worth keeping, and starting it
+            // over saves advancing it through however long it sat idle
+            if state.operations.is_empty() {
+                state.timer = TimingWheel::new(TICK_MS, now_ms);
+            }
+            let id = state.next_id;
+            state.next_id += 1;
+            // the timer counts everything in the current tick as due, so the deadline is
+            // rounded up past its own tick for nothing to expire early
+            let until_deadline = deadline.saturating_duration_since(self.started);
+            let expiration_ms = until_deadline.as_micros().div_ceil(1000) as u64 + TICK_MS;
+            if state.timer.add(expiration_ms, id).is_err() {
+                return Completion::Expired;
+            }
+            for key in &keys {
+                state.watchers.entry(key.clone()).or_default().insert(id);
+            }
+            state.operations.insert(
+                id,
+                DelayedOperation {
+                    keys,
+                    is_complete: is_complete.clone(),
+                    done,
+                },
+            );
+            id
+        };
+        self.waiting.notify_one();
+
+        let _watching = Watching {
+            purgatory: self,
+            id,
+        };
+        if is_complete() {
+            return Completion::Completed;
+        }
+        // the sender only goes away with the operation, which completes it first
+        completion.await.unwrap_or(Completion::Expired)
+    }
+
+    // something about `key` changed, so the operations watching it that can complete now do.
+    // conditions are evaluated without the lock held
+    pub fn check(&self, key: &K) {
+        let candidates: Vec<(u64, Condition)> = {
+            let state = self.state.lock().unwrap();
+            let Some(watchers) = state.watchers.get(key) else {
+                return;
+            };
+            watchers
+                .iter()
+                .map(|id| (*id, state.operations[id].is_complete.clone()))
+                .collect()
+        };
+
+        let completed: Vec<u64> = candidates
+            .into_iter()
+            .filter(|(_, is_complete)| is_complete())
+            .map(|(id, _)| id)
+            .collect();
+        if completed.is_empty() {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        for id in completed {
+            if let Some(operation) = state.remove(id) {
+                let _ = operation.done.send(Completion::Completed);
+            }
+        }
+    }
+
+    // operations still waiting
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().operations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // expires everything whose deadline has passed
+    fn expire(&self, now: Instant) {
+        let mut state = self.state.lock().unwrap();
+        let now_ms = self.elapsed_ms(now);
+        for (_, id) in state.timer.advance(now_ms) {
+            if let Some(operation) = state.remove(id) {
+                let _ = operation.done.send(Completion::Expired);
+            }
+        }
+    }
+}
+
+// takes a wait's operation out of the purgatory however the wait ends
+struct Watching<'a, K: Ord> {
+    purgatory: &'a Purgatory<K>,
+    id: u64,
+}
+
+impl<K: Ord> Drop for Watching<'_, K> {
+    fn drop(&mut self) {
+        self.purgatory.state.lock().unwrap().remove(self.id);
+    }
+}
+
+// advances the purgatory's timer while anything is waiting, and sleeps until something is
+pub async fn run_purgatory_reaper<K: Ord + Clone + Send + 'static>(purgatory: Arc<Purgatory<K>>) {
+    loop {
+        if purgatory.is_empty() {
+            purgatory.waiting.notified().await;
+        }
+        tokio::time::sleep(REAPER_INTERVAL).await;
+        purgatory.expire(Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    fn far_deadline() -> Instant {
+        Instant::now() + Duration::from_secs(60)
+    }
+
+    #[test]
+    fn timing_wheel_cascades_down_to_the_tick() {
+        let mut timer = TimingWheel::new(TICK_MS, 0);
+        // due within the current tick already
+        assert_eq!(timer.add(0, 0), Err(0));
+        for expiration_ms in [5, 19, 45, 500] {
+            timer.add(expiration_ms, expiration_ms).unwrap();
+        }
+
+        let due = |timer: &mut TimingWheel<u64>, now_ms| -> Vec<u64> {
+            timer
+                .advance(now_ms)
+                .into_iter()
+                .map(|(_, entry)| entry)
+                .collect()
+        };
+        assert_eq!(due(&mut timer, 4), vec![]);
+        assert_eq!(due(&mut timer, 5), vec![5]);
+        assert_eq!(due(&mut timer, 44), vec![19]);
+        assert_eq!(due(&mut timer, 45), vec![45]);
+        assert_eq!(due(&mut timer, 499), vec![]);
+        assert_eq!(due(&mut timer, 500), vec![500]);
+    }
+
+    #[test]
+    fn timing_wheel_hands_out_everything_passed_in_one_advance() {
+        let mut timer = TimingWheel::new(TICK_MS, 0);
+        for expiration_ms in [3, 30, 300, 3000] {
+            timer.add(expiration_ms, expiration_ms).unwrap();
+        }
+        let mut due: Vec<u64> = timer
+            .advance(10_000)
+            .into_iter()
+            .map(|(_, entry)| entry)
+            .collect();
+        due.sort();
+        assert_eq!(due, vec![3, 30, 300, 3000]);
+    }
+
+    #[tokio::test]
+    async fn check_completes_the_waits_on_its_key() {
+        let purgatory = Purgatory::new();
+        let ready = Arc::new(AtomicBool::new(false));
+        let is_ready = ready.clone();
+        let waiting = purgatory.clone();
+        let wait = tokio::spawn(async move {
+            waiting
+                .wait(vec!["a"], far_deadline(), move || {
+                    is_ready.load(Ordering::SeqCst)
+                })
+                .await
+        });
+        tokio::task::yield_now().await;
+        assert_eq!(purgatory.len(), 1);
+
+        // neither another key nor an unmet condition completes it
+        ready.store(true, Ordering::SeqCst);
+        purgatory.check(&"b");
+        ready.store(false, Ordering::SeqCst);
+        purgatory.check(&"a");
+        assert_eq!(purgatory.len(), 1);
+
+        ready.store(true, Ordering::SeqCst);
+        purgatory.check(&"a");
+        assert_eq!(wait.await.unwrap(), Completion::Completed);
+        assert!(purgatory.is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_condition_already_met_completes_right_away() {
+        let purgatory = Purgatory::new();
+        let completion = purgatory.wait(vec!["a"], far_deadline(), || true).await;
+        assert_eq!(completion, Completion::Completed);
+        assert!(purgatory.is_empty());
+    }
+
+    #[tokio::test]
+    async fn waits_expire_at_their_deadline() {
+        let purgatory = Purgatory::new();
+        // deadlines round up past their tick, so only one a few ticks gone expires without waiting
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let passed = Instant::now() - Duration::from_millis(5);
+        assert_eq!(
+            purgatory.wait(vec!["a"], passed, || false).await,
+            Completion::Expired
+        );
+
+        let waiting = purgatory.clone();
+        let deadline = Instant::now() + Duration::from_millis(10);
+        let wait = tokio::spawn(async move { waiting.wait(vec!["a"], deadline, || false).await });
+        tokio::task::yield_now().await;
+        purgatory.expire(Instant::now());
+        assert_eq!(purgatory.len(), 1);
+
+        purgatory.expire(deadline + Duration::from_millis(2));
+        assert_eq!(wait.await.unwrap(), Completion::Expired);
+        assert!(purgatory.is_empty());
+    }
+
+    #[tokio::test]
+    async fn the_reaper_expires_waits_on_its_own() {
+        let purgatory = Purgatory::new();
+        let reaper = tokio::spawn(run_purgatory_reaper(purgatory.clone()));
+        let deadline = Instant::now() + Duration::from_millis(10);
+        assert_eq!(
+            purgatory.wait(vec!["a"], deadline, || false).await,
+            Completion::Expired
+        );
+        assert!(Instant::now() >= deadline);
+        reaper.abort();
+    }
+
+    #[tokio::test]
+    async fn dropping_a_wait_gives_it_up() {
+        let purgatory = Purgatory::new();
+        let wait = purgatory.wait(vec!["a", "b"], far_deadline(), || false);
+        let given_up = tokio::time::timeout(Duration::from_millis(1), wait).await;
+        assert!(given_up.is_err());
+        assert!(purgatory.is_empty());
+        assert!(purgatory.state.lock().unwrap().watchers.is_empty());
+    }
+}
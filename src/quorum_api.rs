@@ -0,0 +1,743 @@
+use crate::acl::{Session, OPERATION_CLUSTER_ACTION, OPERATION_DESCRIBE};
+use crate::cluster_metadata::MetadataLog;
+use crate::metadata_snapshot::SnapshotId;
+use crate::raft::RaftQuorum;
+use crate::readers::*;
+use crate::storage::CLUSTER_METADATA_TOPIC;
+use crate::writers::*;
+use crate::{
+    KafkaError, CLUSTER_AUTHORIZATION_FAILED, FENCED_LEADER_EPOCH, INCONSISTENT_CLUSTER_ID,
+    KAFKA_STORAGE_ERROR, NONE, NOT_LEADER_OR_FOLLOWER, POSITION_OUT_OF_RANGE, SNAPSHOT_NOT_FOUND,
+    TAG_BUFFER, UNKNOWN_LEADER_EPOCH, UNKNOWN_TOPIC_OR_PARTITION,
+};
+use std::io::Cursor;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// the tagged fields FetchSnapshot carries the cluster id and the current leader in
+const CLUSTER_ID_TAG: u32 = 0;
+const CURRENT_LEADER_TAG: u32 = 0;
+
+// the quorum only replicates the metadata log, `__cluster_metadata-0`
+fn is_metadata_partition(topic_name: &str, partition_index: i32) -> bool {
+    topic_name == CLUSTER_METADATA_TOPIC && partition_index == 0
+}
+
+// a request from another cluster's voter. this node's cluster id is unset when the log dirs
+// haven't been formatted, and a request's is optional
+fn is_other_cluster(cluster_id: Option<&str>, request_cluster_id: Option<&str>) -> bool {
+    cluster_id
+        .zip(request_cluster_id)
+        .is_some_and(|(cluster_id, request_cluster_id)| cluster_id != request_cluster_id)
+}
+
+// ### VOTE (v0) ### //
+pub struct VoteRequest {
+    pub cluster_id: Option<String>,
+    pub topics: Vec<(String, Vec<VoteData>)>,
+}
+
+pub struct VoteData {
+    pub partition_index: i32,
+    pub candidate_epoch: i32,
+    pub candidate_id: i32,
+    // the epoch and offset the candidate's log ends at
+    pub last_offset_epoch: i32,
+    pub last_offset: i64,
+}
+
+impl VoteRequest {
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+
+        let cluster_id = read_compact_nullable_string(&mut cursor)?;
+
+        let topics_size = read_compact_array_len(&mut cursor)?; // [topics]
+        let mut topics = array_with_capacity(topics_size);
+        for _ in 0..topics_size {
+            let topic_name = read_compact_string(&mut cursor)?;
+
+            let partitions_size = read_compact_array_len(&mut cursor)?; // [partitions]
+            let mut partitions = array_with_capacity(partitions_size);
+            for _ in 0..partitions_size {
+                partitions.push(VoteData {
+                    partition_index: read_int32(&mut cursor)?,
+                    candidate_epoch: read_int32(&mut cursor)?,
+                    candidate_id: read_int32(&mut cursor)?,
+                    last_offset_epoch: read_int32(&mut cursor)?,
+                    last_offset: read_int64(&mut cursor)?,
+                });
+                read_tagged_fields(&mut cursor)?;
+            }
+            read_tagged_fields(&mut cursor)?;
+
+            topics.push((topic_name, partitions));
+        }
+
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(VoteRequest { cluster_id, topics })
+    }
+}
+
+pub struct VoteResponse {
+    pub error_code: i16,
+    pub topics: Vec<(String, Vec<VoteResponsePartition>)>,
+}
+
+// the leader and epoch are this voter's after the vote
+pub struct VoteResponsePartition {
+    pub partition_index: i32,
+    pub error_code: i16,
+    pub leader_id: i32,
+    pub leader_epoch: i32,
+    pub vote_granted: bool,
+}
+
+impl VoteResponse {
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.error_code.to_be_bytes());
+
+        write_compact_array_len(res_buf, self.topics.len());
+        for (topic_name, partitions) in &self.topics {
+            write_compact_string(res_buf, topic_name);
+
+            write_compact_array_len(res_buf, partitions.len());
+            for partition in partitions {
+                res_buf.extend_from_slice(&partition.partition_index.to_be_bytes());
+                res_buf.extend_from_slice(&partition.error_code.to_be_bytes());
+                res_buf.extend_from_slice(&partition.leader_id.to_be_bytes());
+                res_buf.extend_from_slice(&partition.leader_epoch.to_be_bytes());
+                res_buf.push(partition.vote_granted as u8);
+                res_buf.extend_from_slice(TAG_BUFFER);
+            }
+            res_buf.extend_from_slice(TAG_BUFFER);
+        }
+
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+// blocking file io, a granted vote is persisted before it's answered
+pub fn vote(
+    quorum: &RaftQuorum,
+    cluster_id: Option<&str>,
+    session: &Session,
+    request: VoteRequest,
+) -> VoteResponse {
+    let error_code = match () {
+        _ if !session.authorize_cluster(OPERATION_CLUSTER_ACTION) => CLUSTER_AUTHORIZATION_FAILED,
+        _ if is_other_cluster(cluster_id, request.cluster_id.as_deref()) => INCONSISTENT_CLUSTER_ID,
+        _ => NONE,
+    };
+    if error_code != NONE {
+        return VoteResponse {
+            error_code,
+            topics: vec![],
+        };
+    }
+
+    let topics = request
+        .topics
+        .into_iter()
+        .map(|(topic_name, partitions)| {
+            let partitions = partitions
+                .iter()
+                .map(|data| {
+                    let granted = match is_metadata_partition(&topic_name, data.partition_index) {
+                        true => quorum.vote(
+                            data.candidate_epoch,
+                            data.candidate_id,
+                            data.last_offset_epoch,
+                            data.last_offset,
+                        ),
+                        false => Err(UNKNOWN_TOPIC_OR_PARTITION),
+                    };
+                    let state = quorum.state();
+                    VoteResponsePartition {
+                        partition_index: data.partition_index,
+                        error_code: granted.err().unwrap_or(NONE),
+                        leader_id: state.leader_id,
+                        leader_epoch: state.epoch,
+                        vote_granted: granted.unwrap_or(false),
+                    }
+                })
+                .collect();
+            (topic_name, partitions)
+        })
+        .collect();
+
+    VoteResponse {
+        error_code: NONE,
+        topics,
+    }
+}
+
+// ### BEGIN QUORUM EPOCH (v0), END QUORUM EPOCH (v0) ### //
+pub struct QuorumEpochRequest {
+    pub cluster_id: Option<String>,
+    pub topics: Vec<(String, Vec<QuorumEpochData>)>,
+}
+
+pub struct QuorumEpochData {
+    pub partition_index: i32,
+    pub leader_id: i32,
+    pub leader_epoch: i32,
+    // the voters a resigning leader would like to succeed it, EndQuorumEpoch only
+    pub preferred_successors: Vec<i32>,
+}
+
+impl QuorumEpochRequest {
+    // both are non-flexible at v0, and only EndQuorumEpoch has `preferred_successors`
+    pub fn parse(buffer: &[u8], end_epoch: bool) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+
+        let cluster_id = read_nullable_string(&mut cursor)?;
+
+        let topics_size = read_array_len(&mut cursor)?; // [topics]
+        let mut topics = array_with_capacity(topics_size);
+        for _ in 0..topics_size {
+            let topic_name = read_nullable_string(&mut cursor)?.unwrap_or_default();
+
+            let partitions_size = read_array_len(&mut cursor)?; // [partitions]
+            let mut partitions = array_with_capacity(partitions_size);
+            for _ in 0..partitions_size {
+                let partition_index = read_int32(&mut cursor)?;
+                let leader_id = read_int32(&mut cursor)?;
+                let leader_epoch = read_int32(&mut cursor)?;
+
+                let mut preferred_successors = vec![];
+                if end_epoch {
+                    let successors_size = read_array_len(&mut cursor)?; // [preferred_successors]
+                    preferred_successors = array_with_capacity(successors_size);
+                    for _ in 0..successors_size {
+                        preferred_successors.push(read_int32(&mut cursor)?);
+                    }
+                }
+
+                partitions.push(QuorumEpochData {
+                    partition_index,
+                    leader_id,
+                    leader_epoch,
+                    preferred_successors,
+                });
+            }
+
+            topics.push((topic_name, partitions));
+        }
+
+        cursor.finish()?;
+
+        Ok(QuorumEpochRequest { cluster_id, topics })
+    }
+}
+
+pub struct QuorumEpochResponse {
+    pub error_code: i16,
+    pub topics: Vec<(String, Vec<QuorumEpochResponsePartition>)>,
+}
+
+// the leader and epoch are this voter's after the request
+pub struct QuorumEpochResponsePartition {
+    pub partition_index: i32,
+    pub error_code: i16,
+    pub leader_id: i32,
+    pub leader_epoch: i32,
+}
+
+impl QuorumEpochResponse {
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.error_code.to_be_bytes());
+
+        res_buf.extend_from_slice(&(self.topics.len() as i32).to_be_bytes());
+        for (topic_name, partitions) in &self.topics {
+            write_string(res_buf, topic_name);
+
+            res_buf.extend_from_slice(&(partitions.len() as i32).to_be_bytes());
+            for partition in partitions {
+                res_buf.extend_from_slice(&partition.partition_index.to_be_bytes());
+                res_buf.extend_from_slice(&partition.error_code.to_be_bytes());
+                res_buf.extend_from_slice(&partition.leader_id.to_be_bytes());
+                res_buf.extend_from_slice(&partition.leader_epoch.to_be_bytes());
+            }
+        }
+    }
+}
+
+// blocking file io. BeginQuorumEpoch when `end_epoch` is false, EndQuorumEpoch otherwise. a
+// sole voter has no successor to prefer, it takes the next epoch itself
+pub fn quorum_epoch(
+    quorum: &RaftQuorum,
+    cluster_id: Option<&str>,
+    session: &Session,
+    request: QuorumEpochRequest,
+    end_epoch: bool,
+) -> QuorumEpochResponse {
+    let error_code = match () {
+        _ if !session.authorize_cluster(OPERATION_CLUSTER_ACTION) => CLUSTER_AUTHORIZATION_FAILED,
+        _ if is_other_cluster(cluster_id, request.cluster_id.as_deref()) => INCONSISTENT_CLUSTER_ID,
+        _ => NONE,
+    };
+    if error_code != NONE {
+        return QuorumEpochResponse {
+            error_code,
+            topics: vec![],
+        };
+    }
+
+    let topics = request
+        .topics
+        .into_iter()
+        .map(|(topic_name, partitions)| {
+            let partitions = partitions
+                .iter()
+                .map(|data| {
+                    let changed = match is_metadata_partition(&topic_name, data.partition_index) {
+                        true if end_epoch => quorum.end_epoch(data.leader_id, data.leader_epoch),
+                        true => quorum.begin_epoch(data.leader_id, data.leader_epoch),
+                        false => Err(UNKNOWN_TOPIC_OR_PARTITION),
+                    };
+                    let state = quorum.state();
+                    QuorumEpochResponsePartition {
+                        partition_index: data.partition_index,
+                        error_code: changed.err().unwrap_or(NONE),
+                        leader_id: state.leader_id,
+                        leader_epoch: state.epoch,
+                    }
+                })
+                .collect();
+            (topic_name, partitions)
+        })
+        .collect();
+
+    QuorumEpochResponse {
+        error_code: NONE,
+        topics,
+    }
+}
+
+// ### DESCRIBE QUORUM (v0-v1) ### //
+pub struct DescribeQuorumRequest {
+    pub topics: Vec<(String, Vec<i32>)>,
+}
+
+impl DescribeQuorumRequest {
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+
+        let topics_size = read_compact_array_len(&mut cursor)?; // [topics]
+        let mut topics = array_with_capacity(topics_size);
+        for _ in 0..topics_size {
+            let topic_name = read_compact_string(&mut cursor)?;
+
+            let partitions_size = read_compact_array_len(&mut cursor)?; // [partitions]
+            let mut partitions = array_with_capacity(partitions_size);
+            for _ in 0..partitions_size {
+                partitions.push(read_int32(&mut cursor)?);
+                read_tagged_fields(&mut cursor)?;
+            }
+            read_tagged_fields(&mut cursor)?;
+
+            topics.push((topic_name, partitions));
+        }
+
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(DescribeQuorumRequest { topics })
+    }
+}
+
+pub struct DescribeQuorumResponse {
+    pub api_version: i16,
+    pub error_code: i16,
+    pub topics: Vec<(String, Vec<DescribeQuorumResponsePartition>)>,
+}
+
+pub struct DescribeQuorumResponsePartition {
+    pub partition_index: i32,
+    pub error_code: i16,
+    pub leader_id: i32,
+    pub leader_epoch: i32,
+    pub high_watermark: i64,
+    pub current_voters: Vec<ReplicaState>,
+    pub observers: Vec<ReplicaState>,
+}
+
+// -1 for what the leader doesn't know about a replica
+pub struct ReplicaState {
+    pub replica_id: i32,
+    pub log_end_offset: i64,
+    // v1+
+    pub last_fetch_timestamp: i64,
+    pub last_caught_up_timestamp: i64,
+}
+
+impl DescribeQuorumResponsePartition {
+    fn error(partition_index: i32, error_code: i16) -> Self {
+        DescribeQuorumResponsePartition {
+            partition_index,
+            error_code,
+            leader_id: -1,
+            leader_epoch: -1,
+            high_watermark: -1,
+            current_voters: vec![],
+            observers: vec![],
+        }
+    }
+}
+
+impl DescribeQuorumResponse {
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.error_code.to_be_bytes());
+
+        write_compact_array_len(res_buf, self.topics.len());
+        for (topic_name, partitions) in &self.topics {
+            write_compact_string(res_buf, topic_name);
+
+            write_compact_array_len(res_buf, partitions.len());
+            for partition in partitions {
+                res_buf.extend_from_slice(&partition.partition_index.to_be_bytes());
+                res_buf.extend_from_slice(&partition.error_code.to_be_bytes());
+                res_buf.extend_from_slice(&partition.leader_id.to_be_bytes());
+                res_buf.extend_from_slice(&partition.leader_epoch.to_be_bytes());
+                res_buf.extend_from_slice(&partition.high_watermark.to_be_bytes());
+                for replicas in [&partition.current_voters, &partition.observers] {
+                    write_compact_array_len(res_buf, replicas.len());
+                    for replica in replicas {
+                        res_buf.extend_from_slice(&replica.replica_id.to_be_bytes());
+                        res_buf.extend_from_slice(&replica.log_end_offset.to_be_bytes());
+                        if self.api_version >= 1 {
+                            res_buf.extend_from_slice(&replica.last_fetch_timestamp.to_be_bytes());
+                            res_buf
+                                .extend_from_slice(&replica.last_caught_up_timestamp.to_be_bytes());
+                        }
+                        res_buf.extend_from_slice(TAG_BUFFER);
+                    }
+                }
+                res_buf.extend_from_slice(TAG_BUFFER);
+            }
+            res_buf.extend_from_slice(TAG_BUFFER);
+        }
+
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+// only the leader can describe the quorum. nothing replicates the metadata log to the other
+// voters yet, so the log end offset is the high watermark and theirs aren't known
+pub fn describe_quorum(
+    quorum: &RaftQuorum,
+    metadata_log: &MetadataLog,
+    session: &Session,
+    api_version: i16,
+    request: DescribeQuorumRequest,
+) -> DescribeQuorumResponse {
+    if !session.authorize_cluster(OPERATION_DESCRIBE) {
+        return DescribeQuorumResponse {
+            api_version,
+            error_code: CLUSTER_AUTHORIZATION_FAILED,
+            topics: vec![],
+        };
+    }
+
+    let state = quorum.state();
+    let log_end_offset = metadata_log.end().offset;
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(-1, |now| now.as_millis() as i64);
+
+    let topics = request
+        .topics
+        .into_iter()
+        .map(|(topic_name, partitions)| {
+            let partitions = partitions
+                .into_iter()
+                .map(|partition_index| {
+                    if !is_metadata_partition(&topic_name, partition_index) {
+                        return DescribeQuorumResponsePartition::error(
+                            partition_index,
+                            UNKNOWN_TOPIC_OR_PARTITION,
+                        );
+                    }
+                    if state.leader_id != quorum.node_id() {
+                        return DescribeQuorumResponsePartition::error(
+                            partition_index,
+                            NOT_LEADER_OR_FOLLOWER,
+                        );
+                    }
+
+                    let current_voters = quorum
+                        .voters()
+                        .iter()
+                        .map(|&replica_id| match replica_id == quorum.node_id() {
+                            true => ReplicaState {
+                                replica_id,
+                                log_end_offset,
+                                last_fetch_timestamp: now_ms,
+                                last_caught_up_timestamp: now_ms,
+                            },
+                            false => ReplicaState {
+                                replica_id,
+                                log_end_offset: -1,
+                                last_fetch_timestamp: -1,
+                                last_caught_up_timestamp: -1,
+                            },
+                        })
+                        .collect();
+                    DescribeQuorumResponsePartition {
+                        partition_index,
+                        error_code: NONE,
+                        leader_id: state.leader_id,
+                        leader_epoch: state.epoch,
+                        high_watermark: log_end_offset,
+                        current_voters,
+                        observers: vec![],
+                    }
+                })
+                .collect();
+            (topic_name, partitions)
+        })
+        .collect();
+
+    DescribeQuorumResponse {
+        api_version,
+        error_code: NONE,
+        topics,
+    }
+}
+
+// ### FETCH SNAPSHOT (v0) ### //
+pub struct FetchSnapshotRequest {
+    pub cluster_id: Option<String>,
+    pub replica_id: i32,
+    pub max_bytes: i32,
+    pub topics: Vec<(String, Vec<FetchSnapshotData>)>,
+}
+
+pub struct FetchSnapshotData {
+    pub partition: i32,
+    pub current_leader_epoch: i32,
+    pub snapshot_id: SnapshotId,
+    // where in the snapshot to read from
+    pub position: i64,
+}
+
+impl FetchSnapshotRequest {
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+
+        let replica_id = read_int32(&mut cursor)?;
+        let max_bytes = read_int32(&mut cursor)?;
+
+        let topics_size = read_compact_array_len(&mut cursor)?; // [topics]
+        let mut topics = array_with_capacity(topics_size);
+        for _ in 0..topics_size {
+            let topic_name = read_compact_string(&mut cursor)?;
+
+            let partitions_size = read_compact_array_len(&mut cursor)?; // [partitions]
+            let mut partitions = array_with_capacity(partitions_size);
+            for _ in 0..partitions_size {
+                let partition = read_int32(&mut cursor)?;
+                let current_leader_epoch = read_int32(&mut cursor)?;
+                let snapshot_id = SnapshotId {
+                    end_offset: read_int64(&mut cursor)?,
+                    epoch: read_int32(&mut cursor)?,
+                };
+                read_tagged_fields(&mut cursor)?;
+                let position = read_int64(&mut cursor)?;
+                read_tagged_fields(&mut cursor)?;
+
+                partitions.push(FetchSnapshotData {
+                    partition,
+                    current_leader_epoch,
+                    snapshot_id,
+                    position,
+                });
+            }
+            read_tagged_fields(&mut cursor)?;
+
+            topics.push((topic_name, partitions));
+        }
+
+        let mut cluster_id = None;
+        read_tagged_fields_with(&mut cursor, |tag, bytes| {
+            if tag == CLUSTER_ID_TAG {
+                cluster_id = read_compact_nullable_string(&mut Cursor::new(bytes))?;
+            }
+            Ok(())
+        })?;
+        cursor.finish()?;
+
+        Ok(FetchSnapshotRequest {
+            cluster_id,
+            replica_id,
+            max_bytes,
+            topics,
+        })
+    }
+}
+
+pub struct FetchSnapshotResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub topics: Vec<(String, Vec<FetchSnapshotResponsePartition>)>,
+}
+
+pub struct FetchSnapshotResponsePartition {
+    pub index: i32,
+    pub error_code: i16,
+    pub snapshot_id: SnapshotId,
+    // this node's view of the quorum, so a fenced fetcher can find the leader
+    pub leader_id: i32,
+    pub leader_epoch: i32,
+    pub size: i64,
+    pub position: i64,
+    pub unaligned_records: Vec<u8>,
+}
+
+impl FetchSnapshotResponsePartition {
+    fn error(data: &FetchSnapshotData, error_code: i16, leader_id: i32, leader_epoch: i32) -> Self {
+        FetchSnapshotResponsePartition {
+            index: data.partition,
+            error_code,
+            snapshot_id: data.snapshot_id,
+            leader_id,
+            leader_epoch,
+            size: -1,
+            position: -1,
+            unaligned_records: vec![],
+        }
+    }
+}
+
+impl FetchSnapshotResponse {
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.throttle_time_ms.to_be_bytes());
+        res_buf.extend_from_slice(&self.error_code.to_be_bytes());
+
+        write_compact_array_len(res_buf, self.topics.len());
+        for (topic_name, partitions) in &self.topics {
+            write_compact_string(res_buf, topic_name);
+
+            write_compact_array_len(res_buf, partitions.len());
+            for partition in partitions {
+                res_buf.extend_from_slice(&partition.index.to_be_bytes());
+                res_buf.extend_from_slice(&partition.error_code.to_be_bytes());
+                res_buf.extend_from_slice(&partition.snapshot_id.end_offset.to_be_bytes());
+                res_buf.extend_from_slice(&partition.snapshot_id.epoch.to_be_bytes());
+                res_buf.extend_from_slice(TAG_BUFFER);
+                res_buf.extend_from_slice(&partition.size.to_be_bytes());
+                res_buf.extend_from_slice(&partition.position.to_be_bytes());
+                write_compact_bytes(res_buf, &partition.unaligned_records);
+
+                let mut current_leader = partition.leader_id.to_be_bytes().to_vec();
+                current_leader.extend_from_slice(&partition.leader_epoch.to_be_bytes());
+                current_leader.extend_from_slice(TAG_BUFFER);
+                write_unsigned_varint(res_buf, 1);
+                write_unsigned_varint(res_buf, CURRENT_LEADER_TAG);
+                write_unsigned_varint(res_buf, current_leader.len() as u32);
+                res_buf.extend_from_slice(&current_leader);
+            }
+            res_buf.extend_from_slice(TAG_BUFFER);
+        }
+
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+// blocking file io. only the leader serves its snapshots, to fetchers in its epoch. the
+// chunks are cut at `max_bytes` wherever that falls, the fetcher reassembles the file
+pub fn fetch_snapshot(
+    quorum: &RaftQuorum,
+    metadata_log: &MetadataLog,
+    cluster_id: Option<&str>,
+    session: &Session,
+    request: FetchSnapshotRequest,
+) -> FetchSnapshotResponse {
+    let error_code = match () {
+        _ if !session.authorize_cluster(OPERATION_CLUSTER_ACTION) => CLUSTER_AUTHORIZATION_FAILED,
+        _ if is_other_cluster(cluster_id, request.cluster_id.as_deref()) => INCONSISTENT_CLUSTER_ID,
+        _ => NONE,
+    };
+    if error_code != NONE {
+        return FetchSnapshotResponse {
+            throttle_time_ms: 0,
+            error_code,
+            topics: vec![],
+        };
+    }
+
+    let state = quorum.state();
+    let max_bytes = request.max_bytes.max(0) as u64;
+    let topics = request
+        .topics
+        .into_iter()
+        .map(|(topic_name, partitions)| {
+            let partitions = partitions
+                .iter()
+                .map(|data| {
+                    let error = |error_code| {
+                        FetchSnapshotResponsePartition::error(
+                            data,
+                            error_code,
+                            state.leader_id,
+                            state.epoch,
+                        )
+                    };
+                    if !is_metadata_partition(&topic_name, data.partition) {
+                        return error(UNKNOWN_TOPIC_OR_PARTITION);
+                    }
+                    if state.leader_id != quorum.node_id() {
+                        return error(NOT_LEADER_OR_FOLLOWER);
+                    }
+                    if data.current_leader_epoch < state.epoch {
+                        return error(FENCED_LEADER_EPOCH);
+                    }
+                    if data.current_leader_epoch > state.epoch {
+                        return error(UNKNOWN_LEADER_EPOCH);
+                    }
+                    if data.position < 0 {
+                        return error(POSITION_OUT_OF_RANGE);
+                    }
+
+                    let read = metadata_log.read_snapshot(
+                        data.snapshot_id,
+                        data.position as u64,
+                        max_bytes,
+                    );
+                    match read {
+                        Ok(Some((size, _))) if data.position as u64 > size => {
+                            error(POSITION_OUT_OF_RANGE)
+                        }
+                        Ok(Some((size, unaligned_records))) => FetchSnapshotResponsePartition {
+                            index: data.partition,
+                            error_code: NONE,
+                            snapshot_id: data.snapshot_id,
+                            leader_id: state.leader_id,
+                            leader_epoch: state.epoch,
+                            size: size as i64,
+                            position: data.position,
+                            unaligned_records,
+                        },
+                        Ok(None) => error(SNAPSHOT_NOT_FOUND),
+                        Err(e) => {
+                            eprintln!(
+                                "Error reading metadata snapshot {:?}: {e}",
+                                data.snapshot_id
+                            );
+                            error(KAFKA_STORAGE_ERROR)
+                        }
+                    }
+                })
+                .collect();
+            (topic_name, partitions)
+        })
+        .collect();
+
+    FetchSnapshotResponse {
+        throttle_time_ms: 0,
+        error_code: NONE,
+        topics,
+    }
+}
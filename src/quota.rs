@@ -0,0 +1,95 @@
+use crate::client_quota::ClientQuotaStore;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// broker defaults for quota.window.num / quota.window.size.seconds
+const QUOTA_WINDOW_SAMPLES: u32 = 11;
+const QUOTA_WINDOW_SIZE: Duration = Duration::from_secs(1);
+
+// bytes recorded per fixed-size sample, oldest first
+#[derive(Default)]
+struct RateWindow {
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl RateWindow {
+    fn record(&mut self, bytes: u64, now: Instant) {
+        let window = QUOTA_WINDOW_SIZE * QUOTA_WINDOW_SAMPLES;
+        while let Some((start, _)) = self.samples.front() {
+            if now.duration_since(*start) < window {
+                break;
+            }
+            self.samples.pop_front();
+        }
+
+        match self.samples.back_mut() {
+            Some((start, total)) if now.duration_since(*start) < QUOTA_WINDOW_SIZE => {
+                *total += bytes
+            }
+            _ => self.samples.push_back((now, bytes)),
+        }
+    }
+
+    // like kafka, a young window is measured as if it already spanned all but one sample,
+    // so the first few requests of a client can't look like a huge rate spike
+    fn elapsed(&self, now: Instant) -> Duration {
+        let measured = self
+            .samples
+            .front()
+            .map(|(start, _)| now.duration_since(*start))
+            .unwrap_or_default();
+
+        measured.max(QUOTA_WINDOW_SIZE * (QUOTA_WINDOW_SAMPLES - 1))
+    }
+
+    fn rate(&self, now: Instant) -> f64 {
+        let total: u64 = self.samples.iter().map(|(_, bytes)| bytes).sum();
+        total as f64 / self.elapsed(now).as_secs_f64()
+    }
+}
+
+// tracks the fetch byte rate quota of each client over a sliding window. quotas set through
+// AlterClientQuotas win, quota.consumer.default applies to each client id without one
+pub struct QuotaManager {
+    default_bytes_per_sec: Option<u64>,
+    overrides: Arc<ClientQuotaStore>,
+    // keyed by the (user, client id) the quota is tracked under
+    clients: Mutex<HashMap<(String, String), RateWindow>>,
+}
+
+impl QuotaManager {
+    pub fn new(default_bytes_per_sec: Option<u64>, overrides: Arc<ClientQuotaStore>) -> Arc<Self> {
+        Arc::new(QuotaManager {
+            default_bytes_per_sec,
+            overrides,
+            clients: Mutex::new(HashMap::new()),
+        })
+    }
+
+    // records `bytes` against the client and returns how long it has to be throttled for
+    // to get back under its quota
+    pub fn record(&self, user: &str, client_id: &str, bytes: usize) -> Duration {
+        let (quota, tracked) = match self.overrides.consumer_byte_rate(user, client_id) {
+            Some(quota) => quota,
+            None => match self.default_bytes_per_sec {
+                Some(quota) => (quota as f64, (String::new(), client_id.to_string())),
+                None => return Duration::ZERO,
+            },
+        };
+
+        let now = Instant::now();
+        let mut clients = self.clients.lock().unwrap();
+        let window = clients.entry(tracked).or_default();
+        window.record(bytes as u64, now);
+
+        let rate = window.rate(now);
+        let quota = quota.max(1.0);
+        if rate <= quota {
+            return Duration::ZERO;
+        }
+
+        let throttle = window.elapsed(now).mul_f64((rate - quota) / quota);
+        throttle.min(QUOTA_WINDOW_SIZE * QUOTA_WINDOW_SAMPLES)
+    }
+}
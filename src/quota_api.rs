@@ -0,0 +1,393 @@
+use crate::acl::{Session, OPERATION_ALTER_CONFIGS, OPERATION_DESCRIBE_CONFIGS};
+use crate::client_quota::*;
+use crate::readers::*;
+use crate::writers::*;
+use crate::{
+    KafkaError, CLUSTER_AUTHORIZATION_FAILED, INVALID_REQUEST, NONE, TAG_BUFFER,
+    UNKNOWN_SERVER_ERROR,
+};
+use std::io::Cursor;
+
+// how DescribeClientQuotas components match an entity's name
+const MATCH_TYPE_EXACT: i8 = 0;
+const MATCH_TYPE_DEFAULT: i8 = 1;
+const MATCH_TYPE_SPECIFIED: i8 = 2;
+
+pub struct EntityComponent {
+    pub entity_type: String,
+    // the default entity when null
+    pub entity_name: Option<String>,
+}
+
+fn read_entity(cursor: &mut Cursor<&[u8]>) -> Result<Vec<EntityComponent>, KafkaError> {
+    let entity_size = read_compact_array_len(cursor)?; // [entity]
+    let mut entity = array_with_capacity(entity_size);
+    for _ in 0..entity_size {
+        let entity_type = read_compact_string(cursor)?;
+        let entity_name = read_compact_nullable_string(cursor)?;
+        read_tagged_fields(cursor)?;
+        entity.push(EntityComponent {
+            entity_type,
+            entity_name,
+        });
+    }
+    Ok(entity)
+}
+
+fn write_entity(res_buf: &mut Vec<u8>, entity: &ClientQuotaEntity) {
+    write_compact_array_len(res_buf, entity.len()); // [entity]
+    for (entity_type, entity_name) in entity {
+        write_compact_string(res_buf, entity_type);
+        write_compact_nullable_string(res_buf, entity_name.as_deref());
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+fn validate_entity_type(entity_type: &str) -> Result<(), String> {
+    match entity_type {
+        ENTITY_TYPE_USER | ENTITY_TYPE_CLIENT_ID => Ok(()),
+        _ => Err(format!("Custom entity type {entity_type} not supported")),
+    }
+}
+
+// ### DESCRIBE CLIENT QUOTAS (v1) ### //
+pub struct DescribeClientQuotasRequest {
+    pub components: Vec<ComponentFilter>,
+    // only entities with exactly the filtered entity types when set, entities may have
+    // others as well otherwise
+    pub strict: bool,
+}
+
+pub struct ComponentFilter {
+    pub entity_type: String,
+    pub match_type: i8,
+    pub match_name: Option<String>,
+}
+
+impl DescribeClientQuotasRequest {
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+
+        let components_size = read_compact_array_len(&mut cursor)?; // [components]
+        let mut components = array_with_capacity(components_size);
+        for _ in 0..components_size {
+            let entity_type = read_compact_string(&mut cursor)?;
+            let match_type = read_int8(&mut cursor)?;
+            let match_name = read_compact_nullable_string(&mut cursor)?;
+            read_tagged_fields(&mut cursor)?;
+            components.push(ComponentFilter {
+                entity_type,
+                match_type,
+                match_name,
+            });
+        }
+        let strict = read_bool(&mut cursor)?;
+
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(DescribeClientQuotasRequest { components, strict })
+    }
+}
+
+pub struct DescribeClientQuotasResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub error_message: Option<String>,
+    // null alongside an error
+    pub entries: Option<Vec<DescribeClientQuotasEntry>>,
+}
+
+pub struct DescribeClientQuotasEntry {
+    pub entity: ClientQuotaEntity,
+    pub values: Vec<(String, f64)>,
+}
+
+impl DescribeClientQuotasResponse {
+    fn error(error_code: i16, error_message: String) -> Self {
+        DescribeClientQuotasResponse {
+            throttle_time_ms: 0,
+            error_code,
+            error_message: Some(error_message),
+            entries: None,
+        }
+    }
+
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.throttle_time_ms.to_be_bytes());
+        res_buf.extend_from_slice(&self.error_code.to_be_bytes());
+        write_compact_nullable_string(res_buf, self.error_message.as_deref());
+
+        match &self.entries {
+            Some(entries) => {
+                write_compact_array_len(res_buf, entries.len()); // [entries]
+                for entry in entries {
+                    write_entity(res_buf, &entry.entity);
+
+                    write_compact_array_len(res_buf, entry.values.len()); // [values]
+                    for (key, value) in &entry.values {
+                        write_compact_string(res_buf, key);
+                        res_buf.extend_from_slice(&value.to_be_bytes());
+                        res_buf.extend_from_slice(TAG_BUFFER);
+                    }
+
+                    res_buf.extend_from_slice(TAG_BUFFER);
+                }
+            }
+            None => write_unsigned_varint(res_buf, 0),
+        }
+
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+impl ComponentFilter {
+    fn validate(&self) -> Result<(), String> {
+        validate_entity_type(&self.entity_type)?;
+        match (self.match_type, &self.match_name) {
+            (MATCH_TYPE_EXACT, None) => Err(format!(
+                "Exact match of {} requires a name",
+                self.entity_type
+            )),
+            (MATCH_TYPE_EXACT | MATCH_TYPE_DEFAULT | MATCH_TYPE_SPECIFIED, _) => Ok(()),
+            (match_type, _) => Err(format!("Unknown match type {match_type}")),
+        }
+    }
+
+    fn matches(&self, entity: &ClientQuotaEntity) -> bool {
+        match (self.match_type, entity.get(&self.entity_type)) {
+            (MATCH_TYPE_EXACT, Some(name)) => *name == self.match_name,
+            (MATCH_TYPE_DEFAULT, Some(name)) => name.is_none(),
+            (MATCH_TYPE_SPECIFIED, Some(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+pub fn describe_client_quotas(
+    quotas: &ClientQuotaStore,
+    session: &Session,
+    request: &DescribeClientQuotasRequest,
+) -> DescribeClientQuotasResponse {
+    if !session.authorize_cluster(OPERATION_DESCRIBE_CONFIGS) {
+        return DescribeClientQuotasResponse::error(
+            CLUSTER_AUTHORIZATION_FAILED,
+            "Not authorized to describe client quotas".to_string(),
+        );
+    }
+
+    for (i, component) in request.components.iter().enumerate() {
+        if let Err(message) = component.validate() {
+            return DescribeClientQuotasResponse::error(INVALID_REQUEST, message);
+        }
+        if request.components[..i]
+            .iter()
+            .any(|other| other.entity_type == component.entity_type)
+        {
+            return DescribeClientQuotasResponse::error(
+                INVALID_REQUEST,
+                format!("Duplicate {} filter component", component.entity_type),
+            );
+        }
+    }
+
+    let entries = quotas
+        .list()
+        .into_iter()
+        .filter(|(entity, _)| {
+            request
+                .components
+                .iter()
+                .all(|component| component.matches(entity))
+                && (!request.strict || entity.len() == request.components.len())
+        })
+        .map(|(entity, values)| DescribeClientQuotasEntry {
+            entity,
+            values: values.into_iter().collect(),
+        })
+        .collect();
+
+    DescribeClientQuotasResponse {
+        throttle_time_ms: 0,
+        error_code: NONE,
+        error_message: None,
+        entries: Some(entries),
+    }
+}
+
+// ### ALTER CLIENT QUOTAS (v1) ### //
+pub struct AlterClientQuotasRequest {
+    pub entries: Vec<EntryData>,
+    pub validate_only: bool,
+}
+
+pub struct EntryData {
+    pub entity: Vec<EntityComponent>,
+    pub ops: Vec<OpData>,
+}
+
+pub struct OpData {
+    pub key: String,
+    pub value: f64,
+    pub remove: bool,
+}
+
+impl AlterClientQuotasRequest {
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+
+        let entries_size = read_compact_array_len(&mut cursor)?; // [entries]
+        let mut entries = array_with_capacity(entries_size);
+        for _ in 0..entries_size {
+            let entity = read_entity(&mut cursor)?;
+
+            let ops_size = read_compact_array_len(&mut cursor)?; // [ops]
+            let mut ops = array_with_capacity(ops_size);
+            for _ in 0..ops_size {
+                let key = read_compact_string(&mut cursor)?;
+                let value = read_float64(&mut cursor)?;
+                let remove = read_bool(&mut cursor)?;
+                read_tagged_fields(&mut cursor)?;
+                ops.push(OpData { key, value, remove });
+            }
+
+            read_tagged_fields(&mut cursor)?;
+            entries.push(EntryData { entity, ops });
+        }
+        let validate_only = read_bool(&mut cursor)?;
+
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(AlterClientQuotasRequest {
+            entries,
+            validate_only,
+        })
+    }
+}
+
+pub struct AlterClientQuotasResponse {
+    pub throttle_time_ms: i32,
+    pub entries: Vec<AlterClientQuotasEntryResult>,
+}
+
+pub struct AlterClientQuotasEntryResult {
+    pub error_code: i16,
+    pub error_message: Option<String>,
+    pub entity: ClientQuotaEntity,
+}
+
+impl AlterClientQuotasResponse {
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.throttle_time_ms.to_be_bytes());
+
+        write_compact_array_len(res_buf, self.entries.len()); // [entries]
+        for entry in &self.entries {
+            res_buf.extend_from_slice(&entry.error_code.to_be_bytes());
+            write_compact_nullable_string(res_buf, entry.error_message.as_deref());
+            write_entity(res_buf, &entry.entity);
+            res_buf.extend_from_slice(TAG_BUFFER);
+        }
+
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+// the entity and its ops, as the store takes them
+fn validate_entry(entry: &EntryData) -> Result<(ClientQuotaEntity, QuotaOps), String> {
+    if entry.entity.is_empty() {
+        return Err("The entity must not be empty".to_string());
+    }
+    let mut entity = ClientQuotaEntity::new();
+    for component in &entry.entity {
+        validate_entity_type(&component.entity_type)?;
+        if entity
+            .insert(component.entity_type.clone(), component.entity_name.clone())
+            .is_some()
+        {
+            return Err(format!("Duplicate {} entity", component.entity_type));
+        }
+    }
+
+    let mut ops: QuotaOps = vec![];
+    for op in &entry.ops {
+        if !QUOTA_KEYS.contains(&op.key.as_str()) {
+            return Err(format!("Unknown quota key {}", op.key));
+        }
+        if ops.iter().any(|(key, _)| *key == op.key) {
+            return Err(format!("Duplicate quota key {}", op.key));
+        }
+        if !op.remove {
+            if !op.value.is_finite() || op.value <= 0.0 {
+                return Err(format!("{} must be greater than 0", op.key));
+            }
+            // byte rates are longs in kafka
+            if op.key.ends_with("_byte_rate") && op.value.fract() != 0.0 {
+                return Err(format!("{} must be a whole number", op.key));
+            }
+        }
+        ops.push((op.key.clone(), (!op.remove).then_some(op.value)));
+    }
+
+    Ok((entity, ops))
+}
+
+// the valid entries are applied together, an invalid one doesn't hold the others back
+pub fn alter_client_quotas(
+    quotas: &ClientQuotaStore,
+    session: &Session,
+    request: &AlterClientQuotasRequest,
+) -> AlterClientQuotasResponse {
+    let authorized = session.authorize_cluster(OPERATION_ALTER_CONFIGS);
+    let validated = request
+        .entries
+        .iter()
+        .map(|entry| match authorized {
+            true => validate_entry(entry).map_err(|message| (INVALID_REQUEST, message)),
+            false => Err((
+                CLUSTER_AUTHORIZATION_FAILED,
+                "Not authorized to alter client quotas".to_string(),
+            )),
+        })
+        .collect::<Vec<_>>();
+
+    let alterations = validated
+        .iter()
+        .filter_map(|validated| validated.as_ref().ok().cloned())
+        .collect::<Vec<_>>();
+    let stored = match request.validate_only || alterations.is_empty() {
+        true => Ok(()),
+        false => quotas.alter(alterations).map_err(|e| {
+            eprintln!("Error storing client quotas: {e}");
+            e.to_string()
+        }),
+    };
+
+    let entries = request
+        .entries
+        .iter()
+        .zip(validated)
+        .map(|(entry, validated)| {
+            let entity = entry
+                .entity
+                .iter()
+                .map(|component| (component.entity_type.clone(), component.entity_name.clone()))
+                .collect();
+            let (error_code, error_message) = match (validated, &stored) {
+                (Err((error_code, message)), _) => (error_code, Some(message)),
+                (Ok(_), Err(message)) => (UNKNOWN_SERVER_ERROR, Some(message.clone())),
+                (Ok(_), Ok(())) => (NONE, None),
+            };
+            AlterClientQuotasEntryResult {
+                error_code,
+                error_message,
+                entity,
+            }
+        })
+        .collect();
+
+    AlterClientQuotasResponse {
+        throttle_time_ms: 0,
+        entries,
+    }
+}
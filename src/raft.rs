@@ -0,0 +1,408 @@
+use crate::cluster_metadata::MetadataLog;
+use crate::{
+    KafkaError, FENCED_LEADER_EPOCH, INCONSISTENT_VOTER_SET, INVALID_REQUEST, KAFKA_STORAGE_ERROR,
+};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+// next to the metadata log's segments, in kafka's json layout
+const QUORUM_STATE_FILE: &str = "quorum-state";
+
+// what a voter has to remember across restarts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuorumState {
+    pub epoch: i32,
+    // -1 while nobody is known to lead the epoch
+    pub leader_id: i32,
+    // -1 until a vote is cast in the epoch
+    pub voted_id: i32,
+}
+
+impl Default for QuorumState {
+    fn default() -> Self {
+        QuorumState {
+            epoch: 0,
+            leader_id: -1,
+            voted_id: -1,
+        }
+    }
+}
+
+impl QuorumState {
+    // the fields that matter here, without a file it's a quorum that never had an epoch
+    pub fn load(metadata_log_dir: &Path) -> Result<Self, KafkaError> {
+        let path = metadata_log_dir.join(QUORUM_STATE_FILE);
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let field = |name: &str| {
+            json_number(&contents, name).ok_or_else(|| {
+                KafkaError::CorruptedMessage(format!("{} has no {name}", path.display()))
+            })
+        };
+        Ok(QuorumState {
+            epoch: field("leaderEpoch")?,
+            leader_id: field("leaderId")?,
+            voted_id: field("votedId")?,
+        })
+    }
+
+    fn write(&self, path: &Path, voters: &[i32]) -> std::io::Result<()> {
+        let voters = voters
+            .iter()
+            .map(|voter| format!("{{\"voterId\":{voter}}}"))
+            .collect::<Vec<_>>()
+            .join(",");
+        let contents = format!(
+            "{{\"clusterId\":\"\",\"leaderId\":{},\"leaderEpoch\":{},\"votedId\":{},\
+             \"appliedOffset\":0,\"currentVoters\":[{voters}],\"data_version\":0}}",
+            self.leader_id, self.epoch, self.voted_id
+        );
+
+        // a vote has to hit the disk before it's answered
+        let tmp_path = path.with_extension("tmp");
+        let mut file = std::fs::File::create(&tmp_path)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, path)
+    }
+}
+
+// the integer after `"name":`, flat objects are all the quorum state has
+fn json_number(contents: &str, name: &str) -> Option<i32> {
+    let start = contents.find(&format!("\"{name}\":"))? + name.len() + 3;
+    let value = contents[start..].trim_start();
+    let end = value
+        .find(|c: char| c != '-' && !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    value[..end].parse().ok()
+}
+
+// a minimal raft voter for the metadata log, `__cluster_metadata-0`. only the election side
+// is here: a sole voter elects itself, which is the only way this node becomes the leader,
+// and anything else waits to be told who leads through BeginQuorumEpoch. nothing replicates
+// the log to other voters yet
+pub struct RaftQuorum {
+    node_id: i32,
+    voters: Vec<i32>,
+    // unset when the metadata log only lives in memory
+    state_path: Option<PathBuf>,
+    metadata_log: Arc<MetadataLog>,
+    state: Mutex<QuorumState>,
+}
+
+impl RaftQuorum {
+    // blocking file io. a leader that restarts gives its epoch up, so a sole voter starts a
+    // new one. one whose state can't be written stays without a leader, and so does the
+    // metadata log
+    pub fn new(
+        node_id: i32,
+        voters: Vec<i32>,
+        metadata_log: Arc<MetadataLog>,
+        mut restored: QuorumState,
+    ) -> Arc<Self> {
+        if restored.leader_id == node_id {
+            restored.leader_id = -1;
+        }
+        let quorum = Arc::new(RaftQuorum {
+            node_id,
+            voters,
+            state_path: metadata_log.dir().map(|dir| dir.join(QUORUM_STATE_FILE)),
+            metadata_log,
+            state: Mutex::new(restored),
+        });
+
+        let mut state = quorum.state.lock().unwrap();
+        // the error's been logged already
+        let _ = quorum.elect_if_sole_voter(&mut state);
+        drop(state);
+        quorum
+    }
+
+    pub fn node_id(&self) -> i32 {
+        self.node_id
+    }
+
+    pub fn voters(&self) -> &[i32] {
+        &self.voters
+    }
+
+    pub fn state(&self) -> QuorumState {
+        *self.state.lock().unwrap()
+    }
+
+    // blocking file io. a candidate gets at most one vote per epoch, none in an epoch that
+    // already has a leader, and only when its log is at least as far along as this one
+    pub fn vote(
+        &self,
+        candidate_epoch: i32,
+        candidate_id: i32,
+        last_offset_epoch: i32,
+        last_offset: i64,
+    ) -> Result<bool, i16> {
+        if !self.voters.contains(&candidate_id) {
+            return Err(INCONSISTENT_VOTER_SET);
+        }
+        let mut state = self.state.lock().unwrap();
+        if candidate_epoch < state.epoch {
+            return Ok(false);
+        }
+        if candidate_epoch > state.epoch {
+            self.transition(
+                &mut state,
+                QuorumState {
+                    epoch: candidate_epoch,
+                    ..QuorumState::default()
+                },
+            )?;
+        }
+        if state.leader_id != -1 || (state.voted_id != -1 && state.voted_id != candidate_id) {
+            return Ok(false);
+        }
+        let end = self.metadata_log.end();
+        if (last_offset_epoch, last_offset) < (end.epoch, end.offset) {
+            return Ok(false);
+        }
+
+        if state.voted_id != candidate_id {
+            let voted = QuorumState {
+                voted_id: candidate_id,
+                ..*state
+            };
+            self.transition(&mut state, voted)?;
+        }
+        Ok(true)
+    }
+
+    // blocking file io. another voter won `leader_epoch`
+    pub fn begin_epoch(&self, leader_id: i32, leader_epoch: i32) -> Result<(), i16> {
+        if !self.voters.contains(&leader_id) {
+            return Err(INCONSISTENT_VOTER_SET);
+        }
+        let mut state = self.state.lock().unwrap();
+        if leader_epoch < state.epoch {
+            return Err(FENCED_LEADER_EPOCH);
+        }
+        let same_epoch = leader_epoch == state.epoch;
+        // an epoch has one leader, and this node only ever elects itself
+        if (same_epoch && state.leader_id != -1 && state.leader_id != leader_id)
+            || (leader_id == self.node_id && state.leader_id != self.node_id)
+        {
+            return Err(INVALID_REQUEST);
+        }
+        if same_epoch && state.leader_id == leader_id {
+            return Ok(());
+        }
+
+        let voted_id = match same_epoch {
+            true => state.voted_id,
+            false => -1,
+        };
+        self.transition(
+            &mut state,
+            QuorumState {
+                epoch: leader_epoch,
+                leader_id,
+                voted_id,
+            },
+        )
+    }
+
+    // blocking file io. the leader of `leader_epoch` resigned, which a sole voter answers by
+    // electing itself again
+    pub fn end_epoch(&self, leader_id: i32, leader_epoch: i32) -> Result<(), i16> {
+        if !self.voters.contains(&leader_id) {
+            return Err(INCONSISTENT_VOTER_SET);
+        }
+        let mut state = self.state.lock().unwrap();
+        if leader_epoch < state.epoch {
+            return Err(FENCED_LEADER_EPOCH);
+        }
+        if leader_epoch > state.epoch {
+            self.transition(
+                &mut state,
+                QuorumState {
+                    epoch: leader_epoch,
+                    ..QuorumState::default()
+                },
+            )?;
+        } else if state.leader_id == leader_id {
+            let resigned = QuorumState {
+                leader_id: -1,
+                ..*state
+            };
+            self.transition(&mut state, resigned)?;
+        }
+        self.elect_if_sole_voter(&mut state)
+    }
+
+    // its own vote is a majority
+    fn elect_if_sole_voter(&self, state: &mut QuorumState) -> Result<(), i16> {
+        if self.voters != [self.node_id] || state.leader_id != -1 {
+            return Ok(());
+        }
+        self.transition(
+            state,
+            QuorumState {
+                epoch: state.epoch + 1,
+                leader_id: self.node_id,
+                voted_id: self.node_id,
+            },
+        )
+    }
+
+    // the new state is persisted before it takes effect
+    fn transition(&self, state: &mut QuorumState, new: QuorumState) -> Result<(), i16> {
+        if let Some(path) = &self.state_path {
+            let written = path
+                .parent()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|()| new.write(path, &self.voters));
+            if let Err(e) = written {
+                eprintln!("Error writing the metadata quorum state: {e}");
+                return Err(KAFKA_STORAGE_ERROR);
+            }
+        }
+
+        let leading = new.leader_id == self.node_id;
+        if leading && state.leader_id != self.node_id {
+            println!("Became the metadata quorum leader in epoch {}", new.epoch);
+        }
+        *state = new;
+        self.metadata_log
+            .set_leader_epoch(leading.then_some(new.epoch));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cluster_metadata::MetadataRecord;
+
+    #[test]
+    fn a_sole_voter_elects_itself() {
+        let metadata_log = MetadataLog::in_memory();
+        let quorum = RaftQuorum::new(1, vec![1], metadata_log.clone(), QuorumState::default());
+        let elected = QuorumState {
+            epoch: 1,
+            leader_id: 1,
+            voted_id: 1,
+        };
+        assert_eq!(quorum.state(), elected);
+        let appended = metadata_log.update(|_| ((), vec![MetadataRecord::topic("t", 7)]));
+        assert!(appended.is_ok());
+
+        // it resigned by restarting, so it has to win a new epoch
+        let quorum = RaftQuorum::new(1, vec![1], MetadataLog::in_memory(), elected);
+        assert_eq!(quorum.state().epoch, 2);
+        assert_eq!(quorum.state().leader_id, 1);
+
+        assert_eq!(quorum.end_epoch(1, 2), Ok(()));
+        assert_eq!(quorum.state().epoch, 3);
+        assert_eq!(quorum.state().leader_id, 1);
+    }
+
+    #[test]
+    fn one_vote_per_epoch() {
+        let quorum = RaftQuorum::new(
+            1,
+            vec![1, 2, 3],
+            MetadataLog::in_memory(),
+            QuorumState::default(),
+        );
+        assert_eq!(quorum.state(), QuorumState::default());
+
+        assert_eq!(quorum.vote(1, 2, -1, 0), Ok(true));
+        // asking again gets the same answer, anyone else gets nothing
+        assert_eq!(quorum.vote(1, 2, -1, 0), Ok(true));
+        assert_eq!(quorum.vote(1, 3, -1, 0), Ok(false));
+        assert_eq!(quorum.vote(0, 3, -1, 0), Ok(false));
+        assert_eq!(quorum.vote(2, 3, -1, 0), Ok(true));
+        assert_eq!(
+            quorum.state(),
+            QuorumState {
+                epoch: 2,
+                leader_id: -1,
+                voted_id: 3,
+            }
+        );
+        assert_eq!(quorum.vote(3, 9, -1, 0), Err(INCONSISTENT_VOTER_SET));
+    }
+
+    #[test]
+    fn no_vote_for_a_log_behind_this_one() {
+        let metadata_log = MetadataLog::in_memory();
+        metadata_log.set_leader_epoch(Some(4));
+        metadata_log
+            .update(|_| ((), vec![MetadataRecord::topic("t", 7)]))
+            .unwrap();
+        metadata_log.set_leader_epoch(None);
+        let quorum = RaftQuorum::new(1, vec![1, 2, 3], metadata_log, QuorumState::default());
+
+        assert_eq!(quorum.vote(5, 2, -1, 0), Ok(false));
+        assert_eq!(quorum.vote(5, 2, 4, 0), Ok(false));
+        assert_eq!(quorum.vote(5, 3, 4, 1), Ok(true));
+    }
+
+    #[test]
+    fn begin_epoch_follows_the_elected_leader() {
+        let metadata_log = MetadataLog::in_memory();
+        let quorum = RaftQuorum::new(1, vec![1, 2], metadata_log.clone(), QuorumState::default());
+
+        assert_eq!(quorum.begin_epoch(2, 1), Ok(()));
+        assert_eq!(quorum.begin_epoch(2, 1), Ok(()));
+        assert_eq!(
+            quorum.state(),
+            QuorumState {
+                epoch: 1,
+                leader_id: 2,
+                voted_id: -1,
+            }
+        );
+        assert_eq!(quorum.begin_epoch(2, 0), Err(FENCED_LEADER_EPOCH));
+        assert_eq!(quorum.begin_epoch(9, 2), Err(INCONSISTENT_VOTER_SET));
+        // a second leader in the epoch, or this node leading without electing itself
+        assert_eq!(quorum.begin_epoch(1, 1), Err(INVALID_REQUEST));
+        assert_eq!(quorum.begin_epoch(1, 2), Err(INVALID_REQUEST));
+
+        // a follower can't append to the metadata log
+        let appended = metadata_log.update(|_| ((), vec![MetadataRecord::topic("t", 7)]));
+        assert!(matches!(appended, Err(KafkaError::NotController)));
+
+        // the leader resigning leaves the epoch without one
+        assert_eq!(quorum.end_epoch(2, 1), Ok(()));
+        assert_eq!(quorum.state().leader_id, -1);
+        assert_eq!(quorum.end_epoch(2, 0), Err(FENCED_LEADER_EPOCH));
+    }
+
+    #[test]
+    fn quorum_state_survives_a_restart() {
+        let log_dir = std::env::temp_dir().join(format!("raft-test-{}", std::process::id()));
+        std::fs::create_dir_all(&log_dir).unwrap();
+        let metadata_log = MetadataLog::open(&log_dir).unwrap();
+        let metadata_log_dir = metadata_log.dir().unwrap().to_path_buf();
+        assert_eq!(
+            QuorumState::load(&metadata_log_dir).ok(),
+            Some(QuorumState::default())
+        );
+
+        let quorum = RaftQuorum::new(1, vec![1, 2, 3], metadata_log, QuorumState::default());
+        assert_eq!(quorum.vote(3, 2, -1, 0), Ok(true));
+        let restored = QuorumState::load(&metadata_log_dir).ok();
+        std::fs::remove_dir_all(&log_dir).unwrap();
+
+        assert_eq!(
+            restored,
+            Some(QuorumState {
+                epoch: 3,
+                leader_id: -1,
+                voted_id: 2,
+            })
+        );
+    }
+}
@@ -1,41 +1,121 @@
 use crate::KafkaError;
 use std::io::{Cursor, Read};
+use std::ops::{Deref, DerefMut};
+
+const MAX_PREALLOCATED_ELEMENTS: usize = 1024;
+
+// the cursor over a request body (or a response body, for the client), parsers `finish` it
+// to make sure they consumed exactly the whole body
+pub struct RequestReader<'a> {
+    cursor: Cursor<&'a [u8]>,
+}
+
+impl<'a> RequestReader<'a> {
+    pub fn new(buffer: &'a [u8]) -> Self {
+        RequestReader {
+            cursor: Cursor::new(buffer),
+        }
+    }
+
+    pub fn finish(self) -> Result<(), KafkaError> {
+        match remaining(&self.cursor) {
+            0 => Ok(()),
+            remaining => Err(KafkaError::CorruptedMessage(format!(
+                "{remaining} unexpected bytes after the message body"
+            ))),
+        }
+    }
+}
+
+impl<'a> Deref for RequestReader<'a> {
+    type Target = Cursor<&'a [u8]>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.cursor
+    }
+}
+
+impl DerefMut for RequestReader<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.cursor
+    }
+}
+
+fn remaining(cursor: &Cursor<&[u8]>) -> usize {
+    cursor
+        .get_ref()
+        .len()
+        .saturating_sub(cursor.position() as usize)
+}
+
+// running out of bytes means the request is malformed, not that the connection broke
+fn read_exact(cursor: &mut Cursor<&[u8]>, buf: &mut [u8]) -> Result<(), KafkaError> {
+    cursor
+        .read_exact(buf)
+        .map_err(|_| KafkaError::CorruptedMessage("request body ends early".to_string()))
+}
+
+fn check_remaining(cursor: &Cursor<&[u8]>, len: usize) -> Result<(), KafkaError> {
+    if len > remaining(cursor) {
+        return Err(KafkaError::CorruptedMessage(format!(
+            "length {len} runs past the end of the request body"
+        )));
+    }
+    Ok(())
+}
+
+// checks the length against what's left before allocating, so a bogus length can't
+// make us reserve gigabytes
+fn read_vec(cursor: &mut Cursor<&[u8]>, len: usize) -> Result<Vec<u8>, KafkaError> {
+    check_remaining(cursor, len)?;
+
+    let mut buf = vec![0u8; len];
+    read_exact(cursor, &mut buf)?;
+    Ok(buf)
+}
 
 pub fn read_int8(cursor: &mut Cursor<&[u8]>) -> Result<i8, KafkaError> {
     let mut buf = [0u8];
-    cursor.read_exact(&mut buf)?;
+    read_exact(cursor, &mut buf)?;
 
     Ok(i8::from_be_bytes(buf))
 }
 
 pub fn read_int16(cursor: &mut Cursor<&[u8]>) -> Result<i16, KafkaError> {
     let mut buf = [0u8; 2];
-    cursor.read_exact(&mut buf)?;
+    read_exact(cursor, &mut buf)?;
 
     Ok(i16::from_be_bytes(buf))
 }
 
 pub fn read_int32(cursor: &mut Cursor<&[u8]>) -> Result<i32, KafkaError> {
     let mut buf = [0u8; 4];
-    cursor.read_exact(&mut buf)?;
+    read_exact(cursor, &mut buf)?;
 
     Ok(i32::from_be_bytes(buf))
 }
 
 pub fn read_int64(cursor: &mut Cursor<&[u8]>) -> Result<i64, KafkaError> {
     let mut buf = [0u8; 8];
-    cursor.read_exact(&mut buf)?;
+    read_exact(cursor, &mut buf)?;
 
     Ok(i64::from_be_bytes(buf))
 }
 
 pub fn read_int128(cursor: &mut Cursor<&[u8]>) -> Result<i128, KafkaError> {
     let mut buf = [0u8; 16];
-    cursor.read_exact(&mut buf)?;
+    read_exact(cursor, &mut buf)?;
 
     Ok(i128::from_be_bytes(buf))
 }
 
+pub fn read_float64(cursor: &mut Cursor<&[u8]>) -> Result<f64, KafkaError> {
+    let mut buf = [0u8; 8];
+    read_exact(cursor, &mut buf)?;
+
+    Ok(f64::from_be_bytes(buf))
+}
+
 pub fn read_nullable_string(cursor: &mut Cursor<&[u8]>) -> Result<Option<String>, KafkaError> {
     let len = read_int16(cursor)?;
 
@@ -43,10 +123,164 @@ pub fn read_nullable_string(cursor: &mut Cursor<&[u8]>) -> Result<Option<String>
         -1 => Ok(None),
         len if len < 0 => Err(KafkaError::InvalidMessageLength(len as i32)),
         len => {
-            let mut buf = vec![0u8; len as usize];
-            cursor.read_exact(&mut buf)?;
+            let buf = read_vec(cursor, len as usize)?;
+            Ok(String::from_utf8(buf).map(Some)?)
+        }
+    }
+}
+
+pub fn read_bool(cursor: &mut Cursor<&[u8]>) -> Result<bool, KafkaError> {
+    Ok(read_int8(cursor)? != 0)
+}
+
+pub fn read_unsigned_varint(cursor: &mut Cursor<&[u8]>) -> Result<u32, KafkaError> {
+    let mut value = 0u32;
+
+    for shift in (0..35).step_by(7) {
+        let mut buf = [0u8];
+        read_exact(cursor, &mut buf)?;
+
+        value |= ((buf[0] & 0x7f) as u32) << shift;
+        if buf[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(KafkaError::CorruptedMessage(
+        "unsigned varint is longer than 5 bytes".to_string(),
+    ))
+}
 
+// the zigzag encoded signed varints inside record batches
+pub fn read_varint(cursor: &mut Cursor<&[u8]>) -> Result<i32, KafkaError> {
+    let value = read_unsigned_varint(cursor)?;
+    Ok((value >> 1) as i32 ^ -((value & 1) as i32))
+}
+
+pub fn read_varlong(cursor: &mut Cursor<&[u8]>) -> Result<i64, KafkaError> {
+    let mut value = 0u64;
+
+    for shift in (0..70).step_by(7) {
+        let mut buf = [0u8];
+        read_exact(cursor, &mut buf)?;
+
+        value |= ((buf[0] & 0x7f) as u64) << shift;
+        if buf[0] & 0x80 == 0 {
+            return Ok((value >> 1) as i64 ^ -((value & 1) as i64));
+        }
+    }
+
+    Err(KafkaError::CorruptedMessage(
+        "varlong is longer than 10 bytes".to_string(),
+    ))
+}
+
+// record keys, values and headers: a varint length, -1 for null
+pub fn read_varint_bytes(cursor: &mut Cursor<&[u8]>) -> Result<Option<Vec<u8>>, KafkaError> {
+    match read_varint(cursor)? {
+        -1 => Ok(None),
+        len if len < 0 => Err(KafkaError::InvalidMessageLength(len)),
+        len => read_vec(cursor, len as usize).map(Some),
+    }
+}
+
+// every array element takes at least a byte, so a length past what's left can't be genuine
+fn check_array_len(cursor: &Cursor<&[u8]>, len: usize) -> Result<usize, KafkaError> {
+    if len > remaining(cursor) {
+        return Err(KafkaError::CorruptedMessage(format!(
+            "array of {len} elements runs past the end of the request body"
+        )));
+    }
+    Ok(len)
+}
+
+// the Vec for an array of `len` elements. even a valid length can be large in a big frame, so
+// past a point the Vec grows as elements are actually read instead of being reserved up front
+pub fn array_with_capacity<T>(len: usize) -> Vec<T> {
+    Vec::with_capacity(len.min(MAX_PREALLOCATED_ELEMENTS))
+}
+
+// non-flexible arrays, an int32 length
+pub fn read_array_len(cursor: &mut Cursor<&[u8]>) -> Result<usize, KafkaError> {
+    let len = read_int32(cursor)?;
+    let len = usize::try_from(len).map_err(|_| KafkaError::InvalidMessageLength(len))?;
+    check_array_len(cursor, len)
+}
+
+// compact lengths are encoded as N + 1, where 0 marks a null value
+pub fn read_compact_array_len(cursor: &mut Cursor<&[u8]>) -> Result<usize, KafkaError> {
+    let len = read_unsigned_varint(cursor)?.saturating_sub(1) as usize;
+    check_array_len(cursor, len)
+}
+
+pub fn read_compact_bytes(cursor: &mut Cursor<&[u8]>) -> Result<Vec<u8>, KafkaError> {
+    let len = read_compact_array_len(cursor)?;
+    read_vec(cursor, len)
+}
+
+pub fn read_compact_string(cursor: &mut Cursor<&[u8]>) -> Result<String, KafkaError> {
+    Ok(String::from_utf8(read_compact_bytes(cursor)?)?)
+}
+
+pub fn read_compact_nullable_string(
+    cursor: &mut Cursor<&[u8]>,
+) -> Result<Option<String>, KafkaError> {
+    match read_unsigned_varint(cursor)? {
+        0 => Ok(None),
+        len => {
+            let buf = read_vec(cursor, len as usize - 1)?;
             Ok(String::from_utf8(buf).map(Some)?)
         }
     }
 }
+
+// we don't understand any tagged fields yet, so just skip over them
+pub fn read_tagged_fields(cursor: &mut Cursor<&[u8]>) -> Result<(), KafkaError> {
+    let num_fields = read_unsigned_varint(cursor)?;
+
+    for _ in 0..num_fields {
+        let _tag = read_unsigned_varint(cursor)?;
+        let size = read_unsigned_varint(cursor)? as usize;
+        check_remaining(cursor, size)?;
+        cursor.set_position(cursor.position() + size as u64);
+    }
+
+    Ok(())
+}
+
+// like read_tagged_fields, but hands each field's tag and bytes to `field`
+pub fn read_tagged_fields_with(
+    cursor: &mut Cursor<&[u8]>,
+    mut field: impl FnMut(u32, &[u8]) -> Result<(), KafkaError>,
+) -> Result<(), KafkaError> {
+    let num_fields = read_unsigned_varint(cursor)?;
+
+    for _ in 0..num_fields {
+        let tag = read_unsigned_varint(cursor)?;
+        let size = read_unsigned_varint(cursor)? as usize;
+        check_remaining(cursor, size)?;
+        let start = cursor.position() as usize;
+        field(tag, &cursor.get_ref()[start..start + size])?;
+        cursor.set_position((start + size) as u64);
+    }
+
+    Ok(())
+}
+
+pub fn read_compact_nullable_bytes(
+    cursor: &mut Cursor<&[u8]>,
+) -> Result<Option<Vec<u8>>, KafkaError> {
+    match read_unsigned_varint(cursor)? {
+        0 => Ok(None),
+        len => read_vec(cursor, len as usize - 1).map(Some),
+    }
+}
+
+pub fn read_compact_nullable_array_len(
+    cursor: &mut Cursor<&[u8]>,
+) -> Result<Option<usize>, KafkaError> {
+    match read_unsigned_varint(cursor)? {
+        0 => Ok(None),
+        len => check_array_len(cursor, len as usize - 1).map(Some),
+    }
+}
@@ -0,0 +1,337 @@
+use crate::readers::*;
+use crate::storage::ATTRIBUTE_CONTROL;
+use crate::writers::*;
+use crate::KafkaError;
+use std::io::Cursor;
+
+// the low 3 bits of a batch's attributes
+const COMPRESSION_CODEC_MASK: i16 = 0x07;
+
+pub struct Record {
+    pub offset: i64,
+    pub timestamp_ms: i64,
+    pub key: Option<Vec<u8>>,
+    pub value: Option<Vec<u8>>,
+    pub headers: Vec<RecordHeader>,
+}
+
+pub struct RecordHeader {
+    pub key: String,
+    pub value: Option<Vec<u8>>,
+}
+
+impl Record {
+    // one record of a batch, its offset and timestamp are deltas from the batch's base ones.
+    // the length prefix has to cover exactly the record's fields
+    pub fn decode(
+        batch: &mut Cursor<&[u8]>,
+        base_offset: i64,
+        base_timestamp: i64,
+    ) -> Result<Self, KafkaError> {
+        let length = read_varint(batch)?;
+        if length < 0 {
+            return Err(KafkaError::InvalidMessageLength(length));
+        }
+        let start = batch.position();
+
+        let _attributes = read_int8(batch)?;
+        let timestamp_delta = read_varlong(batch)?;
+        let offset_delta = read_varint(batch)?;
+        let key = read_varint_bytes(batch)?;
+        let value = read_varint_bytes(batch)?;
+
+        let headers_count = read_varint(batch)?;
+        if headers_count < 0 {
+            return Err(KafkaError::CorruptedMessage(format!(
+                "record at offset delta {offset_delta} has a negative header count {headers_count}"
+            )));
+        }
+        let mut headers = array_with_capacity(headers_count as usize);
+        for _ in 0..headers_count {
+            let Some(header_key) = read_varint_bytes(batch)? else {
+                return Err(KafkaError::CorruptedMessage(format!(
+                    "record at offset delta {offset_delta} has a header with a null key"
+                )));
+            };
+            let header_value = read_varint_bytes(batch)?;
+            headers.push(RecordHeader {
+                key: String::from_utf8(header_key)?,
+                value: header_value,
+            });
+        }
+
+        if batch.position() - start != length as u64 {
+            return Err(KafkaError::CorruptedMessage(format!(
+                "record at offset delta {offset_delta} is {} bytes, its length says {length}",
+                batch.position() - start
+            )));
+        }
+
+        Ok(Record {
+            offset: base_offset.wrapping_add(offset_delta as i64),
+            timestamp_ms: base_timestamp.wrapping_add(timestamp_delta),
+            key,
+            value,
+            headers,
+        })
+    }
+
+    // the inverse of `decode`, for a batch with the given base offset and timestamp
+    pub fn encode(&self, buf: &mut Vec<u8>, base_offset: i64, base_timestamp: i64) {
+        let mut body = vec![0]; // attributes, unused
+        write_varlong(&mut body, self.timestamp_ms.wrapping_sub(base_timestamp));
+        write_varint(&mut body, self.offset.wrapping_sub(base_offset) as i32);
+        write_varint_bytes(&mut body, self.key.as_deref());
+        write_varint_bytes(&mut body, self.value.as_deref());
+
+        write_varint(&mut body, self.headers.len() as i32);
+        for header in &self.headers {
+            write_varint_bytes(&mut body, Some(header.key.as_bytes()));
+            write_varint_bytes(&mut body, header.value.as_deref());
+        }
+
+        write_varint(buf, body.len() as i32);
+        buf.extend_from_slice(&body);
+    }
+}
+
+// the fields of a (magic v2, uncompressed) record batch, around its encoded records
+struct Batch<'a> {
+    base_offset: i64,
+    partition_leader_epoch: i32,
+    attributes: i16,
+    last_offset_delta: i32,
+    base_timestamp: i64,
+    max_timestamp: i64,
+    producer_id: i64,
+    producer_epoch: i16,
+    base_sequence: i32,
+    records_count: i32,
+    records: &'a [u8],
+}
+
+impl Batch<'_> {
+    fn decode_records(&self) -> Result<Vec<Record>, KafkaError> {
+        let mut cursor = Cursor::new(self.records);
+        let mut decoded = array_with_capacity(self.records_count.max(0) as usize);
+        for _ in 0..self.records_count.max(0) {
+            decoded.push(Record::decode(
+                &mut cursor,
+                self.base_offset,
+                self.base_timestamp,
+            )?);
+        }
+        Ok(decoded)
+    }
+
+    // the batch's own fields with `records` in place of its records. the crc covers
+    // everything from the attributes on
+    fn encode(&self, records: &[Record], buf: &mut Vec<u8>) {
+        let mut checked = vec![];
+        checked.extend_from_slice(&self.attributes.to_be_bytes());
+        checked.extend_from_slice(&self.last_offset_delta.to_be_bytes());
+        checked.extend_from_slice(&self.base_timestamp.to_be_bytes());
+        checked.extend_from_slice(&self.max_timestamp.to_be_bytes());
+        checked.extend_from_slice(&self.producer_id.to_be_bytes());
+        checked.extend_from_slice(&self.producer_epoch.to_be_bytes());
+        checked.extend_from_slice(&self.base_sequence.to_be_bytes());
+        checked.extend_from_slice(&(records.len() as i32).to_be_bytes());
+        for record in records {
+            record.encode(&mut checked, self.base_offset, self.base_timestamp);
+        }
+
+        buf.extend_from_slice(&self.base_offset.to_be_bytes());
+        // everything after the length field: leader epoch, magic, crc and the checked part
+        buf.extend_from_slice(&(4 + 1 + 4 + checked.len() as i32).to_be_bytes());
+        buf.extend_from_slice(&self.partition_leader_epoch.to_be_bytes());
+        buf.push(2);
+        buf.extend_from_slice(&crc32c(&checked).to_be_bytes());
+        buf.extend_from_slice(&checked);
+    }
+}
+
+// (magic v2) record batches laid out back to back, as a Fetch response carries them. a
+// trailing partial batch is ignored like it is in a segment file
+fn read_batches(records: &[u8]) -> Result<Vec<Batch<'_>>, KafkaError> {
+    let mut cursor = Cursor::new(records);
+    let mut batches = vec![];
+
+    while (cursor.position() as usize) < records.len() {
+        let batch_start = cursor.position() as usize;
+        let Ok(base_offset) = read_int64(&mut cursor) else {
+            break;
+        };
+        let Ok(batch_length) = read_int32(&mut cursor) else {
+            break;
+        };
+        let batch_end = batch_start + 12 + batch_length.max(0) as usize;
+        if batch_length <= 0 || batch_end > records.len() {
+            break;
+        }
+        let mut batch = Cursor::new(&records[batch_start + 12..batch_end]);
+
+        let partition_leader_epoch = read_int32(&mut batch)?;
+        let magic = read_int8(&mut batch)?;
+        if magic != 2 {
+            return Err(KafkaError::CorruptedMessage(format!(
+                "record batch at offset {base_offset} has unsupported magic {magic}"
+            )));
+        }
+        let _crc = read_int32(&mut batch)?;
+        let attributes = read_int16(&mut batch)?;
+        if attributes & COMPRESSION_CODEC_MASK != 0 {
+            return Err(KafkaError::CorruptedMessage(format!(
+                "record batch at offset {base_offset} is compressed, which isn't supported"
+            )));
+        }
+        let last_offset_delta = read_int32(&mut batch)?;
+        let base_timestamp = read_int64(&mut batch)?;
+        let max_timestamp = read_int64(&mut batch)?;
+        let producer_id = read_int64(&mut batch)?;
+        let producer_epoch = read_int16(&mut batch)?;
+        let base_sequence = read_int32(&mut batch)?;
+        let records_count = read_int32(&mut batch)?;
+
+        batches.push(Batch {
+            base_offset,
+            partition_leader_epoch,
+            attributes,
+            last_offset_delta,
+            base_timestamp,
+            max_timestamp,
+            producer_id,
+            producer_epoch,
+            base_sequence,
+            records_count,
+            records: &records[batch_start + 12 + batch.position() as usize..batch_end],
+        });
+        cursor.set_position(batch_end as u64);
+    }
+
+    Ok(batches)
+}
+
+pub fn decode_records(records: &[u8]) -> Result<Vec<Record>, KafkaError> {
+    let mut decoded = vec![];
+    for batch in read_batches(records)? {
+        decoded.extend(batch.decode_records()?);
+    }
+    Ok(decoded)
+}
+
+// a new batch of keyless `values` with consecutive offsets from `base_offset`, all stamped
+// `timestamp_ms`. no producer, like the batches a controller writes to the metadata log
+pub fn encode_batch(
+    base_offset: i64,
+    partition_leader_epoch: i32,
+    timestamp_ms: i64,
+    values: Vec<Vec<u8>>,
+) -> Vec<u8> {
+    let records = values.into_iter().map(|value| (None, value)).collect();
+    encode_new_batch(
+        base_offset,
+        partition_leader_epoch,
+        timestamp_ms,
+        0,
+        records,
+    )
+}
+
+// a control batch of the one control record, whose key is (version, type)
+pub fn encode_control_batch(
+    base_offset: i64,
+    partition_leader_epoch: i32,
+    timestamp_ms: i64,
+    control_type: i16,
+    value: Vec<u8>,
+) -> Vec<u8> {
+    let mut key = 0i16.to_be_bytes().to_vec();
+    key.extend_from_slice(&control_type.to_be_bytes());
+    let records = vec![(Some(key), value)];
+    encode_new_batch(
+        base_offset,
+        partition_leader_epoch,
+        timestamp_ms,
+        ATTRIBUTE_CONTROL,
+        records,
+    )
+}
+
+fn encode_new_batch(
+    base_offset: i64,
+    partition_leader_epoch: i32,
+    timestamp_ms: i64,
+    attributes: i16,
+    records: Vec<(Option<Vec<u8>>, Vec<u8>)>,
+) -> Vec<u8> {
+    let records: Vec<Record> = records
+        .into_iter()
+        .enumerate()
+        .map(|(i, (key, value))| Record {
+            offset: base_offset + i as i64,
+            timestamp_ms,
+            key,
+            value: Some(value),
+            headers: vec![],
+        })
+        .collect();
+    let batch = Batch {
+        base_offset,
+        partition_leader_epoch,
+        attributes,
+        last_offset_delta: records.len().saturating_sub(1) as i32,
+        base_timestamp: timestamp_ms,
+        max_timestamp: timestamp_ms,
+        producer_id: -1,
+        producer_epoch: -1,
+        base_sequence: -1,
+        records_count: records.len() as i32,
+        records: &[],
+    };
+
+    let mut buf = vec![];
+    batch.encode(&records, &mut buf);
+    buf
+}
+
+// re-encodes the batches with each record passed through `keep`, which can change it, or drop
+// it by returning false. a batch keeps its offset range, producer and timestamps even with
+// every record dropped, so consumers still move past it
+pub fn rewrite_records(
+    records: &[u8],
+    mut keep: impl FnMut(&mut Record) -> bool,
+) -> Result<Vec<u8>, KafkaError> {
+    let mut rewritten = Vec::with_capacity(records.len());
+    for batch in read_batches(records)? {
+        let mut batch_records = batch.decode_records()?;
+        batch_records.retain_mut(&mut keep);
+        batch.encode(&batch_records, &mut rewritten);
+    }
+    Ok(rewritten)
+}
+
+// CRC-32C (Castagnoli), the checksum of v2 record batches
+fn crc32c(bytes: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0u32; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = i as u32;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = match crc & 1 {
+                    1 => (crc >> 1) ^ 0x82f6_3b78,
+                    _ => crc >> 1,
+                };
+                bit += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+
+    !bytes.iter().fold(!0u32, |crc, byte| {
+        TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
@@ -0,0 +1,627 @@
+use crate::acl::{Session, OPERATION_CLUSTER_ACTION};
+use crate::broker_registry::*;
+use crate::cluster_metadata::{FinalizedFeatures, MetadataImage, MetadataLog, MetadataRecord};
+use crate::readers::*;
+use crate::writers::*;
+use crate::{
+    KafkaError, CLUSTER_AUTHORIZATION_FAILED, DUPLICATE_BROKER_REGISTRATION, FENCED_LEADER_EPOCH,
+    INCONSISTENT_CLUSTER_ID, INELIGIBLE_REPLICA, INVALID_REQUEST, INVALID_UPDATE_VERSION, NONE,
+    NOT_CONTROLLER, OPERATION_NOT_ATTEMPTED, STALE_BROKER_EPOCH, TAG_BUFFER, UNKNOWN_SERVER_ERROR,
+    UNKNOWN_TOPIC_ID, UNKNOWN_TOPIC_OR_PARTITION, UNSUPPORTED_VERSION,
+};
+use std::cmp::Ordering;
+
+// a partition whose leader was elected from outside the isr is recovering until its data is
+const LEADER_RECOVERY_STATE_RECOVERED: i8 = 0;
+
+fn registration_error_code(error: RegistrationError) -> i16 {
+    match error {
+        RegistrationError::Duplicate => DUPLICATE_BROKER_REGISTRATION,
+        RegistrationError::StaleEpoch => STALE_BROKER_EPOCH,
+    }
+}
+
+// ### BROKER REGISTRATION (v0-v3) ### //
+pub struct BrokerRegistrationRequest {
+    pub broker_id: i32,
+    pub cluster_id: String,
+    pub incarnation_id: i128,
+    pub listeners: Vec<BrokerEndpoint>,
+    // name, min and max supported level
+    pub features: Vec<(String, i16, i16)>,
+    pub rack: Option<String>,
+    pub is_migrating_zk_broker: bool,
+    pub log_dirs: Vec<i128>,
+    // -1 when the broker didn't shut down cleanly, or before v3
+    pub previous_broker_epoch: i64,
+}
+
+impl BrokerRegistrationRequest {
+    pub fn parse(buffer: &[u8], api_version: i16) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+
+        let broker_id = read_int32(&mut cursor)?;
+        let cluster_id = read_compact_string(&mut cursor)?;
+        let incarnation_id = read_int128(&mut cursor)?;
+
+        let listeners_size = read_compact_array_len(&mut cursor)?; // [listeners]
+        let mut listeners = array_with_capacity(listeners_size);
+        for _ in 0..listeners_size {
+            let name = read_compact_string(&mut cursor)?;
+            let host = read_compact_string(&mut cursor)?;
+            let port = read_int16(&mut cursor)? as u16;
+            let security_protocol = read_int16(&mut cursor)?;
+            read_tagged_fields(&mut cursor)?;
+            listeners.push(BrokerEndpoint {
+                name,
+                host,
+                port,
+                security_protocol,
+            });
+        }
+
+        let features_size = read_compact_array_len(&mut cursor)?; // [features]
+        let mut features = array_with_capacity(features_size);
+        for _ in 0..features_size {
+            let name = read_compact_string(&mut cursor)?;
+            let min_supported_version = read_int16(&mut cursor)?;
+            let max_supported_version = read_int16(&mut cursor)?;
+            read_tagged_fields(&mut cursor)?;
+            features.push((name, min_supported_version, max_supported_version));
+        }
+
+        let rack = read_compact_nullable_string(&mut cursor)?;
+        let is_migrating_zk_broker = match api_version >= 1 {
+            true => read_bool(&mut cursor)?,
+            false => false,
+        };
+        let mut log_dirs = vec![];
+        if api_version >= 2 {
+            let log_dirs_size = read_compact_array_len(&mut cursor)?; // [log_dirs]
+            log_dirs = array_with_capacity(log_dirs_size);
+            for _ in 0..log_dirs_size {
+                log_dirs.push(read_int128(&mut cursor)?);
+            }
+        }
+        let previous_broker_epoch = match api_version >= 3 {
+            true => read_int64(&mut cursor)?,
+            false => -1,
+        };
+
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(BrokerRegistrationRequest {
+            broker_id,
+            cluster_id,
+            incarnation_id,
+            listeners,
+            features,
+            rack,
+            is_migrating_zk_broker,
+            log_dirs,
+            previous_broker_epoch,
+        })
+    }
+
+    // like kafka, a feature the broker leaves out is one it only supports at level 0, so it
+    // can't join a cluster that finalized it at anything above
+    fn unsupported_feature(&self, finalized: &FinalizedFeatures) -> Option<String> {
+        finalized.levels.iter().find_map(|(name, level)| {
+            let (min, max) = self
+                .features
+                .iter()
+                .find(|(feature, _, _)| feature == name)
+                .map(|(_, min, max)| (*min, *max))
+                .unwrap_or((0, 0));
+            (!(min..=max).contains(level)).then(|| {
+                format!(
+                    "broker {} supports {name} {min} to {max}, the cluster is at {level}",
+                    self.broker_id
+                )
+            })
+        })
+    }
+}
+
+pub struct BrokerRegistrationResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    // -1 alongside an error
+    pub broker_epoch: i64,
+}
+
+impl BrokerRegistrationResponse {
+    fn error(error_code: i16) -> Self {
+        BrokerRegistrationResponse {
+            throttle_time_ms: 0,
+            error_code,
+            broker_epoch: -1,
+        }
+    }
+
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.throttle_time_ms.to_be_bytes());
+        res_buf.extend_from_slice(&self.error_code.to_be_bytes());
+        res_buf.extend_from_slice(&self.broker_epoch.to_be_bytes());
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+// `cluster_id` is this node's own, from meta.properties, `None` when the log dirs haven't been
+// formatted and any cluster goes
+pub fn register_broker(
+    registry: &BrokerRegistry,
+    finalized_features: &FinalizedFeatures,
+    cluster_id: Option<&str>,
+    session: &Session,
+    request: BrokerRegistrationRequest,
+) -> BrokerRegistrationResponse {
+    if !session.authorize_cluster(OPERATION_CLUSTER_ACTION) {
+        return BrokerRegistrationResponse::error(CLUSTER_AUTHORIZATION_FAILED);
+    }
+    if cluster_id.is_some_and(|cluster_id| cluster_id != request.cluster_id) {
+        eprintln!(
+            "Rejecting registration of broker {} from cluster {}",
+            request.broker_id, request.cluster_id
+        );
+        return BrokerRegistrationResponse::error(INCONSISTENT_CLUSTER_ID);
+    }
+    if let Some(message) = request.unsupported_feature(finalized_features) {
+        eprintln!("Rejecting registration: {message}");
+        return BrokerRegistrationResponse::error(UNSUPPORTED_VERSION);
+    }
+
+    let broker_id = request.broker_id;
+    match registry.register(
+        broker_id,
+        request.incarnation_id,
+        request.previous_broker_epoch,
+        request.listeners,
+        request.rack,
+    ) {
+        Ok(broker_epoch) => {
+            println!("Registered broker {broker_id} with epoch {broker_epoch}");
+            BrokerRegistrationResponse {
+                throttle_time_ms: 0,
+                error_code: NONE,
+                broker_epoch,
+            }
+        }
+        Err(e) => BrokerRegistrationResponse::error(registration_error_code(e)),
+    }
+}
+
+// ### BROKER HEARTBEAT (v0-v1) ### //
+pub struct BrokerHeartbeatRequest {
+    pub broker_id: i32,
+    pub broker_epoch: i64,
+    // the highest metadata offset the broker has replayed
+    pub current_metadata_offset: i64,
+    pub want_fence: bool,
+    pub want_shut_down: bool,
+}
+
+impl BrokerHeartbeatRequest {
+    // the offline log dirs v1 adds as a tagged field aren't kept, there's nothing to move
+    // off them
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+
+        let broker_id = read_int32(&mut cursor)?;
+        let broker_epoch = read_int64(&mut cursor)?;
+        let current_metadata_offset = read_int64(&mut cursor)?;
+        let want_fence = read_bool(&mut cursor)?;
+        let want_shut_down = read_bool(&mut cursor)?;
+
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(BrokerHeartbeatRequest {
+            broker_id,
+            broker_epoch,
+            current_metadata_offset,
+            want_fence,
+            want_shut_down,
+        })
+    }
+}
+
+pub struct BrokerHeartbeatResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub is_caught_up: bool,
+    pub is_fenced: bool,
+    pub should_shut_down: bool,
+}
+
+impl BrokerHeartbeatResponse {
+    // a broker that isn't told otherwise stays fenced
+    fn error(error_code: i16) -> Self {
+        BrokerHeartbeatResponse {
+            throttle_time_ms: 0,
+            error_code,
+            is_caught_up: false,
+            is_fenced: true,
+            should_shut_down: false,
+        }
+    }
+
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.throttle_time_ms.to_be_bytes());
+        res_buf.extend_from_slice(&self.error_code.to_be_bytes());
+        res_buf.push(self.is_caught_up as u8);
+        res_buf.push(self.is_fenced as u8);
+        res_buf.push(self.should_shut_down as u8);
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+pub fn broker_heartbeat(
+    registry: &BrokerRegistry,
+    session: &Session,
+    request: &BrokerHeartbeatRequest,
+) -> BrokerHeartbeatResponse {
+    if !session.authorize_cluster(OPERATION_CLUSTER_ACTION) {
+        return BrokerHeartbeatResponse::error(CLUSTER_AUTHORIZATION_FAILED);
+    }
+
+    match registry.heartbeat(
+        request.broker_id,
+        request.broker_epoch,
+        request.want_fence,
+        request.want_shut_down,
+    ) {
+        Ok(outcome) => BrokerHeartbeatResponse {
+            throttle_time_ms: 0,
+            error_code: NONE,
+            is_caught_up: outcome.is_caught_up,
+            is_fenced: outcome.is_fenced,
+            should_shut_down: outcome.should_shut_down,
+        },
+        Err(e) => BrokerHeartbeatResponse::error(registration_error_code(e)),
+    }
+}
+
+// ### ALTER PARTITION (v0-v3) ### //
+pub struct AlterPartitionRequest {
+    pub api_version: i16,
+    pub broker_id: i32,
+    pub broker_epoch: i64,
+    pub topics: Vec<AlterPartitionTopic>,
+}
+
+pub struct AlterPartitionTopic {
+    // the name before v2, the id from then on, the other is left empty
+    pub topic_name: String,
+    pub topic_id: i128,
+    pub partitions: Vec<AlterPartitionData>,
+}
+
+pub struct AlterPartitionData {
+    pub partition_index: i32,
+    pub leader_epoch: i32,
+    // (broker id, broker epoch), the epochs are -1 before v3
+    pub new_isr: Vec<(i32, i64)>,
+    pub leader_recovery_state: i8,
+    pub partition_epoch: i32,
+}
+
+impl AlterPartitionRequest {
+    pub fn parse(buffer: &[u8], api_version: i16) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+
+        let broker_id = read_int32(&mut cursor)?;
+        let broker_epoch = read_int64(&mut cursor)?;
+
+        let topics_size = read_compact_array_len(&mut cursor)?; // [topics]
+        let mut topics = array_with_capacity(topics_size);
+        for _ in 0..topics_size {
+            let (topic_name, topic_id) = match api_version >= 2 {
+                true => (String::new(), read_int128(&mut cursor)?),
+                false => (read_compact_string(&mut cursor)?, 0),
+            };
+
+            let partitions_size = read_compact_array_len(&mut cursor)?; // [partitions]
+            let mut partitions = array_with_capacity(partitions_size);
+            for _ in 0..partitions_size {
+                let partition_index = read_int32(&mut cursor)?;
+                let leader_epoch = read_int32(&mut cursor)?;
+
+                let new_isr_size = read_compact_array_len(&mut cursor)?; // [new_isr(_with_epochs)]
+                let mut new_isr = array_with_capacity(new_isr_size);
+                for _ in 0..new_isr_size {
+                    let broker_id = read_int32(&mut cursor)?;
+                    let broker_epoch = match api_version >= 3 {
+                        true => {
+                            let broker_epoch = read_int64(&mut cursor)?;
+                            read_tagged_fields(&mut cursor)?;
+                            broker_epoch
+                        }
+                        false => -1,
+                    };
+                    new_isr.push((broker_id, broker_epoch));
+                }
+
+                let leader_recovery_state = match api_version >= 1 {
+                    true => read_int8(&mut cursor)?,
+                    false => LEADER_RECOVERY_STATE_RECOVERED,
+                };
+                let partition_epoch = read_int32(&mut cursor)?;
+                read_tagged_fields(&mut cursor)?;
+
+                partitions.push(AlterPartitionData {
+                    partition_index,
+                    leader_epoch,
+                    new_isr,
+                    leader_recovery_state,
+                    partition_epoch,
+                });
+            }
+            read_tagged_fields(&mut cursor)?;
+
+            topics.push(AlterPartitionTopic {
+                topic_name,
+                topic_id,
+                partitions,
+            });
+        }
+
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(AlterPartitionRequest {
+            api_version,
+            broker_id,
+            broker_epoch,
+            topics,
+        })
+    }
+}
+
+pub struct AlterPartitionResponse {
+    pub api_version: i16,
+    pub throttle_time_ms: i32,
+    pub error_code: i16,
+    pub topics: Vec<AlterPartitionResponseTopic>,
+}
+
+pub struct AlterPartitionResponseTopic {
+    pub topic_name: String,
+    pub topic_id: i128,
+    pub partitions: Vec<AlterPartitionResponsePartition>,
+}
+
+// the partition as it is after the change, or as it was when the change was rejected
+pub struct AlterPartitionResponsePartition {
+    pub partition_index: i32,
+    pub error_code: i16,
+    pub leader_id: i32,
+    pub leader_epoch: i32,
+    pub isr: Vec<i32>,
+    pub leader_recovery_state: i8,
+    pub partition_epoch: i32,
+}
+
+impl AlterPartitionResponsePartition {
+    fn error(partition_index: i32, error_code: i16) -> Self {
+        AlterPartitionResponsePartition {
+            partition_index,
+            error_code,
+            leader_id: -1,
+            leader_epoch: -1,
+            isr: vec![],
+            leader_recovery_state: LEADER_RECOVERY_STATE_RECOVERED,
+            partition_epoch: -1,
+        }
+    }
+}
+
+impl AlterPartitionResponse {
+    fn error(api_version: i16, error_code: i16) -> Self {
+        AlterPartitionResponse {
+            api_version,
+            throttle_time_ms: 0,
+            error_code,
+            topics: vec![],
+        }
+    }
+
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.throttle_time_ms.to_be_bytes());
+        res_buf.extend_from_slice(&self.error_code.to_be_bytes());
+
+        write_compact_array_len(res_buf, self.topics.len());
+        for topic in &self.topics {
+            match self.api_version >= 2 {
+                true => res_buf.extend_from_slice(&topic.topic_id.to_be_bytes()),
+                false => write_compact_string(res_buf, &topic.topic_name),
+            }
+
+            write_compact_array_len(res_buf, topic.partitions.len());
+            for partition in &topic.partitions {
+                res_buf.extend_from_slice(&partition.partition_index.to_be_bytes());
+                res_buf.extend_from_slice(&partition.error_code.to_be_bytes());
+                res_buf.extend_from_slice(&partition.leader_id.to_be_bytes());
+                res_buf.extend_from_slice(&partition.leader_epoch.to_be_bytes());
+                write_compact_array_len(res_buf, partition.isr.len());
+                for broker_id in &partition.isr {
+                    res_buf.extend_from_slice(&broker_id.to_be_bytes());
+                }
+                if self.api_version >= 1 {
+                    res_buf.push(partition.leader_recovery_state as u8);
+                }
+                res_buf.extend_from_slice(&partition.partition_epoch.to_be_bytes());
+                res_buf.extend_from_slice(TAG_BUFFER);
+            }
+            res_buf.extend_from_slice(TAG_BUFFER);
+        }
+
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+// blocking file io. every accepted change goes into the metadata log as one
+// PartitionChangeRecord, all of the request's in a single batch. like kafka, the leader has to
+// name the leader and partition epochs the partition is at, and every broker in the new isr
+// has to hold an active registration here. only the controller side is here, partition
+// leaders don't send it yet (see IsrTracker)
+pub fn alter_partition(
+    metadata_log: &MetadataLog,
+    registry: &BrokerRegistry,
+    session: &Session,
+    request: AlterPartitionRequest,
+) -> AlterPartitionResponse {
+    let api_version = request.api_version;
+    if !session.authorize_cluster(OPERATION_CLUSTER_ACTION) {
+        return AlterPartitionResponse::error(api_version, CLUSTER_AUTHORIZATION_FAILED);
+    }
+    if !registry.has_epoch(request.broker_id, request.broker_epoch) {
+        return AlterPartitionResponse::error(api_version, STALE_BROKER_EPOCH);
+    }
+
+    let updated = metadata_log.update(|image| {
+        let mut records = vec![];
+        let topics = request
+            .topics
+            .iter()
+            .map(|topic| {
+                let topic_id = match api_version >= 2 {
+                    true => Some(topic.topic_id),
+                    false => image
+                        .topics
+                        .iter()
+                        .find(|(_, name)| **name == topic.topic_name)
+                        .map(|(topic_id, _)| *topic_id),
+                };
+                let partitions = topic
+                    .partitions
+                    .iter()
+                    .map(|data| {
+                        let changed =
+                            topic_id
+                                .ok_or(UNKNOWN_TOPIC_OR_PARTITION)
+                                .and_then(|topic_id| {
+                                    change_isr(
+                                        image,
+                                        registry,
+                                        &request,
+                                        topic_id,
+                                        data,
+                                        &mut records,
+                                    )
+                                });
+                        changed.unwrap_or_else(|error_code| {
+                            AlterPartitionResponsePartition::error(data.partition_index, error_code)
+                        })
+                    })
+                    .collect();
+                AlterPartitionResponseTopic {
+                    topic_name: topic.topic_name.clone(),
+                    topic_id: topic.topic_id,
+                    partitions,
+                }
+            })
+            .collect();
+        (topics, records)
+    });
+
+    match updated {
+        Ok(topics) => AlterPartitionResponse {
+            api_version,
+            throttle_time_ms: 0,
+            error_code: NONE,
+            topics,
+        },
+        Err(e) => {
+            eprintln!("Error writing isr changes to the metadata log: {e}");
+            AlterPartitionResponse::error(api_version, UNKNOWN_SERVER_ERROR)
+        }
+    }
+}
+
+// a change that leaves the isr as it is gets the partition back without a record
+fn change_isr(
+    image: &MetadataImage,
+    registry: &BrokerRegistry,
+    request: &AlterPartitionRequest,
+    topic_id: i128,
+    data: &AlterPartitionData,
+    records: &mut Vec<MetadataRecord>,
+) -> Result<AlterPartitionResponsePartition, i16> {
+    let unknown = match request.api_version >= 2 {
+        true => UNKNOWN_TOPIC_ID,
+        false => UNKNOWN_TOPIC_OR_PARTITION,
+    };
+    let topic_partition = image
+        .topic_partition(topic_id, data.partition_index)
+        .ok_or(unknown)?;
+    let partition = image.partitions.get(&topic_partition).ok_or(unknown)?;
+
+    if partition.leader != request.broker_id {
+        return Err(INVALID_REQUEST);
+    }
+    // epochs past this controller's mean the leader heard from a newer one
+    match data.leader_epoch.cmp(&partition.leader_epoch) {
+        Ordering::Less => return Err(FENCED_LEADER_EPOCH),
+        Ordering::Greater => return Err(NOT_CONTROLLER),
+        Ordering::Equal => {}
+    }
+    match data.partition_epoch.cmp(&partition.partition_epoch) {
+        Ordering::Less => return Err(INVALID_UPDATE_VERSION),
+        Ordering::Greater => return Err(NOT_CONTROLLER),
+        Ordering::Equal => {}
+    }
+    // the recovery state isn't kept, so every partition counts as recovered, and one can't
+    // go back to recovering
+    if data.leader_recovery_state != LEADER_RECOVERY_STATE_RECOVERED {
+        return Err(INVALID_REQUEST);
+    }
+
+    let new_isr: Vec<i32> = data
+        .new_isr
+        .iter()
+        .map(|(broker_id, _)| *broker_id)
+        .collect();
+    let mut distinct = new_isr.clone();
+    distinct.sort_unstable();
+    distinct.dedup();
+    if distinct.len() != new_isr.len() || !new_isr.contains(&partition.leader) {
+        return Err(INVALID_REQUEST);
+    }
+    let ineligible = data.new_isr.iter().any(|(broker_id, broker_epoch)| {
+        !partition.replicas.contains(broker_id) || !registry.is_active(*broker_id, *broker_epoch)
+    });
+    if ineligible {
+        // INELIGIBLE_REPLICA came with v2
+        return Err(match request.api_version >= 2 {
+            true => INELIGIBLE_REPLICA,
+            false => OPERATION_NOT_ATTEMPTED,
+        });
+    }
+
+    let partition_epoch = match new_isr == partition.isr {
+        true => partition.partition_epoch,
+        false => {
+            records.push(MetadataRecord::isr_change(
+                topic_id,
+                data.partition_index,
+                &new_isr,
+            ));
+            println!(
+                "Changing the isr of {}-{} from {:?} to {new_isr:?}",
+                topic_partition.topic, topic_partition.partition, partition.isr
+            );
+            partition.partition_epoch + 1
+        }
+    };
+    Ok(AlterPartitionResponsePartition {
+        partition_index: data.partition_index,
+        error_code: NONE,
+        leader_id: partition.leader,
+        leader_epoch: partition.leader_epoch,
+        isr: new_isr,
+        leader_recovery_state: LEADER_RECOVERY_STATE_RECOVERED,
+        partition_epoch,
+    })
+}
@@ -0,0 +1,254 @@
+use crate::broker::open_logs;
+use crate::{
+    handle_connection, BrokerConfig, BrokerState, KafkaError, Metrics, NoopFetchInterceptor,
+    RequestLogs,
+};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+use tokio::task::JoinSet;
+
+// big enough for any request or response to go through without waiting on the other side
+const REPLAY_BUFFER_BYTES: usize = 64 << 20;
+
+pub const REPLAY_USAGE: &str = "usage: replay <recording> [--config <server.properties>] [--quiet]";
+
+// appends every request frame the broker reads (request.record.path) to a file, for the
+// `replay` subcommand to feed back through the handlers. an entry is the connection id (int64)
+// followed by the frame as it came in, size prefix and all
+pub struct RequestRecorder {
+    file: Mutex<File>,
+    next_connection_id: AtomicU64,
+}
+
+impl RequestRecorder {
+    pub fn open(path: impl AsRef<Path>) -> Result<Arc<Self>, KafkaError> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(Arc::new(RequestRecorder {
+            file: Mutex::new(file),
+            next_connection_id: AtomicU64::new(0),
+        }))
+    }
+
+    // ids only tell apart the connections of one run, a reopened recording starts over
+    pub fn connection_id(&self) -> u64 {
+        self.next_connection_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    // blocking file io, `frame` is the request without its size prefix
+    pub fn record(&self, connection_id: u64, frame: &[u8]) -> Result<(), KafkaError> {
+        let mut entry = Vec::with_capacity(12 + frame.len());
+        entry.extend_from_slice(&connection_id.to_be_bytes());
+        entry.extend_from_slice(&(frame.len() as i32).to_be_bytes());
+        entry.extend_from_slice(frame);
+
+        let mut file = self.file.lock().unwrap();
+        file.write_all(&entry)?;
+        Ok(())
+    }
+}
+
+pub struct RecordedRequest {
+    pub connection_id: u64,
+    // without its size prefix
+    pub frame: Vec<u8>,
+}
+
+// a recording cut off mid-entry, by a broker that died while writing it, ends at the last
+// whole entry
+pub fn read_recording(path: impl AsRef<Path>) -> Result<Vec<RecordedRequest>, KafkaError> {
+    let mut contents = vec![];
+    File::open(path)?.read_to_end(&mut contents)?;
+
+    let mut requests = vec![];
+    let mut rest = contents.as_slice();
+    while rest.len() >= 12 {
+        let connection_id = u64::from_be_bytes(rest[..8].try_into().unwrap());
+        let size = i32::from_be_bytes(rest[8..12].try_into().unwrap());
+        if size < 0 {
+            return Err(KafkaError::InvalidMessageLength(size));
+        }
+        let Some(frame) = rest[12..].get(..size as usize) else {
+            break;
+        };
+        requests.push(RecordedRequest {
+            connection_id,
+            frame: frame.to_vec(),
+        });
+        rest = &rest[12 + size as usize..];
+    }
+
+    Ok(requests)
+}
+
+pub struct ReplayOptions {
+    pub recording: PathBuf,
+    // the broker defaults when unset
+    pub config: Option<PathBuf>,
+    // only the summary is printed, not a line per request
+    pub quiet: bool,
+}
+
+impl ReplayOptions {
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut recording = None;
+        let mut config = None;
+        let mut quiet = false;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("{arg} expects a value"))?;
+                    config = Some(PathBuf::from(value));
+                }
+                "--quiet" => quiet = true,
+                other if other.starts_with("--") => return Err(format!("unknown option {other}")),
+                other if recording.is_none() => recording = Some(PathBuf::from(other)),
+                other => return Err(format!("unexpected argument {other}")),
+            }
+        }
+
+        Ok(ReplayOptions {
+            recording: recording.ok_or("a recording is required")?,
+            config,
+            quiet,
+        })
+    }
+}
+
+// feeds a recording through a broker's handlers without any sockets, one request at a time in
+// the order they were recorded, each connection over its own in-memory stream. nothing is
+// recorded, audited or fault injected while replaying, so runs over the same logs come out
+// the same
+pub async fn replay(options: &ReplayOptions) -> Result<(), KafkaError> {
+    let requests = read_recording(&options.recording)?;
+    let mut config = match &options.config {
+        Some(path) => BrokerConfig::load(path)?,
+        None => BrokerConfig::default(),
+    };
+    config.request_record_path = None;
+    config.audit_log_path = None;
+    config.fault_injection = None;
+
+    let (stores, logs, meta) = open_logs(&config)?;
+    let request_timeout = Duration::from_millis(config.request_timeout_ms);
+    let state = BrokerState::new(
+        Arc::new(config),
+        Metrics::new(),
+        logs.clone(),
+        meta,
+        stores,
+        RequestLogs::default(),
+        Arc::new(NoopFetchInterceptor),
+    );
+
+    let mut connections: HashMap<u64, Option<DuplexStream>> = HashMap::new();
+    let mut served = JoinSet::new();
+    let started = Instant::now();
+    let mut replayed = 0;
+    for request in &requests {
+        let stream = connections.entry(request.connection_id).or_insert_with(|| {
+            let (client, server) = tokio::io::duplex(REPLAY_BUFFER_BYTES);
+            let state = state.clone();
+            served.spawn(async move {
+                // the replay ends by hanging up on the broker
+                let _ = handle_connection(server, "/localhost".to_string(), state).await;
+            });
+            Some(client)
+        });
+        let Some(client) = stream else {
+            continue;
+        };
+
+        let request_start = Instant::now();
+        let response =
+            tokio::time::timeout(request_timeout, exchange(client, &request.frame)).await;
+        let description = describe_request(request);
+        match response {
+            Ok(Ok(response_size)) => {
+                replayed += 1;
+                if !options.quiet {
+                    println!(
+                        "{description}: {response_size} byte response in {:.3}ms",
+                        request_start.elapsed().as_secs_f64() * 1000.0
+                    );
+                }
+            }
+            // like a real client, nothing more is sent once the broker has closed the connection
+            Ok(Err(e)) => {
+                println!("{description}: connection closed ({e})");
+                *stream = None;
+            }
+            Err(_) => {
+                println!(
+                    "{description}: no response within {}ms",
+                    request_timeout.as_millis()
+                );
+                *stream = None;
+            }
+        }
+    }
+    let elapsed = started.elapsed();
+    let connection_count = connections.len();
+
+    connections.clear();
+    while served.join_next().await.is_some() {}
+    tokio::task::spawn_blocking(move || logs.close())
+        .await
+        .map_err(|e| KafkaError::Io(std::io::Error::other(e)))??;
+
+    println!(
+        "Replayed {replayed} of {} requests over {} connections in {:.3}ms",
+        requests.len(),
+        connection_count,
+        elapsed.as_secs_f64() * 1000.0
+    );
+    Ok(())
+}
+
+// sends the request and reads back its response, returning the response's size
+async fn exchange(client: &mut DuplexStream, frame: &[u8]) -> Result<usize, KafkaError> {
+    client
+        .write_all(&(frame.len() as i32).to_be_bytes())
+        .await?;
+    client.write_all(frame).await?;
+
+    let mut size_buf = [0u8; 4];
+    client.read_exact(&mut size_buf).await?;
+    let size = i32::from_be_bytes(size_buf);
+    if size < 0 {
+        return Err(KafkaError::InvalidMessageLength(size));
+    }
+    let mut response = vec![0u8; size as usize];
+    client.read_exact(&mut response).await?;
+
+    Ok(response.len() + 4)
+}
+
+// connection, api key, version and correlation id, from the start of the request header
+fn describe_request(request: &RecordedRequest) -> String {
+    let frame = &request.frame;
+    if frame.len() < 8 {
+        return format!("connection {} malformed request", request.connection_id);
+    }
+    format!(
+        "connection {} api key {} v{} request {}",
+        request.connection_id,
+        i16::from_be_bytes([frame[0], frame[1]]),
+        i16::from_be_bytes([frame[2], frame[3]]),
+        i32::from_be_bytes([frame[4], frame[5], frame[6], frame[7]])
+    )
+}
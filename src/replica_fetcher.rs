@@ -0,0 +1,296 @@
+use crate::cluster_metadata::PartitionRegistration;
+use crate::purgatory::Purgatory;
+use crate::storage::{LogStore, TopicPartition};
+use crate::topic_config::TopicConfigStore;
+use crate::{
+    run_blocking, BrokerState, FetchRequest, FetchResponse, KafkaClient, KafkaError,
+    RequestPartition, RequestTopic, NONE, OFFSET_OUT_OF_RANGE,
+};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+
+// a partition followed from its leader, and the offset its next fetch starts at
+#[derive(Clone)]
+struct FollowedPartition {
+    topic_partition: TopicPartition,
+    topic_id: i128,
+    // the leader's epoch in the assignment, which fetches are fenced on
+    leader_epoch: i32,
+    fetch_offset: i64,
+}
+
+// follows every partition the cluster metadata log makes this broker a replica of but not
+// the leader, with a fetch loop per leader. the assignments are the ones read at startup
+pub async fn run_replica_fetchers(state: Arc<BrokerState>) {
+    let node_id = state.config.node_id;
+    let listener_name = &state.config.inter_broker_listener_name;
+
+    // dropped with this task, which aborts the fetchers along with it
+    let mut fetchers = JoinSet::new();
+    let image = state.metadata_log.image();
+    for (leader, partitions) in image.followed_partitions(node_id) {
+        // the leader's endpoint for the inter-broker listener, or its first one when it
+        // registered under other names
+        let endpoints = image.brokers.get(&leader);
+        let Some(endpoint) = endpoints.and_then(|endpoints| {
+            endpoints
+                .iter()
+                .find(|endpoint| &endpoint.name == listener_name)
+                .or(endpoints.first())
+        }) else {
+            eprintln!(
+                "Broker {leader} has no registered endpoint, not following its {} partition(s)",
+                partitions.len()
+            );
+            continue;
+        };
+
+        let address = format!("{}:{}", endpoint.host, endpoint.port);
+        fetchers.spawn(fetch_from_leader(
+            state.clone(),
+            leader,
+            address,
+            partitions,
+        ));
+    }
+
+    while fetchers.join_next().await.is_some() {}
+}
+
+// reconnects after the backoff whenever the connection or a fetch fails
+async fn fetch_from_leader(
+    state: Arc<BrokerState>,
+    leader: i32,
+    address: String,
+    partitions: Vec<(TopicPartition, PartitionRegistration)>,
+) {
+    let backoff = Duration::from_millis(state.config.replica_fetch_backoff_ms);
+    let logs = state.logs.clone();
+    let mut followed = match run_blocking(move || open_replicas(&*logs, partitions)).await {
+        Ok(followed) if !followed.is_empty() => followed,
+        Ok(_) => return,
+        Err(e) => {
+            eprintln!("Error opening replicas led by broker {leader}: {e}");
+            return;
+        }
+    };
+    println!(
+        "Following {} partition(s) from broker {leader} at {address}",
+        followed.len()
+    );
+
+    loop {
+        if let Err(e) = follow(&state, &address, &mut followed).await {
+            eprintln!("Error fetching from broker {leader} at {address}: {e}");
+        }
+        if followed.is_empty() {
+            return;
+        }
+        tokio::time::sleep(backoff).await;
+    }
+}
+
+// blocking file io. a partition that can't be opened isn't followed
+fn open_replicas(
+    logs: &dyn LogStore,
+    partitions: Vec<(TopicPartition, PartitionRegistration)>,
+) -> Vec<FollowedPartition> {
+    partitions
+        .into_iter()
+        .filter_map(|(topic_partition, partition)| {
+            match logs.open_replica(&topic_partition, partition.topic_id) {
+                Ok(fetch_offset) => Some(FollowedPartition {
+                    topic_partition,
+                    topic_id: partition.topic_id,
+                    leader_epoch: partition.leader_epoch,
+                    fetch_offset,
+                }),
+                Err(error_code) => {
+                    eprintln!("Error opening replica {topic_partition:?}: error code {error_code}");
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+// only returns once the connection fails, or once there's nothing left to follow
+async fn follow(
+    state: &Arc<BrokerState>,
+    address: &str,
+    followed: &mut Vec<FollowedPartition>,
+) -> Result<(), KafkaError> {
+    let client_id = format!("broker-{}-fetcher", state.config.node_id);
+    let mut client = KafkaClient::connect(address, &client_id).await?;
+
+    while !followed.is_empty() {
+        let sent = Instant::now();
+        let response = client.fetch(&fetch_request(state, followed)).await?;
+        if response.error_code != NONE {
+            eprintln!(
+                "Fetch from {address} failed with error code {}",
+                response.error_code
+            );
+            tokio::time::sleep(Duration::from_millis(state.config.replica_fetch_backoff_ms)).await;
+            continue;
+        }
+
+        let logs = state.logs.clone();
+        let topic_configs = state.topic_configs.clone();
+        let purgatory = state.fetch_purgatory.clone();
+        let mut partitions = followed.clone();
+        let (partitions, appended) = run_blocking(move || {
+            let appended = apply_response(
+                &*logs,
+                &topic_configs,
+                &purgatory,
+                &mut partitions,
+                response,
+            );
+            (partitions, appended)
+        })
+        .await?;
+        *followed = partitions;
+
+        // a leader that answers right away instead of holding the fetch until there's
+        // something new would otherwise be polled in a tight loop. one that held it has
+        // already waited
+        if !appended {
+            let wait = Duration::from_millis(state.config.replica_fetch_wait_max_ms as u64);
+            tokio::time::sleep(wait.saturating_sub(sent.elapsed())).await;
+        }
+    }
+    Ok(())
+}
+
+fn fetch_request(state: &BrokerState, followed: &[FollowedPartition]) -> FetchRequest {
+    let config = &state.config;
+    let mut topics: BTreeMap<i128, Vec<RequestPartition>> = BTreeMap::new();
+    for partition in followed {
+        topics
+            .entry(partition.topic_id)
+            .or_default()
+            .push(RequestPartition {
+                partition: partition.topic_partition.partition,
+                // a leader with a newer epoch than this broker's metadata log fences the
+                // fetch, as one that's moved on from leading does
+                current_leader_epoch: partition.leader_epoch,
+                fetch_offset: partition.fetch_offset,
+                last_fetched_epoch: -1,
+                log_start_offset: -1,
+                partition_max_bytes: config.replica_fetch_max_bytes,
+            });
+    }
+
+    FetchRequest {
+        correlation_id: 0,
+        max_wait_ms: config.replica_fetch_wait_max_ms,
+        min_bytes: 1,
+        max_bytes: config
+            .replica_fetch_max_bytes
+            .saturating_mul(followed.len() as i32),
+        isolation_level: 0,
+        session_id: 0,
+        session_epoch: -1,
+        topics: topics
+            .into_iter()
+            .map(|(topic_id, partitions)| RequestTopic {
+                topic_id,
+                partitions,
+            })
+            .collect(),
+        forgotten_topics: vec![],
+        rack_id: String::new(),
+        replica_id: config.node_id,
+        replica_epoch: -1,
+    }
+}
+
+// blocking file io. appends what came back for each partition and moves its high watermark
+// up to the leader's, as far as this replica has caught up, which fetches from this replica
+// waiting on the partition get to see. returns whether anything was appended. a partition
+// out of range is started over at the leader's log start offset when it fell behind it, and
+// no longer followed when it's ahead of the leader, which would take truncating it
+fn apply_response(
+    logs: &dyn LogStore,
+    topic_configs: &TopicConfigStore,
+    purgatory: &Purgatory<TopicPartition>,
+    followed: &mut Vec<FollowedPartition>,
+    response: FetchResponse,
+) -> bool {
+    let mut appended = false;
+    let mut stopped = vec![];
+    for topic in response.responses {
+        for fetched in topic.partitions {
+            let Some(partition) = followed.iter_mut().find(|partition| {
+                partition.topic_id == topic.topic_id
+                    && partition.topic_partition.partition == fetched.partition_index
+            }) else {
+                continue;
+            };
+            match fetched.error_code {
+                NONE => {}
+                OFFSET_OUT_OF_RANGE if fetched.log_start_offset > partition.fetch_offset => {
+                    match logs.reset_replica(&partition.topic_partition, fetched.log_start_offset) {
+                        Ok(fetch_offset) => {
+                            println!(
+                                "Replica {:?} fell behind its leader's log start offset, starting over at {fetch_offset}",
+                                partition.topic_partition
+                            );
+                            partition.fetch_offset = fetch_offset;
+                        }
+                        Err(error_code) => {
+                            eprintln!(
+                                "Error resetting replica {:?}: error code {error_code}",
+                                partition.topic_partition
+                            );
+                            stopped.push(partition.topic_partition.clone());
+                        }
+                    }
+                    continue;
+                }
+                OFFSET_OUT_OF_RANGE => {
+                    eprintln!(
+                        "Replica {:?} is ahead of its leader at offset {}, no longer following it",
+                        partition.topic_partition, partition.fetch_offset
+                    );
+                    stopped.push(partition.topic_partition.clone());
+                    continue;
+                }
+                error_code => {
+                    eprintln!(
+                        "Error fetching {:?} from its leader: error code {error_code}",
+                        partition.topic_partition
+                    );
+                    continue;
+                }
+            }
+
+            let records = fetched.records.unwrap_or_default();
+            let topic_partition = &partition.topic_partition;
+            let segment_bytes = topic_configs.segment_bytes(&topic_partition.topic);
+            match logs.append_replica(topic_partition, &records, segment_bytes) {
+                Ok(log_end_offset) => {
+                    appended |= log_end_offset > partition.fetch_offset;
+                    partition.fetch_offset = log_end_offset;
+                }
+                Err(error_code) => {
+                    eprintln!(
+                        "Error appending to replica {:?}: error code {error_code}",
+                        partition.topic_partition
+                    );
+                    continue;
+                }
+            }
+            logs.set_high_watermark(
+                &partition.topic_partition,
+                Some(fetched.high_watermark.min(partition.fetch_offset)),
+            );
+            purgatory.check(&partition.topic_partition);
+        }
+    }
+    followed.retain(|partition| !stopped.contains(&partition.topic_partition));
+    appended
+}
@@ -0,0 +1,21 @@
+// what a fetching client tells us about itself, for selectors that pick by locality
+pub struct ClientMetadata<'a> {
+    pub rack_id: &'a str,
+    pub client_id: &'a str,
+    pub client_host: &'a str,
+}
+
+// kafka's `replica.selector.class`: picks the replica a consumer should fetch a partition
+// from instead of the leader. `None` keeps it fetching from the leader
+pub trait ReplicaSelector: Send + Sync {
+    fn select(&self, topic_id: i128, partition: i32, client: &ClientMetadata) -> Option<i32>;
+}
+
+// a single broker leads every partition it has, so there's never a closer replica
+pub struct LeaderSelector;
+
+impl ReplicaSelector for LeaderSelector {
+    fn select(&self, _topic_id: i128, _partition: i32, _client: &ClientMetadata) -> Option<i32> {
+        None
+    }
+}
@@ -0,0 +1,227 @@
+use crate::config::BrokerConfig;
+use crate::readers::*;
+use crate::scram::{ScramCredentialStore, ScramExchange, ScramMechanism};
+use crate::writers::*;
+use crate::{
+    KafkaError, APIVERSIONS, ILLEGAL_SASL_STATE, NONE, SASL_AUTHENTICATE,
+    SASL_AUTHENTICATION_FAILED, SASL_HANDSHAKE, TAG_BUFFER, UNSUPPORTED_SASL_MECHANISM,
+};
+
+// ### SASL HANDSHAKE (v1) ### //
+pub struct SaslHandshakeRequest {
+    pub mechanism: String,
+}
+
+impl SaslHandshakeRequest {
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+        let mechanism = read_nullable_string(&mut cursor)?.unwrap_or_default();
+        cursor.finish()?;
+
+        Ok(SaslHandshakeRequest { mechanism })
+    }
+}
+
+pub struct SaslHandshakeResponse {
+    pub error_code: i16,
+    pub mechanisms: Vec<String>,
+}
+
+impl SaslHandshakeResponse {
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.error_code.to_be_bytes());
+
+        res_buf.extend_from_slice(&(self.mechanisms.len() as i32).to_be_bytes()); // [mechanisms]
+        for mechanism in &self.mechanisms {
+            res_buf.extend_from_slice(&(mechanism.len() as i16).to_be_bytes());
+            res_buf.extend_from_slice(mechanism.as_bytes());
+        }
+    }
+}
+
+// ### SASL AUTHENTICATE (v2) ### //
+pub struct SaslAuthenticateRequest {
+    pub auth_bytes: Vec<u8>,
+}
+
+impl SaslAuthenticateRequest {
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+        let auth_bytes = read_compact_bytes(&mut cursor)?;
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(SaslAuthenticateRequest { auth_bytes })
+    }
+}
+
+pub struct SaslAuthenticateResponse {
+    pub error_code: i16,
+    pub error_message: Option<String>,
+    pub auth_bytes: Vec<u8>,
+    pub session_lifetime_ms: i64,
+}
+
+impl SaslAuthenticateResponse {
+    fn error(error_code: i16, error_message: &str) -> Self {
+        SaslAuthenticateResponse {
+            error_code,
+            error_message: Some(error_message.to_string()),
+            auth_bytes: vec![],
+            session_lifetime_ms: 0,
+        }
+    }
+
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.error_code.to_be_bytes());
+        write_compact_nullable_string(res_buf, self.error_message.as_deref());
+        write_compact_bytes(res_buf, &self.auth_bytes);
+        res_buf.extend_from_slice(&self.session_lifetime_ms.to_be_bytes());
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+// ### CONNECTION AUTH STATE ### //
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SaslState {
+    // sasl isn't enabled on the broker, every request is let through
+    Disabled,
+    AwaitingHandshake,
+    AwaitingAuthenticate { mechanism: String },
+    // SCRAM takes a second SaslAuthenticate, answering the server's challenge
+    ScramChallenged { exchange: Box<ScramExchange> },
+    Authenticated { principal: String },
+    // the client gets its error response and the connection is closed afterwards
+    Failed,
+}
+
+impl SaslState {
+    pub fn new(config: &BrokerConfig) -> Self {
+        if config.is_sasl_enabled() {
+            SaslState::AwaitingHandshake
+        } else {
+            SaslState::Disabled
+        }
+    }
+
+    pub fn allows(&self, api_key: i16) -> bool {
+        match self {
+            SaslState::Disabled | SaslState::Authenticated { .. } => api_key != SASL_AUTHENTICATE,
+            SaslState::AwaitingHandshake => api_key == APIVERSIONS || api_key == SASL_HANDSHAKE,
+            SaslState::AwaitingAuthenticate { .. } | SaslState::ScramChallenged { .. } => {
+                api_key == SASL_AUTHENTICATE
+            }
+            SaslState::Failed => false,
+        }
+    }
+
+    pub fn principal(&self) -> Option<&str> {
+        match self {
+            SaslState::Authenticated { principal } => Some(principal),
+            _ => None,
+        }
+    }
+
+    pub fn handshake(
+        &mut self,
+        config: &BrokerConfig,
+        request: SaslHandshakeRequest,
+    ) -> SaslHandshakeResponse {
+        let mechanisms = config.sasl_enabled_mechanisms.clone();
+
+        if *self != SaslState::AwaitingHandshake {
+            return SaslHandshakeResponse {
+                error_code: ILLEGAL_SASL_STATE,
+                mechanisms,
+            };
+        }
+
+        let mechanism = request.mechanism.to_uppercase();
+        if !mechanisms.contains(&mechanism) {
+            *self = SaslState::Failed;
+            return SaslHandshakeResponse {
+                error_code: UNSUPPORTED_SASL_MECHANISM,
+                mechanisms,
+            };
+        }
+
+        *self = SaslState::AwaitingAuthenticate { mechanism };
+        SaslHandshakeResponse {
+            error_code: NONE,
+            mechanisms,
+        }
+    }
+
+    pub fn authenticate(
+        &mut self,
+        config: &BrokerConfig,
+        scram_credentials: &ScramCredentialStore,
+        request: SaslAuthenticateRequest,
+    ) -> SaslAuthenticateResponse {
+        let result =
+            match self {
+                SaslState::AwaitingAuthenticate { mechanism } => {
+                    match (mechanism.as_str(), ScramMechanism::from_name(mechanism)) {
+                        ("PLAIN", _) => authenticate_plain(config, &request.auth_bytes)
+                            .map(|principal| (SaslState::Authenticated { principal }, vec![])),
+                        (_, Some(mechanism)) => {
+                            ScramExchange::start(mechanism, scram_credentials, &request.auth_bytes)
+                                .map(|(exchange, server_first)| {
+                                    let exchange = Box::new(exchange);
+                                    (SaslState::ScramChallenged { exchange }, server_first)
+                                })
+                        }
+                        _ => Err("unsupported SASL mechanism"),
+                    }
+                }
+                SaslState::ScramChallenged { exchange } => exchange
+                    .finish(&request.auth_bytes)
+                    .map(|(principal, server_final)| {
+                        (SaslState::Authenticated { principal }, server_final)
+                    }),
+                _ => {
+                    return SaslAuthenticateResponse::error(
+                        ILLEGAL_SASL_STATE,
+                        "SaslAuthenticate sent before a successful SaslHandshake",
+                    );
+                }
+            };
+
+        match result {
+            Ok((state, auth_bytes)) => {
+                *self = state;
+                SaslAuthenticateResponse {
+                    error_code: NONE,
+                    error_message: None,
+                    auth_bytes,
+                    session_lifetime_ms: 0,
+                }
+            }
+            Err(message) => {
+                *self = SaslState::Failed;
+                SaslAuthenticateResponse::error(SASL_AUTHENTICATION_FAILED, message)
+            }
+        }
+    }
+}
+
+// PLAIN tokens are `[authzid] NUL authcid NUL passwd` (RFC 4616)
+fn authenticate_plain(config: &BrokerConfig, auth_bytes: &[u8]) -> Result<String, &'static str> {
+    let token = std::str::from_utf8(auth_bytes).map_err(|_| "invalid PLAIN token encoding")?;
+
+    let mut parts = token.split('\0');
+    let (Some(authzid), Some(username), Some(password), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err("invalid PLAIN token: expected authzid, username, and password");
+    };
+
+    if !authzid.is_empty() && authzid != username {
+        return Err("authorization id must match the username");
+    }
+
+    match config.sasl_plain_users.get(username) {
+        Some(expected) if expected == password => Ok(username.to_string()),
+        _ => Err("Invalid username or password"),
+    }
+}
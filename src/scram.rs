@@ -0,0 +1,381 @@
+use crate::crypto::{
+    base64_decode, base64_encode, constant_time_eq, hmac, random_bytes, sha256, sha512,
+};
+use crate::KafkaError;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+const CREDENTIALS_FILE_NAME: &str = "scram-credentials";
+
+// the iteration counts kafka accepts for a stored credential
+pub const MIN_ITERATIONS: i32 = 4096;
+pub const MAX_ITERATIONS: i32 = 16384;
+
+const INVALID_CREDENTIALS: &str = "Authentication failed: Invalid user credentials";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ScramMechanism {
+    Sha256,
+    Sha512,
+}
+
+impl ScramMechanism {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "SCRAM-SHA-256" => Some(ScramMechanism::Sha256),
+            "SCRAM-SHA-512" => Some(ScramMechanism::Sha512),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ScramMechanism::Sha256 => "SCRAM-SHA-256",
+            ScramMechanism::Sha512 => "SCRAM-SHA-512",
+        }
+    }
+
+    // how AlterUserScramCredentials identifies the mechanism, 0 is UNKNOWN
+    pub fn from_type(mechanism_type: i8) -> Option<Self> {
+        match mechanism_type {
+            1 => Some(ScramMechanism::Sha256),
+            2 => Some(ScramMechanism::Sha512),
+            _ => None,
+        }
+    }
+
+    fn hash(self, message: &[u8]) -> Vec<u8> {
+        match self {
+            ScramMechanism::Sha256 => sha256(message),
+            ScramMechanism::Sha512 => sha512(message),
+        }
+    }
+
+    fn hmac(self, key: &[u8], message: &[u8]) -> Vec<u8> {
+        match self {
+            ScramMechanism::Sha256 => hmac(sha256, 64, key, message),
+            ScramMechanism::Sha512 => hmac(sha512, 128, key, message),
+        }
+    }
+}
+
+// what's kept of a password, enough to check a client's proof without being able to log in
+// as the user (RFC 5802 section 3)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScramCredential {
+    pub salt: Vec<u8>,
+    pub stored_key: Vec<u8>,
+    pub server_key: Vec<u8>,
+    pub iterations: i32,
+}
+
+impl ScramCredential {
+    // `salted_password` is Hi(password, salt, iterations), which clients work out themselves
+    // so the password never reaches the broker
+    pub fn from_salted_password(
+        mechanism: ScramMechanism,
+        salt: &[u8],
+        salted_password: &[u8],
+        iterations: i32,
+    ) -> Self {
+        let client_key = mechanism.hmac(salted_password, b"Client Key");
+        ScramCredential {
+            salt: salt.to_vec(),
+            stored_key: mechanism.hash(&client_key),
+            server_key: mechanism.hmac(salted_password, b"Server Key"),
+            iterations,
+        }
+    }
+
+    fn to_line(&self, username: &str, mechanism: ScramMechanism) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{username}",
+            mechanism.name(),
+            self.iterations,
+            base64_encode(&self.salt),
+            base64_encode(&self.stored_key),
+            base64_encode(&self.server_key)
+        )
+    }
+
+    fn from_line(line: &str) -> Option<(String, ScramMechanism, Self)> {
+        let mut fields = line.splitn(6, '\t');
+        let mechanism = ScramMechanism::from_name(fields.next()?)?;
+        let credential = ScramCredential {
+            iterations: fields.next()?.parse().ok()?,
+            salt: base64_decode(fields.next()?)?,
+            stored_key: base64_decode(fields.next()?)?,
+            server_key: base64_decode(fields.next()?)?,
+        };
+        Some((fields.next()?.to_string(), mechanism, credential))
+    }
+}
+
+// one alteration of AlterUserScramCredentials, validated already
+pub enum CredentialChange {
+    Upsert(ScramCredential),
+    Delete,
+}
+
+// the credentials of every SCRAM user, kept in a `scram-credentials` file in the first log dir
+pub struct ScramCredentialStore {
+    // `None` keeps the credentials in memory only
+    path: Option<PathBuf>,
+    credentials: Mutex<BTreeMap<(String, ScramMechanism), ScramCredential>>,
+}
+
+impl ScramCredentialStore {
+    pub fn load(log_dir: impl AsRef<Path>) -> Result<Arc<Self>, KafkaError> {
+        let path = log_dir.as_ref().join(CREDENTIALS_FILE_NAME);
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let credentials = contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let (username, mechanism, credential) = ScramCredential::from_line(line)
+                    .ok_or_else(|| {
+                        KafkaError::InvalidConfig(format!(
+                            "malformed line in {}: {line}",
+                            path.display()
+                        ))
+                    })?;
+                Ok(((username, mechanism), credential))
+            })
+            .collect::<Result<_, KafkaError>>()?;
+
+        Ok(Arc::new(ScramCredentialStore {
+            path: Some(path),
+            credentials: Mutex::new(credentials),
+        }))
+    }
+
+    pub fn in_memory() -> Arc<Self> {
+        Arc::new(ScramCredentialStore {
+            path: None,
+            credentials: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    pub fn get(&self, username: &str, mechanism: ScramMechanism) -> Option<ScramCredential> {
+        let credentials = self.credentials.lock().unwrap();
+        credentials.get(&(username.to_string(), mechanism)).cloned()
+    }
+
+    // the changes are applied together, and only once they've been written out
+    pub fn alter(
+        &self,
+        changes: Vec<(String, ScramMechanism, CredentialChange)>,
+    ) -> Result<(), KafkaError> {
+        let mut credentials = self.credentials.lock().unwrap();
+        let mut updated = credentials.clone();
+        for (username, mechanism, change) in changes {
+            match change {
+                CredentialChange::Upsert(credential) => {
+                    updated.insert((username, mechanism), credential);
+                }
+                CredentialChange::Delete => {
+                    updated.remove(&(username, mechanism));
+                }
+            }
+        }
+
+        self.persist(&updated)?;
+        *credentials = updated;
+        Ok(())
+    }
+
+    // written to a temp file first so a crash can't leave a half-written file behind
+    fn persist(
+        &self,
+        credentials: &BTreeMap<(String, ScramMechanism), ScramCredential>,
+    ) -> Result<(), KafkaError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let contents = credentials
+            .iter()
+            .map(|((username, mechanism), credential)| {
+                credential.to_line(username, *mechanism) + "\n"
+            })
+            .collect::<String>();
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, contents)?;
+        std::fs::rename(&tmp_path, path)?;
+
+        Ok(())
+    }
+}
+
+// the server side of a SCRAM exchange (RFC 5802) between the client-first and client-final
+// messages. channel binding isn't supported, like kafka
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScramExchange {
+    mechanism: ScramMechanism,
+    username: String,
+    credential: ScramCredential,
+    // `n,,` or `y,,` (with the authzid when the client sent one), echoed back in client-final
+    gs2_header: String,
+    client_first_bare: String,
+    server_first: String,
+    nonce: String,
+}
+
+impl ScramExchange {
+    // answers client-first with server-first
+    pub fn start(
+        mechanism: ScramMechanism,
+        credentials: &ScramCredentialStore,
+        client_first: &[u8],
+    ) -> Result<(Self, Vec<u8>), &'static str> {
+        let client_first =
+            std::str::from_utf8(client_first).map_err(|_| "invalid SCRAM message encoding")?;
+
+        let mut parts = client_first.splitn(3, ',');
+        let (Some(cbind_flag), Some(authzid), Some(client_first_bare)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err("invalid SCRAM client-first message");
+        };
+        if cbind_flag.starts_with("p=") {
+            return Err("SCRAM channel binding is not supported");
+        }
+        if cbind_flag != "n" && cbind_flag != "y" {
+            return Err("invalid SCRAM client-first message");
+        }
+
+        let mut attributes = client_first_bare.split(',');
+        let (Some(username), Some(client_nonce)) = (
+            attributes.next().and_then(|a| a.strip_prefix("n=")),
+            attributes.next().and_then(|a| a.strip_prefix("r=")),
+        ) else {
+            return Err("invalid SCRAM client-first message");
+        };
+        // the rest are extensions, the only one kafka defines asks for delegation tokens
+        if attributes.any(|extension| extension == "tokenauth=true") {
+            return Err("delegation token authentication is not supported");
+        }
+
+        let username = decode_sasl_name(username).ok_or("invalid SCRAM username")?;
+        match authzid.strip_prefix("a=") {
+            Some(authzid) if decode_sasl_name(authzid).as_deref() != Some(username.as_str()) => {
+                return Err("authorization id must match the username");
+            }
+            None if !authzid.is_empty() => return Err("invalid SCRAM client-first message"),
+            _ => {}
+        }
+        if client_nonce.is_empty() {
+            return Err("invalid SCRAM client-first message");
+        }
+
+        let credential = credentials
+            .get(&username, mechanism)
+            .ok_or(INVALID_CREDENTIALS)?;
+        let server_nonce = server_nonce().map_err(|e| {
+            eprintln!("Error generating a SCRAM nonce: {e}");
+            "failed to generate a SCRAM nonce"
+        })?;
+        let nonce = format!("{client_nonce}{server_nonce}");
+        let server_first = format!(
+            "r={nonce},s={},i={}",
+            base64_encode(&credential.salt),
+            credential.iterations
+        );
+
+        let exchange = ScramExchange {
+            mechanism,
+            username,
+            credential,
+            gs2_header: format!("{cbind_flag},{authzid},"),
+            client_first_bare: client_first_bare.to_string(),
+            server_first: server_first.clone(),
+            nonce,
+        };
+        Ok((exchange, server_first.into_bytes()))
+    }
+
+    // checks client-final's proof, returning the username and server-final
+    pub fn finish(&self, client_final: &[u8]) -> Result<(String, Vec<u8>), &'static str> {
+        let client_final =
+            std::str::from_utf8(client_final).map_err(|_| "invalid SCRAM message encoding")?;
+
+        let Some((without_proof, proof)) = client_final.rsplit_once(",p=") else {
+            return Err("invalid SCRAM client-final message");
+        };
+        let mut attributes = without_proof.split(',');
+        let (Some(channel_binding), Some(nonce)) = (
+            attributes.next().and_then(|a| a.strip_prefix("c=")),
+            attributes.next().and_then(|a| a.strip_prefix("r=")),
+        ) else {
+            return Err("invalid SCRAM client-final message");
+        };
+
+        if base64_decode(channel_binding).as_deref() != Some(self.gs2_header.as_bytes()) {
+            return Err("SCRAM channel binding does not match the client-first message");
+        }
+        if nonce != self.nonce {
+            return Err("SCRAM nonce does not match the server-first message");
+        }
+        let proof = base64_decode(proof).ok_or("invalid SCRAM client proof")?;
+
+        let auth_message = format!(
+            "{},{},{without_proof}",
+            self.client_first_bare, self.server_first
+        );
+        let client_signature = self
+            .mechanism
+            .hmac(&self.credential.stored_key, auth_message.as_bytes());
+        if proof.len() != client_signature.len() {
+            return Err(INVALID_CREDENTIALS);
+        }
+        let client_key = proof
+            .iter()
+            .zip(&client_signature)
+            .map(|(p, s)| p ^ s)
+            .collect::<Vec<_>>();
+        if !constant_time_eq(
+            &self.mechanism.hash(&client_key),
+            &self.credential.stored_key,
+        ) {
+            return Err(INVALID_CREDENTIALS);
+        }
+
+        let server_signature = self
+            .mechanism
+            .hmac(&self.credential.server_key, auth_message.as_bytes());
+        let server_final = format!("v={}", base64_encode(&server_signature));
+        Ok((self.username.clone(), server_final.into_bytes()))
+    }
+}
+
+// saslnames escape `,` and `=` as `=2C` and `=3D`, any other `=` is invalid
+fn decode_sasl_name(name: &str) -> Option<String> {
+    let mut decoded = String::with_capacity(name.len());
+    let mut rest = name;
+    while let Some(at) = rest.find('=') {
+        decoded.push_str(&rest[..at]);
+        match rest.get(at..at + 3) {
+            Some("=2C") => decoded.push(','),
+            Some("=3D") => decoded.push('='),
+            _ => return None,
+        }
+        rest = &rest[at + 3..];
+    }
+    decoded.push_str(rest);
+    Some(decoded)
+}
+
+// 128 random bits as hex, printable and free of the `,` SCRAM attributes are split on
+fn server_nonce() -> std::io::Result<String> {
+    let random = random_bytes(16)?;
+    Ok(random.iter().map(|byte| format!("{byte:02x}")).collect())
+}
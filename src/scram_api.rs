@@ -0,0 +1,245 @@
+use crate::acl::{Session, OPERATION_ALTER};
+use crate::readers::*;
+use crate::scram::*;
+use crate::writers::*;
+use crate::{
+    KafkaError, CLUSTER_AUTHORIZATION_FAILED, DUPLICATE_RESOURCE, NONE, RESOURCE_NOT_FOUND,
+    TAG_BUFFER, UNACCEPTABLE_CREDENTIAL, UNKNOWN_SERVER_ERROR, UNSUPPORTED_SASL_MECHANISM,
+};
+
+// ### ALTER USER SCRAM CREDENTIALS (v0) ### //
+pub struct AlterUserScramCredentialsRequest {
+    pub deletions: Vec<ScramCredentialDeletion>,
+    pub upsertions: Vec<ScramCredentialUpsertion>,
+}
+
+pub struct ScramCredentialDeletion {
+    pub name: String,
+    pub mechanism: i8,
+}
+
+pub struct ScramCredentialUpsertion {
+    pub name: String,
+    pub mechanism: i8,
+    pub iterations: i32,
+    pub salt: Vec<u8>,
+    pub salted_password: Vec<u8>,
+}
+
+impl AlterUserScramCredentialsRequest {
+    pub fn parse(buffer: &[u8]) -> Result<Self, KafkaError> {
+        let mut cursor = RequestReader::new(buffer);
+
+        let deletions_size = read_compact_array_len(&mut cursor)?; // [deletions]
+        let mut deletions = array_with_capacity(deletions_size);
+        for _ in 0..deletions_size {
+            let name = read_compact_string(&mut cursor)?;
+            let mechanism = read_int8(&mut cursor)?;
+            read_tagged_fields(&mut cursor)?;
+            deletions.push(ScramCredentialDeletion { name, mechanism });
+        }
+
+        let upsertions_size = read_compact_array_len(&mut cursor)?; // [upsertions]
+        let mut upsertions = array_with_capacity(upsertions_size);
+        for _ in 0..upsertions_size {
+            let name = read_compact_string(&mut cursor)?;
+            let mechanism = read_int8(&mut cursor)?;
+            let iterations = read_int32(&mut cursor)?;
+            let salt = read_compact_bytes(&mut cursor)?;
+            let salted_password = read_compact_bytes(&mut cursor)?;
+            read_tagged_fields(&mut cursor)?;
+            upsertions.push(ScramCredentialUpsertion {
+                name,
+                mechanism,
+                iterations,
+                salt,
+                salted_password,
+            });
+        }
+
+        read_tagged_fields(&mut cursor)?;
+        cursor.finish()?;
+
+        Ok(AlterUserScramCredentialsRequest {
+            deletions,
+            upsertions,
+        })
+    }
+}
+
+pub struct AlterUserScramCredentialsResponse {
+    pub throttle_time_ms: i32,
+    pub results: Vec<AlterUserScramCredentialsResult>,
+}
+
+pub struct AlterUserScramCredentialsResult {
+    pub user: String,
+    pub error_code: i16,
+    pub error_message: Option<String>,
+}
+
+impl AlterUserScramCredentialsResponse {
+    pub fn encode(&self, res_buf: &mut Vec<u8>) {
+        res_buf.extend_from_slice(&self.throttle_time_ms.to_be_bytes());
+
+        write_compact_array_len(res_buf, self.results.len()); // [results]
+        for result in &self.results {
+            write_compact_string(res_buf, &result.user);
+            res_buf.extend_from_slice(&result.error_code.to_be_bytes());
+            write_compact_nullable_string(res_buf, result.error_message.as_deref());
+            res_buf.extend_from_slice(TAG_BUFFER);
+        }
+
+        res_buf.extend_from_slice(TAG_BUFFER);
+    }
+}
+
+type AlterationError = (i16, String);
+
+fn validate_deletion(
+    credentials: &ScramCredentialStore,
+    deletion: &ScramCredentialDeletion,
+) -> Result<(ScramMechanism, CredentialChange), AlterationError> {
+    let mechanism = validate_mechanism(&deletion.name, deletion.mechanism)?;
+    match credentials.get(&deletion.name, mechanism) {
+        Some(_) => Ok((mechanism, CredentialChange::Delete)),
+        None => Err((
+            RESOURCE_NOT_FOUND,
+            "Attempt to delete a user credential that does not exist".to_string(),
+        )),
+    }
+}
+
+fn validate_upsertion(
+    upsertion: &ScramCredentialUpsertion,
+) -> Result<(ScramMechanism, CredentialChange), AlterationError> {
+    let mechanism = validate_mechanism(&upsertion.name, upsertion.mechanism)?;
+    let unacceptable = |message: &str| Err((UNACCEPTABLE_CREDENTIAL, message.to_string()));
+    if upsertion.iterations < MIN_ITERATIONS {
+        return unacceptable("Too few iterations");
+    }
+    if upsertion.iterations > MAX_ITERATIONS {
+        return unacceptable("Too many iterations");
+    }
+    if upsertion.salt.is_empty() || upsertion.salted_password.is_empty() {
+        return unacceptable("Salt and salted password must not be empty");
+    }
+
+    let credential = ScramCredential::from_salted_password(
+        mechanism,
+        &upsertion.salt,
+        &upsertion.salted_password,
+        upsertion.iterations,
+    );
+    Ok((mechanism, CredentialChange::Upsert(credential)))
+}
+
+fn validate_mechanism(name: &str, mechanism: i8) -> Result<ScramMechanism, AlterationError> {
+    if name.is_empty() {
+        return Err((
+            UNACCEPTABLE_CREDENTIAL,
+            "Username must not be empty".to_string(),
+        ));
+    }
+    ScramMechanism::from_type(mechanism).ok_or((
+        UNSUPPORTED_SASL_MECHANISM,
+        "Unknown SCRAM mechanism".to_string(),
+    ))
+}
+
+// one result per user. like kafka, a user with any invalid alteration has none of them
+// applied, while the other users' alterations still go through
+pub fn alter_user_scram_credentials(
+    credentials: &ScramCredentialStore,
+    session: &Session,
+    request: &AlterUserScramCredentialsRequest,
+) -> AlterUserScramCredentialsResponse {
+    let alterations = request
+        .deletions
+        .iter()
+        .map(|deletion| {
+            let validated = validate_deletion(credentials, deletion);
+            (deletion.name.as_str(), deletion.mechanism, validated)
+        })
+        .chain(request.upsertions.iter().map(|upsertion| {
+            let validated = validate_upsertion(upsertion);
+            (upsertion.name.as_str(), upsertion.mechanism, validated)
+        }))
+        .collect::<Vec<_>>();
+
+    let mut users: Vec<(&str, Option<AlterationError>)> = vec![];
+    for (i, (name, mechanism, validated)) in alterations.iter().enumerate() {
+        let duplicated = alterations[..i]
+            .iter()
+            .any(|(other_name, other_mechanism, _)| {
+                other_name == name && other_mechanism == mechanism
+            });
+        let error = match validated {
+            _ if duplicated => Some((
+                DUPLICATE_RESOURCE,
+                "A user credential cannot be altered twice in the same request".to_string(),
+            )),
+            Err(error) => Some(error.clone()),
+            Ok(_) => None,
+        };
+
+        match users.iter_mut().find(|(user, _)| user == name) {
+            Some((_, user_error)) => {
+                if user_error.is_none() {
+                    *user_error = error;
+                }
+            }
+            None => users.push((name, error)),
+        }
+    }
+
+    if !session.authorize_cluster(OPERATION_ALTER) {
+        for (_, error) in &mut users {
+            *error = Some((
+                CLUSTER_AUTHORIZATION_FAILED,
+                "Not authorized to alter user SCRAM credentials".to_string(),
+            ));
+        }
+    }
+
+    let changes = alterations
+        .into_iter()
+        .filter(|(name, _, _)| {
+            users
+                .iter()
+                .any(|(user, error)| user == name && error.is_none())
+        })
+        .filter_map(|(name, _, validated)| {
+            let (mechanism, change) = validated.ok()?;
+            Some((name.to_string(), mechanism, change))
+        })
+        .collect::<Vec<_>>();
+    let stored = match changes.is_empty() {
+        true => Ok(()),
+        false => credentials.alter(changes).map_err(|e| {
+            eprintln!("Error storing SCRAM credentials: {e}");
+            e.to_string()
+        }),
+    };
+
+    let results = users
+        .into_iter()
+        .map(|(user, error)| {
+            let (error_code, error_message) = match (error, &stored) {
+                (Some((error_code, message)), _) => (error_code, Some(message)),
+                (None, Err(message)) => (UNKNOWN_SERVER_ERROR, Some(message.clone())),
+                (None, Ok(())) => (NONE, None),
+            };
+            AlterUserScramCredentialsResult {
+                user: user.to_string(),
+                error_code,
+                error_message,
+            }
+        })
+        .collect();
+
+    AlterUserScramCredentialsResponse {
+        throttle_time_ms: 0,
+        results,
+    }
+}
@@ -0,0 +1,1259 @@
+use crate::leader_epoch::LeaderEpochCache;
+use crate::producer_state::{ProducerState, ProducerStateTable};
+use crate::topic_config::{CleanupConfig, TopicConfigStore};
+use crate::{
+    KafkaError, FENCED_LEADER_EPOCH, INCONSISTENT_TOPIC_ID, INVALID_PARTITIONS,
+    INVALID_TOPIC_EXCEPTION, KAFKA_STORAGE_ERROR, OFFSET_OUT_OF_RANGE, TOPIC_ALREADY_EXISTS,
+    UNKNOWN_LEADER_EPOCH, UNKNOWN_TOPIC_ID, UNKNOWN_TOPIC_OR_PARTITION,
+};
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// the metadata log is trimmed by snapshots, never by topic retention
+pub(crate) const CLUSTER_METADATA_TOPIC: &str = "__cluster_metadata";
+
+// left in each log dir on a graceful stop, its absence on start means segments may have
+// torn writes to recover from
+const CLEAN_SHUTDOWN_FILE: &str = ".kafka_cleanshutdown";
+
+const PARTITION_METADATA_FILE: &str = "partition.metadata";
+
+// holds the pid of the broker using the log dir
+const LOCK_FILE: &str = ".lock";
+
+// kafka's limit, leaving room for the `-<partition>` suffix in a 255 byte directory name
+const MAX_TOPIC_NAME_LEN: usize = 249;
+
+// baseOffset through recordsCount, the whole record batch header
+const BATCH_HEADER_LEN: usize = 61;
+const ATTRIBUTE_TRANSACTIONAL: i16 = 0x10;
+pub(crate) const ATTRIBUTE_CONTROL: i16 = 0x20;
+// baseOffset + batchLength, batchLength counts everything after it
+const BATCH_LENGTH_END: u64 = 12;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TopicPartition {
+    pub topic: String,
+    pub partition: i32,
+}
+
+struct Segment {
+    base_offset: i64,
+    // offset after the segment's last complete batch
+    next_offset: i64,
+    size: u64,
+    // largest record timestamp in the segment, the file's mtime when it has no batches
+    max_timestamp_ms: i64,
+    path: PathBuf,
+}
+
+struct PartitionLog {
+    // the log dir the partition's directory lives in
+    log_dir: PathBuf,
+    topic_id: Option<i128>,
+    // ordered by base offset, the last one is the active segment
+    segments: Vec<Segment>,
+    leader_epochs: LeaderEpochCache,
+    producers: ProducerStateTable,
+    // set for partitions followed by the replica fetcher, and for led ones with followers in
+    // their isr. the log end offset is the high watermark otherwise
+    replicated_high_watermark: Option<i64>,
+}
+
+impl PartitionLog {
+    fn log_start_offset(&self) -> i64 {
+        self.segments.first().map(|s| s.base_offset).unwrap_or(0)
+    }
+
+    fn log_end_offset(&self) -> i64 {
+        self.segments.last().map(|s| s.next_offset).unwrap_or(0)
+    }
+
+    fn size(&self) -> u64 {
+        self.segments.iter().map(|s| s.size).sum()
+    }
+
+    // the epoch the metadata log assigned the partition, or the latest one it logged for a
+    // partition the metadata log doesn't know
+    fn leader_epoch(&self, metadata_epoch: Option<i32>) -> i32 {
+        metadata_epoch.unwrap_or_else(|| self.leader_epochs.latest_epoch().unwrap_or(0))
+    }
+
+    fn high_watermark(&self) -> i64 {
+        let log_end_offset = self.log_end_offset();
+        self.replicated_high_watermark
+            .map_or(log_end_offset, |high_watermark| {
+                high_watermark.min(log_end_offset)
+            })
+    }
+}
+
+pub enum EpochEndOffset {
+    Found { leader_epoch: i32, end_offset: i64 },
+    UnknownPartition,
+    // the client's current_leader_epoch is older than this log's
+    FencedLeaderEpoch,
+    // the client's current_leader_epoch is newer than any this log has seen
+    UnknownLeaderEpoch,
+}
+
+pub struct FetchedPartition {
+    // the log end offset of a partition led here, nothing is transactional so every appended
+    // batch is committed. held back to what every follower in the isr has replicated, and a
+    // follower's trails its leader's
+    pub high_watermark: i64,
+    pub log_start_offset: i64,
+    // whole record batches, exactly as they sit in the segment files
+    pub records: Vec<u8>,
+}
+
+// how much a partition read may return
+pub struct FetchLimits {
+    pub max_bytes: usize,
+    // the first batch is returned even when it doesn't fit, so an oversized batch can't
+    // stall a consumer
+    pub min_one: bool,
+    // no further batches are read once it has passed, so a slow read can't hold the response
+    // back past the fetch's max_wait_ms. `min_one` still gets its batch
+    pub deadline: Option<Instant>,
+    // followers replicate everything up to the log end offset, anyone else only gets what the
+    // isr has, which is what can't go away when leadership moves
+    pub read_past_high_watermark: bool,
+}
+
+impl FetchLimits {
+    // the offset reads stop at. a fetch can still start anywhere up to the log end offset, a
+    // consumer past the high watermark just gets nothing until the isr catches up
+    pub fn end_offset(&self, high_watermark: i64, log_end_offset: i64) -> i64 {
+        match self.read_past_high_watermark {
+            true => log_end_offset,
+            false => high_watermark,
+        }
+    }
+
+    // whether a read holding `read` bytes has to stop before a batch of `batch_len` bytes
+    pub fn stops_before(&self, read: usize, batch_len: usize) -> bool {
+        if self.min_one && read == 0 {
+            return false;
+        }
+        let expired = self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline);
+        read + batch_len > self.max_bytes || expired
+    }
+}
+
+pub struct PartitionInfo {
+    pub topic_partition: TopicPartition,
+    // `None` for a file partition without a partition.metadata
+    pub topic_id: Option<i128>,
+    pub leader_epoch: i32,
+}
+
+pub struct LogDirUsage {
+    pub log_dir: PathBuf,
+    // set when the directory itself can't be read
+    pub error: Option<std::io::Error>,
+    // on-disk size of every partition's segments
+    pub partition_sizes: Vec<(TopicPartition, u64)>,
+}
+
+// what the request handlers and the log cleaner need from partition storage. the
+// `log.store` config picks between segment files (LogManager) and memory (MemoryLogStore)
+pub trait LogStore: Send + Sync {
+    fn log_dir_usage(&self) -> Vec<LogDirUsage>;
+
+    // called once on a graceful stop, the broker exits right after
+    fn close(&self) -> std::io::Result<()>;
+
+    fn has_partition(&self, topic_partition: &TopicPartition) -> bool;
+
+    // every partition, ordered by topic then partition
+    fn list_partitions(&self) -> Vec<PartitionInfo>;
+
+    // the batches from `fetch_offset` on that fit in the limits, or the partition-level error
+    // the fetch gets. `current_leader_epoch` is fenced against `metadata_epoch`, the epoch the
+    // metadata log has for the partition, or the log's own latest one without it
+    fn fetch(
+        &self,
+        topic_id: i128,
+        partition: i32,
+        current_leader_epoch: i32,
+        metadata_epoch: Option<i32>,
+        fetch_offset: i64,
+        limits: &FetchLimits,
+    ) -> Result<FetchedPartition, i16>;
+
+    // where `leader_epoch` ended in the partition's log, for OffsetForLeaderEpoch. fenced like
+    // `fetch`
+    fn end_offset_for_epoch(
+        &self,
+        topic_partition: &TopicPartition,
+        current_leader_epoch: i32,
+        metadata_epoch: Option<i32>,
+        leader_epoch: i32,
+    ) -> EpochEndOffset;
+
+    // deletes the old data of every partition of a topic with the delete cleanup.policy, per
+    // its retention.ms and retention.bytes, moving the log start offset up accordingly
+    fn enforce_retention(&self, topic_configs: &TopicConfigStore);
+
+    // picks up partitions created behind the broker's back since it started, returning the
+    // ones it found
+    fn discover_partitions(&self) -> Vec<TopicPartition>;
+
+    // the producers with a batch still in the partition's log, `None` for an unknown partition
+    fn producers(&self, topic_partition: &TopicPartition) -> Option<Vec<ProducerState>>;
+
+    // adds a topic of `num_partitions` empty partitions and returns its new topic id, or the
+    // error code a request creating it gets. every partition's only replica is this broker
+    fn create_topic(&self, topic: &str, num_partitions: i32) -> Result<i128, i16>;
+
+    // a partition this broker follows, created empty with the leader's topic id when it isn't
+    // here yet. returns the log end offset to fetch from
+    fn open_replica(&self, topic_partition: &TopicPartition, topic_id: i128) -> Result<i64, i16>;
+
+    // appends batches fetched from the partition's leader as they are, offsets and all. the
+    // first has to start at the log end offset. a trailing partial batch, which a leader sends
+    // when the next batch doesn't fit the fetch, is left for the next fetch. returns the new
+    // log end offset. a new segment is rolled first when the batches would take the active one
+    // past `segment_bytes`
+    fn append_replica(
+        &self,
+        topic_partition: &TopicPartition,
+        records: &[u8],
+        segment_bytes: u64,
+    ) -> Result<i64, i16>;
+
+    // empties a followed partition and starts it over at `log_start_offset`, for a replica
+    // that fell behind what its leader still has. returns the offset to fetch from
+    fn reset_replica(
+        &self,
+        topic_partition: &TopicPartition,
+        log_start_offset: i64,
+    ) -> Result<i64, i16>;
+
+    // `None` puts the high watermark back at the log end offset
+    fn set_high_watermark(&self, topic_partition: &TopicPartition, high_watermark: Option<i64>);
+
+    fn high_watermark(&self, topic_partition: &TopicPartition) -> Option<i64>;
+
+    fn log_end_offset(&self, topic_partition: &TopicPartition) -> Option<i64>;
+
+    // by topic id like `fetch`, what a fetch out of range is told the partition starts at
+    fn log_start_offset(&self, topic_id: i128, partition: i32) -> Option<i64>;
+}
+
+// the partition logs found in log.dirs, keyed by topic-partition
+pub struct LogManager {
+    log_dirs: Vec<PathBuf>,
+    locks: Mutex<Vec<DirLock>>,
+    partitions: Mutex<BTreeMap<TopicPartition, PartitionLog>>,
+}
+
+impl LogManager {
+    pub fn load(log_dirs: &[PathBuf]) -> Result<Arc<Self>, KafkaError> {
+        let mut partitions = BTreeMap::new();
+        let mut locks = vec![];
+
+        for log_dir in log_dirs {
+            // a missing dir is created and locked now, rather than left for whichever write
+            // gets to it first. the lock is taken before anything in the dir is recovered or
+            // deleted
+            std::fs::create_dir_all(log_dir)?;
+            locks.push(DirLock::acquire(log_dir)?);
+            let partition_dirs = partition_dirs(log_dir)?;
+
+            // the marker only vouches for the shutdown that wrote it
+            let recover = match std::fs::remove_file(log_dir.join(CLEAN_SHUTDOWN_FILE)) {
+                Ok(()) => false,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => true,
+                Err(e) => return Err(e.into()),
+            };
+            if recover {
+                println!(
+                    "No clean shutdown marker in {}, recovering its segments",
+                    log_dir.display()
+                );
+            }
+
+            for (topic_partition, dir) in partition_dirs {
+                let log = load_partition(log_dir, &dir, recover)?;
+                partitions.insert(topic_partition, log);
+            }
+        }
+
+        Ok(Arc::new(LogManager {
+            log_dirs: log_dirs.to_vec(),
+            locks: Mutex::new(locks),
+            partitions: Mutex::new(partitions),
+        }))
+    }
+}
+
+impl LogStore for LogManager {
+    // stats the segment files rather than trusting the sizes seen at startup
+    fn log_dir_usage(&self) -> Vec<LogDirUsage> {
+        let partitions = self.partitions.lock().unwrap();
+
+        self.log_dirs
+            .iter()
+            .map(|log_dir| {
+                let error = std::fs::metadata(log_dir)
+                    .and_then(|metadata| match metadata.is_dir() {
+                        true => Ok(()),
+                        false => Err(std::io::Error::other("not a directory")),
+                    })
+                    .err();
+
+                let partition_sizes = partitions
+                    .iter()
+                    .filter(|(_, log)| &log.log_dir == log_dir)
+                    .map(|(topic_partition, log)| {
+                        let size = log
+                            .segments
+                            .iter()
+                            .filter_map(|segment| std::fs::metadata(&segment.path).ok())
+                            .map(|metadata| metadata.len())
+                            .sum();
+                        (topic_partition.clone(), size)
+                    })
+                    .collect();
+
+                LogDirUsage {
+                    log_dir: log_dir.clone(),
+                    error,
+                    partition_sizes,
+                }
+            })
+            .collect()
+    }
+
+    // writes the clean shutdown markers and gives up the log dir locks. holds the partitions
+    // lock so no cleaner run can be deleting segments meanwhile
+    fn close(&self) -> std::io::Result<()> {
+        let _partitions = self.partitions.lock().unwrap();
+
+        for log_dir in &self.log_dirs {
+            if log_dir.is_dir() {
+                File::create(log_dir.join(CLEAN_SHUTDOWN_FILE))?.sync_all()?;
+            }
+        }
+        for lock in self.locks.lock().unwrap().drain(..) {
+            lock.release()?;
+        }
+        Ok(())
+    }
+
+    fn has_partition(&self, topic_partition: &TopicPartition) -> bool {
+        self.partitions
+            .lock()
+            .unwrap()
+            .contains_key(topic_partition)
+    }
+
+    fn list_partitions(&self) -> Vec<PartitionInfo> {
+        self.partitions
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(topic_partition, log)| PartitionInfo {
+                topic_partition: topic_partition.clone(),
+                topic_id: log.topic_id,
+                leader_epoch: log.leader_epoch(None),
+            })
+            .collect()
+    }
+
+    fn fetch(
+        &self,
+        topic_id: i128,
+        partition: i32,
+        current_leader_epoch: i32,
+        metadata_epoch: Option<i32>,
+        fetch_offset: i64,
+        limits: &FetchLimits,
+    ) -> Result<FetchedPartition, i16> {
+        let partitions = self.partitions.lock().unwrap();
+        let mut topic_known = false;
+
+        for (topic_partition, log) in partitions.iter() {
+            if log.topic_id != Some(topic_id) {
+                continue;
+            }
+            topic_known = true;
+
+            if topic_partition.partition == partition {
+                check_leader_epoch(current_leader_epoch, log.leader_epoch(metadata_epoch))?;
+                let log_start_offset = log.log_start_offset();
+                let end_offset = limits.end_offset(log.high_watermark(), log.log_end_offset());
+                if !(log_start_offset..=log.log_end_offset()).contains(&fetch_offset) {
+                    return Err(OFFSET_OUT_OF_RANGE);
+                }
+
+                let read = read_records(&log.segments, fetch_offset, end_offset, limits);
+                let records = read.map_err(|e| {
+                    eprintln!("Error reading {topic_partition:?} log: {e}");
+                    KAFKA_STORAGE_ERROR
+                })?;
+                return Ok(FetchedPartition {
+                    high_watermark: log.high_watermark(),
+                    log_start_offset,
+                    records,
+                });
+            }
+        }
+
+        match topic_known {
+            true => Err(UNKNOWN_TOPIC_OR_PARTITION),
+            false => Err(UNKNOWN_TOPIC_ID),
+        }
+    }
+
+    fn end_offset_for_epoch(
+        &self,
+        topic_partition: &TopicPartition,
+        current_leader_epoch: i32,
+        metadata_epoch: Option<i32>,
+        leader_epoch: i32,
+    ) -> EpochEndOffset {
+        let partitions = self.partitions.lock().unwrap();
+        let Some(log) = partitions.get(topic_partition) else {
+            return EpochEndOffset::UnknownPartition;
+        };
+
+        // -1 means the client doesn't know the current epoch, which skips fencing
+        if current_leader_epoch >= 0 {
+            match current_leader_epoch.cmp(&log.leader_epoch(metadata_epoch)) {
+                std::cmp::Ordering::Less => return EpochEndOffset::FencedLeaderEpoch,
+                std::cmp::Ordering::Greater => return EpochEndOffset::UnknownLeaderEpoch,
+                std::cmp::Ordering::Equal => {}
+            }
+        }
+
+        let (leader_epoch, end_offset) = log
+            .leader_epochs
+            .end_offset_for(leader_epoch, log.log_end_offset());
+        EpochEndOffset::Found {
+            leader_epoch,
+            end_offset,
+        }
+    }
+
+    // deletes the old segments of every partition per its retention.ms and retention.bytes,
+    // moving the log start offset up to the oldest remaining segment
+    fn enforce_retention(&self, topic_configs: &TopicConfigStore) {
+        let now_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_millis() as i64)
+            .unwrap_or_default();
+        let mut partitions = self.partitions.lock().unwrap();
+
+        for (topic_partition, log) in partitions.iter_mut() {
+            let CleanupConfig {
+                delete,
+                retention_ms,
+                retention_bytes,
+            } = topic_configs.cleanup_config(&topic_partition.topic);
+            if !delete {
+                continue;
+            }
+
+            let mut size = log.size();
+            let mut deletable = 0;
+            // the active segment is never deleted
+            for segment in &log.segments[..log.segments.len().saturating_sub(1)] {
+                let expired = retention_ms >= 0 && now_ms - segment.max_timestamp_ms > retention_ms;
+                let oversized =
+                    retention_bytes >= 0 && size - segment.size >= retention_bytes as u64;
+                if !expired && !oversized {
+                    break;
+                }
+                size -= segment.size;
+                deletable += 1;
+            }
+
+            for segment in log.segments.drain(..deletable) {
+                if let Err(e) = delete_segment(&segment.path) {
+                    eprintln!("Error deleting segment {}: {e}", segment.path.display());
+                }
+            }
+
+            if deletable > 0 {
+                let log_start_offset = log.log_start_offset();
+                if log.leader_epochs.truncate_from_start(log_start_offset) {
+                    if let Err(e) = log.leader_epochs.flush() {
+                        eprintln!("Error writing leader epoch checkpoint: {e}");
+                    }
+                }
+                println!(
+                    "Deleted {deletable} segment(s) of {}-{}, log start offset is now {}",
+                    topic_partition.topic,
+                    topic_partition.partition,
+                    log.log_start_offset()
+                );
+            }
+        }
+    }
+
+    // only the log dirs locked at startup are scanned. a directory without its
+    // partition.metadata yet is still being created, so it's left for a later scan
+    fn discover_partitions(&self) -> Vec<TopicPartition> {
+        let locked_dirs = self
+            .locks
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|lock| lock.path.parent().map(Path::to_path_buf))
+            .collect::<Vec<_>>();
+        let mut discovered = vec![];
+
+        for log_dir in &locked_dirs {
+            let partition_dirs = match partition_dirs(log_dir) {
+                Ok(partition_dirs) => partition_dirs,
+                Err(e) => {
+                    eprintln!("Error scanning log dir {}: {e}", log_dir.display());
+                    continue;
+                }
+            };
+
+            for (topic_partition, dir) in partition_dirs {
+                if self.has_partition(&topic_partition)
+                    || !dir.join(PARTITION_METADATA_FILE).exists()
+                {
+                    continue;
+                }
+
+                // segments may still be being written, so a torn tail is skipped, not truncated
+                match load_partition(log_dir, &dir, false) {
+                    Ok(log) => {
+                        self.partitions
+                            .lock()
+                            .unwrap()
+                            .entry(topic_partition.clone())
+                            .or_insert(log);
+                        discovered.push(topic_partition);
+                    }
+                    Err(e) => eprintln!("Error loading partition {}: {e}", dir.display()),
+                }
+            }
+        }
+
+        discovered
+    }
+
+    fn producers(&self, topic_partition: &TopicPartition) -> Option<Vec<ProducerState>> {
+        let partitions = self.partitions.lock().unwrap();
+        let log = partitions.get(topic_partition)?;
+        Some(log.producers.active(log.log_start_offset()))
+    }
+
+    // holding the partitions lock keeps discovery from loading them half-created
+    fn create_topic(&self, topic: &str, num_partitions: i32) -> Result<i128, i16> {
+        check_new_topic(topic, num_partitions)?;
+        let mut partitions = self.partitions.lock().unwrap();
+        if partitions.keys().any(|tp| tp.topic == topic) {
+            return Err(TOPIC_ALREADY_EXISTS);
+        }
+
+        if self.locks.lock().unwrap().is_empty() {
+            eprintln!("Error creating topic {topic}: none of log.dirs exist");
+            return Err(KAFKA_STORAGE_ERROR);
+        }
+
+        let topic_id = generate_topic_id();
+        for partition in 0..num_partitions {
+            let topic_partition = TopicPartition {
+                topic: topic.to_string(),
+                partition,
+            };
+            let log = self.create_partition(&partitions, &topic_partition, topic_id)?;
+            partitions.insert(topic_partition, log);
+        }
+
+        println!("Created topic {topic} with {num_partitions} partition(s)");
+        Ok(topic_id)
+    }
+
+    fn open_replica(&self, topic_partition: &TopicPartition, topic_id: i128) -> Result<i64, i16> {
+        let mut partitions = self.partitions.lock().unwrap();
+        if let Some(log) = partitions.get_mut(topic_partition) {
+            if log.topic_id.is_some_and(|existing| existing != topic_id) {
+                return Err(INCONSISTENT_TOPIC_ID);
+            }
+            log.replicated_high_watermark.get_or_insert(0);
+            return Ok(log.log_end_offset());
+        }
+
+        let mut log = self.create_partition(&partitions, topic_partition, topic_id)?;
+        log.replicated_high_watermark = Some(0);
+        partitions.insert(topic_partition.clone(), log);
+        println!(
+            "Created replica {}-{}",
+            topic_partition.topic, topic_partition.partition
+        );
+        Ok(0)
+    }
+
+    fn append_replica(
+        &self,
+        topic_partition: &TopicPartition,
+        records: &[u8],
+        segment_bytes: u64,
+    ) -> Result<i64, i16> {
+        let batches = whole_batches(records);
+        let mut partitions = self.partitions.lock().unwrap();
+        let log = partitions
+            .get_mut(topic_partition)
+            .ok_or(UNKNOWN_TOPIC_OR_PARTITION)?;
+        let Some(first) = batches.first() else {
+            return Ok(log.log_end_offset());
+        };
+        if first.base_offset != log.log_end_offset() {
+            return Err(OFFSET_OUT_OF_RANGE);
+        }
+
+        let storage_error = |e: std::io::Error| {
+            eprintln!("Error appending to {topic_partition:?} log: {e}");
+            KAFKA_STORAGE_ERROR
+        };
+        let len = batches.iter().map(|batch| batch.len).sum::<u64>();
+        let active = log.segments.last().ok_or(KAFKA_STORAGE_ERROR)?;
+        // an empty segment takes the batches however big they are, rolling wouldn't help
+        if active.size > 0 && active.size + len > segment_bytes {
+            let segment = roll_segment(active, log.log_end_offset()).map_err(storage_error)?;
+            log.segments.push(segment);
+        }
+
+        let segment = log.segments.last_mut().ok_or(KAFKA_STORAGE_ERROR)?;
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(&segment.path)
+            .map_err(storage_error)?;
+        if let Err(e) = file.write_all(&records[..len as usize]) {
+            // whatever made it in is a torn batch the next append would land after
+            if let Err(e) = file.set_len(segment.size) {
+                eprintln!("Error truncating {}: {e}", segment.path.display());
+            }
+            return Err(storage_error(e));
+        }
+
+        let mut epochs_changed = false;
+        for batch in &batches {
+            // an empty segment's timestamp is its mtime, which no batch should be measured
+            // against
+            segment.max_timestamp_ms = match segment.next_offset == segment.base_offset {
+                true => batch.max_timestamp_ms,
+                false => segment.max_timestamp_ms.max(batch.max_timestamp_ms),
+            };
+            segment.next_offset = batch.next_offset();
+            segment.size += batch.len;
+            epochs_changed |= log
+                .leader_epochs
+                .assign(batch.partition_leader_epoch, batch.base_offset);
+            log.producers.apply(batch);
+        }
+        if epochs_changed {
+            log.leader_epochs.flush().map_err(storage_error)?;
+        }
+
+        Ok(log.log_end_offset())
+    }
+
+    // the new segment goes in before the old ones are deleted, so a crash in between leaves a
+    // log that still starts where the leader's does once the old segments are gone
+    fn reset_replica(
+        &self,
+        topic_partition: &TopicPartition,
+        log_start_offset: i64,
+    ) -> Result<i64, i16> {
+        let mut partitions = self.partitions.lock().unwrap();
+        let log = partitions
+            .get_mut(topic_partition)
+            .ok_or(UNKNOWN_TOPIC_OR_PARTITION)?;
+        if log_start_offset <= log.log_end_offset() {
+            return Err(OFFSET_OUT_OF_RANGE);
+        }
+
+        let storage_error = |e: std::io::Error| {
+            eprintln!("Error resetting {topic_partition:?} replica: {e}");
+            KAFKA_STORAGE_ERROR
+        };
+        let active = log.segments.last().ok_or(KAFKA_STORAGE_ERROR)?;
+        let segment = roll_segment(active, log_start_offset).map_err(storage_error)?;
+        for old in std::mem::replace(&mut log.segments, vec![segment]) {
+            delete_segment(&old.path).map_err(storage_error)?;
+        }
+        if log.leader_epochs.truncate_from_start(log_start_offset) {
+            log.leader_epochs.flush().map_err(storage_error)?;
+        }
+        log.producers = ProducerStateTable::default();
+        log.replicated_high_watermark = Some(log_start_offset);
+
+        Ok(log_start_offset)
+    }
+
+    fn set_high_watermark(&self, topic_partition: &TopicPartition, high_watermark: Option<i64>) {
+        if let Some(log) = self.partitions.lock().unwrap().get_mut(topic_partition) {
+            log.replicated_high_watermark = high_watermark;
+        }
+    }
+
+    fn high_watermark(&self, topic_partition: &TopicPartition) -> Option<i64> {
+        let partitions = self.partitions.lock().unwrap();
+        partitions
+            .get(topic_partition)
+            .map(|log| log.high_watermark())
+    }
+
+    fn log_end_offset(&self, topic_partition: &TopicPartition) -> Option<i64> {
+        let partitions = self.partitions.lock().unwrap();
+        partitions
+            .get(topic_partition)
+            .map(|log| log.log_end_offset())
+    }
+
+    fn log_start_offset(&self, topic_id: i128, partition: i32) -> Option<i64> {
+        let partitions = self.partitions.lock().unwrap();
+        partitions
+            .iter()
+            .find(|(topic_partition, log)| {
+                log.topic_id == Some(topic_id) && topic_partition.partition == partition
+            })
+            .map(|(_, log)| log.log_start_offset())
+    }
+}
+
+impl LogManager {
+    // in whichever locked log dir holds the fewest partitions, like kafka spreads them
+    fn create_partition(
+        &self,
+        partitions: &BTreeMap<TopicPartition, PartitionLog>,
+        topic_partition: &TopicPartition,
+        topic_id: i128,
+    ) -> Result<PartitionLog, i16> {
+        let locked_dirs = self
+            .locks
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|lock| lock.path.parent().map(Path::to_path_buf))
+            .collect::<Vec<_>>();
+        let Some(log_dir) = locked_dirs.iter().min_by_key(|log_dir| {
+            partitions
+                .values()
+                .filter(|log| &log.log_dir == *log_dir)
+                .count()
+        }) else {
+            eprintln!("Error creating partition {topic_partition:?}: none of log.dirs exist");
+            return Err(KAFKA_STORAGE_ERROR);
+        };
+        let dir = log_dir.join(format!(
+            "{}-{}",
+            topic_partition.topic, topic_partition.partition
+        ));
+
+        create_partition_dir(&dir, topic_id)
+            .map_err(KafkaError::from)
+            .and_then(|()| load_partition(log_dir, &dir, false))
+            .map_err(|e| {
+                eprintln!("Error creating partition {}: {e}", dir.display());
+                KAFKA_STORAGE_ERROR
+            })
+    }
+}
+
+pub async fn run_partition_discovery(logs: Arc<dyn LogStore>, scan_interval: Duration) {
+    let mut interval = tokio::time::interval(scan_interval);
+    // the partitions there at startup have just been loaded
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+
+        let logs = logs.clone();
+        match tokio::task::spawn_blocking(move || logs.discover_partitions()).await {
+            Ok(discovered) => {
+                for topic_partition in discovered {
+                    println!(
+                        "Loaded new partition {}-{}",
+                        topic_partition.topic, topic_partition.partition
+                    );
+                }
+            }
+            Err(e) => eprintln!("Error discovering partitions: {e}"),
+        }
+    }
+}
+
+pub async fn run_log_cleaner(
+    logs: Arc<dyn LogStore>,
+    topic_configs: Arc<TopicConfigStore>,
+    check_interval: Duration,
+) {
+    let mut interval = tokio::time::interval(check_interval);
+    loop {
+        interval.tick().await;
+
+        let logs = logs.clone();
+        let topic_configs = topic_configs.clone();
+        // file deletion is blocking io, keep it off the connection handling threads
+        let cleaned =
+            tokio::task::spawn_blocking(move || logs.enforce_retention(&topic_configs)).await;
+        if let Err(e) = cleaned {
+            eprintln!("Error running log cleaner: {e}");
+        }
+    }
+}
+
+// fences a fetch made with an epoch other than the partition's own. -1 means the client
+// doesn't know the current epoch, which skips fencing
+pub(crate) fn check_leader_epoch(current_leader_epoch: i32, leader_epoch: i32) -> Result<(), i16> {
+    if current_leader_epoch < 0 {
+        return Ok(());
+    }
+    match current_leader_epoch.cmp(&leader_epoch) {
+        std::cmp::Ordering::Less => Err(FENCED_LEADER_EPOCH),
+        std::cmp::Ordering::Greater => Err(UNKNOWN_LEADER_EPOCH),
+        std::cmp::Ordering::Equal => Ok(()),
+    }
+}
+
+// an advisory lock on the dir's lock file, held for as long as the broker has the dir open.
+// the os releases it with the process however that exits, so a crashed broker never leaves
+// a lock behind. the file also records the owner's pid for the error another broker gets
+struct DirLock {
+    path: PathBuf,
+    file: File,
+}
+
+impl DirLock {
+    fn acquire(log_dir: &Path) -> Result<Self, KafkaError> {
+        let path = log_dir.join(LOCK_FILE);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let mut owner = String::new();
+                file.read_to_string(&mut owner)?;
+                return Err(KafkaError::LogDirLocked {
+                    log_dir: log_dir.to_path_buf(),
+                    pid: owner.trim().parse().unwrap_or_default(),
+                });
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+
+        file.set_len(0)?;
+        file.write_all(std::process::id().to_string().as_bytes())?;
+        file.sync_all()?;
+        Ok(DirLock { path, file })
+    }
+
+    // the file itself stays, a broker that opened it just before it was deleted would lock a
+    // different file than the next one to start
+    fn release(self) -> std::io::Result<()> {
+        self.file.unlock()
+    }
+}
+
+// the partition directories directly under a log dir, the metadata log's aside
+fn partition_dirs(log_dir: &Path) -> std::io::Result<Vec<(TopicPartition, PathBuf)>> {
+    let mut partition_dirs = vec![];
+
+    for entry in std::fs::read_dir(log_dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let Some(topic_partition) = parse_partition_dir(&entry.file_name().to_string_lossy())
+        else {
+            continue;
+        };
+        if topic_partition.topic == CLUSTER_METADATA_TOPIC {
+            continue;
+        }
+
+        partition_dirs.push((topic_partition, entry.path()));
+    }
+
+    Ok(partition_dirs)
+}
+
+// partition directories are named `<topic>-<partition>`
+fn parse_partition_dir(name: &str) -> Option<TopicPartition> {
+    let (topic, partition) = name.rsplit_once('-')?;
+    Some(TopicPartition {
+        topic: topic.to_string(),
+        partition: partition.parse().ok()?,
+    })
+}
+
+// a new empty segment starting at `base_offset`, next to `active` in the partition's directory
+fn roll_segment(active: &Segment, base_offset: i64) -> std::io::Result<Segment> {
+    let path = active.path.with_file_name(format!("{base_offset:020}.log"));
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    let max_timestamp_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_millis() as i64)
+        .unwrap_or_default();
+
+    Ok(Segment {
+        base_offset,
+        next_offset: base_offset,
+        size: 0,
+        max_timestamp_ms,
+        path,
+    })
+}
+
+// an empty first segment, then the partition.metadata that marks the directory complete
+fn create_partition_dir(dir: &Path, topic_id: i128) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    File::create(dir.join(format!("{:020}.log", 0)))?;
+    std::fs::write(
+        dir.join(PARTITION_METADATA_FILE),
+        format!("version: 0\ntopic_id: {}\n", encode_uuid(topic_id)),
+    )
+}
+
+// the checks kafka makes before creating a topic, shared by both log stores
+pub(crate) fn check_new_topic(topic: &str, num_partitions: i32) -> Result<(), i16> {
+    let legal = !topic.is_empty()
+        && topic.len() <= MAX_TOPIC_NAME_LEN
+        && topic != "."
+        && topic != ".."
+        && topic != CLUSTER_METADATA_TOPIC
+        && topic
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, b'.' | b'_' | b'-'));
+    if !legal {
+        return Err(INVALID_TOPIC_EXCEPTION);
+    }
+    if num_partitions < 1 {
+        return Err(INVALID_PARTITIONS);
+    }
+    Ok(())
+}
+
+fn load_partition(log_dir: &Path, dir: &Path, recover: bool) -> Result<PartitionLog, KafkaError> {
+    let topic_id = match std::fs::read_to_string(dir.join(PARTITION_METADATA_FILE)) {
+        Ok(metadata) => metadata
+            .lines()
+            .find_map(|line| line.strip_prefix("topic_id:"))
+            .and_then(|topic_id| decode_uuid(topic_id.trim())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut segment_paths = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension() != Some(OsStr::new("log")) {
+            continue;
+        }
+        let Some(base_offset) = path
+            .file_stem()
+            .and_then(|stem| stem.to_str()?.parse::<i64>().ok())
+        else {
+            continue;
+        };
+
+        segment_paths.push((base_offset, path));
+    }
+    segment_paths.sort();
+
+    // epochs the checkpoint is missing (e.g. batches written after it was last flushed)
+    // are picked up from the batches themselves
+    let mut leader_epochs = LeaderEpochCache::load(dir)?;
+    let mut epochs_changed = false;
+    let mut producers = ProducerStateTable::default();
+    let mut segments = Vec::with_capacity(segment_paths.len());
+    for (base_offset, path) in segment_paths {
+        segments.push(load_segment(path, base_offset, recover, |batch| {
+            epochs_changed |= leader_epochs.assign(batch.partition_leader_epoch, batch.base_offset);
+            producers.apply(batch);
+        })?);
+    }
+
+    if epochs_changed {
+        leader_epochs.flush()?;
+    }
+
+    Ok(PartitionLog {
+        log_dir: log_dir.to_path_buf(),
+        topic_id,
+        segments,
+        leader_epochs,
+        producers,
+        replicated_high_watermark: None,
+    })
+}
+
+pub(crate) struct BatchHeader {
+    pub base_offset: i64,
+    pub partition_leader_epoch: i32,
+    pub attributes: i16,
+    pub last_offset_delta: i32,
+    pub max_timestamp_ms: i64,
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+    pub base_sequence: i32,
+    // the whole batch, size prefix included
+    pub len: u64,
+}
+
+impl BatchHeader {
+    // `None` when there isn't a whole header or its length can't be right
+    pub(crate) fn parse(header: &[u8]) -> Option<Self> {
+        let header = header.get(..BATCH_HEADER_LEN)?;
+        let batch_length = i32::from_be_bytes(header[8..12].try_into().unwrap());
+        if batch_length <= 0 {
+            return None;
+        }
+
+        Some(BatchHeader {
+            base_offset: i64::from_be_bytes(header[0..8].try_into().unwrap()),
+            partition_leader_epoch: i32::from_be_bytes(header[12..16].try_into().unwrap()),
+            attributes: i16::from_be_bytes(header[21..23].try_into().unwrap()),
+            last_offset_delta: i32::from_be_bytes(header[23..27].try_into().unwrap()),
+            max_timestamp_ms: i64::from_be_bytes(header[35..43].try_into().unwrap()),
+            producer_id: i64::from_be_bytes(header[43..51].try_into().unwrap()),
+            producer_epoch: i16::from_be_bytes(header[51..53].try_into().unwrap()),
+            base_sequence: i32::from_be_bytes(header[53..57].try_into().unwrap()),
+            len: BATCH_LENGTH_END + batch_length as u64,
+        })
+    }
+
+    pub(crate) fn is_transactional(&self) -> bool {
+        self.attributes & ATTRIBUTE_TRANSACTIONAL != 0
+    }
+
+    // transaction commit and abort markers
+    pub(crate) fn is_control(&self) -> bool {
+        self.attributes & ATTRIBUTE_CONTROL != 0
+    }
+
+    // the offset after the batch's last record
+    pub(crate) fn next_offset(&self) -> i64 {
+        self.base_offset + self.last_offset_delta as i64 + 1
+    }
+}
+
+// the headers of the complete batches at the start of `records`, up to the first partial or
+// malformed one
+pub(crate) fn whole_batches(records: &[u8]) -> Vec<BatchHeader> {
+    let mut batches = vec![];
+    let mut position = 0;
+    while let Some(batch) = records
+        .get(position..)
+        .and_then(BatchHeader::parse)
+        .filter(|batch| position + batch.len as usize <= records.len())
+    {
+        position += batch.len as usize;
+        batches.push(batch);
+    }
+    batches
+}
+
+// `None` once there are no more complete batches from `position` on. a torn write at the
+// end of the segment ends it, everything before it still counts
+fn read_batch_header(
+    file: &mut File,
+    position: u64,
+    size: u64,
+) -> std::io::Result<Option<BatchHeader>> {
+    if position + BATCH_HEADER_LEN as u64 > size {
+        return Ok(None);
+    }
+
+    let mut header = [0u8; BATCH_HEADER_LEN];
+    file.seek(SeekFrom::Start(position))?;
+    file.read_exact(&mut header)?;
+
+    Ok(BatchHeader::parse(&header).filter(|batch| position + batch.len <= size))
+}
+
+// walks the batch headers, the record data itself is never read. `on_batch` gets each
+// batch's header, in offset order. when recovering, a torn write at the end
+// of the segment is truncated away
+fn load_segment(
+    path: PathBuf,
+    base_offset: i64,
+    recover: bool,
+    mut on_batch: impl FnMut(&BatchHeader),
+) -> Result<Segment, KafkaError> {
+    let mut file = File::open(&path)?;
+    let metadata = file.metadata()?;
+    let mut size = metadata.len();
+
+    let mut next_offset = base_offset;
+    let mut max_timestamp_ms = None;
+    let mut position = 0;
+
+    while let Some(batch) = read_batch_header(&mut file, position, size)? {
+        on_batch(&batch);
+        next_offset = batch.next_offset();
+        max_timestamp_ms = max_timestamp_ms.max(Some(batch.max_timestamp_ms));
+        position += batch.len;
+    }
+
+    if recover && position < size {
+        println!(
+            "Truncating {} bytes of incomplete batch data from {}",
+            size - position,
+            path.display()
+        );
+        OpenOptions::new()
+            .write(true)
+            .open(&path)?
+            .set_len(position)?;
+        size = position;
+    }
+
+    let max_timestamp_ms = match max_timestamp_ms {
+        Some(max_timestamp_ms) => max_timestamp_ms,
+        None => metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|modified| modified.as_millis() as i64)
+            .unwrap_or_default(),
+    };
+
+    Ok(Segment {
+        base_offset,
+        next_offset,
+        size,
+        max_timestamp_ms,
+        path,
+    })
+}
+
+// copies whole batches, starting with the one holding `fetch_offset`, for as long as the
+// limits allow
+// the whole batches between `fetch_offset` and `end_offset`
+fn read_records(
+    segments: &[Segment],
+    fetch_offset: i64,
+    end_offset: i64,
+    limits: &FetchLimits,
+) -> std::io::Result<Vec<u8>> {
+    let mut records = Vec::new();
+    // the last segment starting at or before the offset is the one holding it
+    let first = segments
+        .partition_point(|segment| segment.base_offset <= fetch_offset)
+        .saturating_sub(1);
+
+    for segment in &segments[first..] {
+        // a read past its deadline stops before opening anything
+        if limits.stops_before(records.len(), 0) {
+            return Ok(records);
+        }
+        let mut file = File::open(&segment.path)?;
+        let size = file.metadata()?.len();
+        let mut position = 0;
+
+        while let Some(batch) = read_batch_header(&mut file, position, size)? {
+            if batch.next_offset() > end_offset {
+                return Ok(records);
+            }
+            if batch.next_offset() > fetch_offset {
+                let batch_len = batch.len as usize;
+                if limits.stops_before(records.len(), batch_len) {
+                    return Ok(records);
+                }
+
+                let start = records.len();
+                records.resize(start + batch_len, 0);
+                file.seek(SeekFrom::Start(position))?;
+                file.read_exact(&mut records[start..])?;
+            }
+            position += batch.len;
+        }
+    }
+
+    Ok(records)
+}
+
+// removes the segment's log file along with its indexes
+fn delete_segment(log_path: &Path) -> std::io::Result<()> {
+    for extension in ["index", "timeindex", "txnindex"] {
+        match std::fs::remove_file(log_path.with_extension(extension)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    std::fs::remove_file(log_path)
+}
+
+// a random version 4 uuid. like kafka, ids that would print starting with `-` are skipped so
+// they can't be mistaken for command line flags
+pub(crate) fn generate_topic_id() -> i128 {
+    let random = || RandomState::new().build_hasher().finish() as u128;
+    loop {
+        let bits = (random() << 64) | random();
+        let bits = (bits & !(0xf << 76) & !(0x3 << 62)) | (0x4 << 76) | (0x2 << 62);
+        let topic_id = bits as i128;
+        if !encode_uuid(topic_id).starts_with('-') {
+            return topic_id;
+        }
+    }
+}
+
+// kafka prints uuids as url-safe base64 without padding
+pub fn encode_uuid(topic_id: i128) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+    let bits = topic_id as u128;
+
+    (0..22)
+        .map(|i| {
+            // the last character holds the 2 bits left over, padded out with zeros
+            let value = match i {
+                21 => (bits & 0x3) << 4,
+                _ => (bits >> (122 - 6 * i)) & 0x3f,
+            };
+            ALPHABET[value as usize] as char
+        })
+        .collect()
+}
+
+pub fn decode_uuid(encoded: &str) -> Option<i128> {
+    if encoded.len() != 22 {
+        return None;
+    }
+
+    let mut bits: u128 = 0;
+    for (i, c) in encoded.bytes().enumerate() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'-' => 62,
+            b'_' => 63,
+            _ => return None,
+        } as u128;
+
+        // 22 characters carry 132 bits, the last one only has 2 that aren't padding
+        bits = match i {
+            21 => (bits << 2) | (value >> 4),
+            _ => (bits << 6) | value,
+        };
+    }
+
+    Some(bits as i128)
+}
@@ -0,0 +1,204 @@
+use crate::config::topic_config_def;
+use crate::KafkaError;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+// a `<topic>.properties` file per topic with overrides, in the first log dir. a topic's
+// partition directories can be spread over several log dirs, so the topic has no directory
+// of its own to keep them in
+const OVERRIDES_DIR_NAME: &str = "topic-configs";
+const OVERRIDES_EXTENSION: &str = "properties";
+// where every topic's overrides used to be kept, moved over on load
+const LEGACY_OVERRIDES_FILE_NAME: &str = "topic-config-overrides.properties";
+
+// what the log cleaner goes by, the overrides merged over the registry defaults
+pub struct CleanupConfig {
+    // cleanup.policy includes delete, retention doesn't apply to compact only topics
+    pub delete: bool,
+    // -1 for no limit
+    pub retention_ms: i64,
+    pub retention_bytes: i64,
+}
+
+// dynamic per-topic config overrides, the storage layer falls back to the registry
+// defaults for anything not overridden here
+pub struct TopicConfigStore {
+    // unset when overrides only live in memory
+    dir: Option<PathBuf>,
+    overrides: Mutex<BTreeMap<String, BTreeMap<String, String>>>,
+}
+
+impl TopicConfigStore {
+    pub fn load(log_dir: impl AsRef<Path>) -> Result<Arc<Self>, KafkaError> {
+        let dir = log_dir.as_ref().join(OVERRIDES_DIR_NAME);
+        let mut overrides: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries.collect::<Result<Vec<_>, _>>()?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let path = entry.path();
+            if path.extension().and_then(|extension| extension.to_str())
+                != Some(OVERRIDES_EXTENSION)
+            {
+                continue;
+            }
+            let Some(topic) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let contents = std::fs::read_to_string(&path)?;
+            overrides.insert(topic.to_string(), parse_overrides(&path, &contents)?);
+        }
+
+        let store = TopicConfigStore {
+            dir: Some(dir),
+            overrides: Mutex::new(overrides),
+        };
+        store.migrate_legacy_overrides(log_dir.as_ref())?;
+        Ok(Arc::new(store))
+    }
+
+    pub fn in_memory() -> Arc<Self> {
+        Arc::new(TopicConfigStore {
+            dir: None,
+            overrides: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    pub fn overrides(&self, topic: &str) -> BTreeMap<String, String> {
+        let overrides = self.overrides.lock().unwrap();
+        overrides.get(topic).cloned().unwrap_or_default()
+    }
+
+    // the override if there is one, the registry default otherwise
+    pub fn get(&self, topic: &str, name: &str) -> Option<String> {
+        let overrides = self.overrides.lock().unwrap();
+        overrides
+            .get(topic)
+            .and_then(|configs| configs.get(name).cloned())
+            .or_else(|| topic_config_def(name)?.default.map(str::to_string))
+    }
+
+    pub fn cleanup_config(&self, topic: &str) -> CleanupConfig {
+        let number = |name| {
+            self.get(topic, name)
+                .and_then(|value| value.parse::<i64>().ok())
+                .unwrap_or(-1)
+        };
+        let delete = self
+            .get(topic, "cleanup.policy")
+            .unwrap_or_default()
+            .split(',')
+            .any(|policy| policy.trim() == "delete");
+
+        CleanupConfig {
+            delete,
+            retention_ms: number("retention.ms"),
+            retention_bytes: number("retention.bytes"),
+        }
+    }
+
+    // the size the topic's active segment is rolled at
+    pub fn segment_bytes(&self, topic: &str) -> u64 {
+        self.get(topic, "segment.bytes")
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(u64::MAX)
+    }
+
+    // replaces all overrides of the topic. `configs` has to be validated already
+    pub fn set_overrides(
+        &self,
+        topic: &str,
+        configs: BTreeMap<String, String>,
+    ) -> Result<(), KafkaError> {
+        let mut overrides = self.overrides.lock().unwrap();
+        self.persist(topic, &configs)?;
+        match configs.is_empty() {
+            true => overrides.remove(topic),
+            false => overrides.insert(topic.to_string(), configs),
+        };
+
+        Ok(())
+    }
+
+    // only the topic's own file is rewritten, through a temp file so a crash can't leave a
+    // half-written one behind. a topic without overrides has no file
+    fn persist(&self, topic: &str, configs: &BTreeMap<String, String>) -> Result<(), KafkaError> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        let path = dir.join(format!("{topic}.{OVERRIDES_EXTENSION}"));
+
+        if configs.is_empty() {
+            return match std::fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+                _ => Ok(()),
+            };
+        }
+
+        let contents = configs
+            .iter()
+            .map(|(name, value)| format!("{name}={value}\n"))
+            .collect::<String>();
+        std::fs::create_dir_all(dir)?;
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, contents)?;
+        std::fs::rename(&tmp_path, path)?;
+
+        Ok(())
+    }
+
+    // `<topic>/<config>=<value>` lines, neither topic nor config names can contain a `/`.
+    // the old file goes once each of its topics has been written out, overrides already in
+    // the new layout win
+    fn migrate_legacy_overrides(&self, log_dir: &Path) -> Result<(), KafkaError> {
+        let path = log_dir.join(LEGACY_OVERRIDES_FILE_NAME);
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut legacy: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            let parsed = line
+                .split_once('=')
+                .and_then(|(key, value)| Some((key.split_once('/')?, value)));
+            let Some(((topic, name), value)) = parsed else {
+                return Err(malformed(&path, line));
+            };
+            legacy
+                .entry(topic.to_string())
+                .or_default()
+                .insert(name.to_string(), value.to_string());
+        }
+
+        for (topic, configs) in legacy {
+            if self.overrides(&topic).is_empty() {
+                self.set_overrides(&topic, configs)?;
+            }
+        }
+        std::fs::remove_file(&path)?;
+
+        Ok(())
+    }
+}
+
+// `<config>=<value>` lines
+fn parse_overrides(path: &Path, contents: &str) -> Result<BTreeMap<String, String>, KafkaError> {
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let (name, value) = line.split_once('=').ok_or_else(|| malformed(path, line))?;
+            Ok((name.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+fn malformed(path: &Path, line: &str) -> KafkaError {
+    KafkaError::InvalidConfig(format!("malformed line in {}: {line}", path.display()))
+}
@@ -0,0 +1,162 @@
+use crate::is_flexible_version;
+use crate::readers::*;
+use std::io::Cursor;
+
+const BYTES_PER_ROW: usize = 16;
+
+// a run of bytes in a frame and what they decode to
+struct Field {
+    start: usize,
+    end: usize,
+    annotation: String,
+}
+
+// `frame` is the request as read, without its size prefix. offsets in the dump count from
+// the start of the size prefix, like a packet capture would
+pub fn trace_request(client_host: &str, frame: &[u8]) -> String {
+    let mut fields = vec![Field {
+        start: 0,
+        end: 4,
+        annotation: format!("size {}", frame.len()),
+    }];
+    let header_len = request_header_fields(frame, &mut fields);
+    let title = match header_len {
+        Some(_) => {
+            let api_key = i16::from_be_bytes([frame[0], frame[1]]);
+            let api_ver = i16::from_be_bytes([frame[2], frame[3]]);
+            format!("api key {api_key} v{api_ver}")
+        }
+        None => {
+            fields.truncate(1);
+            "malformed header".to_string()
+        }
+    };
+    push_body(&mut fields, 4 + header_len.unwrap_or(0), 4 + frame.len());
+
+    let mut prefixed = (frame.len() as i32).to_be_bytes().to_vec();
+    prefixed.extend_from_slice(frame);
+    dump(
+        &format!(
+            "<<< request from {client_host} ({title}, {} bytes)",
+            prefixed.len()
+        ),
+        &prefixed,
+        &fields,
+    )
+}
+
+// `frame` has its size prefix. `header_tagged` says whether the response header is the
+// flexible one, which ApiVersions and the bare error responses never use
+pub fn trace_response(client_host: &str, frame: &[u8], header_tagged: bool) -> String {
+    let mut fields = vec![];
+    let mut body_start = frame.len().min(4);
+    if frame.len() >= 8 {
+        fields.push(Field {
+            start: 0,
+            end: 4,
+            annotation: format!("size {}", frame.len() - 4),
+        });
+        fields.push(Field {
+            start: 4,
+            end: 8,
+            annotation: format!(
+                "correlation_id {}",
+                i32::from_be_bytes([frame[4], frame[5], frame[6], frame[7]])
+            ),
+        });
+        body_start = 8;
+
+        if header_tagged {
+            let mut cursor = Cursor::new(&frame[8..]);
+            if read_tagged_fields(&mut cursor).is_ok() {
+                body_start = 8 + cursor.position() as usize;
+                fields.push(tagged_fields_field(frame[8], 8, body_start));
+            }
+        }
+    }
+    push_body(&mut fields, body_start, frame.len());
+
+    dump(
+        &format!(">>> response to {client_host} ({} bytes)", frame.len()),
+        frame,
+        &fields,
+    )
+}
+
+// the fields of the request header, offset past the size prefix. returns the header's
+// length, `None` when it doesn't parse
+fn request_header_fields(frame: &[u8], fields: &mut Vec<Field>) -> Option<usize> {
+    let mut cursor = Cursor::new(frame);
+    let mut field = |cursor: &Cursor<&[u8]>, start: usize, annotation: String| {
+        fields.push(Field {
+            start: 4 + start,
+            end: 4 + cursor.position() as usize,
+            annotation,
+        });
+        cursor.position() as usize
+    };
+
+    let api_key = read_int16(&mut cursor).ok()?;
+    let start = field(&cursor, 0, format!("api_key {api_key}"));
+    let api_ver = read_int16(&mut cursor).ok()?;
+    let start = field(&cursor, start, format!("api_version {api_ver}"));
+    let correlation_id = read_int32(&mut cursor).ok()?;
+    let start = field(&cursor, start, format!("correlation_id {correlation_id}"));
+    let client_id = read_nullable_string(&mut cursor).ok()?;
+    let annotation = match client_id {
+        Some(client_id) => format!("client_id {client_id:?} (int16 length)"),
+        None => "client_id null".to_string(),
+    };
+    let start = field(&cursor, start, annotation);
+
+    if !is_flexible_version(api_key, api_ver) {
+        return Some(start);
+    }
+    read_tagged_fields(&mut cursor).ok()?;
+    let end = cursor.position() as usize;
+    fields.push(tagged_fields_field(frame[start], 4 + start, 4 + end));
+    Some(end)
+}
+
+// the count is the first varint, a single byte for any sane number of fields
+fn tagged_fields_field(count: u8, start: usize, end: usize) -> Field {
+    Field {
+        start,
+        end,
+        annotation: format!("tagged_fields ({})", count & 0x7f),
+    }
+}
+
+fn push_body(fields: &mut Vec<Field>, start: usize, end: usize) {
+    if start < end {
+        fields.push(Field {
+            start,
+            end,
+            annotation: format!("body ({} bytes)", end - start),
+        });
+    }
+}
+
+// one field per row, wrapped every 16 bytes, with its annotation on the first row
+fn dump(title: &str, bytes: &[u8], fields: &[Field]) -> String {
+    let mut out = title.to_string();
+    for field in fields {
+        let end = field.end.min(bytes.len());
+        let mut annotation = Some(field.annotation.as_str());
+        for row_start in (field.start..end).step_by(BYTES_PER_ROW) {
+            let row = &bytes[row_start..end.min(row_start + BYTES_PER_ROW)];
+            let hex = row
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let line = format!(
+                "\n  {row_start:04x}  {hex:<width$}  {}",
+                annotation.take().unwrap_or_default(),
+                width = BYTES_PER_ROW * 3 - 1
+            );
+            out.push_str(line.trim_end());
+        }
+    }
+    out
+}
@@ -0,0 +1,59 @@
+pub fn write_unsigned_varint(buf: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        buf.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+// non-flexible strings, an int16 length
+pub fn write_string(buf: &mut Vec<u8>, value: &str) {
+    buf.extend_from_slice(&(value.len() as i16).to_be_bytes());
+    buf.extend_from_slice(value.as_bytes());
+}
+
+// compact lengths are encoded as N + 1, where 0 marks a null value
+pub fn write_compact_array_len(buf: &mut Vec<u8>, len: usize) {
+    write_unsigned_varint(buf, len as u32 + 1);
+}
+
+pub fn write_compact_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    write_compact_array_len(buf, bytes.len());
+    buf.extend_from_slice(bytes);
+}
+
+pub fn write_compact_string(buf: &mut Vec<u8>, value: &str) {
+    write_compact_bytes(buf, value.as_bytes());
+}
+
+pub fn write_compact_nullable_string(buf: &mut Vec<u8>, value: Option<&str>) {
+    match value {
+        Some(value) => write_compact_string(buf, value),
+        None => write_unsigned_varint(buf, 0),
+    }
+}
+
+// the zigzag encoded signed varints inside record batches
+pub fn write_varint(buf: &mut Vec<u8>, value: i32) {
+    write_unsigned_varint(buf, ((value << 1) ^ (value >> 31)) as u32);
+}
+
+pub fn write_varlong(buf: &mut Vec<u8>, value: i64) {
+    let mut value = ((value << 1) ^ (value >> 63)) as u64;
+    while value >= 0x80 {
+        buf.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+// record keys, values and headers: a varint length, -1 for null
+pub fn write_varint_bytes(buf: &mut Vec<u8>, bytes: Option<&[u8]>) {
+    match bytes {
+        Some(bytes) => {
+            write_varint(buf, bytes.len() as i32);
+            buf.extend_from_slice(bytes);
+        }
+        None => write_varint(buf, -1),
+    }
+}
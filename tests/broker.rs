@@ -0,0 +1,393 @@
+use redis_starter_rust::{
+    encode_batch, Broker, BrokerHandle, FetchRequest, KafkaClient, MetadataRecord,
+    PartitionRegistration, RequestPartition, RequestTopic, TopicPartition,
+};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const API_VERSIONS: i16 = 18;
+const FETCH: i16 = 1;
+const METADATA: i16 = 3;
+const TOPIC_ID: i128 = 0x0102_0304_0506_0708_090a_0b0c_0d0e_0f10;
+
+// (api key, min, max) in the order ApiVersions v4 lists them
+const API_KEYS: [(i16, i16, i16); 36] = [
+    (1, 16, 16),
+    (3, 12, 12),
+    (8, 8, 8),
+    (9, 8, 8),
+    (11, 9, 9),
+    (12, 4, 4),
+    (13, 5, 5),
+    (14, 5, 5),
+    (15, 5, 5),
+    (16, 4, 4),
+    (17, 1, 1),
+    (18, 0, 4),
+    (23, 4, 4),
+    (29, 3, 3),
+    (30, 3, 3),
+    (31, 3, 3),
+    (32, 4, 4),
+    (35, 4, 4),
+    (36, 2, 2),
+    (43, 2, 2),
+    (44, 1, 1),
+    (45, 0, 0),
+    (46, 0, 0),
+    (48, 1, 1),
+    (49, 1, 1),
+    (51, 0, 0),
+    (52, 0, 0),
+    (53, 0, 0),
+    (54, 0, 0),
+    (55, 0, 1),
+    (56, 0, 3),
+    (59, 0, 0),
+    (60, 0, 0),
+    (61, 0, 0),
+    (62, 0, 3),
+    (63, 0, 1),
+];
+
+async fn start_broker() -> BrokerHandle {
+    Broker::builder()
+        .bind_address("127.0.0.1:0".parse().unwrap())
+        .config("log.store", "memory")
+        .start()
+        .await
+        .unwrap()
+}
+
+// a raw request frame with a v2 header, and the whole response frame back, size prefix and all
+async fn round_trip(stream: &mut TcpStream, api_key: i16, api_ver: i16, body: &[u8]) -> Vec<u8> {
+    let mut request = vec![];
+    request.extend_from_slice(&api_key.to_be_bytes());
+    request.extend_from_slice(&api_ver.to_be_bytes());
+    request.extend_from_slice(&1i32.to_be_bytes()); // correlation_id
+    request.extend_from_slice(&4i16.to_be_bytes());
+    request.extend_from_slice(b"test");
+    request.push(0); // TAG_BUFFER
+    request.extend_from_slice(body);
+
+    stream
+        .write_all(&(request.len() as i32).to_be_bytes())
+        .await
+        .unwrap();
+    stream.write_all(&request).await.unwrap();
+
+    let size = stream.read_i32().await.unwrap();
+    let mut response = size.to_be_bytes().to_vec();
+    response.resize(4 + size as usize, 0);
+    stream.read_exact(&mut response[4..]).await.unwrap();
+    response
+}
+
+fn framed(body: Vec<u8>) -> Vec<u8> {
+    let mut frame = (body.len() as i32).to_be_bytes().to_vec();
+    frame.extend_from_slice(&body);
+    frame
+}
+
+// stamped now so retention leaves them be
+fn batches() -> [Vec<u8>; 2] {
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64;
+    [
+        encode_batch(0, 0, now_ms, vec![b"a".to_vec(), b"b".to_vec()]),
+        encode_batch(2, 0, now_ms, vec![b"c".to_vec()]),
+    ]
+}
+
+// a follower's partition holding `batches`, with the high watermark left at `high_watermark`
+fn open_partition(broker: &BrokerHandle, batches: &[Vec<u8>], high_watermark: Option<i64>) {
+    let logs = &broker.state().logs;
+    let topic_partition = TopicPartition {
+        topic: "t".into(),
+        partition: 0,
+    };
+    logs.open_replica(&topic_partition, TOPIC_ID).unwrap();
+    for batch in batches {
+        logs.append_replica(&topic_partition, batch, u64::MAX)
+            .unwrap();
+    }
+    logs.set_high_watermark(&topic_partition, high_watermark);
+}
+
+fn consumer_fetch(fetch_offset: i64) -> FetchRequest {
+    FetchRequest {
+        correlation_id: 0,
+        max_wait_ms: 0,
+        min_bytes: 1,
+        max_bytes: 1_048_576,
+        isolation_level: 0,
+        session_id: 0,
+        session_epoch: -1,
+        topics: vec![RequestTopic {
+            topic_id: TOPIC_ID,
+            partitions: vec![RequestPartition {
+                partition: 0,
+                current_leader_epoch: -1,
+                fetch_offset,
+                last_fetched_epoch: -1,
+                log_start_offset: -1,
+                partition_max_bytes: 1_048_576,
+            }],
+        }],
+        forgotten_topics: vec![],
+        rack_id: String::new(),
+        replica_id: -1,
+        replica_epoch: -1,
+    }
+}
+
+// the v16 body for a response with the one partition
+fn fetch_response_body(
+    error_code: i16,
+    high_watermark: i64,
+    log_start_offset: i64,
+    records: Option<&[u8]>,
+) -> Vec<u8> {
+    let mut body = vec![];
+    body.extend_from_slice(&0i32.to_be_bytes()); // throttle_time_ms
+    body.extend_from_slice(&0i16.to_be_bytes()); // error_code
+    body.extend_from_slice(&0i32.to_be_bytes()); // session_id
+    body.push(2); // [responses]
+    body.extend_from_slice(&TOPIC_ID.to_be_bytes());
+    body.push(2); // [partitions]
+    body.extend_from_slice(&0i32.to_be_bytes());
+    body.extend_from_slice(&error_code.to_be_bytes());
+    body.extend_from_slice(&high_watermark.to_be_bytes());
+    body.extend_from_slice(&high_watermark.to_be_bytes()); // last_stable_offset
+    body.extend_from_slice(&log_start_offset.to_be_bytes());
+    body.push(0); // null aborted_transactions
+    body.extend_from_slice(&(-1i32).to_be_bytes()); // preferred_read_replica
+    match records {
+        Some(records) => {
+            let mut len = records.len() as u32 + 1;
+            while len >= 0x80 {
+                body.push(len as u8 | 0x80);
+                len >>= 7;
+            }
+            body.push(len as u8);
+            body.extend_from_slice(records);
+        }
+        None => body.push(0),
+    }
+    body.extend_from_slice(&[0, 0, 0]); // partition, topic and response TAG_BUFFERs
+    body
+}
+
+#[tokio::test]
+async fn api_versions_v4_lists_every_api() {
+    let broker = start_broker().await;
+    let mut stream = TcpStream::connect(broker.local_addr().unwrap())
+        .await
+        .unwrap();
+
+    let mut request = vec![];
+    request.push(5);
+    request.extend_from_slice(b"test");
+    request.push(4);
+    request.extend_from_slice(b"1.0");
+    request.push(0); // TAG_BUFFER
+    let response = round_trip(&mut stream, API_VERSIONS, 4, &request).await;
+
+    // a v0 response header, just the correlation id
+    let mut expected = 1i32.to_be_bytes().to_vec();
+    expected.extend_from_slice(&0i16.to_be_bytes()); // error_code
+    expected.push(API_KEYS.len() as u8 + 1); // [api_keys]
+    for (api_key, min, max) in API_KEYS {
+        expected.extend_from_slice(&api_key.to_be_bytes());
+        expected.extend_from_slice(&min.to_be_bytes());
+        expected.extend_from_slice(&max.to_be_bytes());
+        expected.push(0); // TAG_BUFFER
+    }
+    expected.extend_from_slice(&0i32.to_be_bytes()); // throttle_time_ms
+
+    // the one tagged field, supported_features with metadata.version 1 to 21
+    expected.extend_from_slice(&[1, 0, 23, 2, 17]);
+    expected.extend_from_slice(b"metadata.version");
+    expected.extend_from_slice(&[0, 1, 0, 21, 0]);
+    assert_eq!(response, framed(expected));
+
+    broker.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn api_versions_falls_back_to_v0_for_unknown_versions() {
+    let broker = start_broker().await;
+    let mut stream = TcpStream::connect(broker.local_addr().unwrap())
+        .await
+        .unwrap();
+
+    let response = round_trip(&mut stream, API_VERSIONS, 5, &[]).await;
+
+    // UNSUPPORTED_VERSION with just the ApiVersions range, in a v0 body
+    let mut expected = 1i32.to_be_bytes().to_vec();
+    expected.extend_from_slice(&35i16.to_be_bytes());
+    expected.extend_from_slice(&1i32.to_be_bytes()); // [api_keys]
+    expected.extend_from_slice(&API_VERSIONS.to_be_bytes());
+    expected.extend_from_slice(&0i16.to_be_bytes());
+    expected.extend_from_slice(&4i16.to_be_bytes());
+    assert_eq!(response, framed(expected));
+
+    broker.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn fetch_returns_the_stored_batches() {
+    let broker = start_broker().await;
+    let batches = batches();
+    open_partition(&broker, &batches, None);
+
+    let mut client = KafkaClient::connect(broker.local_addr().unwrap(), "test")
+        .await
+        .unwrap();
+    let mut request = vec![];
+    consumer_fetch(0).encode(&mut request);
+    let (_, response) = client.send(FETCH, 16, &request).await.unwrap();
+
+    assert_eq!(
+        response,
+        fetch_response_body(0, 3, 0, Some(&batches.concat()))
+    );
+
+    broker.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn fetch_stops_consumers_at_the_high_watermark() {
+    let broker = start_broker().await;
+    let batches = batches();
+    open_partition(&broker, &batches, Some(2));
+
+    let mut client = KafkaClient::connect(broker.local_addr().unwrap(), "test")
+        .await
+        .unwrap();
+    let mut request = vec![];
+    consumer_fetch(0).encode(&mut request);
+    let (_, response) = client.send(FETCH, 16, &request).await.unwrap();
+    assert_eq!(response, fetch_response_body(0, 2, 0, Some(&batches[0])));
+
+    // up to the log end offset is in range, there's just nothing to read past the high
+    // watermark yet
+    for fetch_offset in [2, 3] {
+        let mut request = vec![];
+        consumer_fetch(fetch_offset).encode(&mut request);
+        let (_, response) = client.send(FETCH, 16, &request).await.unwrap();
+        assert_eq!(response, fetch_response_body(0, 2, 0, Some(&[])));
+    }
+
+    // past it is out of range, with where the log starts to go back to
+    let mut request = vec![];
+    consumer_fetch(4).encode(&mut request);
+    let (_, response) = client.send(FETCH, 16, &request).await.unwrap();
+    assert_eq!(response, fetch_response_body(1, -1, 0, None));
+
+    broker.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn metadata_describes_the_partition() {
+    let broker = start_broker().await;
+    let addr = broker.local_addr().unwrap();
+    open_partition(&broker, &batches(), None);
+
+    let mut client = KafkaClient::connect(addr, "test").await.unwrap();
+    // the topic "t" by name, no auto creation, no authorized operations
+    let mut request = vec![2];
+    request.extend_from_slice(&0i128.to_be_bytes());
+    request.extend_from_slice(&[2, b't', 0]);
+    request.extend_from_slice(&[0, 0, 0]);
+    let (_, response) = client.send(METADATA, 12, &request).await.unwrap();
+
+    let mut expected = vec![];
+    expected.extend_from_slice(&0i32.to_be_bytes()); // throttle_time_ms
+                                                     // the broker itself, on the port it bound, without a rack
+    expected.push(2); // [brokers]
+    expected.extend_from_slice(&1i32.to_be_bytes());
+    expected.push(10);
+    expected.extend_from_slice(b"127.0.0.1");
+    expected.extend_from_slice(&(addr.port() as i32).to_be_bytes());
+    expected.extend_from_slice(&[0, 0]); // rack, TAG_BUFFER
+                                         // no meta.properties for a memory store, so no cluster id
+    expected.push(0);
+    expected.extend_from_slice(&1i32.to_be_bytes()); // controller_id
+
+    expected.push(2); // [topics]
+    expected.extend_from_slice(&0i16.to_be_bytes());
+    expected.extend_from_slice(&[2, b't']);
+    expected.extend_from_slice(&TOPIC_ID.to_be_bytes());
+    expected.push(0); // is_internal
+    expected.push(2); // [partitions]
+    expected.extend_from_slice(&0i16.to_be_bytes());
+    expected.extend_from_slice(&0i32.to_be_bytes()); // partition_index
+    expected.extend_from_slice(&1i32.to_be_bytes()); // leader_id
+    expected.extend_from_slice(&0i32.to_be_bytes()); // leader_epoch
+    expected.push(2); // [replica_nodes]
+    expected.extend_from_slice(&1i32.to_be_bytes());
+    expected.push(2); // [isr_nodes]
+    expected.extend_from_slice(&1i32.to_be_bytes());
+    expected.extend_from_slice(&[1, 0]); // [offline_replicas], TAG_BUFFER
+    expected.extend_from_slice(&i32::MIN.to_be_bytes()); // topic_authorized_operations
+    expected.extend_from_slice(&[0, 0]); // topic and response TAG_BUFFERs
+    assert_eq!(response, expected);
+
+    broker.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn fetch_fences_leader_epochs_against_the_metadata_log() {
+    let broker = start_broker().await;
+    let batches = batches();
+    open_partition(&broker, &batches, None);
+    // the metadata log has the partition at leader epoch 3, ahead of what its batches carry
+    let registration = PartitionRegistration {
+        topic_id: TOPIC_ID,
+        replicas: vec![1],
+        isr: vec![1],
+        leader: 1,
+        leader_epoch: 3,
+        partition_epoch: 0,
+    };
+    broker
+        .state()
+        .metadata_log
+        .update(|_| {
+            let records = vec![
+                MetadataRecord::topic("t", TOPIC_ID),
+                MetadataRecord::partition_registration(0, &registration),
+            ];
+            ((), records)
+        })
+        .unwrap();
+
+    let mut client = KafkaClient::connect(broker.local_addr().unwrap(), "test")
+        .await
+        .unwrap();
+    // FENCED_LEADER_EPOCH from a stale leader, UNKNOWN_LEADER_EPOCH from one not seen yet
+    for (current_leader_epoch, error_code) in [(2, 74), (4, 76)] {
+        let mut fetch = consumer_fetch(0);
+        fetch.topics[0].partitions[0].current_leader_epoch = current_leader_epoch;
+        let mut request = vec![];
+        fetch.encode(&mut request);
+        let (_, response) = client.send(FETCH, 16, &request).await.unwrap();
+        assert_eq!(response, fetch_response_body(error_code, -1, -1, None));
+    }
+
+    let mut fetch = consumer_fetch(0);
+    fetch.topics[0].partitions[0].current_leader_epoch = 3;
+    let mut request = vec![];
+    fetch.encode(&mut request);
+    let (_, response) = client.send(FETCH, 16, &request).await.unwrap();
+    assert_eq!(
+        response,
+        fetch_response_body(0, 3, 0, Some(&batches.concat()))
+    );
+
+    broker.shutdown().await.unwrap();
+}